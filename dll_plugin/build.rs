@@ -1,11 +1,33 @@
 use std::env;
 use std::path::Path;
 
+use hidpi_build::ImageSet;
+
 fn main() {
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
 
+    hidpi_build::generate_resource_constants(Path::new(&crate_dir).join("src/resource.h"), "res.rs", "INT")
+        .expect("generate resource constants");
+
+    // PC.bmp is drawn for 100%; PC.scale-<percent>.bmp files beside it are
+    // picked up as the variants for other scales
+    let images = [ImageSet::scaled_files("IDB_BITMAP1", Path::new(&crate_dir).join("src/PC.bmp")).expect("find images")];
+    let rc_file = hidpi_build::embed_images(Path::new(&crate_dir).join("src/Resource.rc"), &images)
+        .expect("pack images");
+
+    compile_resources(&rc_file);
+}
+
+#[cfg(feature = "winres")]
+fn compile_resources(rc_file: &Path) {
     let mut res = winres::WindowsResource::new();
-    res.set_resource_file(Path::new(&crate_dir).join("src/Resource.rc").to_str().unwrap());
+    res.set_resource_file(rc_file.to_str().unwrap());
     res.compile()
         .expect("compile resources");
-}
\ No newline at end of file
+}
+
+#[cfg(not(feature = "winres"))]
+fn compile_resources(rc_file: &Path) {
+    hidpi_build::compile_resources(rc_file)
+        .expect("compile resources");
+}
@@ -1,7 +1,8 @@
+use std::cell::RefCell;
 use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
 use winapi::{
-    ctypes::c_void,
     shared::{
         minwindef::{
             INT,
@@ -10,8 +11,8 @@ use winapi::{
             LPARAM,
             WPARAM,
             LRESULT,
-            FALSE,
             TRUE,
+            HMODULE,
         },
         ntdef:: {
             HANDLE,
@@ -37,46 +38,61 @@ use winapi::{
             WS_VISIBLE,
             WS_EX_LEFT,
             IDC_ARROW,
-            SPI_GETICONTITLELOGFONT,
             STM_SETIMAGE,
             IMAGE_BITMAP,
             COLOR_WINDOW,
             DestroyWindow,
+            GetWindow,
+            GW_CHILD,
+            GW_HWNDNEXT,
             RegisterClassExW,
+            UnregisterClassW,
             LoadCursorW,
             DefWindowProcW,
-            SystemParametersInfoForDpi,
             SendMessageW,
             SetWindowTextW,
             CreateWindowExW,
-            LoadBitmapW,
             GetPropW,
             SetPropW,
+            RemovePropW,
         },
-        winbase::{
-            MulDiv,
-        },
-        wingdi::{
-            LOGFONTW,
-            CreateFontIndirectW,
-        },
+        wingdi::DeleteObject,
         commctrl::{
             SetWindowSubclass,
             DefSubclassProc,
         },
         libloaderapi::{
-            GetModuleHandleW,
+            GetModuleHandleExW,
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+            GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
         },
     },
 };
 use hidpi::{
     get_dpi_for_system,
-    get_window_font,
     make_l_param,
     get_parent_relative_window_rect,
+    get_window_font,
     to_wstring,
     get_thread_dpi_awareness_context,
+    Dpi,
+    DpiAwarenessContext,
+    DpiFontCache,
+    FontDescription,
+    GdiFonts,
+    Logical,
     awareness_context_to_str,
+    Filter,
+    image::load_bitmap_scaled,
+};
+use dll_plugin_import::{
+    abi::negotiate,
+    Capabilities,
+    HostServices,
+    LogLevel,
+    PluginVTable,
+    Services,
+    PLUGIN_ABI_VERSION,
 };
 
 pub mod res;
@@ -84,10 +100,105 @@ use res::*;
 
 pub const HWND_NAME_EXTERNAL: &'static str = "External Content";
 pub const PLUGINWINDOWCLASSNAME: &'static str = "Plugin Window Class";
-pub const DEFAULT_PADDING96: INT = 20;
+pub const DEFAULT_PADDING96: Logical<INT> = Logical(20);
+pub const STATIC_TEXT_HEIGHT96: Logical<INT> = Logical(75);
+pub const STATIC_IMAGE_HEIGHT96: Logical<INT> = Logical(200);
 pub const PROP_FONTSET: &'static str = "FONT_SET";
+// The content's HBITMAP. The static control showing it doesn't delete it
+pub const PROP_BITMAP: &str = "BITMAP";
+// How the bitmap is resampled from the DPI its variant was drawn for, unless
+// the host's IMAGE_FILTER_SETTING says otherwise
+pub const IMAGE_FILTER: Filter = Filter::Lanczos3;
+pub const IMAGE_FILTER_SETTING: &'static str = "image_filter";
 pub const DEFAULT_CHAR_BUFFER: usize = 200;
 
+thread_local! {
+    // Fonts for the content created on this thread, deleted once the last of
+    // the content is destroyed
+    static FONTS: RefCell<DpiFontCache<GdiFonts>> = RefCell::new(DpiFontCache::new(GdiFonts));
+}
+
+// What plugin_entry hands the host. The content ignores DPI changes, see
+// SubclassProc
+static PLUGIN_VTABLE: PluginVTable = PluginVTable {
+    abi_version: PLUGIN_ABI_VERSION,
+    size: mem::size_of::<PluginVTable>() as u32,
+    name: b"dll_plugin\0".as_ptr() as *const c_char,
+    capabilities: Capabilities::MULTIPLE_INSTANCES,
+    create_content: Some(create_content),
+    destroy_content: Some(destroy_content),
+    init: Some(init),
+};
+
+// The same for a version 1 host, which can't hand over its services
+static PLUGIN_VTABLE_V1: PluginVTable = PluginVTable {
+    abi_version: 1,
+    size: PluginVTable::size_for(1) as u32,
+    init: None,
+    ..PLUGIN_VTABLE
+};
+
+#[no_mangle]
+pub extern "C" fn plugin_entry(host_abi_version: u32) -> *const PluginVTable
+{
+    negotiate(host_abi_version, &[&PLUGIN_VTABLE_V1, &PLUGIN_VTABLE])
+}
+
+unsafe extern "C" fn init(host: *const HostServices)
+{
+    Services::install(host);
+}
+
+unsafe extern "C" fn create_content(h_instance: *mut c_void, width: c_int, height: c_int) -> *mut c_void
+{
+    create_content_hwnd(h_instance as HINSTANCE, width, height) as *mut c_void
+}
+
+unsafe extern "C" fn destroy_content(content: *mut c_void)
+{
+    // Hand back the fonts the content's controls were using
+    let mut h_wnd_child = GetWindow(content as HWND, GW_CHILD);
+    while h_wnd_child != ptr::null_mut()
+    {
+        let h_font = get_window_font(h_wnd_child);
+        FONTS.with(|fonts| fonts.borrow_mut().release(h_font));
+        h_wnd_child = GetWindow(h_wnd_child, GW_HWNDNEXT);
+    }
+
+    // and delete the bitmap once the control showing it is gone
+    let prop_bitmap_str = to_wstring(PROP_BITMAP);
+    let h_bmp = RemovePropW(content as HWND, prop_bitmap_str.as_ptr());
+    DestroyWindow(content as HWND);
+    if !h_bmp.is_null()
+    {
+        DeleteObject(h_bmp);
+    }
+
+    // Windows keeps the class after the library is unloaded, so a reloaded
+    // build would get this one's window procedure. Unregistering fails while
+    // other content is open, and succeeds once the last of it is destroyed.
+    // The fonts go with it, so none are left behind when the host unloads
+    // the library.
+    let plugin_window_class_name_str = to_wstring(PLUGINWINDOWCLASSNAME);
+    if UnregisterClassW(plugin_window_class_name_str.as_ptr(), this_module()) != 0
+    {
+        FONTS.with(|fonts| fonts.borrow_mut().clear());
+    }
+}
+
+// The plugin's own module. The host may load a copy of the library under
+// another name, so it is found by address rather than by file name.
+fn this_module() -> HMODULE
+{
+    let mut h_mod: HMODULE = ptr::null_mut();
+    unsafe {
+        GetModuleHandleExW(GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            this_module as *const () as *const u16,
+            &mut h_mod);
+    }
+    h_mod
+}
+
 #[no_mangle]
 pub extern "C" fn class_registration(h_inst: HINSTANCE)
 {
@@ -107,11 +218,6 @@ pub extern "C" fn class_registration(h_inst: HINSTANCE)
     unsafe { RegisterClassExW(&wcex); }
 }
 
-pub fn scale_to_system_dpi(distance: INT, main_monitor_dpi: UINT) -> INT
-{
-    return unsafe { MulDiv(distance, main_monitor_dpi as INT, 96) } as INT
-}
-
 pub extern "system" fn wnd_proc(h_wnd: HWND, message: UINT, w_param: WPARAM, l_param: LPARAM) -> LRESULT
 {
     match message {
@@ -137,13 +243,19 @@ pub extern "system" fn wnd_proc(h_wnd: HWND, message: UINT, w_param: WPARAM, l_p
 #[no_mangle]
 pub extern "C" fn create_content_hwnd(h_instance: HINSTANCE, n_width: INT, n_height: INT) -> HWND
 {
-    // Register the window class
-    class_registration(h_instance);
+    // Register the window class with the plugin's module rather than the
+    // host's, so that every build of the plugin has a class of its own
+    let h_mod = this_module();
+    class_registration(h_mod);
 
-    // Get the "System DPI"
-    // Don't do this in per-monitor aware code as this will either
-    // return 96 or the system DPI but will not return the per-monitor DPI
-    let main_monitor_dpi = get_dpi_for_system();
+    // Ask the host for the DPI the content will be shown at. This content is
+    // system aware, so it's the "System DPI" (a global DPI that is based off
+    // of the scale factor of the primary display), which is what a host too
+    // old to say would have given it too
+    let services = Services::get();
+    let dpi = services.map_or_else(|| Dpi(get_dpi_for_system()), |services| unsafe { services.current_dpi(ptr::null_mut()) });
+    let main_monitor_dpi = dpi.0;
+    let content_width = Logical(n_width) - DEFAULT_PADDING96 - DEFAULT_PADDING96;
 
     // Create an HWND tree that is parented to the message window (HWND_MESSAGE)
     let plugin_window_class_name_str = to_wstring(PLUGINWINDOWCLASSNAME);
@@ -153,27 +265,25 @@ pub extern "C" fn create_content_hwnd(h_instance: HINSTANCE, n_width: INT, n_hei
         hwnd_name_external_str.as_ptr(), 
         WS_VISIBLE | WS_CHILD, 0, 0, n_width, n_height, 
         HWND_MESSAGE, ptr::null_mut(), 
-        h_instance, ptr::null_mut()) };
+        h_mod, ptr::null_mut()) };
             
     // Add some child controls
     let static_str = to_wstring("STATIC");
     let static_name_str = to_wstring("External content static (text) control");
     let h_wnd_static = unsafe { CreateWindowExW(WS_EX_LEFT, static_str.as_ptr(), static_name_str.as_ptr(), SS_LEFT | WS_CHILD | WS_VISIBLE,
-        scale_to_system_dpi(DEFAULT_PADDING96, main_monitor_dpi), 
-        scale_to_system_dpi(DEFAULT_PADDING96, main_monitor_dpi), 
-        scale_to_system_dpi(n_width - 2*DEFAULT_PADDING96, main_monitor_dpi),
-        scale_to_system_dpi(75, main_monitor_dpi),
+        DEFAULT_PADDING96.scale(dpi).0, 
+        DEFAULT_PADDING96.scale(dpi).0, 
+        content_width.scale(dpi).0,
+        STATIC_TEXT_HEIGHT96.scale(dpi).0,
         h_wnd_external_content, ptr::null_mut(), h_instance, ptr::null_mut()) };
     
     // Subclass the static control so that we can ignore WM_SETFONT from the host
     unsafe { SetWindowSubclass(h_wnd_static, Some(SubclassProc), 0, 0); }
 
     // Set the font for the static control
-    let _h_font_old = get_window_font(h_wnd_static);
-    let mut lf_text = LOGFONTW::default();
-    unsafe { SystemParametersInfoForDpi(SPI_GETICONTITLELOGFONT, mem::size_of::<LOGFONTW>() as u32, &mut lf_text as *mut LOGFONTW as *mut c_void, FALSE as u32, main_monitor_dpi); }
-    let h_font_new = unsafe { CreateFontIndirectW(&lf_text) };
-    if h_font_new != ptr::null_mut()
+    let h_font_new = FontDescription::icon_title()
+        .and_then(|description| FONTS.with(|fonts| fonts.borrow_mut().acquire(&description, dpi)));
+    if let Some(h_font_new) = h_font_new
     {
         unsafe {
             SendMessageW(h_wnd_static, WM_SETFONT, h_font_new as usize, make_l_param(TRUE as u16, 0) as isize);
@@ -182,9 +292,12 @@ pub extern "C" fn create_content_hwnd(h_instance: HINSTANCE, n_width: INT, n_hei
 
     // Convert DPI awareness context to a string
 
-    let dpi_awareness_context = get_thread_dpi_awareness_context();
+    let dpi_awareness_context = get_thread_dpi_awareness_context()
+        .and_then(DpiAwarenessContext::from_raw);
 
-    let awareness_context = awareness_context_to_str(dpi_awareness_context);
+    let awareness_context = dpi_awareness_context
+        .map(awareness_context_to_str)
+        .unwrap_or("DPI_AWARENESS_CONTEXT_UNAWARE");
     
     // Build the output string
     let wnd_text = to_wstring(format!("HWND content from an external source. The thread that created this content had a thread context of {}, with a DPI of: {}", awareness_context, main_monitor_dpi));
@@ -192,13 +305,21 @@ pub extern "C" fn create_content_hwnd(h_instance: HINSTANCE, n_width: INT, n_hei
         SetWindowTextW(h_wnd_static, wnd_text.as_ptr());
     }
 
-    // Load a bitmap
-    let module_name_str = to_wstring("dll_plugin.dll");
-    let h_mod = unsafe { GetModuleHandleW(module_name_str.as_ptr()) };
-    let h_bmp = unsafe { LoadBitmapW(h_mod, IDB_BITMAP1 as *const u16) };
+    // Decode the bitmap variant drawn closest to this DPI and resample it to
+    // exactly this DPI, rather than leave Windows to stretch it
+    let filter = services.and_then(|services| services.setting(IMAGE_FILTER_SETTING))
+        .and_then(|filter| parse_filter(&filter))
+        .unwrap_or(IMAGE_FILTER);
+    let h_bmp = load_bitmap_scaled(h_mod, IDB_BITMAP1 as u16, dpi, filter);
     if h_bmp == ptr::null_mut()
     {
         // Out of memory
+        if let Some(services) = services
+        {
+            services.log(LogLevel::Error, "couldn't load the bitmap");
+        }
+        // Give back the window and the font it was given
+        unsafe { destroy_content(h_wnd_external_content as *mut c_void); }
         return ptr::null_mut();
     }
 
@@ -208,18 +329,35 @@ pub extern "C" fn create_content_hwnd(h_instance: HINSTANCE, n_width: INT, n_hei
     let static_str = to_wstring("STATIC");
     let static_name_str = to_wstring("External content static (bitmap) control");
     let h_wnd_image = unsafe { CreateWindowExW(WS_EX_LEFT, static_str.as_ptr(), static_name_str.as_ptr(), SS_BITMAP | WS_CHILD | WS_VISIBLE,
-        scale_to_system_dpi(DEFAULT_PADDING96, main_monitor_dpi),
-        rc_client.bottom + scale_to_system_dpi(DEFAULT_PADDING96, main_monitor_dpi),
-        scale_to_system_dpi(n_width - 2 * DEFAULT_PADDING96, main_monitor_dpi),
-        scale_to_system_dpi(200, main_monitor_dpi),
+        DEFAULT_PADDING96.scale(dpi).0,
+        rc_client.bottom + DEFAULT_PADDING96.scale(dpi).0,
+        content_width.scale(dpi).0,
+        STATIC_IMAGE_HEIGHT96.scale(dpi).0,
         h_wnd_external_content, ptr::null_mut(), h_instance, ptr::null_mut()) };
+    let prop_bitmap_str = to_wstring(PROP_BITMAP);
     unsafe {
         SendMessageW(h_wnd_image, STM_SETIMAGE, IMAGE_BITMAP as usize, h_bmp as LPARAM);
+        SetPropW(h_wnd_external_content, prop_bitmap_str.as_ptr(), h_bmp as HANDLE);
+    }
+
+    if let Some(services) = services
+    {
+        services.log(LogLevel::Info, &format!("created content at {} DPI with {:?} resampling", main_monitor_dpi, filter));
     }
 
     return h_wnd_external_content;
 }
 
+fn parse_filter(filter: &str) -> Option<Filter>
+{
+    match filter {
+        "nearest" => Some(Filter::Nearest),
+        "bilinear" => Some(Filter::Bilinear),
+        "lanczos3" => Some(Filter::Lanczos3),
+        _ => None,
+    }
+}
+
 // Subclass the static control so that the parent can't send a new font when
 // the DPI changes. We want to illustrate how a child HWND can be bitmap
 // stretched by Windows. If the font were reset it would detract from 
@@ -1,3 +1,4 @@
 use winapi::shared::minwindef::INT;
 
-pub const IDB_BITMAP1: INT =                     101;
\ No newline at end of file
+// The #defines of resource.h, generated by build.rs
+include!(concat!(env!("OUT_DIR"), "/res.rs"));
@@ -0,0 +1,269 @@
+//! The C ABI between the host and its plugins.
+//!
+//! A plugin is a dynamic library exporting
+//!
+//! ```text
+//! extern "C" fn plugin_entry(host_abi_version: u32) -> *const PluginVTable
+//! ```
+//!
+//! The host passes the newest ABI version it speaks. The plugin returns a
+//! vtable for the newest version it speaks that is no newer than that, or
+//! null if it speaks none of them, and the host checks that the version is
+//! one it still supports. The vtable must stay valid while the library is
+//! loaded; a `static` in the plugin is the usual way.
+//!
+//! From version 2 a plugin may also export an `init`, which the host calls
+//! once after loading it with a `HostServices` table of functions to call
+//! back into the host with; `services` wraps it for plugins written in Rust.
+//!
+//! Handles are passed as untyped pointers so the ABI is the same on every
+//! platform: on Windows the instance is an `HINSTANCE` and content an `HWND`.
+
+use std::fmt;
+use std::mem;
+use std::ops::BitOr;
+use std::ptr;
+use std::os::raw::{c_char, c_int, c_void};
+
+/// The newest ABI version this host speaks, and that `PluginVTable` lays out.
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+
+/// The oldest ABI version this host still loads.
+pub const MIN_PLUGIN_ABI_VERSION: u32 = 1;
+
+/// The name of the entry point every plugin exports.
+pub const PLUGIN_ENTRY: &str = "plugin_entry";
+
+pub type PluginEntry = unsafe extern "C" fn(host_abi_version: u32) -> *const PluginVTable;
+
+/// Creates the plugin's content as a child of the message-only window, sized
+/// `width` by `height` at 96 DPI, for the host to reparent. Returns null on
+/// failure.
+pub type CreateContent = unsafe extern "C" fn(h_instance: *mut c_void, width: c_int, height: c_int) -> *mut c_void;
+
+/// Destroys content returned by `create_content`.
+pub type DestroyContent = unsafe extern "C" fn(content: *mut c_void);
+
+/// Hands the plugin the host's services, before any content is created. The
+/// table stays valid until the library is unloaded.
+pub type PluginInit = unsafe extern "C" fn(host: *const HostServices);
+
+/// What a plugin can do beyond the basics, as bit flags. Hosts ignore bits
+/// they don't know.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Capabilities(pub u32);
+
+impl Capabilities {
+    pub const NONE: Capabilities = Capabilities(0);
+    /// The content lays itself out again when its DPI changes, so it can move
+    /// between monitors without Windows stretching it. Unless its manifest
+    /// says otherwise, such a plugin is run per-monitor v2 aware, and one
+    /// without it system aware.
+    pub const PER_MONITOR_DPI: Capabilities = Capabilities(1);
+    /// `create_content` may be called again while earlier content is alive.
+    pub const MULTIPLE_INSTANCES: Capabilities = Capabilities(1 << 1);
+
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+}
+
+/// What `plugin_entry` returns.
+///
+/// Fields are only ever added at the end, with a new ABI version; `size` is
+/// the size of the struct the plugin was built with, so the host can tell
+/// which fields are there.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginVTable {
+    pub abi_version: u32,
+    pub size: u32,
+    /// NUL terminated UTF-8.
+    pub name: *const c_char,
+    pub capabilities: Capabilities,
+    pub create_content: Option<CreateContent>,
+    pub destroy_content: Option<DestroyContent>,
+    /// Since version 2, and may be `None` for a plugin that doesn't call back.
+    pub init: Option<PluginInit>,
+}
+
+// The vtable is read only, and plugins put it in a static.
+unsafe impl Sync for PluginVTable {}
+
+impl PluginVTable {
+    /// The size of the vtable of ABI version `version`, 0 for a version this
+    /// host doesn't know.
+    pub const fn size_for(version: u32) -> usize {
+        match version {
+            1 => mem::size_of::<PluginVTable>() - mem::size_of::<Option<PluginInit>>(),
+            2 => mem::size_of::<PluginVTable>(),
+            _ => 0,
+        }
+    }
+}
+
+/// For plugins written in Rust: the newest of `vtables` that a host speaking
+/// `host_abi_version` can read, or null if it can read none of them, as
+/// `plugin_entry` returns. Each older vtable should have the `size` of its
+/// version and no newer fields set.
+pub fn negotiate(host_abi_version: u32, vtables: &[&'static PluginVTable]) -> *const PluginVTable {
+    vtables.iter()
+        .filter(|vtable| vtable.abi_version <= host_abi_version)
+        .max_by_key(|vtable| vtable.abi_version)
+        .map_or(ptr::null(), |&vtable| vtable as *const PluginVTable)
+}
+
+/// How much a logged message matters, passed to `HostServices::log` as the
+/// `u32` of its position here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 4] = [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug];
+
+    pub fn from_raw(level: u32) -> Option<LogLevel> {
+        LogLevel::ALL.get(level as usize).copied()
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warning",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        })
+    }
+}
+
+/// Called by the host when the DPI of the content a plugin subscribed to
+/// changes, with the `user` pointer it subscribed with.
+pub type DpiChangedCallback = unsafe extern "C" fn(user: *mut c_void, content: *mut c_void, dpi: u32);
+
+/// What the host passes to a plugin's `init`: functions to call back into the
+/// host with, each taking `context` first. They may only be called on the
+/// thread the host calls the plugin on. Strings are NUL terminated UTF-8.
+///
+/// Fields are only ever added at the end, and `size` is the size of the
+/// table the host was built with, so a plugin can tell which are there.
+#[repr(C)]
+pub struct HostServices {
+    pub size: u32,
+    pub context: *mut c_void,
+    /// Log `message` at a `LogLevel`.
+    pub log: unsafe extern "C" fn(context: *mut c_void, level: u32, message: *const c_char),
+    /// Read the setting `key` into `buffer`, following the Win32 convention:
+    /// the number of bytes written is returned, not counting the terminator,
+    /// and if `buffer` is null or `capacity` too small nothing is written and
+    /// the capacity needed is returned instead. 0 means there is no such
+    /// setting, which an empty one reads as too.
+    pub get_setting: unsafe extern "C" fn(context: *mut c_void, key: *const c_char, buffer: *mut c_char, capacity: usize) -> usize,
+    /// Set the setting `key`, returning 0 if the host refused.
+    pub set_setting: unsafe extern "C" fn(context: *mut c_void, key: *const c_char, value: *const c_char) -> c_int,
+    /// The DPI `content` is shown at, or with null inside `create_content`
+    /// the DPI the content being created will be. The DPI is the one the
+    /// content's awareness sees, so 96 for DPI unaware content.
+    pub current_dpi: unsafe extern "C" fn(context: *mut c_void, content: *mut c_void) -> u32,
+    /// Have `callback` called when the DPI of `content` changes, until
+    /// unsubscribed. Returns the subscription, never 0.
+    pub subscribe_dpi_changed: unsafe extern "C" fn(context: *mut c_void, content: *mut c_void, callback: DpiChangedCallback, user: *mut c_void) -> u64,
+    /// Stop a subscription. The callback isn't called once this returns.
+    pub unsubscribe_dpi_changed: unsafe extern "C" fn(context: *mut c_void, subscription: u64),
+    /// Ask the host to lay out the window `content` is in again, for content
+    /// whose size needs to change. The host does so after the call returns.
+    pub request_relayout: unsafe extern "C" fn(context: *mut c_void, content: *mut c_void),
+}
+
+impl fmt::Debug for HostServices {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HostServices")
+            .field("size", &self.size)
+            .field("context", &self.context)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe extern "C" fn create_content(_: *mut c_void, _: c_int, _: c_int) -> *mut c_void {
+        ptr::null_mut()
+    }
+
+    unsafe extern "C" fn destroy_content(_: *mut c_void) {}
+
+    unsafe extern "C" fn init(_: *const HostServices) {}
+
+    static V1: PluginVTable = PluginVTable {
+        abi_version: 1,
+        size: PluginVTable::size_for(1) as u32,
+        name: b"test\0".as_ptr() as *const c_char,
+        capabilities: Capabilities::NONE,
+        create_content: Some(create_content),
+        destroy_content: Some(destroy_content),
+        init: None,
+    };
+
+    static V2: PluginVTable = PluginVTable {
+        abi_version: 2,
+        size: PluginVTable::size_for(2) as u32,
+        init: Some(init),
+        ..V1
+    };
+
+    #[test]
+    fn versions_add_fields_at_the_end() {
+        let pointer = mem::size_of::<usize>();
+        // abi_version and size, name, capabilities padded to the alignment of
+        // the pointers, create_content and destroy_content
+        assert_eq!(PluginVTable::size_for(1), 8 + pointer + pointer + 2 * pointer);
+        assert_eq!(PluginVTable::size_for(2), PluginVTable::size_for(1) + pointer);
+        assert_eq!(PluginVTable::size_for(0), 0);
+        assert_eq!(PluginVTable::size_for(PLUGIN_ABI_VERSION + 1), 0);
+        assert_eq!(PluginVTable::size_for(PLUGIN_ABI_VERSION), mem::size_of::<PluginVTable>());
+    }
+
+    #[test]
+    fn negotiate_picks_the_newest_vtable_the_host_reads() {
+        assert_eq!(negotiate(0, &[&V1, &V2]), ptr::null());
+        assert_eq!(negotiate(1, &[&V1, &V2]), &V1 as *const PluginVTable);
+        assert_eq!(negotiate(2, &[&V2, &V1]), &V2 as *const PluginVTable);
+        assert_eq!(negotiate(7, &[&V1, &V2]), &V2 as *const PluginVTable);
+        assert_eq!(negotiate(1, &[&V2]), ptr::null());
+        assert_eq!(negotiate(2, &[]), ptr::null());
+    }
+
+    #[test]
+    fn capabilities_combine_as_bits() {
+        let both = Capabilities::PER_MONITOR_DPI | Capabilities::MULTIPLE_INSTANCES;
+        assert_eq!(both, Capabilities(3));
+        assert!(both.contains(Capabilities::PER_MONITOR_DPI));
+        assert!(both.contains(Capabilities::NONE));
+        assert!(!Capabilities::PER_MONITOR_DPI.contains(both));
+    }
+
+    #[test]
+    fn log_levels_are_their_position() {
+        for (raw, level) in LogLevel::ALL.iter().enumerate() {
+            assert_eq!(LogLevel::from_raw(raw as u32), Some(*level));
+        }
+        assert_eq!(LogLevel::from_raw(4), None);
+        assert_eq!(LogLevel::Warn.to_string(), "warning");
+    }
+}
@@ -0,0 +1,320 @@
+//! The helper process `remote` runs a plugin in: `plugin_host <library>`
+//! loads the plugin and answers the host's requests, read from standard
+//! input, on standard output. It exits when the host closes its end.
+//!
+//! The plugin's calls back into the host are answered here: what it logs is
+//! passed on, as are requests to relayout with protocol version 2, and
+//! `DpiChanged` goes to the plugin's subscribers. Settings are only kept for
+//! as long as the helper runs, since the protocol has no way to ask the host.
+//!
+//! On Windows the content windows are created on the main thread, which
+//! runs a message loop; requests are read on a thread of their own and
+//! handed over with a thread message. The helper is a GUI program there, so
+//! starting it doesn't open a console window.
+
+#![cfg_attr(windows, windows_subsystem = "windows")]
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::io::{self, BufReader, BufWriter, Stdout};
+use std::os::raw::c_void;
+use std::process;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+
+use dll_plugin_import::protocol::{self, Event, ProtocolError, Request, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use dll_plugin_import::{DpiIsolation, Host, LogLevel, Plugin};
+
+// Where events go, shared with the plugin's calls back into the host
+struct Events {
+    writer: RefCell<BufWriter<Stdout>>,
+    version: u32,
+    // the content and DPI of each id, for the plugin's current_dpi
+    contents: RefCell<HashMap<usize, (u64, Option<u32>)>>,
+    // the id of the content being created, which isn't in contents yet
+    creating: Cell<Option<u64>>,
+    settings: RefCell<HashMap<String, String>>,
+}
+
+impl Events {
+    fn send(&self, event: &Event) {
+        // the host is gone if this fails, and the request loop finds out next
+        let _ = protocol::send(&mut *self.writer.borrow_mut(), event);
+    }
+
+    fn id_of(&self, content: *mut c_void) -> Option<u64> {
+        let known = self.contents.borrow().get(&(content as usize)).map(|&(id, _)| id);
+        known.or_else(|| self.creating.get())
+    }
+}
+
+impl Host for Events {
+    fn log(&self, _plugin: &str, level: LogLevel, message: &str) {
+        self.send(&Event::Log { level, message: message.to_owned() });
+    }
+
+    fn setting(&self, _plugin: &str, key: &str) -> Option<String> {
+        self.settings.borrow().get(key).cloned()
+    }
+
+    fn set_setting(&self, _plugin: &str, key: &str, value: &str) -> bool {
+        self.settings.borrow_mut().insert(key.to_owned(), value.to_owned());
+        true
+    }
+
+    // Until the host says, content is taken to be at the DPI of the
+    // monitor its awareness sees, the system's in the case of content that
+    // is being created
+    fn current_dpi(&self, content: *mut c_void) -> u32 {
+        let told = self.contents.borrow().get(&(content as usize)).and_then(|&(_, dpi)| dpi);
+        told.unwrap_or_else(|| window_dpi(content))
+    }
+
+    fn request_relayout(&self, content: *mut c_void) {
+        match self.id_of(content) {
+            Some(id) if self.version >= 2 => self.send(&Event::Relayout { id }),
+            _ => {},
+        }
+    }
+}
+
+struct Helper {
+    plugin: Plugin,
+    // with the isolation each was created under, which calls into the
+    // plugin about it run under too
+    contents: BTreeMap<u64, (*mut c_void, DpiIsolation)>,
+    events: Rc<Events>,
+}
+
+impl Helper {
+    fn send(&mut self, event: &Event) {
+        self.events.send(event);
+    }
+
+    fn log(&mut self, level: LogLevel, message: String) {
+        self.send(&Event::Log { level, message });
+    }
+
+    fn handle(&mut self, request: Request) {
+        match request {
+            Request::Hello { .. } => self.log(LogLevel::Warn, "hello again after the handshake".to_owned()),
+            Request::Create { id, width, height, isolation } => {
+                let plugin = &self.plugin;
+                self.events.creating.set(Some(id));
+                let content = isolation.run(|| unsafe { plugin.create_content(module_handle(), width, height) });
+                self.events.creating.set(None);
+                if content.is_null() {
+                    self.log(LogLevel::Error, format!("{} couldn't create content {}", self.plugin.name(), id));
+                } else {
+                    self.events.contents.borrow_mut().insert(content as usize, (id, None));
+                    if let Some((old, old_isolation)) = self.contents.insert(id, (content, isolation)) {
+                        // a host reusing an id has lost track of the old content
+                        self.events.contents.borrow_mut().remove(&(old as usize));
+                        old_isolation.run(|| unsafe { plugin.destroy_content(old) });
+                    }
+                }
+                self.send(&Event::Created { id, window: content as usize as u64 });
+            },
+            Request::Resize { id, width, height } => match self.contents.get(&id) {
+                Some(&(content, _)) => resize(content, width, height),
+                None => self.log(LogLevel::Warn, format!("resize of unknown content {}", id)),
+            },
+            Request::DpiChanged { id, dpi } => match self.contents.get(&id) {
+                Some(&(content, isolation)) => {
+                    self.events.contents.borrow_mut().insert(content as usize, (id, Some(dpi)));
+                    isolation.run(|| self.plugin.dpi_changed(content, dpi));
+                },
+                None => self.log(LogLevel::Warn, format!("DPI change of unknown content {}", id)),
+            },
+            Request::Destroy { id } => {
+                if let Some((content, isolation)) = self.contents.remove(&id) {
+                    self.events.contents.borrow_mut().remove(&(content as usize));
+                    isolation.run(|| unsafe { self.plugin.destroy_content(content) });
+                }
+                self.send(&Event::Destroyed { id });
+            },
+        }
+    }
+
+    // The host stopped, or sent something this helper doesn't understand
+    fn stop(&mut self, error: ProtocolError) {
+        if error != ProtocolError::Closed {
+            self.log(LogLevel::Error, error.to_string());
+        }
+        for (_, (content, isolation)) in std::mem::take(&mut self.contents) {
+            isolation.run(|| unsafe { self.plugin.destroy_content(content) });
+        }
+    }
+}
+
+fn main() {
+    let path = match env::args_os().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: plugin_host <plugin library>");
+            process::exit(2);
+        },
+    };
+    let mut events = BufWriter::new(io::stdout());
+    let mut requests = BufReader::new(io::stdin());
+
+    let mut plugin = match Plugin::load(&path) {
+        Ok(plugin) => plugin,
+        Err(error) => {
+            let _ = protocol::send(&mut events, &Event::Log { level: LogLevel::Error, message: error.to_string() });
+            process::exit(1);
+        },
+    };
+
+    let version = match protocol::receive(&mut requests) {
+        Ok(Request::Hello { version }) if version >= MIN_PROTOCOL_VERSION => version.min(PROTOCOL_VERSION),
+        Ok(Request::Hello { version }) => {
+            let message = format!("protocol {} is older than {}", version, MIN_PROTOCOL_VERSION);
+            let _ = protocol::send(&mut events, &Event::Log { level: LogLevel::Error, message });
+            process::exit(1);
+        },
+        Ok(_) => {
+            let message = "the host didn't start with hello".to_owned();
+            let _ = protocol::send(&mut events, &Event::Log { level: LogLevel::Error, message });
+            process::exit(1);
+        },
+        Err(_) => process::exit(1),
+    };
+    let hello = Event::Hello { version, name: plugin.name().to_owned(), capabilities: plugin.capabilities() };
+    if protocol::send(&mut events, &hello).is_err() {
+        process::exit(1);
+    }
+
+    let events = Rc::new(Events {
+        writer: RefCell::new(events),
+        version,
+        contents: RefCell::new(HashMap::new()),
+        creating: Cell::new(None),
+        settings: RefCell::new(HashMap::new()),
+    });
+    plugin.init(events.clone());
+    let mut helper = Helper { plugin, contents: BTreeMap::new(), events };
+    let wake = waker();
+    let (sender, received) = mpsc::channel();
+    thread::spawn(move || loop {
+        let request = protocol::receive::<Request, _>(&mut requests);
+        let done = request.is_err();
+        if sender.send(request).is_err() {
+            break;
+        }
+        wake();
+        if done {
+            break;
+        }
+    });
+    serve(&mut helper, received);
+}
+
+#[cfg(not(windows))]
+fn module_handle() -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+#[cfg(not(windows))]
+fn window_dpi(_content: *mut c_void) -> u32 {
+    hidpi::get_system_dpi().0
+}
+
+#[cfg(not(windows))]
+fn resize(_content: *mut c_void, _width: i32, _height: i32) {}
+
+// Nothing to wake, the main thread waits on the channel
+#[cfg(not(windows))]
+fn waker() -> impl Fn() + Send + 'static {
+    || {}
+}
+
+#[cfg(not(windows))]
+fn serve(helper: &mut Helper, received: mpsc::Receiver<Result<Request, ProtocolError>>) {
+    loop {
+        match received.recv() {
+            Ok(Ok(request)) => helper.handle(request),
+            Ok(Err(error)) => return helper.stop(error),
+            Err(_) => return helper.stop(ProtocolError::Closed),
+        }
+    }
+}
+
+#[cfg(windows)]
+use win::{module_handle, resize, serve, waker, window_dpi};
+
+#[cfg(windows)]
+mod win {
+    use std::os::raw::c_void;
+    use std::ptr;
+    use std::sync::mpsc::{Receiver, TryRecvError};
+
+    use winapi::shared::windef::HWND;
+    use winapi::um::{libloaderapi, processthreadsapi, winuser};
+
+    use dll_plugin_import::protocol::{ProtocolError, Request};
+
+    use super::Helper;
+
+    pub fn module_handle() -> *mut c_void {
+        unsafe { libloaderapi::GetModuleHandleW(ptr::null_mut()) as *mut c_void }
+    }
+
+    // The system DPI answers for the thread's awareness, as content being
+    // created is
+    pub fn window_dpi(content: *mut c_void) -> u32 {
+        if content.is_null() {
+            hidpi::get_dpi_for_system()
+        } else {
+            hidpi::get_window_dpi(content as HWND).0
+        }
+    }
+
+    pub fn resize(content: *mut c_void, width: i32, height: i32) {
+        unsafe {
+            winuser::SetWindowPos(
+                content as HWND,
+                ptr::null_mut(),
+                0,
+                0,
+                width,
+                height,
+                winuser::SWP_NOMOVE | winuser::SWP_NOZORDER | winuser::SWP_NOACTIVATE,
+            );
+        }
+    }
+
+    // Posts a thread message to the main thread for each request read
+    pub fn waker() -> impl Fn() + Send + 'static {
+        let thread_id = unsafe { processthreadsapi::GetCurrentThreadId() };
+        // a thread has no message queue to post to until it asks for one
+        let mut msg = winuser::MSG::default();
+        unsafe { winuser::PeekMessageW(&mut msg, ptr::null_mut(), 0, 0, winuser::PM_NOREMOVE) };
+        move || unsafe {
+            winuser::PostThreadMessageW(thread_id, winuser::WM_APP, 0, 0);
+        }
+    }
+
+    pub fn serve(helper: &mut Helper, received: Receiver<Result<Request, ProtocolError>>) {
+        let mut msg = winuser::MSG::default();
+        while unsafe { winuser::GetMessageW(&mut msg, ptr::null_mut(), 0, 0) } > 0 {
+            if msg.hwnd.is_null() && msg.message == winuser::WM_APP {
+                loop {
+                    match received.try_recv() {
+                        Ok(Ok(request)) => helper.handle(request),
+                        Ok(Err(error)) => return helper.stop(error),
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return helper.stop(ProtocolError::Closed),
+                    }
+                }
+            } else {
+                unsafe {
+                    winuser::TranslateMessage(&msg);
+                    winuser::DispatchMessageW(&msg);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Plugin libraries for the tests, built from `tests/fixtures/plugin.rs` with
+//! `rustc` the first time a test asks for each, beside the test binary, the
+//! same for a stand-in helper process from `tests/fixtures/helper.rs`, a
+//! `Host` that records what plugins call it for, and a DPI backend standing
+//! in for the thread awareness Windows would switch.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::os::raw::{c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use hidpi::backend::DpiBackend;
+use hidpi::sys::{
+    BOOL,
+    DWORD,
+    UINT,
+    HANDLE,
+    DPI_AWARENESS,
+    DPI_AWARENESS_CONTEXT,
+    DPI_HOSTING_BEHAVIOR,
+    HMONITOR,
+    HWND,
+    POINT,
+    RECT,
+    MONITOR_DPI_TYPE,
+    LOGFONTW,
+};
+use hidpi::win::WinDpiAwareness;
+use hidpi::{DpiAwarenessContext, DpiHostingBehavior};
+use libloading::{Library, Symbol};
+
+use crate::abi::LogLevel;
+use crate::host::Host;
+
+const PLUGIN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/plugin.rs");
+const HELPER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/helper.rs");
+
+static BUILT: Mutex<BTreeMap<String, PathBuf>> = Mutex::new(BTreeMap::new());
+
+/// The fixture plugin `variant` (see the source for the list), named `name`.
+pub fn plugin(variant: &str, name: &str) -> PathBuf {
+    let crate_name = format!("{}_{}", variant, name.replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+    let file = format!("{}{}{}", env::consts::DLL_PREFIX, crate_name, env::consts::DLL_SUFFIX);
+    built(PLUGIN, &crate_name, &file, |rustc| {
+        rustc.args(["--crate-type", "cdylib", "--cfg"])
+            .arg(format!("fixture=\"{}\"", variant))
+            .env("FIXTURE_NAME", name);
+    })
+}
+
+/// A stand-in for `plugin_host` that does what a control file says; see the
+/// source.
+pub fn helper() -> PathBuf {
+    let file = format!("stand_in_helper{}", env::consts::EXE_SUFFIX);
+    built(HELPER, "stand_in_helper", &file, |rustc| {
+        rustc.args(["--crate-type", "bin"]);
+    })
+}
+
+fn built<F>(source: &str, crate_name: &str, file: &str, configure: F) -> PathBuf where F: FnOnce(&mut Command) {
+    let mut built = BUILT.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(path) = built.get(file) {
+        return path.clone();
+    }
+
+    let exe = env::current_exe().unwrap();
+    let dir = exe.parent().unwrap().join("plugin-fixtures");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(file);
+
+    let source_modified = fs::metadata(source).and_then(|metadata| metadata.modified()).unwrap();
+    let up_to_date = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified >= source_modified);
+    if !up_to_date {
+        build(source, crate_name, &path, configure);
+    }
+    built.insert(file.to_owned(), path.clone());
+    path
+}
+
+// Build into a scratch name and rename, so a test run never sees half a library
+fn build<F>(source: &str, crate_name: &str, path: &Path, configure: F) where F: FnOnce(&mut Command) {
+    let scratch = path.with_extension(format!("{}.tmp", std::process::id()));
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let mut command = Command::new(rustc);
+    command.args(["--edition", "2018", "--crate-name", crate_name]);
+    configure(&mut command);
+    let output = command
+        .arg("-o")
+        .arg(&scratch)
+        .arg(source)
+        .output()
+        .expect("run rustc");
+    assert!(output.status.success(), "building {} failed:\n{}", crate_name, String::from_utf8_lossy(&output.stderr));
+    fs::rename(&scratch, path).unwrap();
+}
+
+/// A fresh, empty directory for one test.
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("dll_plugin_import-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The fixture's own exports, for looking at what the host did to it. The
+/// library is the one a `Plugin` loaded from `path`, as long as it is loaded.
+pub struct Probe {
+    library: Library,
+}
+
+impl Probe {
+    pub fn new(path: &Path) -> Probe {
+        Probe { library: Library::new(path).unwrap() }
+    }
+
+    fn call<T>(&self, export: &[u8]) -> T {
+        unsafe {
+            let function: Symbol<unsafe extern "C" fn() -> T> = self.library.get(export).unwrap();
+            function()
+        }
+    }
+
+    pub fn host_abi_version(&self) -> u32 {
+        self.call(b"fixture_host_abi_version\0")
+    }
+
+    pub fn build(&self) -> u32 {
+        self.call(b"fixture_build\0")
+    }
+
+    pub fn live_content(&self) -> usize {
+        self.call(b"fixture_live_content\0")
+    }
+
+    pub fn inits(&self) -> usize {
+        self.call(b"fixture_inits\0")
+    }
+
+    /// The size `content` was created at.
+    pub fn content_size(&self, content: *mut c_void) -> (c_int, c_int) {
+        let (mut width, mut height) = (0, 0);
+        unsafe {
+            let function: Symbol<unsafe extern "C" fn(*mut c_void, *mut c_int, *mut c_int)> =
+                self.library.get(b"fixture_content_size\0").unwrap();
+            function(content, &mut width, &mut height);
+        }
+        (width, height)
+    }
+}
+
+/// A `Host` keeping settings, which records each call as a line such as
+/// `plugin log info message`, `plugin setting key`, `plugin set key=value`,
+/// `current_dpi 0x0` or `relayout 0x1234`.
+#[derive(Default)]
+pub struct TestHost {
+    calls: RefCell<Vec<String>>,
+    pub settings: RefCell<BTreeMap<(String, String), String>>,
+    /// What `current_dpi` answers.
+    pub dpi: Cell<u32>,
+    /// Whether `set_setting` refuses.
+    pub refuse: Cell<bool>,
+    /// Whether every call panics, after being recorded.
+    pub panic: Cell<bool>,
+}
+
+impl TestHost {
+    pub fn new() -> TestHost {
+        TestHost { dpi: Cell::new(96), ..TestHost::default() }
+    }
+
+    pub fn with_setting(self, plugin: &str, key: &str, value: &str) -> TestHost {
+        self.settings.borrow_mut().insert((plugin.to_owned(), key.to_owned()), value.to_owned());
+        self
+    }
+
+    /// The calls since the last time.
+    pub fn take_calls(&self) -> Vec<String> {
+        self.calls.replace(Vec::new())
+    }
+
+    fn record(&self, call: String) {
+        self.calls.borrow_mut().push(call);
+        if self.panic.get() {
+            panic!("the host fell over");
+        }
+    }
+}
+
+impl Host for TestHost {
+    fn log(&self, plugin: &str, level: LogLevel, message: &str) {
+        self.record(format!("{} log {} {}", plugin, level, message));
+    }
+
+    fn setting(&self, plugin: &str, key: &str) -> Option<String> {
+        self.record(format!("{} setting {}", plugin, key));
+        self.settings.borrow().get(&(plugin.to_owned(), key.to_owned())).cloned()
+    }
+
+    fn set_setting(&self, plugin: &str, key: &str, value: &str) -> bool {
+        self.record(format!("{} set {}={}", plugin, key, value));
+        if self.refuse.get() {
+            return false;
+        }
+        self.settings.borrow_mut().insert((plugin.to_owned(), key.to_owned()), value.to_owned());
+        true
+    }
+
+    fn current_dpi(&self, content: *mut c_void) -> u32 {
+        self.record(format!("current_dpi {:?}", content));
+        self.dpi.get()
+    }
+
+    fn request_relayout(&self, content: *mut c_void) {
+        self.record(format!("relayout {:?}", content));
+    }
+}
+
+/// A `DpiBackend` with nothing but the thread's awareness context and
+/// hosting behavior, which start out unaware and default. Install it with
+/// `hidpi::backend::with_backend`; clones share the thread state.
+#[derive(Clone)]
+pub struct ThreadBackend {
+    state: Rc<RefCell<ThreadState>>,
+}
+
+struct ThreadState {
+    context: DPI_AWARENESS_CONTEXT,
+    hosting: DPI_HOSTING_BEHAVIOR,
+    switches: Vec<String>,
+}
+
+impl ThreadBackend {
+    pub fn new() -> ThreadBackend {
+        ThreadBackend {
+            state: Rc::new(RefCell::new(ThreadState {
+                context: DpiAwarenessContext::Unaware.to_raw(),
+                hosting: DpiHostingBehavior::Default.to_raw(),
+                switches: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn context(&self) -> DpiAwarenessContext {
+        DpiAwarenessContext::from_raw(self.state.borrow().context).unwrap()
+    }
+
+    pub fn hosting(&self) -> DpiHostingBehavior {
+        DpiHostingBehavior::from_raw(self.state.borrow().hosting).unwrap()
+    }
+
+    /// Every change made to the thread, oldest first, as `context System` or
+    /// `hosting Mixed`.
+    pub fn switches(&self) -> Vec<String> {
+        self.state.borrow().switches.clone()
+    }
+}
+
+impl DpiBackend for ThreadBackend {
+    fn get_thread_dpi_awareness_context(&self) -> Option<DPI_AWARENESS_CONTEXT> {
+        Some(self.state.borrow().context)
+    }
+
+    fn set_thread_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<DPI_AWARENESS_CONTEXT> {
+        let known = DpiAwarenessContext::from_raw(context)?;
+        let mut state = self.state.borrow_mut();
+        state.switches.push(format!("context {:?}", known));
+        Some(std::mem::replace(&mut state.context, context))
+    }
+
+    fn get_thread_dpi_hosting_behavior(&self) -> Option<DPI_HOSTING_BEHAVIOR> {
+        Some(self.state.borrow().hosting)
+    }
+
+    fn set_thread_dpi_hosting_behavior(&self, behavior: DPI_HOSTING_BEHAVIOR) -> Option<DPI_HOSTING_BEHAVIOR> {
+        let known = DpiHostingBehavior::from_raw(behavior)?;
+        let mut state = self.state.borrow_mut();
+        state.switches.push(format!("hosting {:?}", known));
+        Some(std::mem::replace(&mut state.hosting, behavior))
+    }
+
+    fn is_process_dpi_aware(&self) -> Option<bool> { None }
+    fn set_process_dpi_aware(&self) -> Option<bool> { None }
+    fn get_process_dpi_awareness(&self) -> io::Result<Option<WinDpiAwareness>> { Ok(None) }
+    fn set_process_dpi_awareness(&self, _: WinDpiAwareness) -> io::Result<bool> { Ok(false) }
+    fn set_process_dpi_awareness_context(&self, _: DPI_AWARENESS_CONTEXT) -> io::Result<bool> { Ok(false) }
+    fn get_awareness_from_dpi_awareness_context(&self, _: DPI_AWARENESS_CONTEXT) -> Option<DPI_AWARENESS> { None }
+    fn are_dpi_awareness_contexts_equal(&self, _: DPI_AWARENESS_CONTEXT, _: DPI_AWARENESS_CONTEXT) -> Option<BOOL> { None }
+    fn get_window_dpi_awareness_context(&self, _: HWND) -> Option<DPI_AWARENESS_CONTEXT> { None }
+    fn get_window_dpi_hosting_behavior(&self, _: HWND) -> Option<DPI_HOSTING_BEHAVIOR> { None }
+    fn get_dpi_from_dpi_awareness_context(&self, _: DPI_AWARENESS_CONTEXT) -> Option<UINT> { None }
+    fn is_valid_dpi_awareness_context(&self, _: DPI_AWARENESS_CONTEXT) -> Option<bool> { None }
+    fn get_dpi_for_system(&self) -> Option<UINT> { None }
+    fn get_dpi_for_window(&self, _: HWND) -> Option<UINT> { None }
+    fn get_dpi_for_monitor(&self, _: HMONITOR, _: MONITOR_DPI_TYPE) -> io::Result<Option<(UINT, UINT)>> { Ok(None) }
+    fn get_system_dpi_for_process(&self, _: HANDLE) -> Option<UINT> { None }
+    fn adjust_window_rect_ex_for_dpi(&self, _: &mut RECT, _: DWORD, _: bool, _: DWORD, _: UINT) -> Option<bool> { None }
+    fn get_system_metrics_for_dpi(&self, _: c_int, _: UINT) -> Option<c_int> { None }
+    fn get_icon_title_log_font_for_dpi(&self, _: UINT) -> Option<LOGFONTW> { None }
+    fn enable_non_client_dpi_scaling(&self, _: HWND) -> Option<bool> { None }
+    fn logical_to_physical_point_for_per_monitor_dpi(&self, _: HWND, _: &mut POINT) -> Option<bool> { None }
+}
@@ -0,0 +1,325 @@
+//! The host side of `HostServices`: what a host implements for its plugins
+//! to call back into, and the table `Plugin::init` hands them.
+//!
+//! Each plugin gets a table of its own, so the host is told which plugin is
+//! calling. The table keeps the plugin's DPI change subscriptions; the host
+//! reports changes with `Plugin::dpi_changed` and the table calls whoever
+//! subscribed to the content. A panic in the host is caught before it
+//! reaches the plugin.
+
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::rc::Rc;
+use std::slice;
+
+use crate::abi::{DpiChangedCallback, HostServices, LogLevel};
+
+/// What a host does for the plugins that call back into it. Every call comes
+/// from the thread the host called the plugin on, from inside that call.
+pub trait Host {
+    fn log(&self, plugin: &str, level: LogLevel, message: &str);
+
+    /// The setting `key` of `plugin`, if there is one.
+    fn setting(&self, plugin: &str, key: &str) -> Option<String>;
+
+    /// Set the setting `key` of `plugin`, returning false if the host won't.
+    fn set_setting(&self, plugin: &str, key: &str, value: &str) -> bool;
+
+    /// The DPI `content` is shown at as its awareness sees it, or with null
+    /// the DPI of the content being created.
+    fn current_dpi(&self, content: *mut c_void) -> u32;
+
+    /// Lay out the window `content` is in again. Since the plugin is still
+    /// running, this should be done later, as from a posted message.
+    fn request_relayout(&self, content: *mut c_void);
+}
+
+#[derive(Clone, Copy)]
+struct Subscription {
+    id: u64,
+    content: *mut c_void,
+    callback: DpiChangedCallback,
+    user: *mut c_void,
+}
+
+/// A plugin's `HostServices` and what its functions need. Boxed, so that
+/// `context` can point at it.
+pub(crate) struct HostTable {
+    table: HostServices,
+    plugin: String,
+    host: Rc<dyn Host>,
+    subscriptions: RefCell<Vec<Subscription>>,
+    next_subscription: Cell<u64>,
+}
+
+impl HostTable {
+    pub(crate) fn new(plugin: &str, host: Rc<dyn Host>) -> Box<HostTable> {
+        let mut table = Box::new(HostTable {
+            table: HostServices {
+                size: mem::size_of::<HostServices>() as u32,
+                context: ptr::null_mut(),
+                log,
+                get_setting,
+                set_setting,
+                current_dpi,
+                subscribe_dpi_changed,
+                unsubscribe_dpi_changed,
+                request_relayout,
+            },
+            plugin: plugin.to_owned(),
+            host,
+            subscriptions: RefCell::new(Vec::new()),
+            next_subscription: Cell::new(1),
+        });
+        table.table.context = &mut *table as *mut HostTable as *mut c_void;
+        table
+    }
+
+    pub(crate) fn services(&self) -> *const HostServices {
+        &self.table
+    }
+
+    // Call the subscribers to `content`, one at a time since a callback may
+    // unsubscribe the others
+    pub(crate) fn dpi_changed(&self, content: *mut c_void, dpi: u32) {
+        let ids: Vec<u64> = self.subscriptions.borrow().iter()
+            .filter(|subscription| subscription.content == content)
+            .map(|subscription| subscription.id)
+            .collect();
+        for id in ids {
+            let subscription = self.subscriptions.borrow().iter().find(|subscription| subscription.id == id).copied();
+            if let Some(subscription) = subscription {
+                unsafe { (subscription.callback)(subscription.user, content, dpi) };
+            }
+        }
+    }
+
+    // Drop the subscriptions to content that was destroyed without them
+    pub(crate) fn forget(&self, content: *mut c_void) {
+        self.subscriptions.borrow_mut().retain(|subscription| subscription.content != content);
+    }
+}
+
+fn guard<T, F>(fallback: T, f: F) -> T where F: FnOnce() -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(fallback)
+}
+
+unsafe fn table<'a>(context: *mut c_void) -> &'a HostTable {
+    &*(context as *const HostTable)
+}
+
+unsafe fn string_arg<'a>(s: *const c_char) -> Option<Cow<'a, str>> {
+    if s.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(s).to_string_lossy())
+    }
+}
+
+unsafe extern "C" fn log(context: *mut c_void, level: u32, message: *const c_char) {
+    guard((), || {
+        let table = table(context);
+        let level = LogLevel::from_raw(level).unwrap_or(LogLevel::Info);
+        let message = string_arg(message).unwrap_or_default();
+        table.host.log(&table.plugin, level, &message);
+    })
+}
+
+unsafe extern "C" fn get_setting(context: *mut c_void, key: *const c_char, buffer: *mut c_char, capacity: usize) -> usize {
+    guard(0, || {
+        let table = table(context);
+        let value = match string_arg(key).and_then(|key| table.host.setting(&table.plugin, &key)) {
+            Some(value) if !value.is_empty() => value,
+            _ => return 0,
+        };
+        let out: &mut [u8] = if buffer.is_null() {
+            &mut []
+        } else {
+            slice::from_raw_parts_mut(buffer as *mut u8, capacity)
+        };
+        hidpi::copy_to_utf8(out, &value).unwrap_or_else(|required| required)
+    })
+}
+
+unsafe extern "C" fn set_setting(context: *mut c_void, key: *const c_char, value: *const c_char) -> c_int {
+    guard(0, || {
+        let table = table(context);
+        match (string_arg(key), string_arg(value)) {
+            (Some(key), Some(value)) => table.host.set_setting(&table.plugin, &key, &value) as c_int,
+            _ => 0,
+        }
+    })
+}
+
+unsafe extern "C" fn current_dpi(context: *mut c_void, content: *mut c_void) -> u32 {
+    guard(hidpi::Dpi::DEFAULT.0, || table(context).host.current_dpi(content))
+}
+
+unsafe extern "C" fn subscribe_dpi_changed(context: *mut c_void, content: *mut c_void, callback: DpiChangedCallback, user: *mut c_void) -> u64 {
+    let table = table(context);
+    let id = table.next_subscription.get();
+    table.next_subscription.set(id + 1);
+    table.subscriptions.borrow_mut().push(Subscription { id, content, callback, user });
+    id
+}
+
+unsafe extern "C" fn unsubscribe_dpi_changed(context: *mut c_void, subscription: u64) {
+    table(context).subscriptions.borrow_mut().retain(|s| s.id != subscription);
+}
+
+unsafe extern "C" fn request_relayout(context: *mut c_void, content: *mut c_void) {
+    guard((), || table(context).host.request_relayout(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+    use crate::fixtures::TestHost;
+
+    fn table(host: TestHost) -> (Rc<TestHost>, Box<HostTable>) {
+        let host = Rc::new(host);
+        let table = HostTable::new("plugin", host.clone());
+        (host, table)
+    }
+
+    // Call get_setting into a buffer of `capacity` bytes, all 0xaa to start with
+    fn get(table: &HostTable, key: &str, capacity: Option<usize>) -> (usize, Vec<u8>) {
+        let services = unsafe { &*table.services() };
+        let key = CString::new(key).unwrap();
+        let mut buffer = vec![0xaa; capacity.unwrap_or(0)];
+        let pointer = if capacity.is_some() { buffer.as_mut_ptr() as *mut c_char } else { ptr::null_mut() };
+        let returned = unsafe { (services.get_setting)(services.context, key.as_ptr(), pointer, buffer.len()) };
+        (returned, buffer)
+    }
+
+    #[test]
+    fn get_setting_sizes_its_buffer_the_win32_way() {
+        let (_, table) = table(TestHost::new()
+            .with_setting("plugin", "greeting", "héllo")
+            .with_setting("plugin", "empty", "")
+            .with_setting("other", "mine", "not yours"));
+
+        // asking for the size, with a null buffer or a small one, writes nothing
+        assert_eq!(get(&table, "greeting", None), (7, vec![]));
+        assert_eq!(get(&table, "greeting", Some(0)), (7, vec![]));
+        assert_eq!(get(&table, "greeting", Some(6)), (7, vec![0xaa; 6]));
+        // the size asked for is enough, and the terminator isn't counted
+        assert_eq!(get(&table, "greeting", Some(7)), (6, b"h\xc3\xa9llo\0".to_vec()));
+        let (written, buffer) = get(&table, "greeting", Some(10));
+        assert_eq!(written, 6);
+        assert_eq!(buffer, b"h\xc3\xa9llo\0\xaa\xaa\xaa");
+
+        // no setting, an empty one and another plugin's all read as 0
+        for key in &["missing", "empty", "mine"] {
+            assert_eq!(get(&table, key, Some(4)), (0, vec![0xaa; 4]));
+        }
+        let services = unsafe { &*table.services() };
+        assert_eq!(unsafe { (services.get_setting)(services.context, ptr::null(), ptr::null_mut(), 0) }, 0);
+    }
+
+    #[test]
+    fn calls_reach_the_host_as_the_plugin() {
+        let (host, table) = table(TestHost::new());
+        host.dpi.set(144);
+        let services = unsafe { &*table.services() };
+        assert_eq!(services.size as usize, mem::size_of::<HostServices>());
+        let c = |s: &str| CString::new(s).unwrap();
+        let content = 0x1234 as *mut c_void;
+        unsafe {
+            (services.log)(services.context, LogLevel::Warn as u32, c("careful").as_ptr());
+            // levels the host doesn't know are info, and no message is empty
+            (services.log)(services.context, 17, ptr::null());
+            assert_eq!((services.set_setting)(services.context, c("key").as_ptr(), c("value").as_ptr()), 1);
+            assert_eq!((services.set_setting)(services.context, c("key").as_ptr(), ptr::null()), 0);
+            host.refuse.set(true);
+            assert_eq!((services.set_setting)(services.context, c("key").as_ptr(), c("other").as_ptr()), 0);
+            assert_eq!((services.current_dpi)(services.context, content), 144);
+            (services.request_relayout)(services.context, content);
+        }
+        assert_eq!(host.take_calls(), [
+            "plugin log warning careful".to_owned(),
+            "plugin log info ".to_owned(),
+            "plugin set key=value".to_owned(),
+            "plugin set key=other".to_owned(),
+            format!("current_dpi {:?}", content),
+            format!("relayout {:?}", content),
+        ]);
+        assert_eq!(host.settings.borrow().get(&("plugin".to_owned(), "key".to_owned())).map(String::as_str), Some("value"));
+    }
+
+    #[test]
+    fn a_host_that_panics_answers_with_the_fallbacks() {
+        let (host, table) = table(TestHost::new().with_setting("plugin", "key", "value"));
+        host.panic.set(true);
+        let services = unsafe { &*table.services() };
+        let key = CString::new("key").unwrap();
+        unsafe {
+            (services.log)(services.context, 0, key.as_ptr());
+            assert_eq!((services.get_setting)(services.context, key.as_ptr(), ptr::null_mut(), 0), 0);
+            assert_eq!((services.set_setting)(services.context, key.as_ptr(), key.as_ptr()), 0);
+            assert_eq!((services.current_dpi)(services.context, ptr::null_mut()), 96);
+            (services.request_relayout)(services.context, ptr::null_mut());
+        }
+        assert_eq!(host.take_calls().len(), 5);
+    }
+
+    thread_local! {
+        static TOLD: RefCell<Vec<(usize, usize, u32)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    unsafe extern "C" fn record(user: *mut c_void, content: *mut c_void, dpi: u32) {
+        TOLD.with(|told| told.borrow_mut().push((user as usize, content as usize, dpi)));
+    }
+
+    // Unsubscribes the subscription `user` points at
+    unsafe extern "C" fn unsubscribe_other(user: *mut c_void, content: *mut c_void, dpi: u32) {
+        let (context, id) = (*(user as *const Cell<(*mut c_void, u64)>)).get();
+        unsubscribe_dpi_changed(context, id);
+        record(ptr::null_mut(), content, dpi);
+    }
+
+    #[test]
+    fn subscribers_are_told_of_their_content_until_they_unsubscribe() {
+        let (_, table) = table(TestHost::new());
+        let services = unsafe { &*table.services() };
+        let (a, b) = (0xa as *mut c_void, 0xb as *mut c_void);
+        let subscribe = |content, callback: DpiChangedCallback, user: usize| unsafe {
+            (services.subscribe_dpi_changed)(services.context, content, callback, user as *mut c_void)
+        };
+        let told = || TOLD.with(|told| told.replace(Vec::new()));
+
+        let first = subscribe(a, record, 1);
+        let second = subscribe(a, record, 2);
+        subscribe(b, record, 3);
+        assert!(first != 0 && second != 0 && first != second);
+        table.dpi_changed(a, 144);
+        assert_eq!(told(), [(1, 0xa, 144), (2, 0xa, 144)]);
+
+        unsafe { (services.unsubscribe_dpi_changed)(services.context, first) };
+        table.dpi_changed(a, 192);
+        table.dpi_changed(0xd as *mut c_void, 96);
+        assert_eq!(told(), [(2, 0xa, 192)]);
+
+        // a callback may unsubscribe one that is still to be called
+        let c = 0xc as *mut c_void;
+        let other = Cell::new((services.context, 0));
+        subscribe(c, unsubscribe_other, &other as *const _ as usize);
+        other.set((services.context, subscribe(c, record, 4)));
+        table.dpi_changed(c, 120);
+        assert_eq!(told(), [(0, 0xc, 120)]);
+
+        // destroyed content takes its subscriptions with it
+        table.dpi_changed(b, 120);
+        assert_eq!(told(), [(3, 0xb, 120)]);
+        table.forget(b);
+        table.dpi_changed(b, 96);
+        assert_eq!(told(), []);
+    }
+}
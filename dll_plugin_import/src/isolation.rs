@@ -0,0 +1,128 @@
+//! Running a plugin's content with the DPI awareness it is written for.
+//!
+//! A child window normally has to share its parent's DPI awareness. Since
+//! Windows 10 1803 a top-level window created with mixed hosting behavior may
+//! host children of another awareness, which keeps content that isn't
+//! per-monitor aware from being laid out for the wrong DPI. `DpiIsolation`
+//! says what the host switches the calling thread to around each call into a
+//! plugin so that the windows the plugin creates get its awareness. Elsewhere
+//! than Windows the functions that switch it are missing, so nothing is.
+
+use hidpi::{DpiAwarenessContext, DpiHostingBehavior, ThreadDpiScope};
+
+/// The window a plugin's content is parented to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HostDpi {
+    /// The awareness the host window runs with.
+    pub awareness: DpiAwarenessContext,
+    /// Whether the host window was created with mixed hosting behavior, so
+    /// children with another awareness can be parented to it.
+    pub mixed: bool,
+}
+
+impl HostDpi {
+    pub fn new(awareness: DpiAwarenessContext, mixed: bool) -> HostDpi {
+        HostDpi { awareness, mixed }
+    }
+}
+
+/// The thread awareness context and hosting behavior to enter around a call
+/// into a plugin, `None` where the host's own are left alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DpiIsolation {
+    pub context: Option<DpiAwarenessContext>,
+    pub hosting: Option<DpiHostingBehavior>,
+}
+
+impl DpiIsolation {
+    /// Run the plugin like the rest of the host.
+    pub const NONE: DpiIsolation = DpiIsolation { context: None, hosting: None };
+
+    /// The isolation for content written for `wants` in `host`. Content that
+    /// matches the host needs none; otherwise it's isolated if the host
+    /// window allows mixed hosting, and has to make do with the host's
+    /// awareness if it doesn't.
+    pub fn new(wants: DpiAwarenessContext, host: HostDpi) -> DpiIsolation {
+        if wants == host.awareness || !host.mixed {
+            DpiIsolation::NONE
+        } else {
+            DpiIsolation { context: Some(wants), hosting: Some(DpiHostingBehavior::Mixed) }
+        }
+    }
+
+    pub fn is_isolated(self) -> bool {
+        self.context.is_some()
+    }
+
+    /// The awareness content created under this isolation ends up with.
+    pub fn content_awareness(self, host: HostDpi) -> DpiAwarenessContext {
+        self.context.unwrap_or(host.awareness)
+    }
+
+    /// Switch the calling thread until the scope is dropped.
+    pub fn enter(self) -> ThreadDpiScope {
+        ThreadDpiScope::enter(self.context, self.hosting)
+    }
+
+    /// Call `f` with the thread switched, putting it back afterwards.
+    pub fn run<F, R>(self, f: F) -> R where F: FnOnce() -> R {
+        let _scope = self.enter();
+        f()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use hidpi::backend::with_backend;
+
+    use super::*;
+    use crate::fixtures::ThreadBackend;
+
+    const SYSTEM_HOST: HostDpi = HostDpi { awareness: DpiAwarenessContext::System, mixed: true };
+
+    #[test]
+    fn only_content_of_another_awareness_in_a_mixed_host_is_isolated() {
+        assert_eq!(DpiIsolation::new(DpiAwarenessContext::System, SYSTEM_HOST), DpiIsolation::NONE);
+        let isolation = DpiIsolation::new(DpiAwarenessContext::PerMonitorV2, SYSTEM_HOST);
+        assert_eq!(isolation, DpiIsolation { context: Some(DpiAwarenessContext::PerMonitorV2), hosting: Some(DpiHostingBehavior::Mixed) });
+        assert!(isolation.is_isolated());
+        assert_eq!(isolation.content_awareness(SYSTEM_HOST), DpiAwarenessContext::PerMonitorV2);
+
+        // without mixed hosting the content has to live with the host's awareness
+        let unmixed = HostDpi::new(DpiAwarenessContext::System, false);
+        let isolation = DpiIsolation::new(DpiAwarenessContext::Unaware, unmixed);
+        assert_eq!(isolation, DpiIsolation::NONE);
+        assert!(!isolation.is_isolated());
+        assert_eq!(isolation.content_awareness(unmixed), DpiAwarenessContext::System);
+    }
+
+    #[test]
+    fn run_switches_the_thread_and_puts_it_back() {
+        let backend = ThreadBackend::new();
+        let isolation = DpiIsolation::new(DpiAwarenessContext::PerMonitorV2, SYSTEM_HOST);
+        let inside = with_backend(backend.clone(), || isolation.run(|| (backend.context(), backend.hosting())));
+        assert_eq!(inside, (DpiAwarenessContext::PerMonitorV2, DpiHostingBehavior::Mixed));
+        // hosting first, so windows created under the context may be mixed
+        assert_eq!(backend.switches(), ["hosting Mixed", "context PerMonitorV2", "context Unaware", "hosting Default"]);
+        assert_eq!((backend.context(), backend.hosting()), (DpiAwarenessContext::Unaware, DpiHostingBehavior::Default));
+    }
+
+    #[test]
+    fn run_without_isolation_leaves_the_thread_alone() {
+        let backend = ThreadBackend::new();
+        assert_eq!(with_backend(backend.clone(), || DpiIsolation::NONE.run(|| 7)), 7);
+        assert_eq!(backend.switches(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn run_puts_the_thread_back_when_the_plugin_panics() {
+        let backend = ThreadBackend::new();
+        let isolation = DpiIsolation::new(DpiAwarenessContext::UnawareGdiScaled, SYSTEM_HOST);
+        let result = with_backend(backend.clone(), || panic::catch_unwind(|| isolation.run(|| panic!("create_content failed"))));
+        assert!(result.is_err());
+        assert_eq!(backend.switches(), ["hosting Mixed", "context UnawareGdiScaled", "context Unaware", "hosting Default"]);
+        assert_eq!(backend.context(), DpiAwarenessContext::Unaware);
+    }
+}
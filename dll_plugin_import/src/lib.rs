@@ -1,16 +1,37 @@
-use winapi::{
-    shared::{
-        minwindef::{
-            INT,
-            HINSTANCE,
-        },
-        windef::{
-            HWND,
-        },
-    },
-};
+//! The plugin ABI, and finding and loading plugins at runtime.
+//!
+//! `abi` is the contract a plugin library implements, `plugin` the host side
+//! that loads libraries and negotiates the ABI version with them, `host` and
+//! `services` the two ends of the table plugins call back into the host
+//! through, `manifest` the optional `plugin.toml` describing a plugin,
+//! `registry` the search of plugin directories, `reload` swapping in new
+//! builds of plugins while the host runs and `isolation` the DPI awareness
+//! plugin content runs with. `remote` runs a plugin in the `plugin_host`
+//! helper process instead, talking to it with `protocol`. Only switching the
+//! thread's awareness and the helper's windows depend on Windows, so plugins
+//! can be built and loaded on any platform.
 
-#[link(name = "dll_plugin.dll")]
-extern "C" {
-    pub fn create_content_hwnd(h_instance: HINSTANCE, n_width: INT, n_height: INT) -> HWND;
-}
+pub mod abi;
+pub mod plugin;
+pub mod host;
+pub mod services;
+pub mod manifest;
+pub mod registry;
+pub mod reload;
+pub mod isolation;
+pub mod protocol;
+pub mod remote;
+
+#[cfg(test)]
+mod fixtures;
+
+pub use abi::{Capabilities, HostServices, LogLevel, PluginVTable, MIN_PLUGIN_ABI_VERSION, PLUGIN_ABI_VERSION, PLUGIN_ENTRY};
+pub use plugin::{discover, Plugin, PluginError};
+pub use host::Host;
+pub use services::{DpiSubscription, Services};
+pub use manifest::{Manifest, ManifestError, Version, MANIFEST_FILE};
+pub use registry::{PluginRegistry, RegisteredPlugin, RegistryError};
+pub use reload::{Reload, Watch};
+pub use isolation::{DpiIsolation, HostDpi};
+pub use protocol::{Event, ProtocolError, Request, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+pub use remote::{RemoteError, RemotePlugin, Supervision, Supervisor};
@@ -0,0 +1,132 @@
+//! `plugin.toml`, the optional manifest a plugin ships beside its library.
+//!
+//! ```toml
+//! name = "dll_plugin"
+//! version = "0.1.0"
+//! # the plugin ABI version the plugin needs the host to speak
+//! host_abi = 1
+//! # optional: unaware, unaware-gdi-scaled, system, per-monitor, per-monitor-v2;
+//! # the host runs the plugin's content with it if it can
+//! dpi_awareness = "system"
+//! # optional, defaults to plugin_entry
+//! entry = "plugin_entry"
+//! # optional, defaults to the only library in the manifest's directory
+//! library = "dll_plugin.dll"
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use hidpi::DpiAwarenessContext;
+use toml::Value;
+
+use crate::abi::PLUGIN_ENTRY;
+
+/// The file name of a manifest.
+pub const MANIFEST_FILE: &str = "plugin.toml";
+
+/// A `major.minor.patch` version. Missing parts are 0, so `1.2` is `1.2.0`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl Version {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Version {
+        Version { major, minor, patch }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for Version {
+    type Err = ManifestError;
+
+    fn from_str(s: &str) -> Result<Version, ManifestError> {
+        let error = || ManifestError(format!("version {:?} is not major.minor.patch", s));
+        let parts = s.split('.')
+            .map(|part| part.parse::<u32>().map_err(|_| error()))
+            .collect::<Result<Vec<_>, _>>()?;
+        match parts[..] {
+            [major] => Ok(Version::new(major, 0, 0)),
+            [major, minor] => Ok(Version::new(major, minor, 0)),
+            [major, minor, patch] => Ok(Version::new(major, minor, patch)),
+            _ => Err(error()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub name: String,
+    pub version: Version,
+    /// The plugin ABI version the plugin needs the host to speak.
+    pub host_abi: u32,
+    /// The awareness the plugin's content is written for, `None` if it
+    /// doesn't say and its capabilities decide.
+    pub dpi_awareness: Option<DpiAwarenessContext>,
+    /// The name of the entry point, `plugin_entry` unless it says otherwise.
+    pub entry: String,
+    /// The library's file name, relative to the manifest.
+    pub library: Option<String>,
+}
+
+impl Manifest {
+    /// Parse and check the contents of a `plugin.toml`. Unknown keys are an
+    /// error, so a misspelt one doesn't go unnoticed.
+    pub fn parse(text: &str) -> Result<Manifest, ManifestError> {
+        let value: Value = text.parse().map_err(|e: toml::de::Error| ManifestError(e.to_string()))?;
+        let table = value.as_table().ok_or_else(|| ManifestError("not a table".to_owned()))?;
+        if let Some(key) = table.keys().find(|key| !KEYS.contains(&key.as_str())) {
+            return Err(ManifestError(format!("unknown key {:?}", key)));
+        }
+
+        let string = |key: &str| -> Result<Option<String>, ManifestError> {
+            match table.get(key) {
+                None => Ok(None),
+                Some(Value::String(s)) if !s.is_empty() && !s.contains('\0') => Ok(Some(s.clone())),
+                Some(_) => Err(ManifestError(format!("{} must be a non-empty string", key))),
+            }
+        };
+        let required = |key: &str| string(key)?.ok_or_else(|| ManifestError(format!("no {}", key)));
+
+        let host_abi = match table.get("host_abi") {
+            Some(Value::Integer(abi)) if *abi > 0 && *abi <= u32::MAX as i64 => *abi as u32,
+            Some(_) => return Err(ManifestError("host_abi must be a positive integer".to_owned())),
+            None => return Err(ManifestError("no host_abi".to_owned())),
+        };
+        let dpi_awareness = match string("dpi_awareness")? {
+            Some(awareness) => Some(awareness.parse().map_err(|e: hidpi::ParseDpiAwarenessContextError| ManifestError(e.to_string()))?),
+            None => None,
+        };
+
+        Ok(Manifest {
+            name: required("name")?,
+            version: required("version")?.parse()?,
+            host_abi,
+            dpi_awareness,
+            entry: string("entry")?.unwrap_or_else(|| PLUGIN_ENTRY.to_owned()),
+            library: string("library")?,
+        })
+    }
+}
+
+const KEYS: [&str; 6] = ["name", "version", "host_abi", "dpi_awareness", "entry", "library"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestError(pub String);
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ManifestError {}
@@ -0,0 +1,454 @@
+//! Loading plugins and calling through their vtables.
+
+use std::error::Error;
+use std::ffi::{CStr, OsStr};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use libloading::Library;
+
+use crate::abi::{
+    Capabilities,
+    CreateContent,
+    DestroyContent,
+    PluginEntry,
+    PluginInit,
+    PluginVTable,
+    MIN_PLUGIN_ABI_VERSION,
+    PLUGIN_ABI_VERSION,
+    PLUGIN_ENTRY,
+};
+use crate::host::{Host, HostTable};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginError {
+    /// The library couldn't be loaded, with the loader's message.
+    Load(PathBuf, String),
+    /// The library doesn't export the entry point, so it isn't a plugin.
+    NoEntry(PathBuf, String),
+    /// `plugin_entry` returned null: the plugin speaks no ABI version this
+    /// host does.
+    Declined(PathBuf),
+    /// The plugin answered with an ABI version this host doesn't load.
+    Version(PathBuf, u32),
+    /// The vtable is short, has no name or lacks a function.
+    Invalid(PathBuf, &'static str),
+}
+
+impl PluginError {
+    pub fn path(&self) -> &Path {
+        match self {
+            PluginError::Load(path, _)
+            | PluginError::NoEntry(path, _)
+            | PluginError::Declined(path)
+            | PluginError::Version(path, _)
+            | PluginError::Invalid(path, _) => path,
+        }
+    }
+
+    // For errors from a shadow copy, which should name the original
+    pub(crate) fn with_path(self, path: &Path) -> PluginError {
+        let path = path.to_owned();
+        match self {
+            PluginError::Load(_, message) => PluginError::Load(path, message),
+            PluginError::NoEntry(_, entry) => PluginError::NoEntry(path, entry),
+            PluginError::Declined(_) => PluginError::Declined(path),
+            PluginError::Version(_, version) => PluginError::Version(path, version),
+            PluginError::Invalid(_, what) => PluginError::Invalid(path, what),
+        }
+    }
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.path().display())?;
+        match self {
+            PluginError::Load(_, message) => write!(f, "{}", message),
+            PluginError::NoEntry(_, entry) => write!(f, "no {} export", entry),
+            PluginError::Declined(_) => write!(f, "speaks no plugin ABI between {} and {}", MIN_PLUGIN_ABI_VERSION, PLUGIN_ABI_VERSION),
+            PluginError::Version(_, version) => write!(f, "plugin ABI {} is not between {} and {}", version, MIN_PLUGIN_ABI_VERSION, PLUGIN_ABI_VERSION),
+            PluginError::Invalid(_, what) => write!(f, "{}", what),
+        }
+    }
+}
+
+impl Error for PluginError {}
+
+/// A loaded plugin. The library stays loaded until the `Plugin` is dropped,
+/// so content it created must be destroyed first.
+pub struct Plugin {
+    path: PathBuf,
+    name: String,
+    abi_version: u32,
+    capabilities: Capabilities,
+    create_content: CreateContent,
+    destroy_content: DestroyContent,
+    init: Option<PluginInit>,
+    // dropped before the library, which is when the plugin stops using it
+    host: Option<Box<HostTable>>,
+    _library: Library,
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Plugin")
+            .field("path", &self.path)
+            .field("name", &self.name)
+            .field("abi_version", &self.abi_version)
+            .field("capabilities", &self.capabilities)
+            .field("initialized", &self.host.is_some())
+            .finish()
+    }
+}
+
+impl Plugin {
+    /// Load the library at `path` and negotiate the ABI version with its
+    /// `plugin_entry`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Plugin, PluginError> {
+        Plugin::load_with_entry(path, PLUGIN_ENTRY)
+    }
+
+    /// As `load`, for a plugin whose entry point is named `entry`.
+    pub fn load_with_entry<P: AsRef<Path>>(path: P, entry: &str) -> Result<Plugin, PluginError> {
+        let path = path.as_ref();
+        let library = Library::new(path).map_err(|e| PluginError::Load(path.to_owned(), e.to_string()))?;
+        let symbol = format!("{}\0", entry);
+        let plugin_entry: PluginEntry = unsafe { library.get::<PluginEntry>(symbol.as_bytes()) }
+            .map(|plugin_entry| *plugin_entry)
+            .map_err(|_| PluginError::NoEntry(path.to_owned(), entry.to_owned()))?;
+
+        let vtable = unsafe { plugin_entry(PLUGIN_ABI_VERSION) };
+        if vtable.is_null() {
+            return Err(PluginError::Declined(path.to_owned()));
+        }
+        let invalid = |what| PluginError::Invalid(path.to_owned(), what);
+
+        // The fields of older versions come first, and are all that can be
+        // read until the size says there are more
+        let (abi_version, size) = unsafe { ((*vtable).abi_version, (*vtable).size) };
+        if !(MIN_PLUGIN_ABI_VERSION..=PLUGIN_ABI_VERSION).contains(&abi_version) {
+            return Err(PluginError::Version(path.to_owned(), abi_version));
+        }
+        if (size as usize) < PluginVTable::size_for(abi_version) {
+            return Err(invalid("vtable is smaller than its ABI version says"));
+        }
+        let init = if abi_version >= 2 {
+            unsafe { (*vtable).init }
+        } else {
+            None
+        };
+        let vtable = unsafe { &*(vtable as *const VTableV1) };
+        if vtable.name.is_null() {
+            return Err(invalid("no name"));
+        }
+        let name = unsafe { CStr::from_ptr(vtable.name) }
+            .to_str()
+            .map_err(|_| invalid("name is not UTF-8"))?
+            .to_owned();
+        let create_content = vtable.create_content.ok_or_else(|| invalid("no create_content"))?;
+        let destroy_content = vtable.destroy_content.ok_or_else(|| invalid("no destroy_content"))?;
+
+        Ok(Plugin {
+            path: path.to_owned(),
+            name,
+            abi_version,
+            capabilities: vtable.capabilities,
+            create_content,
+            destroy_content,
+            init,
+            host: None,
+            _library: library,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The ABI version the plugin and host agreed on.
+    pub fn abi_version(&self) -> u32 {
+        self.abi_version
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Call the plugin's `create_content`.
+    ///
+    /// # Safety
+    /// `h_instance` must be a module handle, and on Windows this must run on
+    /// a thread with a message loop, since the content is windows.
+    pub unsafe fn create_content(&self, h_instance: *mut c_void, width: c_int, height: c_int) -> *mut c_void {
+        (self.create_content)(h_instance, width, height)
+    }
+
+    /// Call the plugin's `destroy_content`.
+    ///
+    /// # Safety
+    /// `content` must have come from this plugin's `create_content` and not
+    /// been destroyed yet.
+    pub unsafe fn destroy_content(&self, content: *mut c_void) {
+        (self.destroy_content)(content);
+        if let Some(host) = &self.host {
+            host.forget(content);
+        }
+    }
+
+    /// Hand the plugin `host`'s services, returning whether it takes them.
+    /// Plugins older than ABI version 2 don't. This should be done before any
+    /// content is created, and only the first call does anything.
+    pub fn init(&mut self, host: Rc<dyn Host>) -> bool {
+        let init = match self.init {
+            Some(init) => init,
+            None => return false,
+        };
+        if self.host.is_none() {
+            let table = HostTable::new(&self.name, host);
+            unsafe { init(table.services()) };
+            self.host = Some(table);
+        }
+        true
+    }
+
+    /// Tell the plugin the DPI of `content` changed, as the content's
+    /// awareness sees it. Plugins that didn't take the host's services, or
+    /// didn't subscribe to the content, aren't told. Content created under a
+    /// `DpiIsolation` should be told under it too, as
+    /// `RegisteredPlugin::dpi_changed` does.
+    pub fn dpi_changed(&self, content: *mut c_void, dpi: u32) {
+        if let Some(host) = &self.host {
+            host.dpi_changed(content, dpi);
+        }
+    }
+}
+
+// The fields every version of the vtable has
+#[repr(C)]
+struct VTableV1 {
+    abi_version: u32,
+    size: u32,
+    name: *const c_char,
+    capabilities: Capabilities,
+    create_content: Option<CreateContent>,
+    destroy_content: Option<DestroyContent>,
+}
+
+/// Whether `path` is named like a dynamic library on this platform.
+pub fn is_library(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(std::env::consts::DLL_EXTENSION))
+}
+
+/// Try to load every dynamic library in `dir`, in file name order. Libraries
+/// that aren't plugins come back as `PluginError::NoEntry`. `PluginRegistry`
+/// also reads manifests and settles duplicates.
+pub fn discover<P: AsRef<Path>>(dir: P) -> io::Result<Vec<Result<Plugin, PluginError>>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_library(&path) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths.into_iter().map(Plugin::load).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+    use crate::abi::LogLevel;
+    use crate::fixtures::{self, Probe, TestHost};
+
+    fn load(variant: &str) -> Result<Plugin, PluginError> {
+        Plugin::load(fixtures::plugin(variant, variant))
+    }
+
+    #[test]
+    fn a_plugin_loads_at_the_newest_version() {
+        let path = fixtures::plugin("v2", "Fixture Plugin");
+        let plugin = Plugin::load(&path).unwrap();
+        let probe = Probe::new(&path);
+
+        assert_eq!(probe.host_abi_version(), PLUGIN_ABI_VERSION);
+        assert_eq!(plugin.abi_version(), 2);
+        assert_eq!(plugin.name(), "Fixture Plugin");
+        assert_eq!(plugin.path(), path);
+        // bits the host doesn't know are kept
+        assert!(plugin.capabilities().contains(Capabilities::PER_MONITOR_DPI | Capabilities::MULTIPLE_INSTANCES | Capabilities(1 << 31)));
+
+        let content = unsafe { plugin.create_content(ptr::null_mut(), 300, 200) };
+        assert!(!content.is_null());
+        assert_eq!(probe.content_size(content), (300, 200));
+        assert_eq!(probe.live_content(), 1);
+        unsafe { plugin.destroy_content(content) };
+        assert_eq!(probe.live_content(), 0);
+        assert!(unsafe { plugin.create_content(ptr::null_mut(), -1, 0) }.is_null());
+    }
+
+    #[test]
+    fn a_plugin_calls_back_into_its_host() {
+        let path = fixtures::plugin("v2", "callbacks");
+        let mut plugin = Plugin::load(&path).unwrap();
+        let host = Rc::new(TestHost::new().with_setting("callbacks", "greeting", "hi there"));
+        host.dpi.set(144);
+        assert!(plugin.init(host.clone()));
+        assert!(plugin.init(host.clone()));
+        assert_eq!(Probe::new(&path).inits(), 1);
+
+        let content = unsafe { plugin.create_content(ptr::null_mut(), 300, 200) };
+        assert_eq!(host.take_calls(), [
+            "current_dpi 0x0",
+            "callbacks setting greeting",
+            "callbacks setting greeting",
+            "callbacks log info create 300x200 at 144 dpi, greeting hi there",
+            "callbacks set last_created=300x200",
+        ]);
+        assert_eq!(host.settings.borrow().get(&("callbacks".to_owned(), "last_created".to_owned())).map(String::as_str), Some("300x200"));
+
+        host.refuse.set(true);
+        let other = unsafe { plugin.create_content(ptr::null_mut(), 40, 30) };
+        assert_eq!(host.take_calls()[3..], [
+            "callbacks log info create 40x30 at 144 dpi, greeting hi there",
+            "callbacks set last_created=40x30",
+            "callbacks log info last_created refused",
+        ]);
+
+        // only the content whose DPI changed is told, and asks for a relayout
+        plugin.dpi_changed(content, 192);
+        assert_eq!(host.take_calls(), ["callbacks log info dpi of 300x200 changed to 192".to_owned(), format!("relayout {:?}", content)]);
+
+        // destroyed content unsubscribed
+        unsafe { plugin.destroy_content(content) };
+        plugin.dpi_changed(content, 96);
+        assert_eq!(host.take_calls(), ["callbacks log info destroy 300x200"]);
+        plugin.dpi_changed(other, 96);
+        assert_eq!(host.take_calls()[0], "callbacks log info dpi of 40x30 changed to 96");
+        unsafe { plugin.destroy_content(other) };
+    }
+
+    #[test]
+    fn a_version_1_plugin_loads_without_init() {
+        let path = fixtures::plugin("v1", "v1");
+        let mut plugin = Plugin::load(&path).unwrap();
+        assert_eq!(plugin.abi_version(), 1);
+        assert_eq!(plugin.name(), "v1");
+
+        struct NoHost;
+        impl Host for NoHost {
+            fn log(&self, _: &str, _: LogLevel, _: &str) {}
+            fn setting(&self, _: &str, _: &str) -> Option<String> { None }
+            fn set_setting(&self, _: &str, _: &str, _: &str) -> bool { false }
+            fn current_dpi(&self, _: *mut c_void) -> u32 { 96 }
+            fn request_relayout(&self, _: *mut c_void) {}
+        }
+        assert!(!plugin.init(Rc::new(NoHost)));
+        assert_eq!(Probe::new(&path).inits(), 0);
+    }
+
+    #[test]
+    fn plugins_answer_older_hosts_with_older_vtables() {
+        let path = fixtures::plugin("v2", "older hosts");
+        let library = Library::new(&path).unwrap();
+        let plugin_entry = unsafe { library.get::<PluginEntry>(b"plugin_entry\0") }.unwrap();
+
+        let v1 = unsafe { plugin_entry(1) };
+        assert!(!v1.is_null());
+        assert_eq!(unsafe { ((*v1).abi_version, (*v1).size as usize) }, (1, PluginVTable::size_for(1)));
+        assert!(unsafe { plugin_entry(0) }.is_null());
+        let newer = unsafe { plugin_entry(PLUGIN_ABI_VERSION + 5) };
+        assert_eq!(unsafe { (*newer).abi_version }, 2);
+    }
+
+    #[test]
+    fn bad_vtables_are_refused() {
+        let path = |variant: &str| fixtures::plugin(variant, variant);
+        assert_eq!(load("declined").unwrap_err(), PluginError::Declined(path("declined")));
+        assert_eq!(load("too_old").unwrap_err(), PluginError::Version(path("too_old"), 0));
+        assert_eq!(load("too_new").unwrap_err(), PluginError::Version(path("too_new"), 3));
+        assert_eq!(load("short").unwrap_err(), PluginError::Invalid(path("short"), "vtable is smaller than its ABI version says"));
+        assert_eq!(load("no_name").unwrap_err(), PluginError::Invalid(path("no_name"), "no name"));
+        assert_eq!(load("bad_name").unwrap_err(), PluginError::Invalid(path("bad_name"), "name is not UTF-8"));
+        assert_eq!(load("no_create").unwrap_err(), PluginError::Invalid(path("no_create"), "no create_content"));
+        assert_eq!(load("no_destroy").unwrap_err(), PluginError::Invalid(path("no_destroy"), "no destroy_content"));
+
+        assert_eq!(
+            load("declined").unwrap_err().to_string(),
+            format!("{}: speaks no plugin ABI between 1 and {}", path("declined").display(), PLUGIN_ABI_VERSION),
+        );
+    }
+
+    #[test]
+    fn libraries_without_the_entry_point_are_not_plugins() {
+        let path = fixtures::plugin("no_entry", "no_entry");
+        assert_eq!(Plugin::load(&path).unwrap_err(), PluginError::NoEntry(path.clone(), PLUGIN_ENTRY.to_owned()));
+        assert_eq!(Plugin::load_with_entry(&path, "other_entry").unwrap().name(), "no_entry");
+    }
+
+    #[test]
+    fn files_that_are_not_libraries_fail_to_load() {
+        let dir = fixtures::scratch_dir("not-libraries");
+        let fake = dir.join(format!("fake.{}", std::env::consts::DLL_EXTENSION));
+        fs::write(&fake, "not a library").unwrap();
+        assert!(matches!(Plugin::load(&fake), Err(PluginError::Load(path, _)) if path == fake));
+        assert!(matches!(Plugin::load(dir.join("missing.so")), Err(PluginError::Load(..))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors_from_shadow_copies_name_the_original() {
+        let original = Path::new("plugins/original.dll");
+        let shadow = PathBuf::from("shadow/copy.dll");
+        for error in [
+            PluginError::Load(shadow.clone(), "gone".to_owned()),
+            PluginError::NoEntry(shadow.clone(), "plugin_entry".to_owned()),
+            PluginError::Declined(shadow.clone()),
+            PluginError::Version(shadow.clone(), 9),
+            PluginError::Invalid(shadow.clone(), "no name"),
+        ] {
+            assert_eq!(error.with_path(original).path(), original);
+        }
+    }
+
+    #[test]
+    fn discover_loads_every_library_in_name_order() {
+        let dir = fixtures::scratch_dir("discover");
+        let ext = std::env::consts::DLL_EXTENSION;
+        fs::copy(fixtures::plugin("v2", "b"), dir.join(format!("b.{}", ext))).unwrap();
+        fs::copy(fixtures::plugin("v1", "a"), dir.join(format!("a.{}", ext))).unwrap();
+        fs::copy(fixtures::plugin("no_entry", "c"), dir.join(format!("c.{}", ext))).unwrap();
+        fs::write(dir.join("readme.txt"), "not a plugin").unwrap();
+        fs::create_dir(dir.join(format!("d.{}", ext))).unwrap();
+
+        let found = discover(&dir).unwrap();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].as_ref().unwrap().name(), "a");
+        assert_eq!(found[1].as_ref().unwrap().name(), "b");
+        assert!(matches!(&found[2], Err(PluginError::NoEntry(path, _)) if path == &dir.join(format!("c.{}", ext))));
+        drop(found);
+
+        assert!(discover(dir.join("missing")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn libraries_are_named_for_the_platform() {
+        let ext = std::env::consts::DLL_EXTENSION;
+        assert!(is_library(Path::new(&format!("plugin.{}", ext))));
+        assert!(is_library(Path::new(&format!("plugin.{}", ext.to_uppercase()))));
+        assert!(!is_library(Path::new("plugin.toml")));
+        assert!(!is_library(Path::new("plugin")));
+    }
+}
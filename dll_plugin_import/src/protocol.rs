@@ -0,0 +1,512 @@
+//! The messages between a host and a plugin running in a helper process.
+//!
+//! Each message is a frame: its length as a little-endian `u32`, then a tag
+//! byte and the fields, integers little-endian and strings as a `u32` byte
+//! count and UTF-8. The host sends `Request`s and the helper answers with
+//! `Event`s; both start with `Hello`, the host offering the newest protocol
+//! version it speaks and the helper answering with the version they will
+//! use, which is no newer. Handles are passed as `u64` so the frames are the
+//! same whichever side is 32 bit.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use hidpi::{DpiAwarenessContext, DpiHostingBehavior};
+
+use crate::abi::{Capabilities, LogLevel};
+use crate::isolation::DpiIsolation;
+
+/// The newest protocol version this crate speaks.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// The oldest protocol version this crate still speaks.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// The largest frame either side accepts, so a corrupt length can't make
+/// the other allocate gigabytes.
+pub const MAX_FRAME: usize = 1 << 16;
+
+/// What the host asks of the helper. Content is named by an id the host
+/// picks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Hello { version: u32 },
+    /// Create content `width` by `height` at 96 DPI, calling the plugin with
+    /// the thread switched as `isolation` says. The helper runs with no
+    /// awareness of the host's, so `isolation.context` should be given even
+    /// where it matches the host's.
+    Create { id: u64, width: i32, height: i32, isolation: DpiIsolation },
+    /// The host laid the content out at a new size, in physical pixels.
+    Resize { id: u64, width: i32, height: i32 },
+    /// The window the content is in moved to a monitor with another DPI.
+    DpiChanged { id: u64, dpi: u32 },
+    Destroy { id: u64 },
+}
+
+/// What the helper tells the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The protocol version to use and the plugin the helper loaded.
+    Hello { version: u32, name: String, capabilities: Capabilities },
+    /// The content's window, for the host to reparent, or 0 if the plugin
+    /// couldn't create it.
+    Created { id: u64, window: u64 },
+    Destroyed { id: u64 },
+    Log { level: LogLevel, message: String },
+    /// The plugin asked for the content to be laid out again. Since
+    /// version 2.
+    Relayout { id: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// The other side closed the channel between frames.
+    Closed,
+    /// Reading or writing failed, with the error's message.
+    Io(String),
+    /// A frame longer than `MAX_FRAME`.
+    TooLarge(usize),
+    /// A tag neither side of this protocol version sends.
+    UnknownMessage(u8),
+    /// A frame that ends early, has bytes left over or holds a bad value.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::Closed => write!(f, "the channel was closed"),
+            ProtocolError::Io(message) => write!(f, "{}", message),
+            ProtocolError::TooLarge(len) => write!(f, "a {} byte frame is larger than {}", len, MAX_FRAME),
+            ProtocolError::UnknownMessage(tag) => write!(f, "unknown message {}", tag),
+            ProtocolError::Malformed(what) => write!(f, "malformed message: {}", what),
+        }
+    }
+}
+
+impl Error for ProtocolError {}
+
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> ProtocolError {
+        ProtocolError::Io(e.to_string())
+    }
+}
+
+/// A message that can be put in a frame.
+pub trait Message: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(payload: &[u8]) -> Result<Self, ProtocolError>;
+}
+
+/// Write `message` as one frame and flush it.
+pub fn send<M: Message, W: Write>(writer: &mut W, message: &M) -> Result<(), ProtocolError> {
+    let mut payload = Vec::new();
+    message.encode(&mut payload);
+    write_frame(writer, &payload)
+}
+
+/// Read one frame and decode it.
+pub fn receive<M: Message, R: Read>(reader: &mut R) -> Result<M, ProtocolError> {
+    M::decode(&read_frame(reader)?)
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> Result<(), ProtocolError> {
+    if payload.len() > MAX_FRAME {
+        return Err(ProtocolError::TooLarge(payload.len()));
+    }
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one frame. The channel closing before the first byte of a frame is
+/// `Closed`; closing partway through one is `Malformed`.
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Vec<u8>, ProtocolError> {
+    let mut len = [0; 4];
+    let mut read = 0;
+    while read < len.len() {
+        match reader.read(&mut len[read..]) {
+            Ok(0) if read == 0 => return Err(ProtocolError::Closed),
+            Ok(0) => return Err(ProtocolError::Malformed("the channel closed in a frame length")),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(ProtocolError::TooLarge(len));
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => ProtocolError::Malformed("the channel closed in a frame"),
+        _ => e.into(),
+    })?;
+    Ok(payload)
+}
+
+impl Message for Request {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Request::Hello { version } => {
+                out.push(1);
+                put_u32(out, *version);
+            },
+            Request::Create { id, width, height, isolation } => {
+                out.push(2);
+                put_u64(out, *id);
+                put_i32(out, *width);
+                put_i32(out, *height);
+                out.push(context_tag(isolation.context));
+                out.push(match isolation.hosting {
+                    None => 0,
+                    Some(DpiHostingBehavior::Default) => 1,
+                    Some(DpiHostingBehavior::Mixed) => 2,
+                });
+            },
+            Request::Resize { id, width, height } => {
+                out.push(3);
+                put_u64(out, *id);
+                put_i32(out, *width);
+                put_i32(out, *height);
+            },
+            Request::DpiChanged { id, dpi } => {
+                out.push(4);
+                put_u64(out, *id);
+                put_u32(out, *dpi);
+            },
+            Request::Destroy { id } => {
+                out.push(5);
+                put_u64(out, *id);
+            },
+        }
+    }
+
+    fn decode(payload: &[u8]) -> Result<Request, ProtocolError> {
+        let mut fields = Fields(payload);
+        let request = match fields.u8()? {
+            1 => Request::Hello { version: fields.u32()? },
+            2 => {
+                let (id, width, height) = (fields.u64()?, fields.i32()?, fields.i32()?);
+                let context = context_from_tag(fields.u8()?)?;
+                let hosting = match fields.u8()? {
+                    0 => None,
+                    1 => Some(DpiHostingBehavior::Default),
+                    2 => Some(DpiHostingBehavior::Mixed),
+                    _ => return Err(ProtocolError::Malformed("unknown hosting behavior")),
+                };
+                Request::Create { id, width, height, isolation: DpiIsolation { context, hosting } }
+            },
+            3 => Request::Resize { id: fields.u64()?, width: fields.i32()?, height: fields.i32()? },
+            4 => Request::DpiChanged { id: fields.u64()?, dpi: fields.u32()? },
+            5 => Request::Destroy { id: fields.u64()? },
+            tag => return Err(ProtocolError::UnknownMessage(tag)),
+        };
+        fields.end()?;
+        Ok(request)
+    }
+}
+
+impl Message for Event {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Event::Hello { version, name, capabilities } => {
+                out.push(1);
+                put_u32(out, *version);
+                put_str(out, name);
+                put_u32(out, capabilities.0);
+            },
+            Event::Created { id, window } => {
+                out.push(2);
+                put_u64(out, *id);
+                put_u64(out, *window);
+            },
+            Event::Destroyed { id } => {
+                out.push(3);
+                put_u64(out, *id);
+            },
+            Event::Log { level, message } => {
+                out.push(4);
+                out.push(*level as u8);
+                put_str(out, message);
+            },
+            Event::Relayout { id } => {
+                out.push(5);
+                put_u64(out, *id);
+            },
+        }
+    }
+
+    fn decode(payload: &[u8]) -> Result<Event, ProtocolError> {
+        let mut fields = Fields(payload);
+        let event = match fields.u8()? {
+            1 => Event::Hello { version: fields.u32()?, name: fields.string()?, capabilities: Capabilities(fields.u32()?) },
+            2 => Event::Created { id: fields.u64()?, window: fields.u64()? },
+            3 => Event::Destroyed { id: fields.u64()? },
+            4 => {
+                let level = LogLevel::from_raw(fields.u8()?.into()).ok_or(ProtocolError::Malformed("unknown log level"))?;
+                Event::Log { level, message: fields.string()? }
+            },
+            5 => Event::Relayout { id: fields.u64()? },
+            tag => return Err(ProtocolError::UnknownMessage(tag)),
+        };
+        fields.end()?;
+        Ok(event)
+    }
+}
+
+// Spelt out rather than looked up in `DpiAwarenessContext::ALL`, so the
+// frames don't change if it does
+fn context_tag(context: Option<DpiAwarenessContext>) -> u8 {
+    match context {
+        None => 0,
+        Some(DpiAwarenessContext::Unaware) => 1,
+        Some(DpiAwarenessContext::UnawareGdiScaled) => 2,
+        Some(DpiAwarenessContext::System) => 3,
+        Some(DpiAwarenessContext::PerMonitor) => 4,
+        Some(DpiAwarenessContext::PerMonitorV2) => 5,
+    }
+}
+
+fn context_from_tag(tag: u8) -> Result<Option<DpiAwarenessContext>, ProtocolError> {
+    match tag {
+        0 => Ok(None),
+        1 => Ok(Some(DpiAwarenessContext::Unaware)),
+        2 => Ok(Some(DpiAwarenessContext::UnawareGdiScaled)),
+        3 => Ok(Some(DpiAwarenessContext::System)),
+        4 => Ok(Some(DpiAwarenessContext::PerMonitor)),
+        5 => Ok(Some(DpiAwarenessContext::PerMonitorV2)),
+        _ => Err(ProtocolError::Malformed("unknown awareness context")),
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_i32(out: &mut Vec<u8>, value: i32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_u32(out, s.len() as u32);
+    out.extend_from_slice(s.as_bytes());
+}
+
+// The fields of a frame, read front to back
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ProtocolError> {
+        if self.0.len() < len {
+            return Err(ProtocolError::Malformed("the frame is too short"));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ProtocolError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn i32(&mut self) -> Result<i32, ProtocolError> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn u64(&mut self) -> Result<u64, ProtocolError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn string(&mut self) -> Result<String, ProtocolError> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| ProtocolError::Malformed("a string is not UTF-8"))
+    }
+
+    fn end(&self) -> Result<(), ProtocolError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ProtocolError::Malformed("bytes left over"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requests() -> Vec<Request> {
+        let mut requests = vec![
+            Request::Hello { version: PROTOCOL_VERSION },
+            Request::Create { id: 1, width: 300, height: 200, isolation: DpiIsolation::NONE },
+            Request::Resize { id: u64::MAX, width: -1, height: i32::MIN },
+            Request::DpiChanged { id: 7, dpi: 144 },
+            Request::Destroy { id: 0 },
+        ];
+        for &context in &DpiAwarenessContext::ALL {
+            for &hosting in &[None, Some(DpiHostingBehavior::Default), Some(DpiHostingBehavior::Mixed)] {
+                requests.push(Request::Create { id: 2, width: 0, height: 0, isolation: DpiIsolation { context: Some(context), hosting } });
+            }
+        }
+        requests
+    }
+
+    fn events() -> Vec<Event> {
+        let mut events = vec![
+            Event::Hello { version: 1, name: "Plugin — ünïcode".to_owned(), capabilities: Capabilities(1 | 2 | 1 << 31) },
+            Event::Hello { version: 2, name: String::new(), capabilities: Capabilities::NONE },
+            Event::Created { id: 1, window: 0x1234_5678_9abc },
+            Event::Created { id: 2, window: 0 },
+            Event::Destroyed { id: u64::MAX },
+            Event::Relayout { id: 3 },
+        ];
+        for &level in &LogLevel::ALL {
+            events.push(Event::Log { level, message: format!("{} message", level) });
+        }
+        events
+    }
+
+    fn frame<M: Message>(message: &M) -> Vec<u8> {
+        let mut out = Vec::new();
+        send(&mut out, message).unwrap();
+        out
+    }
+
+    #[test]
+    fn messages_survive_a_round_trip() {
+        for request in requests() {
+            assert_eq!(receive::<Request, _>(&mut &frame(&request)[..]), Ok(request));
+        }
+        for event in events() {
+            assert_eq!(receive::<Event, _>(&mut &frame(&event)[..]), Ok(event));
+        }
+    }
+
+    #[test]
+    fn frames_are_laid_out_as_documented() {
+        let isolation = DpiIsolation { context: Some(DpiAwarenessContext::System), hosting: Some(DpiHostingBehavior::Mixed) };
+        let bytes = frame(&Request::Create { id: 0x0102, width: 300, height: -2, isolation });
+        assert_eq!(bytes, [
+            19, 0, 0, 0,
+            2,
+            2, 1, 0, 0, 0, 0, 0, 0,
+            44, 1, 0, 0,
+            0xfe, 0xff, 0xff, 0xff,
+            3, 2,
+        ]);
+        let bytes = frame(&Event::Log { level: LogLevel::Warn, message: "hé".to_owned() });
+        assert_eq!(bytes, [9, 0, 0, 0, 4, 1, 3, 0, 0, 0, b'h', 0xc3, 0xa9]);
+    }
+
+    #[test]
+    fn frames_follow_each_other_until_the_channel_closes() {
+        let mut stream = Vec::new();
+        for event in events() {
+            send(&mut stream, &event).unwrap();
+        }
+        let mut reader = &stream[..];
+        for event in events() {
+            assert_eq!(receive(&mut reader), Ok(event));
+        }
+        assert_eq!(receive::<Event, _>(&mut reader), Err(ProtocolError::Closed));
+    }
+
+    // Hands out a byte at a time, interrupted before each
+    struct Trickle<'a> {
+        data: &'a [u8],
+        interrupt: bool,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+            if self.data.is_empty() || buffer.is_empty() {
+                return Ok(0);
+            }
+            buffer[0] = self.data[0];
+            self.data = &self.data[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn frames_can_arrive_a_byte_at_a_time() {
+        let bytes = frame(&Request::DpiChanged { id: 9, dpi: 120 });
+        let mut reader = Trickle { data: &bytes, interrupt: false };
+        assert_eq!(receive(&mut reader), Ok(Request::DpiChanged { id: 9, dpi: 120 }));
+        assert_eq!(receive::<Request, _>(&mut reader), Err(ProtocolError::Closed));
+    }
+
+    #[test]
+    fn cut_frames_are_malformed() {
+        let bytes = frame(&Request::Destroy { id: 5 });
+        assert_eq!(read_frame(&mut &bytes[..0]), Err(ProtocolError::Closed));
+        for len in 1..4 {
+            assert_eq!(read_frame(&mut &bytes[..len]), Err(ProtocolError::Malformed("the channel closed in a frame length")));
+        }
+        for len in 4..bytes.len() {
+            assert_eq!(read_frame(&mut &bytes[..len]), Err(ProtocolError::Malformed("the channel closed in a frame")));
+        }
+    }
+
+    #[test]
+    fn frames_are_limited_in_size() {
+        let too_long = ((MAX_FRAME + 1) as u32).to_le_bytes();
+        assert_eq!(read_frame(&mut &too_long[..]), Err(ProtocolError::TooLarge(MAX_FRAME + 1)));
+        let mut out = Vec::new();
+        assert_eq!(write_frame(&mut out, &vec![0; MAX_FRAME + 1]), Err(ProtocolError::TooLarge(MAX_FRAME + 1)));
+        assert!(out.is_empty());
+
+        let longest = Event::Log { level: LogLevel::Info, message: "x".repeat(MAX_FRAME - 6) };
+        assert_eq!(receive::<Event, _>(&mut &frame(&longest)[..]), Ok(longest));
+    }
+
+    #[test]
+    fn malformed_payloads_are_refused() {
+        let request = Request::decode;
+        let event = Event::decode;
+        fn malformed<T>(what: &'static str) -> Result<T, ProtocolError> {
+            Err(ProtocolError::Malformed(what))
+        }
+
+        assert_eq!(request(&[]), malformed("the frame is too short"));
+        assert_eq!(request(&[0]), Err(ProtocolError::UnknownMessage(0)));
+        assert_eq!(request(&[6]), Err(ProtocolError::UnknownMessage(6)));
+        assert_eq!(request(&[1, 2, 0, 0]), malformed("the frame is too short"));
+        assert_eq!(request(&[1, 2, 0, 0, 0, 0]), malformed("bytes left over"));
+        let create = |context: u8, hosting: u8| {
+            let mut payload = vec![2];
+            payload.extend_from_slice(&[0; 16]);
+            payload.extend_from_slice(&[context, hosting]);
+            request(&payload)
+        };
+        assert!(create(5, 2).is_ok());
+        assert_eq!(create(6, 0), malformed("unknown awareness context"));
+        assert_eq!(create(0, 3), malformed("unknown hosting behavior"));
+
+        assert_eq!(event(&[6]), Err(ProtocolError::UnknownMessage(6)));
+        assert_eq!(event(&[4, 4, 0, 0, 0, 0]), malformed("unknown log level"));
+        assert_eq!(event(&[4, 0, 2, 0, 0, 0, 0xff, 0xfe]), malformed("a string is not UTF-8"));
+        assert_eq!(event(&[4, 0, 3, 0, 0, 0, b'a', b'b']), malformed("the frame is too short"));
+        assert_eq!(event(&[3, 1, 0, 0, 0, 0, 0, 0]), malformed("the frame is too short"));
+    }
+}
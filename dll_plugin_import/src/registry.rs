@@ -0,0 +1,602 @@
+//! Finding plugins in a list of directories.
+//!
+//! A plugin is either a library directly in one of the directories, or a
+//! subdirectory holding a `plugin.toml` and the library it describes:
+//!
+//! ```text
+//! plugins/
+//!     simple.dll
+//!     described/
+//!         plugin.toml
+//!         described.dll
+//! ```
+//!
+//! Libraries without a manifest that turn out not to be plugins are skipped
+//! quietly, since plugins may share a directory with other libraries.
+//!
+//! A registry with a shadow directory loads copies of the libraries made
+//! there instead, so the originals can be rebuilt while the host runs; see
+//! `reload`. A registry with a `Host` hands it to each plugin it keeps.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use hidpi::DpiAwarenessContext;
+
+use crate::abi::{Capabilities, MIN_PLUGIN_ABI_VERSION, PLUGIN_ABI_VERSION, PLUGIN_ENTRY};
+use crate::host::Host;
+use crate::isolation::{DpiIsolation, HostDpi};
+use crate::manifest::{Manifest, Version, MANIFEST_FILE};
+use crate::plugin::{is_library, Plugin, PluginError};
+use crate::reload::{remove_shadow_copies, shadow_copy};
+
+/// A plugin the registry loaded, with its manifest if it has one.
+#[derive(Debug)]
+pub struct RegisteredPlugin {
+    pub plugin: Plugin,
+    pub manifest: Option<Manifest>,
+    /// The library the plugin was found as. `plugin.path()` is a copy of it
+    /// when the registry has a shadow directory.
+    pub source: PathBuf,
+}
+
+impl RegisteredPlugin {
+    pub fn name(&self) -> &str {
+        self.plugin.name()
+    }
+
+    /// `None` for a plugin without a manifest.
+    pub fn version(&self) -> Option<Version> {
+        self.manifest.as_ref().map(|manifest| manifest.version)
+    }
+
+    /// The awareness the plugin's content is written for: what the manifest
+    /// asks for, else per-monitor v2 for a plugin with the `PER_MONITOR_DPI`
+    /// capability and system aware for one without.
+    pub fn dpi_awareness(&self) -> DpiAwarenessContext {
+        match self.manifest.as_ref().and_then(|manifest| manifest.dpi_awareness) {
+            Some(awareness) => awareness,
+            None if self.plugin.capabilities().contains(Capabilities::PER_MONITOR_DPI) => DpiAwarenessContext::PerMonitorV2,
+            None => DpiAwarenessContext::System,
+        }
+    }
+
+    /// What to switch the thread to around calls into the plugin for content
+    /// parented to `host`.
+    pub fn isolation(&self, host: HostDpi) -> DpiIsolation {
+        DpiIsolation::new(self.dpi_awareness(), host)
+    }
+
+    /// Call the plugin's `create_content` with the thread switched to the
+    /// plugin's awareness, if `host` allows it.
+    ///
+    /// # Safety
+    /// As `Plugin::create_content`.
+    pub unsafe fn create_content(&self, host: HostDpi, h_instance: *mut c_void, width: c_int, height: c_int) -> *mut c_void {
+        self.isolation(host).run(|| self.plugin.create_content(h_instance, width, height))
+    }
+
+    /// Call the plugin's `destroy_content`, switched as for `create_content`.
+    ///
+    /// # Safety
+    /// As `Plugin::destroy_content`, and `host` must be what the content was
+    /// created for.
+    pub unsafe fn destroy_content(&self, host: HostDpi, content: *mut c_void) {
+        self.isolation(host).run(|| self.plugin.destroy_content(content))
+    }
+
+    /// Tell the plugin the DPI of `content` changed, switched as for
+    /// `create_content` so its subscribers see the awareness they created the
+    /// content with.
+    pub fn dpi_changed(&self, host: HostDpi, content: *mut c_void, dpi: u32) {
+        self.isolation(host).run(|| self.plugin.dpi_changed(content, dpi))
+    }
+}
+
+/// Why something in a plugin directory wasn't registered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    /// A directory couldn't be read.
+    Dir(PathBuf, String),
+    /// A manifest couldn't be read or is invalid, or, at the library's path,
+    /// a library doesn't match its manifest.
+    Manifest(PathBuf, String),
+    /// A library couldn't be copied to the shadow directory.
+    Shadow(PathBuf, String),
+    Plugin(PluginError),
+    /// Another plugin with the same name was kept: a newer one, or one of the
+    /// same version found first.
+    Duplicate { name: String, path: PathBuf, kept: PathBuf },
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::Dir(path, message)
+            | RegistryError::Manifest(path, message)
+            | RegistryError::Shadow(path, message) => write!(f, "{}: {}", path.display(), message),
+            RegistryError::Plugin(error) => error.fmt(f),
+            RegistryError::Duplicate { name, path, kept } => write!(f, "{}: {} from {} is used instead", path.display(), name, kept.display()),
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+/// The plugins found in a list of directories, earlier directories taking
+/// precedence when the same plugin is in several at the same version.
+#[derive(Default)]
+pub struct PluginRegistry {
+    pub dirs: Vec<PathBuf>,
+    /// Where libraries are copied to be loaded, `None` to load them in place.
+    pub shadow: Option<PathBuf>,
+    /// What the plugins call back into, `None` to give them nothing.
+    pub host: Option<Rc<dyn Host>>,
+    plugins: Vec<RegisteredPlugin>,
+    problems: Vec<RegistryError>,
+}
+
+impl fmt::Debug for PluginRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PluginRegistry")
+            .field("dirs", &self.dirs)
+            .field("shadow", &self.shadow)
+            .field("host", &self.host.is_some())
+            .field("plugins", &self.plugins)
+            .field("problems", &self.problems)
+            .finish()
+    }
+}
+
+impl PluginRegistry {
+    pub fn new() -> PluginRegistry {
+        PluginRegistry::default()
+    }
+
+    pub fn dir<P: Into<PathBuf>>(mut self, dir: P) -> PluginRegistry {
+        self.dirs.push(dir.into());
+        self
+    }
+
+    /// Load copies of the libraries made in `dir`, which is created if need be.
+    pub fn shadow<P: Into<PathBuf>>(mut self, dir: P) -> PluginRegistry {
+        self.shadow = Some(dir.into());
+        self
+    }
+
+    /// Hand `host`'s services to the plugins kept by `scan` and `replace`.
+    pub fn host(mut self, host: Rc<dyn Host>) -> PluginRegistry {
+        self.host = Some(host);
+        self
+    }
+
+    /// Find, check and load the plugins in `dirs`. A missing directory is not
+    /// a problem. Rescanning unloads the plugins found before, so their
+    /// content must be destroyed first.
+    pub fn scan(&mut self) {
+        self.plugins.clear();
+        self.problems.clear();
+
+        let mut candidates = Vec::new();
+        for dir in &self.dirs {
+            match sorted_entries(dir) {
+                Ok(entries) => candidates.extend(entries),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+                Err(e) => self.problems.push(RegistryError::Dir(dir.clone(), e.to_string())),
+            }
+        }
+
+        let shadow = self.shadow.clone();
+        for path in candidates {
+            let manifest_path = path.join(MANIFEST_FILE);
+            let found = if manifest_path.is_file() {
+                load_described(&manifest_path, shadow.as_deref())
+            } else if path.is_file() && is_library(&path) {
+                match load_library(&path, None, shadow.as_deref()) {
+                    Err(RegistryError::Plugin(PluginError::NoEntry(..))) => continue,
+                    found => found,
+                }
+            } else {
+                continue;
+            };
+            match found {
+                Ok(found) => self.add(found),
+                Err(error) => self.problems.push(error),
+            }
+        }
+
+        // Only once duplicates are settled, so no plugin that is dropped
+        // starts using the host
+        if let Some(host) = &self.host {
+            for found in &mut self.plugins {
+                found.plugin.init(host.clone());
+            }
+        }
+    }
+
+    // A plugin only replaces one of the same name found earlier if it's newer
+    fn add(&mut self, found: RegisteredPlugin) {
+        let existing = match self.plugins.iter_mut().find(|plugin| plugin.name() == found.name()) {
+            Some(existing) => existing,
+            None => {
+                self.plugins.push(found);
+                return;
+            },
+        };
+        let dropped = if found.version() > existing.version() {
+            mem::replace(existing, found)
+        } else {
+            found
+        };
+        self.problems.push(RegistryError::Duplicate {
+            name: dropped.name().to_owned(),
+            path: dropped.source.clone(),
+            kept: existing.source.clone(),
+        });
+    }
+
+    /// The plugins loaded by the last `scan`, in the order they were found.
+    pub fn plugins(&self) -> &[RegisteredPlugin] {
+        &self.plugins
+    }
+
+    /// Everything the last `scan` skipped, and why.
+    pub fn problems(&self) -> &[RegistryError] {
+        &self.problems
+    }
+
+    pub fn find(&self, name: &str) -> Option<&RegisteredPlugin> {
+        self.plugins.iter().find(|plugin| plugin.name() == name)
+    }
+
+    /// Put `found` in the place of the plugin with its name, or after the
+    /// others if there isn't one, and return the plugin it replaced. Content
+    /// created by that plugin must be destroyed before it is dropped.
+    pub fn replace(&mut self, mut found: RegisteredPlugin) -> Option<RegisteredPlugin> {
+        if let Some(host) = &self.host {
+            found.plugin.init(host.clone());
+        }
+        match self.plugins.iter_mut().find(|plugin| plugin.name() == found.name()) {
+            Some(existing) => Some(mem::replace(existing, found)),
+            None => {
+                self.plugins.push(found);
+                None
+            },
+        }
+    }
+}
+
+fn sorted_entries(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+// Read and check a manifest before loading the library it describes
+fn load_described(manifest_path: &Path, shadow: Option<&Path>) -> Result<RegisteredPlugin, RegistryError> {
+    let error = |message: String| RegistryError::Manifest(manifest_path.to_owned(), message);
+    let text = fs::read_to_string(manifest_path).map_err(|e| error(e.to_string()))?;
+    let manifest = Manifest::parse(&text).map_err(|e| error(e.to_string()))?;
+    if manifest.host_abi < MIN_PLUGIN_ABI_VERSION || manifest.host_abi > PLUGIN_ABI_VERSION {
+        return Err(error(format!("needs plugin ABI {}, the host speaks {} to {}", manifest.host_abi, MIN_PLUGIN_ABI_VERSION, PLUGIN_ABI_VERSION)));
+    }
+
+    let dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    let library = match &manifest.library {
+        Some(library) => dir.join(library),
+        None => {
+            let libraries: Vec<PathBuf> = sorted_entries(dir)
+                .map_err(|e| error(e.to_string()))?
+                .into_iter()
+                .filter(|path| path.is_file() && is_library(path))
+                .collect();
+            match &libraries[..] {
+                [library] => library.clone(),
+                [] => return Err(error("no library beside it".to_owned())),
+                _ => return Err(error("several libraries beside it, say which with library".to_owned())),
+            }
+        },
+    };
+
+    load_library(&library, Some(manifest), shadow)
+}
+
+// Load `library`, or a copy of it in `shadow`, and check it agrees with its
+// manifest. The manifest itself has been checked already.
+pub(crate) fn load_library(library: &Path, manifest: Option<Manifest>, shadow: Option<&Path>) -> Result<RegisteredPlugin, RegistryError> {
+    let path = match shadow {
+        Some(shadow) => {
+            remove_shadow_copies(shadow, library);
+            shadow_copy(library, shadow).map_err(|e| RegistryError::Shadow(library.to_owned(), e.to_string()))?
+        },
+        None => library.to_owned(),
+    };
+    let entry = manifest.as_ref().map_or(PLUGIN_ENTRY, |manifest| &manifest.entry);
+    let loaded = Plugin::load_with_entry(&path, entry)
+        .map_err(|error| RegistryError::Plugin(error.with_path(library)))
+        .and_then(|plugin| match &manifest {
+            Some(manifest) => check_against(manifest, &plugin, library).map(|()| plugin),
+            None => Ok(plugin),
+        });
+    match loaded {
+        Ok(plugin) => Ok(RegisteredPlugin { plugin, manifest, source: library.to_owned() }),
+        Err(error) => {
+            // the copy is no use to anyone, and the library is unloaded by now
+            if shadow.is_some() {
+                let _ = fs::remove_file(&path);
+            }
+            Err(error)
+        },
+    }
+}
+
+fn check_against(manifest: &Manifest, plugin: &Plugin, library: &Path) -> Result<(), RegistryError> {
+    let error = |message: String| Err(RegistryError::Manifest(library.to_owned(), message));
+    if plugin.name() != manifest.name {
+        return error(format!("calls itself {:?}, but its manifest names it {:?}", plugin.name(), manifest.name));
+    }
+    if plugin.abi_version() < manifest.host_abi {
+        return error(format!("{} answered with plugin ABI {}, but its manifest needs {}", manifest.entry, plugin.abi_version(), manifest.host_abi));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+    use std::process;
+    use std::ptr;
+
+    use hidpi::backend::with_backend;
+
+    use super::*;
+    use crate::abi::LogLevel;
+    use crate::fixtures::{self, Probe, ThreadBackend};
+
+    // A copy of the fixture `variant` named `name`, as `<dir>/<stem>.so`
+    fn library(dir: &Path, stem: &str, variant: &str, name: &str) -> PathBuf {
+        let path = dir.join(format!("{}{}{}", DLL_PREFIX, stem, DLL_SUFFIX));
+        fs::create_dir_all(dir).unwrap();
+        fs::copy(fixtures::plugin(variant, name), &path).unwrap();
+        path
+    }
+
+    fn manifest(dir: &Path, text: &str) -> PathBuf {
+        let path = dir.join(MANIFEST_FILE);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn scan(registry: &mut PluginRegistry) -> Vec<(String, PathBuf)> {
+        registry.scan();
+        registry.plugins().iter().map(|found| (found.name().to_owned(), found.source.clone())).collect()
+    }
+
+    struct NoHost;
+
+    impl Host for NoHost {
+        fn log(&self, _: &str, _: LogLevel, _: &str) {}
+        fn setting(&self, _: &str, _: &str) -> Option<String> { None }
+        fn set_setting(&self, _: &str, _: &str, _: &str) -> bool { false }
+        fn current_dpi(&self, _: *mut c_void) -> u32 { 96 }
+        fn request_relayout(&self, _: *mut c_void) {}
+    }
+
+    #[test]
+    fn plain_and_described_plugins_are_found() {
+        let dir = fixtures::scratch_dir("registry-found");
+        let plain = library(&dir, "plain", "v2", "plain");
+        let described = library(&dir.join("described"), "described", "v2", "described");
+        manifest(&dir.join("described"), "name = \"described\"\nversion = \"1.2\"\nhost_abi = 2\ndpi_awareness = \"system\"\n");
+        // neither a plugin nor a library, so nothing to say about either
+        library(&dir, "other", "no_entry", "other");
+        fs::write(dir.join("readme.txt"), "not a plugin").unwrap();
+        fs::create_dir(dir.join("empty")).unwrap();
+
+        let mut registry = PluginRegistry::new().dir(&dir).dir(dir.join("missing"));
+        assert_eq!(scan(&mut registry), [("described".to_owned(), described.clone()), ("plain".to_owned(), plain.clone())]);
+        assert_eq!(registry.problems(), []);
+
+        let found = registry.find("described").unwrap();
+        assert_eq!(found.version(), Some(Version::new(1, 2, 0)));
+        assert_eq!(found.plugin.path(), described);
+        assert_eq!(found.dpi_awareness(), DpiAwarenessContext::System);
+        let found = registry.find("plain").unwrap();
+        assert_eq!(found.version(), None);
+        assert!(found.manifest.is_none());
+        // the fixture has PER_MONITOR_DPI
+        assert_eq!(found.dpi_awareness(), DpiAwarenessContext::PerMonitorV2);
+        assert!(registry.find("other").is_none());
+    }
+
+    #[test]
+    fn manifests_name_the_library_and_entry() {
+        let dir = fixtures::scratch_dir("registry-entry");
+        let sub = dir.join("renamed");
+        library(&sub, "unused", "v1", "unused");
+        let named = library(&sub, "named", "no_entry", "named");
+        manifest(&sub, &format!("name = \"named\"\nversion = \"1\"\nhost_abi = 1\nentry = \"other_entry\"\nlibrary = {:?}\n", named.file_name().unwrap().to_str().unwrap()));
+
+        let mut registry = PluginRegistry::new().dir(&dir);
+        assert_eq!(scan(&mut registry), [("named".to_owned(), named.clone())]);
+        assert_eq!(registry.problems(), []);
+        assert_eq!(registry.find("named").unwrap().manifest.as_ref().unwrap().entry, "other_entry");
+    }
+
+    #[test]
+    fn bad_manifests_are_reported() {
+        let dir = fixtures::scratch_dir("registry-manifests");
+        let case = |name: &str, text: &str, libraries: &[&str]| {
+            let sub = dir.join(name);
+            for stem in libraries {
+                library(&sub, stem, "v2", "manifests");
+            }
+            manifest(&sub, text)
+        };
+        let syntax = case("a_syntax", "name = \n", &["syntax"]);
+        let unknown = case("b_unknown", "name = \"manifests\"\nversion = \"1\"\nhost_abi = 1\nlibary = \"x\"\n", &["unknown"]);
+        let too_new = case("c_too_new", "name = \"manifests\"\nversion = \"1\"\nhost_abi = 9\n", &["too_new"]);
+        let none = case("d_none", "name = \"manifests\"\nversion = \"1\"\nhost_abi = 1\n", &[]);
+        let several = case("e_several", "name = \"manifests\"\nversion = \"1\"\nhost_abi = 1\n", &["one", "two"]);
+        case("f_mismatch", "name = \"other\"\nversion = \"1\"\nhost_abi = 1\n", &["mismatch"]);
+        let mismatch = dir.join("f_mismatch").join(format!("{}mismatch{}", DLL_PREFIX, DLL_SUFFIX));
+        case("g_missing", "name = \"manifests\"\nversion = \"1\"\nhost_abi = 1\nlibrary = \"gone.so\"\n", &[]);
+
+        let mut registry = PluginRegistry::new().dir(&dir);
+        assert_eq!(scan(&mut registry), []);
+        let problems = registry.problems();
+        assert_eq!(problems.len(), 7, "{:?}", problems);
+        assert!(matches!(&problems[0], RegistryError::Manifest(path, _) if *path == syntax));
+        assert_eq!(problems[1], RegistryError::Manifest(unknown, "unknown key \"libary\"".to_owned()));
+        assert_eq!(problems[2], RegistryError::Manifest(too_new, format!("needs plugin ABI 9, the host speaks {} to {}", MIN_PLUGIN_ABI_VERSION, PLUGIN_ABI_VERSION)));
+        assert_eq!(problems[3], RegistryError::Manifest(none, "no library beside it".to_owned()));
+        assert_eq!(problems[4], RegistryError::Manifest(several, "several libraries beside it, say which with library".to_owned()));
+        assert_eq!(problems[5], RegistryError::Manifest(mismatch, "calls itself \"manifests\", but its manifest names it \"other\"".to_owned()));
+        assert!(matches!(&problems[6], RegistryError::Plugin(PluginError::Load(path, _)) if path.ends_with("g_missing/gone.so")));
+    }
+
+    #[test]
+    fn a_plugin_that_speaks_too_old_an_abi_for_its_manifest_is_refused() {
+        let dir = fixtures::scratch_dir("registry-abi");
+        let sub = dir.join("old");
+        let old = library(&sub, "old", "v1", "old");
+        manifest(&sub, "name = \"old\"\nversion = \"1\"\nhost_abi = 2\n");
+
+        let mut registry = PluginRegistry::new().dir(&dir);
+        assert_eq!(scan(&mut registry), []);
+        assert_eq!(registry.problems(), [RegistryError::Manifest(old, "plugin_entry answered with plugin ABI 1, but its manifest needs 2".to_owned())]);
+    }
+
+    // Logs what the plugin says with the awareness the thread had as it said it
+    struct RecordingHost {
+        backend: ThreadBackend,
+        logs: RefCell<Vec<(String, DpiAwarenessContext)>>,
+    }
+
+    impl Host for RecordingHost {
+        fn log(&self, _: &str, _: LogLevel, message: &str) {
+            self.logs.borrow_mut().push((message.to_owned(), self.backend.context()));
+        }
+        fn setting(&self, _: &str, _: &str) -> Option<String> { None }
+        fn set_setting(&self, _: &str, _: &str, _: &str) -> bool { true }
+        fn current_dpi(&self, _: *mut c_void) -> u32 { 96 }
+        fn request_relayout(&self, _: *mut c_void) {}
+    }
+
+    #[test]
+    fn calls_about_content_run_inside_its_isolation() {
+        let dir = fixtures::scratch_dir("registry-isolation");
+        let sub = dir.join("isolated");
+        library(&sub, "isolated", "v2", "isolated");
+        manifest(&sub, "name = \"isolated\"\nversion = \"1\"\nhost_abi = 2\ndpi_awareness = \"system\"\n");
+        let backend = ThreadBackend::new();
+        let host = Rc::new(RecordingHost { backend: backend.clone(), logs: RefCell::new(Vec::new()) });
+        let mut registry = PluginRegistry::new().dir(&dir).host(host.clone());
+        registry.scan();
+        let found = registry.find("isolated").unwrap();
+
+        let run = |host_dpi: HostDpi| {
+            with_backend(backend.clone(), || unsafe {
+                let content = found.create_content(host_dpi, ptr::null_mut(), 300, 200);
+                found.dpi_changed(host_dpi, content, 144);
+                found.destroy_content(host_dpi, content);
+                // unsubscribed by now
+                found.dpi_changed(host_dpi, content, 192);
+            });
+            host.logs.replace(Vec::new())
+        };
+        let logs = |awareness: DpiAwarenessContext| vec![
+            ("create 300x200 at 96 dpi, greeting none".to_owned(), awareness),
+            ("dpi of 300x200 changed to 144".to_owned(), awareness),
+            ("destroy 300x200".to_owned(), awareness),
+        ];
+
+        let mixed = HostDpi::new(DpiAwarenessContext::PerMonitorV2, true);
+        assert_eq!(found.isolation(mixed).context, Some(DpiAwarenessContext::System));
+        assert_eq!(run(mixed), logs(DpiAwarenessContext::System));
+        assert_eq!(backend.switches().len(), 4 * 4);
+        assert_eq!(backend.context(), DpiAwarenessContext::Unaware);
+
+        // a host that can't isolate leaves the thread as it is
+        let unmixed = HostDpi::new(DpiAwarenessContext::PerMonitorV2, false);
+        assert_eq!(run(unmixed), logs(DpiAwarenessContext::Unaware));
+        assert_eq!(backend.switches().len(), 4 * 4);
+    }
+
+    #[test]
+    fn duplicates_are_settled_by_version() {
+        let first = fixtures::scratch_dir("registry-duplicates-first");
+        let second = fixtures::scratch_dir("registry-duplicates-second");
+        let described = |dir: &Path, name: &str, version: &str| {
+            let sub = dir.join(name);
+            let path = library(&sub, name, "v2", "dup");
+            manifest(&sub, &format!("name = \"dup\"\nversion = \"{}\"\nhost_abi = 1\n", version));
+            path
+        };
+        let plain = library(&first, "a_plain", "v2", "dup");
+        let old = described(&first, "b_old", "1.0");
+        let new = described(&second, "a_new", "1.2");
+        let same = described(&second, "b_same", "1.2.0");
+
+        let mut registry = PluginRegistry::new().dir(&first).dir(&second).host(Rc::new(NoHost));
+        assert_eq!(scan(&mut registry), [("dup".to_owned(), new.clone())]);
+        let duplicate = |path: &PathBuf, kept: &PathBuf| RegistryError::Duplicate { name: "dup".to_owned(), path: path.clone(), kept: kept.clone() };
+        assert_eq!(registry.problems(), [
+            // a manifest's version is newer than none at all
+            duplicate(&plain, &old),
+            duplicate(&old, &new),
+            // the first of the same version is kept
+            duplicate(&same, &new),
+        ]);
+        // only the plugin kept is handed the host
+        assert_eq!(Probe::new(&new).inits(), 1);
+        assert_eq!(registry.find("dup").unwrap().version(), Some(Version::new(1, 2, 0)));
+    }
+
+    #[test]
+    fn shadow_copies_are_loaded_and_this_process_cleans_up_its_own() {
+        let dir = fixtures::scratch_dir("registry-shadow");
+        let shadow = dir.join("shadow");
+        let original = library(&dir, "shadowed", "v2", "shadowed");
+        let copy = |process: u32, count: usize| shadow.join(format!("{}shadowed.{}-{}{}", DLL_PREFIX, process, count, DLL_SUFFIX));
+        fs::create_dir_all(&shadow).unwrap();
+        let stale = copy(process::id(), 999_999);
+        let others = copy(process::id().wrapping_add(1), 0);
+        let unrelated = shadow.join("notes.txt");
+        for path in [&stale, &others, &unrelated] {
+            fs::write(path, "").unwrap();
+        }
+
+        let mut registry = PluginRegistry::new().dir(&dir).shadow(&shadow);
+        assert_eq!(scan(&mut registry), [("shadowed".to_owned(), original.clone())]);
+        let loaded = registry.find("shadowed").unwrap().plugin.path().to_owned();
+        assert_eq!(loaded.parent(), Some(shadow.as_path()));
+        assert_eq!(fs::read(&loaded).unwrap(), fs::read(&original).unwrap());
+        assert!(!stale.exists());
+        // another running host may still have these loaded
+        assert!(others.exists());
+        assert!(unrelated.exists());
+
+        // a copy that fails to load is removed again
+        let sub = dir.join("wrong");
+        library(&sub, "wrong", "v2", "wrong");
+        manifest(&sub, "name = \"right\"\nversion = \"1\"\nhost_abi = 1\n");
+        scan(&mut registry);
+        assert_eq!(registry.problems().len(), 1);
+        let copies: Vec<_> = fs::read_dir(&shadow).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.contains("wrong"))
+            .collect();
+        assert_eq!(copies, Vec::<String>::new());
+    }
+}
@@ -0,0 +1,360 @@
+//! Reloading a plugin when its library is rebuilt, for development.
+//!
+//! A loaded library can't be overwritten on Windows, and loading the same
+//! path again only hands back the library already loaded, so a registry with
+//! a shadow directory loads a uniquely named copy of each library instead.
+//! A `Watch` then polls the original, and once a new build of it has stopped
+//! changing loads a copy of that. The host swaps it in with
+//! `PluginRegistry::replace`, destroying the content the old build created
+//! before dropping it and creating it again with the new one; everything
+//! else the host holds is left as it was.
+//!
+//! ```ignore
+//! match watch.poll(Instant::now()) {
+//!     Reload::Reloaded(found) => {
+//!         destroy_content(&registry);
+//!         drop(registry.replace(found));
+//!         create_content(&registry);
+//!     },
+//!     Reload::Failed(error) => eprintln!("{}", error),
+//!     Reload::Unchanged | Reload::Settling => {},
+//! }
+//! ```
+//!
+//! The original is polled rather than watched for notifications, since the
+//! host polls from a timer on its UI thread anyway and a build writes the
+//! library in several steps that would have to be waited out all the same.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::manifest::Manifest;
+use crate::plugin::PluginError;
+use crate::registry::{load_library, RegisteredPlugin, RegistryError};
+
+/// How long a library has to stay the same before it is reloaded, unless a
+/// `Watch` is told otherwise.
+pub const DEFAULT_SETTLE: Duration = Duration::from_millis(500);
+
+/// What `Watch::poll` found.
+#[derive(Debug)]
+pub enum Reload {
+    /// The library is the one loaded.
+    Unchanged,
+    /// The library changed and is waiting to settle, or is missing.
+    Settling,
+    /// A new build of the library was loaded, to be swapped in for the old.
+    Reloaded(RegisteredPlugin),
+    /// A new build couldn't be loaded. The old one can stay in use; the
+    /// build is retried when the library changes again.
+    Failed(RegistryError),
+}
+
+/// Watches the library a plugin was loaded from.
+#[derive(Debug)]
+pub struct Watch {
+    source: PathBuf,
+    name: String,
+    manifest: Option<Manifest>,
+    shadow: PathBuf,
+    /// How long a change has to go without another before it is reloaded.
+    pub settle: Duration,
+    seen: Option<Stamp>,
+    pending: Option<(Option<Stamp>, Instant)>,
+}
+
+impl Watch {
+    /// Watch the library `found` was loaded from, loading new builds of it
+    /// from copies in `shadow`. `found` itself is taken to be up to date.
+    pub fn new<P: Into<PathBuf>>(found: &RegisteredPlugin, shadow: P) -> Watch {
+        Watch {
+            source: found.source.clone(),
+            name: found.name().to_owned(),
+            manifest: found.manifest.clone(),
+            shadow: shadow.into(),
+            settle: DEFAULT_SETTLE,
+            seen: Stamp::of(&found.source),
+            pending: None,
+        }
+    }
+
+    pub fn settle(mut self, settle: Duration) -> Watch {
+        self.settle = settle;
+        self
+    }
+
+    /// The library being watched.
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    /// The name of the plugin being watched.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Look at the library again, `now` being the time of the call. A
+    /// change is only acted on once it has been seen unchanged for `settle`,
+    /// so a library a build is still writing isn't loaded, and while there is
+    /// no library at all the build is taken to be still going.
+    pub fn poll(&mut self, now: Instant) -> Reload {
+        let stamp = Stamp::of(&self.source);
+        if stamp == self.seen {
+            self.pending = None;
+            return Reload::Unchanged;
+        }
+        if stamp.is_none() {
+            self.pending = None;
+            return Reload::Settling;
+        }
+        match self.pending {
+            Some((pending, since)) if pending == stamp => {
+                if now.saturating_duration_since(since) < self.settle {
+                    return Reload::Settling;
+                }
+            },
+            _ => {
+                self.pending = Some((stamp, now));
+                return Reload::Settling;
+            },
+        }
+
+        // whatever happens this build has been dealt with
+        self.pending = None;
+        self.seen = stamp;
+        match load_library(&self.source, self.manifest.clone(), Some(&self.shadow)) {
+            Ok(found) if found.name() != self.name => {
+                Reload::Failed(RegistryError::Plugin(PluginError::Invalid(self.source.clone(), "was rebuilt under another name")))
+            },
+            Ok(found) => Reload::Reloaded(found),
+            Err(error) => Reload::Failed(error),
+        }
+    }
+}
+
+// What tells one build of a library from the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl Stamp {
+    // `None` while there's no library, as partway through some builds
+    fn of(path: &Path) -> Option<Stamp> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Stamp { modified: metadata.modified().ok()?, len: metadata.len() })
+    }
+}
+
+static COPIES: AtomicUsize = AtomicUsize::new(0);
+
+/// Copy `library` into `dir` under a name no other copy has had, creating
+/// `dir` if need be. Copies are named `<stem>.<process>-<count>.<extension>`.
+pub fn shadow_copy(library: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let (stem, extension) = stem_and_extension(library);
+    let count = COPIES.fetch_add(1, Ordering::Relaxed);
+    let copy = dir.join(format!("{}.{}-{}.{}", stem, process::id(), count, extension));
+    fs::create_dir_all(dir)?;
+    fs::copy(library, &copy)?;
+    Ok(copy)
+}
+
+/// Delete the copies of `library` this process made in `dir` that can be
+/// deleted. Copies that are still loaded can't be on Windows, and are left
+/// for next time; copies made by other processes, which may still be running
+/// them, are left alone.
+pub fn remove_shadow_copies(dir: &Path, library: &Path) {
+    let (stem, extension) = stem_and_extension(library);
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name();
+        if name.to_str().is_some_and(|name| is_copy(name, &stem, &extension, process::id())) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+fn stem_and_extension(library: &Path) -> (String, String) {
+    let part = |part: Option<&OsStr>| part.map(|part| part.to_string_lossy().into_owned()).unwrap_or_default();
+    (part(library.file_stem()), part(library.extension()))
+}
+
+fn is_copy(name: &str, stem: &str, extension: &str, process: u32) -> bool {
+    let middle = name.strip_prefix(stem)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .and_then(|rest| rest.strip_suffix(extension))
+        .and_then(|rest| rest.strip_suffix('.'));
+    match middle.and_then(|middle| middle.split_once('-')) {
+        Some((made_by, count)) => {
+            let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+            made_by == process.to_string() && digits(count)
+        },
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+    use super::*;
+    use crate::fixtures::{self, Probe};
+    use crate::registry::PluginRegistry;
+
+    const SETTLE: Duration = Duration::from_millis(500);
+
+    struct Setup {
+        dir: PathBuf,
+        source: PathBuf,
+        shadow: PathBuf,
+        registry: PluginRegistry,
+    }
+
+    // The v2 fixture named `name` as a plugin in a directory of its own,
+    // loaded from a shadow copy
+    fn setup(test: &str, name: &str) -> Setup {
+        let dir = fixtures::scratch_dir(test);
+        let source = dir.join("plugins").join(format!("{}watched{}", DLL_PREFIX, DLL_SUFFIX));
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::copy(fixtures::plugin("v2", name), &source).unwrap();
+        let shadow = dir.join("shadow");
+        let mut registry = PluginRegistry::new().dir(dir.join("plugins")).shadow(&shadow);
+        registry.scan();
+        assert_eq!(registry.problems(), []);
+        Setup { dir, source, shadow, registry }
+    }
+
+    impl Setup {
+        fn watch(&self) -> Watch {
+            Watch::new(&self.registry.plugins()[0], &self.shadow).settle(SETTLE)
+        }
+
+        // A new build of the library, written in place
+        fn build(&self, variant: &str, name: &str) {
+            fs::copy(fixtures::plugin(variant, name), &self.source).unwrap();
+        }
+    }
+
+    fn reloaded(reload: Reload) -> RegisteredPlugin {
+        match reload {
+            Reload::Reloaded(found) => found,
+            other => panic!("expected a reload, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_unchanged_library_stays_unchanged() {
+        let setup = setup("reload-unchanged", "watched");
+        let mut watch = setup.watch();
+        assert_eq!(watch.source(), setup.source);
+        assert_eq!(watch.name(), "watched");
+        let start = Instant::now();
+        for step in 0..4 {
+            assert!(matches!(watch.poll(start + SETTLE * step), Reload::Unchanged));
+        }
+    }
+
+    #[test]
+    fn a_new_build_is_loaded_once_it_settles() {
+        let setup = setup("reload-settles", "watched");
+        let mut watch = setup.watch();
+        let start = Instant::now();
+
+        setup.build("rebuilt", "watched");
+        assert!(matches!(watch.poll(start), Reload::Settling));
+        assert!(matches!(watch.poll(start + SETTLE / 2), Reload::Settling));
+        // the build writes again, which starts the wait over
+        fs::write(&setup.source, b"half a library").unwrap();
+        assert!(matches!(watch.poll(start + SETTLE / 2), Reload::Settling));
+        setup.build("rebuilt", "watched");
+        let changed = start + SETTLE;
+        assert!(matches!(watch.poll(changed), Reload::Settling));
+        assert!(matches!(watch.poll(changed + SETTLE - Duration::from_millis(1)), Reload::Settling));
+
+        let found = reloaded(watch.poll(changed + SETTLE));
+        assert_eq!(found.name(), "watched");
+        assert_eq!(found.source, setup.source);
+        assert_eq!(found.plugin.path().parent(), Some(setup.shadow.as_path()));
+        assert_ne!(found.plugin.path(), setup.registry.plugins()[0].plugin.path());
+        assert_eq!(Probe::new(found.plugin.path()).build(), 2);
+        assert_eq!(Probe::new(setup.registry.plugins()[0].plugin.path()).build(), 1);
+        assert!(matches!(watch.poll(changed + SETTLE * 2), Reload::Unchanged));
+    }
+
+    #[test]
+    fn a_build_that_fails_to_load_is_reported_once() {
+        let setup = setup("reload-failed", "watched");
+        let mut watch = setup.watch();
+        let start = Instant::now();
+
+        setup.build("declined", "watched");
+        assert!(matches!(watch.poll(start), Reload::Settling));
+        match watch.poll(start + SETTLE) {
+            Reload::Failed(RegistryError::Plugin(PluginError::Declined(path))) => assert_eq!(path, setup.source),
+            other => panic!("expected the build to be declined, got {:?}", other),
+        }
+        // not retried until the library changes again
+        assert!(matches!(watch.poll(start + SETTLE * 2), Reload::Unchanged));
+        // its copy is gone; the one in use is only left where it's locked
+        assert!(fs::read_dir(&setup.shadow).unwrap().count() <= 1);
+
+        setup.build("rebuilt", "watched");
+        let changed = start + SETTLE * 3;
+        assert!(matches!(watch.poll(changed), Reload::Settling));
+        assert_eq!(Probe::new(reloaded(watch.poll(changed + SETTLE)).plugin.path()).build(), 2);
+    }
+
+    #[test]
+    fn a_library_that_disappears_mid_build_is_waited_for() {
+        let setup = setup("reload-disappears", "watched");
+        let mut watch = setup.watch();
+        let start = Instant::now();
+
+        fs::remove_file(&setup.source).unwrap();
+        for step in 0..4 {
+            assert!(matches!(watch.poll(start + SETTLE * step), Reload::Settling));
+        }
+        // the wait for the new build starts once it's there
+        setup.build("rebuilt", "watched");
+        let written = start + SETTLE * 4;
+        assert!(matches!(watch.poll(written), Reload::Settling));
+        assert_eq!(Probe::new(reloaded(watch.poll(written + SETTLE)).plugin.path()).build(), 2);
+    }
+
+    #[test]
+    fn a_build_renamed_into_place_is_loaded() {
+        let setup = setup("reload-renamed", "watched");
+        let mut watch = setup.watch();
+        let start = Instant::now();
+
+        let scratch = setup.dir.join("next.tmp");
+        fs::copy(fixtures::plugin("rebuilt", "watched"), &scratch).unwrap();
+        assert!(matches!(watch.poll(start), Reload::Unchanged));
+        fs::rename(&scratch, &setup.source).unwrap();
+        assert!(matches!(watch.poll(start), Reload::Settling));
+        assert_eq!(Probe::new(reloaded(watch.poll(start + SETTLE)).plugin.path()).build(), 2);
+    }
+
+    #[test]
+    fn a_plugin_rebuilt_under_another_name_is_refused() {
+        let setup = setup("reload-other-name", "watched");
+        let mut watch = setup.watch();
+        let start = Instant::now();
+
+        setup.build("v2", "someone else");
+        assert!(matches!(watch.poll(start), Reload::Settling));
+        match watch.poll(start + SETTLE) {
+            Reload::Failed(error) => assert_eq!(error, RegistryError::Plugin(PluginError::Invalid(setup.source.clone(), "was rebuilt under another name"))),
+            other => panic!("expected the build to be refused, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,656 @@
+//! Running a plugin in a helper process, so a plugin that crashes takes only
+//! the helper down.
+//!
+//! The helper, `plugin_host` in this crate, is started with the plugin's
+//! library as its argument and speaks `protocol` over its standard input and
+//! output; what it writes to standard error goes wherever the host's does.
+//! On Windows the content is still a window, created by the helper and
+//! embedded in the host by handle. A `Supervisor` starts the helper again
+//! when it dies, creating the content the host had again, and gives up if
+//! it keeps dying.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::abi::{Capabilities, LogLevel};
+use crate::isolation::DpiIsolation;
+use crate::protocol::{self, Event, ProtocolError, Request, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
+/// How long the host waits for the helper to answer, unless told otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteError {
+    /// The helper couldn't be started, with the reason.
+    Spawn(PathBuf, String),
+    Protocol(ProtocolError),
+    /// The helper answered with a protocol version this host doesn't speak.
+    Version(u32),
+    /// The helper didn't answer in time; what it was asked.
+    Timeout(&'static str),
+    /// The helper exited, with how and the last error it logged.
+    Exited(String),
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RemoteError::Spawn(path, message) => write!(f, "{}: {}", path.display(), message),
+            RemoteError::Protocol(error) => error.fmt(f),
+            RemoteError::Version(version) => write!(f, "protocol {} is not between {} and {}", version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION),
+            RemoteError::Timeout(what) => write!(f, "no answer to {}", what),
+            RemoteError::Exited(how) => write!(f, "the helper exited: {}", how),
+        }
+    }
+}
+
+impl Error for RemoteError {}
+
+impl From<ProtocolError> for RemoteError {
+    fn from(error: ProtocolError) -> RemoteError {
+        RemoteError::Protocol(error)
+    }
+}
+
+/// A running helper process with a plugin loaded.
+pub struct RemotePlugin {
+    child: Child,
+    requests: BufWriter<ChildStdin>,
+    events: Receiver<Result<Event, ProtocolError>>,
+    version: u32,
+    name: String,
+    capabilities: Capabilities,
+    /// How long to wait for an answer.
+    pub timeout: Duration,
+    logs: Vec<(LogLevel, String)>,
+    relayouts: Vec<u64>,
+    failure: Option<RemoteError>,
+}
+
+impl fmt::Debug for RemotePlugin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RemotePlugin")
+            .field("pid", &self.child.id())
+            .field("version", &self.version)
+            .field("name", &self.name)
+            .field("capabilities", &self.capabilities)
+            .finish()
+    }
+}
+
+impl RemotePlugin {
+    /// Start `helper` for the plugin at `plugin` and wait for it to load it.
+    pub fn spawn(helper: &Path, plugin: &Path, timeout: Duration) -> Result<RemotePlugin, RemoteError> {
+        let mut child = Command::new(helper)
+            .arg(plugin)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| RemoteError::Spawn(helper.to_owned(), e.to_string()))?;
+        let requests = BufWriter::new(child.stdin.take().expect("stdin is piped"));
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        // Events are read on a thread of their own so waiting for them can time out
+        let (sender, events) = mpsc::channel();
+        thread::spawn(move || loop {
+            let event = protocol::receive::<Event, _>(&mut stdout);
+            let done = event.is_err();
+            if sender.send(event).is_err() || done {
+                break;
+            }
+        });
+
+        let mut remote = RemotePlugin {
+            child,
+            requests,
+            events,
+            version: 0,
+            name: String::new(),
+            capabilities: Capabilities::NONE,
+            timeout,
+            logs: Vec::new(),
+            relayouts: Vec::new(),
+            failure: None,
+        };
+        remote.send(&Request::Hello { version: PROTOCOL_VERSION })?;
+        match remote.wait_for("hello", |event| match event {
+            Event::Hello { version, name, capabilities } => Some((*version, name.clone(), *capabilities)),
+            _ => None,
+        })? {
+            (version, _, _) if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) => Err(RemoteError::Version(version)),
+            (version, name, capabilities) => {
+                remote.version = version;
+                remote.name = name;
+                remote.capabilities = capabilities;
+                Ok(remote)
+            },
+        }
+    }
+
+    /// The protocol version the host and helper agreed on.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// The helper's process id.
+    pub fn process_id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Have the plugin create content, returning its window. A plugin that
+    /// can't create it answers with 0.
+    pub fn create(&mut self, id: u64, width: i32, height: i32, isolation: DpiIsolation) -> Result<u64, RemoteError> {
+        self.send(&Request::Create { id, width, height, isolation })?;
+        self.wait_for("create", |event| match *event {
+            Event::Created { id: created, window } if created == id => Some(window),
+            _ => None,
+        })
+    }
+
+    pub fn resize(&mut self, id: u64, width: i32, height: i32) -> Result<(), RemoteError> {
+        self.send(&Request::Resize { id, width, height })
+    }
+
+    pub fn dpi_changed(&mut self, id: u64, dpi: u32) -> Result<(), RemoteError> {
+        self.send(&Request::DpiChanged { id, dpi })
+    }
+
+    /// Have the plugin destroy content and wait until it has.
+    pub fn destroy(&mut self, id: u64) -> Result<(), RemoteError> {
+        self.send(&Request::Destroy { id })?;
+        self.wait_for("destroy", |event| match *event {
+            Event::Destroyed { id: destroyed } if destroyed == id => Some(()),
+            _ => None,
+        })
+    }
+
+    /// Collect what the helper sent since the last call without waiting,
+    /// returning why it stopped if it has.
+    pub fn poll(&mut self) -> Option<&RemoteError> {
+        while self.failure.is_none() {
+            match self.events.try_recv() {
+                Ok(Ok(event)) => self.keep(event),
+                Ok(Err(error)) => self.fail(error),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.fail(ProtocolError::Closed),
+            }
+        }
+        self.failure.as_ref()
+    }
+
+    /// The messages the helper logged since the last call.
+    pub fn take_logs(&mut self) -> Vec<(LogLevel, String)> {
+        self.poll();
+        std::mem::take(&mut self.logs)
+    }
+
+    /// The content the plugin asked to have laid out again since the last
+    /// call, by id.
+    pub fn take_relayouts(&mut self) -> Vec<u64> {
+        self.poll();
+        std::mem::take(&mut self.relayouts)
+    }
+
+    fn send(&mut self, request: &Request) -> Result<(), RemoteError> {
+        if let Some(failure) = &self.failure {
+            return Err(failure.clone());
+        }
+        if let Err(error) = protocol::send(&mut self.requests, request) {
+            self.fail(error);
+            return Err(self.failure.clone().expect("just failed"));
+        }
+        Ok(())
+    }
+
+    // Wait for the event `answer` picks out, keeping the others
+    fn wait_for<T, F>(&mut self, what: &'static str, answer: F) -> Result<T, RemoteError>
+        where F: Fn(&Event) -> Option<T>
+    {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(failure) = &self.failure {
+                return Err(failure.clone());
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(left) {
+                Ok(Ok(event)) => match answer(&event) {
+                    Some(answer) => return Ok(answer),
+                    None => self.keep(event),
+                },
+                Ok(Err(error)) => self.fail(error),
+                Err(RecvTimeoutError::Timeout) => return Err(RemoteError::Timeout(what)),
+                Err(RecvTimeoutError::Disconnected) => self.fail(ProtocolError::Closed),
+            }
+        }
+    }
+
+    fn keep(&mut self, event: Event) {
+        match event {
+            Event::Log { level, message } => self.logs.push((level, message)),
+            Event::Relayout { id } => self.relayouts.push(id),
+            _ => {},
+        }
+    }
+
+    // The channel failing usually means the helper died, which says more
+    fn fail(&mut self, error: ProtocolError) {
+        let failure = match error {
+            ProtocolError::Closed | ProtocolError::Io(_) => {
+                let status = self.exit_status();
+                let last_error = self.logs.iter().rev().find(|(level, _)| *level == LogLevel::Error);
+                match last_error {
+                    Some((_, message)) => RemoteError::Exited(format!("{}, after {}", status, message)),
+                    None => RemoteError::Exited(status),
+                }
+            },
+            error => RemoteError::Protocol(error),
+        };
+        self.failure = Some(failure);
+    }
+}
+
+impl RemotePlugin {
+    // A helper that closed its end of the channel is exiting or of no more
+    // use, so it's killed rather than waited for. Killing one that already
+    // exited changes nothing, and waiting reaps it either way.
+    fn exit_status(&mut self) -> String {
+        if let Ok(Some(status)) = self.child.try_wait() {
+            return status.to_string();
+        }
+        let _ = self.child.kill();
+        match self.child.wait() {
+            Ok(status) => status.to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+}
+
+impl Drop for RemotePlugin {
+    fn drop(&mut self) {
+        // The helper's windows go with it
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// What `Supervisor::poll` found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Supervision {
+    Running,
+    /// The helper died and was started again, and the content recreated:
+    /// the id and new window of each, 0 where the plugin couldn't create it.
+    Restarted { error: RemoteError, windows: Vec<(u64, u64)> },
+    /// The helper died too often, or couldn't be started again. Nothing is
+    /// tried until `start` is called.
+    GaveUp(RemoteError),
+    /// There is no helper, since it gave up or was never started.
+    Stopped,
+}
+
+// What the host asked for, to ask again of a new helper
+#[derive(Debug, Clone, Copy)]
+struct Content {
+    width: i32,
+    height: i32,
+    isolation: DpiIsolation,
+    resized: Option<(i32, i32)>,
+    dpi: Option<u32>,
+}
+
+/// Keeps a plugin's helper running.
+#[derive(Debug)]
+pub struct Supervisor {
+    helper: PathBuf,
+    plugin: PathBuf,
+    pub timeout: Duration,
+    /// The most restarts allowed within `period` before giving up.
+    pub max_restarts: usize,
+    pub period: Duration,
+    remote: Option<RemotePlugin>,
+    contents: BTreeMap<u64, Content>,
+    restarts: VecDeque<Instant>,
+    // logged by helpers that have since died
+    logs: Vec<(LogLevel, String)>,
+}
+
+impl Supervisor {
+    pub fn new<H: Into<PathBuf>, P: Into<PathBuf>>(helper: H, plugin: P) -> Supervisor {
+        Supervisor {
+            helper: helper.into(),
+            plugin: plugin.into(),
+            timeout: DEFAULT_TIMEOUT,
+            max_restarts: 3,
+            period: Duration::from_secs(60),
+            remote: None,
+            contents: BTreeMap::new(),
+            restarts: VecDeque::new(),
+            logs: Vec::new(),
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Supervisor {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn max_restarts(mut self, max_restarts: usize, period: Duration) -> Supervisor {
+        self.max_restarts = max_restarts;
+        self.period = period;
+        self
+    }
+
+    /// Start the helper, if it isn't running. Content the host had before
+    /// the helper gave up is forgotten.
+    pub fn start(&mut self) -> Result<&RemotePlugin, RemoteError> {
+        if self.remote.is_none() {
+            self.contents.clear();
+            self.restarts.clear();
+            self.remote = Some(RemotePlugin::spawn(&self.helper, &self.plugin, self.timeout)?);
+        }
+        Ok(self.remote.as_ref().expect("just started"))
+    }
+
+    /// The helper, while it's running.
+    pub fn remote(&self) -> Option<&RemotePlugin> {
+        self.remote.as_ref()
+    }
+
+    /// Create content in the helper, remembering it so it can be created
+    /// again if the helper dies.
+    pub fn create(&mut self, id: u64, width: i32, height: i32, isolation: DpiIsolation) -> Result<u64, RemoteError> {
+        let window = self.running()?.create(id, width, height, isolation)?;
+        if window != 0 {
+            self.contents.insert(id, Content { width, height, isolation, resized: None, dpi: None });
+        }
+        Ok(window)
+    }
+
+    pub fn resize(&mut self, id: u64, width: i32, height: i32) -> Result<(), RemoteError> {
+        if let Some(content) = self.contents.get_mut(&id) {
+            content.resized = Some((width, height));
+        }
+        self.running()?.resize(id, width, height)
+    }
+
+    pub fn dpi_changed(&mut self, id: u64, dpi: u32) -> Result<(), RemoteError> {
+        if let Some(content) = self.contents.get_mut(&id) {
+            content.dpi = Some(dpi);
+        }
+        self.running()?.dpi_changed(id, dpi)
+    }
+
+    pub fn destroy(&mut self, id: u64) -> Result<(), RemoteError> {
+        self.contents.remove(&id);
+        self.running()?.destroy(id)
+    }
+
+    /// The messages the helper logged since the last call.
+    pub fn take_logs(&mut self) -> Vec<(LogLevel, String)> {
+        let mut logs = std::mem::take(&mut self.logs);
+        if let Some(remote) = &mut self.remote {
+            logs.extend(remote.take_logs());
+        }
+        logs
+    }
+
+    /// The content the plugin asked to have laid out again since the last
+    /// call, by id.
+    pub fn take_relayouts(&mut self) -> Vec<u64> {
+        self.remote.as_mut().map(RemotePlugin::take_relayouts).unwrap_or_default()
+    }
+
+    /// See whether the helper is still running, `now` being the time of the
+    /// call, and start it again if it isn't.
+    pub fn poll(&mut self, now: Instant) -> Supervision {
+        let error = match self.remote.as_mut().map(RemotePlugin::poll) {
+            None => return Supervision::Stopped,
+            Some(None) => return Supervision::Running,
+            Some(Some(error)) => error.clone(),
+        };
+        if let Some(mut dead) = self.remote.take() {
+            self.logs.extend(dead.take_logs());
+        }
+
+        while self.restarts.front().is_some_and(|&restart| now.saturating_duration_since(restart) >= self.period) {
+            self.restarts.pop_front();
+        }
+        if self.restarts.len() >= self.max_restarts {
+            return Supervision::GaveUp(error);
+        }
+        self.restarts.push_back(now);
+
+        let mut remote = match RemotePlugin::spawn(&self.helper, &self.plugin, self.timeout) {
+            Ok(remote) => remote,
+            Err(error) => return Supervision::GaveUp(error),
+        };
+        let mut windows = Vec::new();
+        for (&id, content) in &self.contents {
+            let window = remote.create(id, content.width, content.height, content.isolation).unwrap_or(0);
+            if window != 0 {
+                if let Some((width, height)) = content.resized {
+                    let _ = remote.resize(id, width, height);
+                }
+                if let Some(dpi) = content.dpi {
+                    let _ = remote.dpi_changed(id, dpi);
+                }
+            }
+            windows.push((id, window));
+        }
+        self.contents.retain(|id, _| !windows.contains(&(*id, 0)));
+        self.remote = Some(remote);
+        Supervision::Restarted { error, windows }
+    }
+
+    fn running(&mut self) -> Result<&mut RemotePlugin, RemoteError> {
+        self.remote.as_mut().ok_or_else(|| RemoteError::Exited("it is not running".to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use hidpi::{DpiAwarenessContext, DpiHostingBehavior};
+
+    use super::*;
+    use crate::fixtures;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    // The control file of a stand-in helper, starting out in `mode`
+    fn control(test: &str, mode: &str) -> PathBuf {
+        let control = fixtures::scratch_dir(test).join("control");
+        set_mode(&control, mode);
+        control
+    }
+
+    fn set_mode(control: &Path, mode: &str) {
+        fs::write(control, mode).unwrap();
+    }
+
+    // What the helpers started with `control` were asked, one line each
+    fn asked(control: &Path) -> Vec<String> {
+        let mut log = control.as_os_str().to_owned();
+        log.push(".log");
+        fs::read_to_string(log).unwrap_or_default().lines().map(str::to_owned).collect()
+    }
+
+    fn supervisor(control: &Path) -> Supervisor {
+        Supervisor::new(fixtures::helper(), control).timeout(TIMEOUT)
+    }
+
+    // Poll, `now` being the time, until the helper is found to have stopped
+    fn until_stopped(supervisor: &mut Supervisor, now: Instant) -> Supervision {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            match supervisor.poll(now) {
+                Supervision::Running if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                supervision => return supervision,
+            }
+        }
+    }
+
+    fn exited(code: i32, after: &str) -> RemoteError {
+        let status = if cfg!(windows) { format!("exit code: {}", code) } else { format!("exit status: {}", code) };
+        RemoteError::Exited(format!("{}, after {}", status, after))
+    }
+
+    const ISOLATION: DpiIsolation = DpiIsolation { context: Some(DpiAwarenessContext::System), hosting: Some(DpiHostingBehavior::Mixed) };
+
+    #[test]
+    fn a_remote_plugin_answers_requests() {
+        let control = control("remote-answers", "ok");
+        let mut remote = RemotePlugin::spawn(&fixtures::helper(), &control, TIMEOUT).unwrap();
+        assert_eq!(remote.version(), PROTOCOL_VERSION);
+        assert_eq!(remote.name(), "stand-in");
+        assert_eq!(remote.capabilities(), Capabilities::PER_MONITOR_DPI | Capabilities::MULTIPLE_INSTANCES);
+
+        assert_eq!(remote.create(1, 300, 200, ISOLATION), Ok(1001));
+        remote.resize(1, 450, 300).unwrap();
+        remote.dpi_changed(1, 144).unwrap();
+        remote.destroy(1).unwrap();
+        // the relayout came before the answer to destroy
+        assert_eq!(remote.take_relayouts(), [1]);
+        assert_eq!(remote.poll(), None);
+        assert_eq!(asked(&control), ["start", "hello 2", "create 1 300x200 3/2", "resize 1 450x300", "dpi 1 144", "destroy 1"]);
+    }
+
+    #[test]
+    fn a_helper_that_stops_answering_times_out() {
+        let control = control("remote-hangs", "ok");
+        let mut remote = RemotePlugin::spawn(&fixtures::helper(), &control, Duration::from_millis(200)).unwrap();
+        set_mode(&control, "hang");
+        assert_eq!(remote.create(1, 300, 200, DpiIsolation::NONE), Err(RemoteError::Timeout("create")));
+    }
+
+    #[test]
+    fn a_helper_that_exits_before_the_handshake_fails_to_spawn() {
+        let control = control("remote-no-hello", "no_hello");
+        let started = Instant::now();
+        let error = RemotePlugin::spawn(&fixtures::helper(), &control, TIMEOUT).unwrap_err();
+        let status = if cfg!(windows) { "exit code: 4" } else { "exit status: 4" };
+        assert_eq!(error, RemoteError::Exited(status.to_owned()));
+        // the channel closing is enough, the timeout isn't waited out
+        assert!(started.elapsed() < TIMEOUT);
+
+        let missing = control.with_file_name("missing helper");
+        assert!(matches!(RemotePlugin::spawn(&missing, &control, TIMEOUT), Err(RemoteError::Spawn(path, _)) if path == missing));
+    }
+
+    #[test]
+    fn a_crashed_helper_is_restarted_with_the_content_it_had() {
+        let control = control("remote-restarted", "ok");
+        let mut supervisor = supervisor(&control);
+        assert_eq!(supervisor.poll(Instant::now()), Supervision::Stopped);
+        supervisor.start().unwrap();
+        assert_eq!(supervisor.create(1, 300, 200, ISOLATION), Ok(1001));
+        assert_eq!(supervisor.create(2, 100, 50, DpiIsolation::NONE), Ok(1002));
+        set_mode(&control, "refuse then ok");
+        assert_eq!(supervisor.create(3, 10, 10, DpiIsolation::NONE), Ok(0));
+        supervisor.resize(1, 600, 400).unwrap();
+        supervisor.dpi_changed(2, 144).unwrap();
+        assert_eq!(supervisor.poll(Instant::now()), Supervision::Running);
+
+        set_mode(&control, "exit then ok");
+        let _ = supervisor.dpi_changed(1, 192);
+        let first_pid = supervisor.remote().unwrap().process_id();
+        assert_eq!(until_stopped(&mut supervisor, Instant::now()), Supervision::Restarted {
+            error: exited(3, "crashing"),
+            windows: vec![(1, 1001), (2, 1002)],
+        });
+        assert_ne!(supervisor.remote().unwrap().process_id(), first_pid);
+        assert!(supervisor.take_logs().contains(&(LogLevel::Error, "crashing".to_owned())));
+
+        // the new helper is asked for what the host had, as it last had it;
+        // answering destroy means it has seen everything before
+        assert_eq!(supervisor.poll(Instant::now()), Supervision::Running);
+        supervisor.destroy(1).unwrap();
+        let asked = asked(&control);
+        let restart = asked.iter().rposition(|line| line == "start").unwrap();
+        assert_eq!(asked[restart..], [
+            "start",
+            "hello 2",
+            "create 1 300x200 3/2",
+            "resize 1 600x400",
+            "dpi 1 192",
+            "create 2 100x50 0/0",
+            "dpi 2 144",
+            "destroy 1",
+        ]);
+    }
+
+    #[test]
+    fn a_helper_that_sends_garbage_is_restarted() {
+        let control = control("remote-garbage", "ok");
+        let mut supervisor = supervisor(&control);
+        supervisor.start().unwrap();
+        set_mode(&control, "garbage then ok");
+        supervisor.resize(1, 10, 10).unwrap();
+        assert_eq!(until_stopped(&mut supervisor, Instant::now()), Supervision::Restarted {
+            error: RemoteError::Protocol(ProtocolError::UnknownMessage(99)),
+            windows: vec![],
+        });
+        assert_eq!(supervisor.create(1, 300, 200, DpiIsolation::NONE), Ok(1001));
+    }
+
+    #[test]
+    fn a_helper_that_keeps_dying_is_given_up_on() {
+        let control = control("remote-gives-up", "ok");
+        let mut supervisor = supervisor(&control).max_restarts(1, Duration::from_secs(60));
+        let start = Instant::now();
+        supervisor.start().unwrap();
+
+        set_mode(&control, "exit then ok");
+        let _ = supervisor.resize(1, 10, 10);
+        assert!(matches!(until_stopped(&mut supervisor, start), Supervision::Restarted { .. }));
+        // a minute on the restart has been forgotten
+        set_mode(&control, "exit then ok");
+        let _ = supervisor.resize(1, 10, 10);
+        assert!(matches!(until_stopped(&mut supervisor, start + Duration::from_secs(60)), Supervision::Restarted { .. }));
+        set_mode(&control, "exit then ok");
+        let _ = supervisor.resize(1, 10, 10);
+        assert_eq!(until_stopped(&mut supervisor, start + Duration::from_secs(61)), Supervision::GaveUp(exited(3, "crashing")));
+
+        assert_eq!(supervisor.poll(start + Duration::from_secs(62)), Supervision::Stopped);
+        assert!(supervisor.remote().is_none());
+        assert_eq!(supervisor.resize(1, 10, 10), Err(RemoteError::Exited("it is not running".to_owned())));
+        supervisor.start().unwrap();
+        assert_eq!(supervisor.poll(start + Duration::from_secs(62)), Supervision::Running);
+    }
+
+    #[test]
+    fn a_helper_that_cannot_be_started_again_is_given_up_on() {
+        let control = control("remote-no-restart", "ok");
+        let mut supervisor = supervisor(&control);
+        supervisor.start().unwrap();
+        supervisor.create(1, 300, 200, DpiIsolation::NONE).unwrap();
+
+        set_mode(&control, "exit then no_hello");
+        let _ = supervisor.resize(1, 10, 10);
+        let status = if cfg!(windows) { "exit code: 4" } else { "exit status: 4" };
+        assert_eq!(until_stopped(&mut supervisor, Instant::now()), Supervision::GaveUp(RemoteError::Exited(status.to_owned())));
+        assert_eq!(supervisor.poll(Instant::now()), Supervision::Stopped);
+
+        // starting again forgets the content
+        set_mode(&control, "ok");
+        supervisor.start().unwrap();
+        set_mode(&control, "exit then ok");
+        let _ = supervisor.resize(1, 10, 10);
+        assert!(matches!(until_stopped(&mut supervisor, Instant::now()), Supervision::Restarted { windows, .. } if windows.is_empty()));
+    }
+}
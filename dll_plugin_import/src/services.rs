@@ -0,0 +1,255 @@
+//! Calling back into the host from a plugin written in Rust.
+//!
+//! The plugin's `init` installs the table the host passes it, after which
+//! `Services::get` finds it anywhere in the plugin:
+//!
+//! ```ignore
+//! unsafe extern "C" fn init(host: *const HostServices) {
+//!     Services::install(host);
+//! }
+//!
+//! let dpi = Services::get().map_or(Dpi(get_dpi_for_system()), |services| unsafe { services.current_dpi(ptr::null_mut()) });
+//! ```
+//!
+//! `get` is `None` in a host older than ABI version 2, which has no services
+//! to give. Like the table itself, `Services` may only be used on the thread
+//! the host calls the plugin on. The functions taking content are unsafe,
+//! since the host is free to use the pointer as the content it gave out.
+
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use hidpi::Dpi;
+
+use crate::abi::{HostServices, LogLevel};
+
+// The table of the host that loaded this copy of the library
+static INSTALLED: AtomicPtr<HostServices> = AtomicPtr::new(ptr::null_mut());
+
+/// The host's services, for the plugin to call.
+#[derive(Clone, Copy)]
+pub struct Services {
+    table: &'static HostServices,
+}
+
+impl fmt::Debug for Services {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.table.fmt(f)
+    }
+}
+
+impl Services {
+    /// Keep the table the host passed to the plugin's `init`.
+    ///
+    /// # Safety
+    /// `host` must be what the host passed to `init`, or null.
+    pub unsafe fn install(host: *const HostServices) {
+        INSTALLED.store(host as *mut HostServices, Ordering::Release);
+    }
+
+    /// The installed services, if the host gave the plugin any.
+    pub fn get() -> Option<Services> {
+        let table = INSTALLED.load(Ordering::Acquire);
+        if table.is_null() {
+            None
+        } else {
+            // The host keeps the table until it unloads the library
+            Some(Services { table: unsafe { &*table } })
+        }
+    }
+
+    pub fn log(self, level: LogLevel, message: &str) {
+        let message = c_string(message);
+        unsafe { (self.table.log)(self.table.context, level as u32, message.as_ptr()) }
+    }
+
+    /// The plugin's setting `key`, if the host has one. An empty setting
+    /// reads as none.
+    pub fn setting(self, key: &str) -> Option<String> {
+        let key = c_string(key);
+        let get_setting = |buffer: &mut [u8]| unsafe {
+            let capacity = buffer.len();
+            let buffer = if capacity == 0 { ptr::null_mut() } else { buffer.as_mut_ptr() as *mut c_char };
+            (self.table.get_setting)(self.table.context, key.as_ptr(), buffer, capacity)
+        };
+        let capacity = get_setting(&mut []);
+        if capacity == 0 {
+            return None;
+        }
+        let mut buffer = vec![0; capacity];
+        match get_setting(&mut buffer) {
+            // a size that's changed since means the setting has too
+            written if written == 0 || written >= capacity => None,
+            written => {
+                buffer.truncate(written);
+                String::from_utf8(buffer).ok()
+            },
+        }
+    }
+
+    /// Set the plugin's setting `key`, returning false if the host refused.
+    pub fn set_setting(self, key: &str, value: &str) -> bool {
+        let (key, value) = (c_string(key), c_string(value));
+        unsafe { (self.table.set_setting)(self.table.context, key.as_ptr(), value.as_ptr()) != 0 }
+    }
+
+    /// The DPI `content` is shown at, or with null inside `create_content`
+    /// the DPI of the content being created, as the content's awareness sees
+    /// it.
+    ///
+    /// # Safety
+    /// `content` must be null or content the plugin created and hasn't
+    /// destroyed.
+    pub unsafe fn current_dpi(self, content: *mut c_void) -> Dpi {
+        Dpi((self.table.current_dpi)(self.table.context, content))
+    }
+
+    /// Call `f` with the new DPI whenever the DPI of `content` changes, until
+    /// the subscription is dropped. Drop it before destroying the content.
+    ///
+    /// # Safety
+    /// `content` must be content the plugin created and hasn't destroyed.
+    pub unsafe fn on_dpi_changed<F>(self, content: *mut c_void, f: F) -> DpiSubscription where F: FnMut(Dpi) + 'static {
+        let callback: *mut Box<dyn FnMut(Dpi)> = Box::into_raw(Box::new(Box::new(f)));
+        let id = (self.table.subscribe_dpi_changed)(self.table.context, content, dpi_changed, callback as *mut c_void);
+        DpiSubscription { services: self, id, callback }
+    }
+
+    /// Ask the host to lay out the window `content` is in again, once the
+    /// plugin has returned to it.
+    ///
+    /// # Safety
+    /// As `on_dpi_changed`.
+    pub unsafe fn request_relayout(self, content: *mut c_void) {
+        (self.table.request_relayout)(self.table.context, content)
+    }
+}
+
+/// Calls a closure while the DPI of some content changes, see
+/// `Services::on_dpi_changed`.
+pub struct DpiSubscription {
+    services: Services,
+    id: u64,
+    callback: *mut Box<dyn FnMut(Dpi)>,
+}
+
+impl fmt::Debug for DpiSubscription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DpiSubscription").field("id", &self.id).finish()
+    }
+}
+
+impl Drop for DpiSubscription {
+    fn drop(&mut self) {
+        let table = self.services.table;
+        unsafe {
+            (table.unsubscribe_dpi_changed)(table.context, self.id);
+            drop(Box::from_raw(self.callback));
+        }
+    }
+}
+
+// A panic can't unwind into the host
+unsafe extern "C" fn dpi_changed(user: *mut c_void, _content: *mut c_void, dpi: u32) {
+    let callback = &mut *(user as *mut Box<dyn FnMut(Dpi)>);
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| callback(Dpi(dpi))));
+}
+
+// Strings stop at the first NUL, as C would read them
+fn c_string(s: &str) -> CString {
+    let s = s.split('\0').next().unwrap_or_default();
+    CString::new(s).expect("split at the first NUL")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::fixtures::TestHost;
+    use crate::host::HostTable;
+
+    fn services(host: TestHost) -> (Rc<TestHost>, Box<HostTable>, Services) {
+        let host = Rc::new(host);
+        let table = HostTable::new("plugin", host.clone());
+        // the table outlives every use of the services in these tests
+        let services = Services { table: unsafe { &*table.services() } };
+        (host, table, services)
+    }
+
+    #[test]
+    fn only_an_installed_table_is_found() {
+        let (_, table, _) = services(TestHost::new());
+        assert!(Services::get().is_none());
+        unsafe { Services::install(table.services()) };
+        assert!(Services::get().is_some_and(|services| ptr::eq(services.table, table.services())));
+        unsafe { Services::install(ptr::null()) };
+        assert!(Services::get().is_none());
+    }
+
+    #[test]
+    fn settings_are_read_and_written() {
+        let long = "ü".repeat(1000);
+        let (host, _table, services) = services(TestHost::new()
+            .with_setting("plugin", "greeting", "hello")
+            .with_setting("plugin", "long", &long)
+            .with_setting("plugin", "empty", ""));
+        assert_eq!(services.setting("greeting").as_deref(), Some("hello"));
+        assert_eq!(services.setting("long"), Some(long));
+        assert_eq!(services.setting("empty"), None);
+        assert_eq!(services.setting("missing"), None);
+
+        assert!(services.set_setting("greeting", "bye"));
+        assert_eq!(services.setting("greeting").as_deref(), Some("bye"));
+        // strings stop at a NUL, as they would for a plugin written in C
+        assert!(services.set_setting("cut\0off", "short\0ened"));
+        assert_eq!(services.setting("cut").as_deref(), Some("short"));
+        host.refuse.set(true);
+        assert!(!services.set_setting("greeting", "again"));
+        assert_eq!(services.setting("greeting").as_deref(), Some("bye"));
+    }
+
+    #[test]
+    fn calls_about_content_reach_the_host() {
+        let (host, _table, services) = services(TestHost::new());
+        host.dpi.set(120);
+        let content = 0x40 as *mut c_void;
+        services.log(LogLevel::Error, "broken");
+        assert_eq!(unsafe { services.current_dpi(content) }, Dpi(120));
+        unsafe { services.request_relayout(content) };
+        assert_eq!(host.take_calls(), [
+            "plugin log error broken".to_owned(),
+            format!("current_dpi {:?}", content),
+            format!("relayout {:?}", content),
+        ]);
+    }
+
+    #[test]
+    fn dpi_changes_are_passed_on_until_the_subscription_is_dropped() {
+        let (_, table, services) = services(TestHost::new());
+        let content = 0x40 as *mut c_void;
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let panicking = unsafe { services.on_dpi_changed(content, |_| panic!("the plugin fell over")) };
+        let subscription = {
+            let seen = seen.clone();
+            unsafe { services.on_dpi_changed(content, move |dpi| seen.borrow_mut().push(dpi)) }
+        };
+
+        table.dpi_changed(content, 144);
+        table.dpi_changed(0x80 as *mut c_void, 192);
+        // the panic stayed in the plugin, and the callbacks after it are called
+        assert_eq!(*seen.borrow(), [Dpi(144)]);
+
+        drop(panicking);
+        drop(subscription);
+        table.dpi_changed(content, 96);
+        assert_eq!(*seen.borrow(), [Dpi(144)]);
+        // the closure went with the subscription
+        assert_eq!(Rc::strong_count(&seen), 1);
+    }
+}
@@ -0,0 +1,159 @@
+// A stand-in for plugin_host for the tests of dll_plugin_import's
+// Supervisor, built as a program by src/fixtures.rs. Instead of a plugin
+// library its argument is a control file holding a mode, read again before
+// each request is answered, and it appends what it is asked to the file
+// with .log added to its name. The protocol is written out here from its
+// documentation.
+//
+// Modes:
+//   ok         answers like plugin_host with a plugin called "stand-in",
+//              content id's window being 1000 + id, and asks for a relayout
+//              of content whose DPI changed
+//   refuse     answers creates with window 0
+//   exit       logs an error and exits with status 3
+//   hang       stops answering
+//   garbage    answers with a message of an unknown kind
+//   no_hello   exits with status 4 before the handshake
+//
+// "<mode> then <next>" acts as mode once, setting the mode to next as it does.
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+struct Control {
+    path: String,
+}
+
+impl Control {
+    fn mode(&self) -> String {
+        let mode = fs::read_to_string(&self.path).unwrap_or_default().trim().to_owned();
+        match mode.split_once(" then ") {
+            Some((now, next)) => {
+                self.set_mode(next);
+                now.to_owned()
+            },
+            None => mode,
+        }
+    }
+
+    fn set_mode(&self, mode: &str) {
+        fs::write(&self.path, mode).unwrap();
+    }
+
+    fn log(&self, line: String) {
+        let mut log = OpenOptions::new().create(true).append(true).open(format!("{}.log", self.path)).unwrap();
+        writeln!(log, "{}", line).unwrap();
+    }
+}
+
+fn read_frame(input: &mut impl Read) -> Option<Vec<u8>> {
+    let mut len = [0; 4];
+    input.read_exact(&mut len).ok()?;
+    let mut payload = vec![0; u32::from_le_bytes(len) as usize];
+    input.read_exact(&mut payload).ok()?;
+    Some(payload)
+}
+
+fn write_frame(output: &mut impl Write, payload: &[u8]) {
+    output.write_all(&(payload.len() as u32).to_le_bytes()).unwrap();
+    output.write_all(payload).unwrap();
+    output.flush().unwrap();
+}
+
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(&self.0[..N]);
+        self.0 = &self.0[N..];
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    fn i32(&mut self) -> i32 {
+        i32::from_le_bytes(self.take())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+}
+
+fn event(tag: u8, fields: &[&[u8]]) -> Vec<u8> {
+    let mut payload = vec![tag];
+    for field in fields {
+        payload.extend_from_slice(field);
+    }
+    payload
+}
+
+fn string(s: &str) -> Vec<u8> {
+    let mut bytes = (s.len() as u32).to_le_bytes().to_vec();
+    bytes.extend_from_slice(s.as_bytes());
+    bytes
+}
+
+fn main() {
+    let control = Control { path: env::args().nth(1).expect("a control file") };
+    let mut input = io::stdin();
+    let mut output = io::stdout();
+    control.log("start".to_owned());
+    if control.mode() == "no_hello" {
+        process::exit(4);
+    }
+
+    let hello = read_frame(&mut input).expect("hello");
+    let mut fields = Fields(&hello);
+    assert_eq!(fields.u8(), 1);
+    let version = fields.u32().min(2);
+    control.log(format!("hello {}", version));
+    write_frame(&mut output, &event(1, &[&version.to_le_bytes(), &string("stand-in"), &3u32.to_le_bytes()]));
+
+    while let Some(request) = read_frame(&mut input) {
+        let mut fields = Fields(&request);
+        let tag = fields.u8();
+        let line = match tag {
+            2 => format!("create {} {}x{} {}/{}", fields.u64(), fields.i32(), fields.i32(), fields.u8(), fields.u8()),
+            3 => format!("resize {} {}x{}", fields.u64(), fields.i32(), fields.i32()),
+            4 => format!("dpi {} {}", fields.u64(), fields.u32()),
+            5 => format!("destroy {}", fields.u64()),
+            tag => format!("unknown {}", tag),
+        };
+        control.log(line);
+        let mut fields = Fields(&request[1..]);
+        let id = fields.u64();
+
+        match control.mode().as_str() {
+            "exit" => {
+                write_frame(&mut output, &event(4, &[&[0], &string("crashing")]));
+                process::exit(3);
+            },
+            "hang" => loop {
+                thread::sleep(Duration::from_secs(60));
+            },
+            "garbage" => write_frame(&mut output, &[99]),
+            mode => match tag {
+                2 => {
+                    let window: u64 = if mode == "refuse" { 0 } else { 1000 + id };
+                    write_frame(&mut output, &event(2, &[&id.to_le_bytes(), &window.to_le_bytes()]));
+                },
+                4 => write_frame(&mut output, &event(5, &[&id.to_le_bytes()])),
+                5 => write_frame(&mut output, &event(3, &[&id.to_le_bytes()])),
+                _ => {},
+            },
+        }
+    }
+    control.log("closed".to_owned());
+}
@@ -0,0 +1,230 @@
+// A plugin for the tests of dll_plugin_import, built as a cdylib by
+// src/fixtures.rs with `--cfg fixture="<variant>"` and its name in
+// FIXTURE_NAME. The ABI is declared here from its documentation, the way a
+// plugin written in another language would have to.
+//
+// Variants:
+//   v2          the default: ABI 2, or a v1 view for a version 1 host
+//   rebuilt     v2 with changes, as the next build of it
+//   v1          ABI 1 only
+//   declined    returns null
+//   too_old     ABI 0
+//   too_new     ABI 3
+//   short       ABI 2 with the size of a version 1 vtable
+//   no_name     a null name
+//   bad_name    a name that isn't UTF-8
+//   no_create   no create_content
+//   no_destroy  no destroy_content
+//   no_entry    exports other_entry instead of plugin_entry
+//
+// Given the host's services, the plugin logs what it does to its content at
+// LogLevel::Info. Creating content it logs the DPI and the setting greeting
+// the host has for it and sets last_created to the size, logging if the host
+// refuses, and subscribes to the content's DPI changes, logging them and
+// asking for a relayout.
+
+#![allow(dead_code)]
+
+use std::mem;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+
+type CreateContent = unsafe extern "C" fn(h_instance: *mut c_void, width: c_int, height: c_int) -> *mut c_void;
+type DestroyContent = unsafe extern "C" fn(content: *mut c_void);
+type PluginInit = unsafe extern "C" fn(host: *const HostServices);
+type DpiChangedCallback = unsafe extern "C" fn(user: *mut c_void, content: *mut c_void, dpi: u32);
+
+#[repr(C)]
+pub struct PluginVTable {
+    abi_version: u32,
+    size: u32,
+    name: *const c_char,
+    capabilities: u32,
+    create_content: Option<CreateContent>,
+    destroy_content: Option<DestroyContent>,
+    init: Option<PluginInit>,
+}
+
+unsafe impl Sync for PluginVTable {}
+
+#[repr(C)]
+pub struct HostServices {
+    size: u32,
+    context: *mut c_void,
+    log: unsafe extern "C" fn(context: *mut c_void, level: u32, message: *const c_char),
+    get_setting: unsafe extern "C" fn(context: *mut c_void, key: *const c_char, buffer: *mut c_char, capacity: usize) -> usize,
+    set_setting: unsafe extern "C" fn(context: *mut c_void, key: *const c_char, value: *const c_char) -> c_int,
+    current_dpi: unsafe extern "C" fn(context: *mut c_void, content: *mut c_void) -> u32,
+    subscribe_dpi_changed: unsafe extern "C" fn(context: *mut c_void, content: *mut c_void, callback: DpiChangedCallback, user: *mut c_void) -> u64,
+    unsubscribe_dpi_changed: unsafe extern "C" fn(context: *mut c_void, subscription: u64),
+    request_relayout: unsafe extern "C" fn(context: *mut c_void, content: *mut c_void),
+}
+
+const INFO: u32 = 2;
+
+const V1_SIZE: u32 = (mem::size_of::<PluginVTable>() - mem::size_of::<Option<PluginInit>>()) as u32;
+const V2_SIZE: u32 = mem::size_of::<PluginVTable>() as u32;
+
+// PER_MONITOR_DPI | MULTIPLE_INSTANCES, and a bit no host knows
+const CAPABILITIES: u32 = 1 | 2 | 1 << 31;
+
+const NAME: *const c_char = concat!(env!("FIXTURE_NAME"), "\0").as_ptr() as *const c_char;
+
+static HOST_ABI_VERSION: AtomicU32 = AtomicU32::new(0);
+static LIVE_CONTENT: AtomicUsize = AtomicUsize::new(0);
+static INITS: AtomicUsize = AtomicUsize::new(0);
+static HOST: AtomicPtr<HostServices> = AtomicPtr::new(ptr::null_mut());
+
+struct Content {
+    width: c_int,
+    height: c_int,
+    subscription: u64,
+}
+
+fn host() -> Option<&'static HostServices> {
+    unsafe { HOST.load(Ordering::SeqCst).as_ref() }
+}
+
+fn log(host: &HostServices, message: String) {
+    let message = format!("{}\0", message);
+    unsafe { (host.log)(host.context, INFO, message.as_ptr() as *const c_char) };
+}
+
+// Asking for the size first, then reading it into a buffer that size
+unsafe fn setting(host: &HostServices, key: &str) -> Option<String> {
+    let key = format!("{}\0", key);
+    let key = key.as_ptr() as *const c_char;
+    let capacity = (host.get_setting)(host.context, key, ptr::null_mut(), 0);
+    if capacity == 0 {
+        return None;
+    }
+    let mut buffer = vec![0u8; capacity];
+    let written = (host.get_setting)(host.context, key, buffer.as_mut_ptr() as *mut c_char, capacity);
+    assert!(written < capacity && buffer[written] == 0, "get_setting wrote {} bytes into {}", written, capacity);
+    buffer.truncate(written);
+    Some(String::from_utf8(buffer).unwrap())
+}
+
+unsafe fn set_setting(host: &HostServices, key: &str, value: &str) -> bool {
+    let (key, value) = (format!("{}\0", key), format!("{}\0", value));
+    (host.set_setting)(host.context, key.as_ptr() as *const c_char, value.as_ptr() as *const c_char) != 0
+}
+
+unsafe extern "C" fn dpi_changed(_user: *mut c_void, content: *mut c_void, dpi: u32) {
+    if let Some(host) = host() {
+        let size = &*(content as *const Content);
+        log(host, format!("dpi of {}x{} changed to {}", size.width, size.height, dpi));
+        (host.request_relayout)(host.context, content);
+    }
+}
+
+unsafe extern "C" fn create_content(_h_instance: *mut c_void, width: c_int, height: c_int) -> *mut c_void {
+    if width < 0 || height < 0 {
+        return ptr::null_mut();
+    }
+    LIVE_CONTENT.fetch_add(1, Ordering::SeqCst);
+    let content = Box::into_raw(Box::new(Content { width, height, subscription: 0 }));
+    if let Some(host) = host() {
+        let dpi = (host.current_dpi)(host.context, ptr::null_mut());
+        let greeting = setting(host, "greeting").unwrap_or_else(|| "none".to_owned());
+        log(host, format!("create {}x{} at {} dpi, greeting {}", width, height, dpi, greeting));
+        if !set_setting(host, "last_created", &format!("{}x{}", width, height)) {
+            log(host, "last_created refused".to_owned());
+        }
+        (*content).subscription = (host.subscribe_dpi_changed)(host.context, content as *mut c_void, dpi_changed, ptr::null_mut());
+    }
+    content as *mut c_void
+}
+
+unsafe extern "C" fn destroy_content(content: *mut c_void) {
+    let content = Box::from_raw(content as *mut Content);
+    if let Some(host) = host() {
+        log(host, format!("destroy {}x{}", content.width, content.height));
+        (host.unsubscribe_dpi_changed)(host.context, content.subscription);
+    }
+    LIVE_CONTENT.fetch_sub(1, Ordering::SeqCst);
+}
+
+unsafe extern "C" fn init(host: *const HostServices) {
+    INITS.fetch_add(1, Ordering::SeqCst);
+    HOST.store(host as *mut HostServices, Ordering::SeqCst);
+}
+
+static V2: PluginVTable = PluginVTable {
+    abi_version: if cfg!(fixture = "too_old") { 0 } else if cfg!(fixture = "too_new") { 3 } else { 2 },
+    size: if cfg!(fixture = "short") { V1_SIZE } else if cfg!(fixture = "too_new") { V2_SIZE + 8 } else { V2_SIZE },
+    name: if cfg!(fixture = "no_name") {
+        ptr::null()
+    } else if cfg!(fixture = "bad_name") {
+        b"caf\xe9\0".as_ptr() as *const c_char
+    } else {
+        NAME
+    },
+    capabilities: CAPABILITIES,
+    create_content: if cfg!(fixture = "no_create") { None } else { Some(create_content) },
+    destroy_content: if cfg!(fixture = "no_destroy") { None } else { Some(destroy_content) },
+    init: Some(init),
+};
+
+static V1: PluginVTable = PluginVTable {
+    abi_version: 1,
+    size: V1_SIZE,
+    init: None,
+    ..V2
+};
+
+fn entry(host_abi_version: u32) -> *const PluginVTable {
+    HOST_ABI_VERSION.store(host_abi_version, Ordering::SeqCst);
+    if cfg!(fixture = "declined") || host_abi_version == 0 {
+        ptr::null()
+    } else if cfg!(fixture = "v1") || host_abi_version == 1 {
+        &V1
+    } else {
+        &V2
+    }
+}
+
+#[cfg(not(fixture = "no_entry"))]
+#[no_mangle]
+pub extern "C" fn plugin_entry(host_abi_version: u32) -> *const PluginVTable {
+    entry(host_abi_version)
+}
+
+#[cfg(fixture = "no_entry")]
+#[no_mangle]
+pub extern "C" fn other_entry(host_abi_version: u32) -> *const PluginVTable {
+    entry(host_abi_version)
+}
+
+/// The version the host passed to the entry point last.
+#[no_mangle]
+pub extern "C" fn fixture_host_abi_version() -> u32 {
+    HOST_ABI_VERSION.load(Ordering::SeqCst)
+}
+
+/// Which build of the plugin this is: 2 for rebuilt, 1 for the others.
+#[no_mangle]
+pub extern "C" fn fixture_build() -> u32 {
+    if cfg!(fixture = "rebuilt") { 2 } else { 1 }
+}
+
+/// Content created and not yet destroyed.
+#[no_mangle]
+pub extern "C" fn fixture_live_content() -> usize {
+    LIVE_CONTENT.load(Ordering::SeqCst)
+}
+
+/// Calls to init.
+#[no_mangle]
+pub extern "C" fn fixture_inits() -> usize {
+    INITS.load(Ordering::SeqCst)
+}
+
+/// The size `content` was created at.
+#[no_mangle]
+pub unsafe extern "C" fn fixture_content_size(content: *mut c_void, width: *mut c_int, height: *mut c_int) {
+    let content = &*(content as *const Content);
+    *width = content.width;
+    *height = content.height;
+}
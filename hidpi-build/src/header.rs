@@ -0,0 +1,611 @@
+//! Rust constants generated from the `#define`s of a Visual Studio
+//! `resource.h`, so that the ids used by the code always match the ids
+//! compiled into the resources.
+//!
+//! Values may be decimal, hex or octal literals with the usual `L`/`U`
+//! suffixes, and constant expressions over literals and earlier defines:
+//!
+//! ```text
+//! #define IDC_FIRST        0x3E8
+//! #define IDC_SECOND       (IDC_FIRST + 1)
+//! ```
+//!
+//! Defines inside `#if`/`#ifdef`/`#ifndef` blocks, such as the
+//! `APSTUDIO_INVOKED` bookkeeping, are skipped because their conditions are
+//! not evaluated. So are function-like macros and defines whose value is not
+//! an integer expression (strings, or symbols from other headers); those are
+//! listed in `Header::skipped`.
+//!
+//! A second name for an id is written as the first name, and is not a
+//! collision:
+//!
+//! ```text
+//! #define IDC_BUTTON1         1000
+//! #define IDC_BUTTON_UNAWARE  IDC_BUTTON1
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Write};
+
+/// A `#define NAME value` with its value evaluated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Define {
+    pub name: String,
+    pub value: i64,
+    /// 1-based.
+    pub line: usize,
+    /// The earlier define this one is another name for, when its value is
+    /// just that name.
+    pub alias_of: Option<String>,
+}
+
+/// A `#define` that produced no constant, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    pub name: String,
+    pub line: usize,
+    pub reason: String,
+}
+
+/// Several defines of one category with the same value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub category: String,
+    pub value: i64,
+    pub names: Vec<String>,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} share the {} value {}", self.names.join(", "), self.category, self.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for HeaderError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Header {
+    pub defines: Vec<Define>,
+    pub skipped: Vec<Skipped>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(&'static str),
+}
+
+// Longest first, so that `<<` is not read as two `<`
+const OPERATORS: &[&str] = &[
+    "<<", ">>", "<=", ">=", "==", "!=", "||", "&&",
+    "(", ")", "+", "-", "*", "/", "%", "|", "&", "^", "~", "!", "<", ">",
+];
+
+fn parse_number(literal: &str) -> Result<i64, String> {
+    let digits = literal.trim_end_matches(&['u', 'U', 'l', 'L'][..]);
+    let (digits, radix) = if digits.starts_with("0x") || digits.starts_with("0X") {
+        (&digits[2..], 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        (&digits[1..], 8)
+    } else {
+        (digits, 10)
+    };
+
+    // unsigned values such as 0xFFFFFFFF are kept as their 64 bit value
+    u64::from_str_radix(digits, radix)
+        .map(|value| value as i64)
+        .map_err(|_| format!("invalid number `{}`", literal))
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..len])?));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_owned()));
+            len
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(format!("unexpected `{}`", c));
+        };
+        rest = rest[len..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+// Precedence climbing over the C operators that show up in resource headers
+struct Evaluator<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    known: &'a BTreeMap<String, i64>,
+    // Set when evaluating an `#if`, where `defined` is an operator and
+    // unknown names are 0
+    defined: Option<&'a dyn Fn(&str) -> bool>,
+}
+
+fn binary_precedence(op: &str) -> Option<u8> {
+    match op {
+        "||" => Some(1),
+        "&&" => Some(2),
+        "|" => Some(3),
+        "^" => Some(4),
+        "&" => Some(5),
+        "==" | "!=" => Some(6),
+        "<" | ">" | "<=" | ">=" => Some(7),
+        "<<" | ">>" => Some(8),
+        "+" | "-" => Some(9),
+        "*" | "/" | "%" => Some(10),
+        _ => None,
+    }
+}
+
+impl<'a> Evaluator<'a> {
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(*value),
+            Some(Token::Ident(name)) if name == "defined" && self.defined.is_some() => {
+                let parenthesized = self.peek_op() == Some("(");
+                if parenthesized {
+                    self.pos += 1;
+                }
+                let value = match self.next() {
+                    Some(Token::Ident(name)) => (self.defined.unwrap())(name) as i64,
+                    _ => return Err("`defined` without a name".to_owned()),
+                };
+                if parenthesized && self.next() != Some(&Token::Op(")")) {
+                    return Err("missing `)`".to_owned());
+                }
+                Ok(value)
+            },
+            Some(Token::Ident(name)) => match self.known.get(name) {
+                Some(value) => Ok(*value),
+                None if self.defined.is_some() => Ok(0),
+                None => Err(format!("`{}` is not defined in this header", name)),
+            },
+            Some(Token::Op("(")) => {
+                let value = self.expression(0)?;
+                match self.next() {
+                    Some(Token::Op(")")) => Ok(value),
+                    _ => Err("missing `)`".to_owned()),
+                }
+            },
+            Some(Token::Op("-")) => Ok(self.primary()?.wrapping_neg()),
+            Some(Token::Op("+")) => self.primary(),
+            Some(Token::Op("~")) => Ok(!self.primary()?),
+            Some(Token::Op("!")) => Ok((self.primary()? == 0) as i64),
+            Some(Token::Op(op)) => Err(format!("unexpected `{}`", op)),
+            None => Err("expression ends early".to_owned()),
+        }
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> Result<i64, String> {
+        let mut lhs = self.primary()?;
+
+        while let Some(op) = self.peek_op() {
+            let precedence = match binary_precedence(op) {
+                Some(precedence) if precedence > min_precedence => precedence,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.expression(precedence)?;
+
+            lhs = match op {
+                "||" => (lhs != 0 || rhs != 0) as i64,
+                "&&" => (lhs != 0 && rhs != 0) as i64,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err("division by zero".to_owned()),
+                "/" => lhs.wrapping_div(rhs),
+                _ => lhs.wrapping_rem(rhs),
+            };
+        }
+
+        Ok(lhs)
+    }
+}
+
+/// Evaluate a constant expression, with `known` supplying the defines it may refer to.
+pub fn evaluate(expression: &str, known: &BTreeMap<String, i64>) -> Result<i64, String> {
+    evaluate_with(expression, known, None)
+}
+
+/// Evaluate the condition of an `#if` or `#elif`. `defined(NAME)` asks
+/// `is_defined`, and names without a value in `known` are 0, as in C.
+pub fn evaluate_condition(expression: &str, known: &BTreeMap<String, i64>, is_defined: &dyn Fn(&str) -> bool) -> Result<bool, String> {
+    evaluate_with(expression, known, Some(is_defined)).map(|value| value != 0)
+}
+
+fn evaluate_with(expression: &str, known: &BTreeMap<String, i64>, defined: Option<&dyn Fn(&str) -> bool>) -> Result<i64, String> {
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Err("no value".to_owned());
+    }
+
+    let mut evaluator = Evaluator { tokens: &tokens, pos: 0, known, defined };
+    let value = evaluator.expression(0)?;
+    if evaluator.pos < tokens.len() {
+        return Err("unexpected tokens after the value".to_owned());
+    }
+    Ok(value)
+}
+
+// Removes comments, keeping track of `/* */` comments spanning lines
+fn strip_comments(line: &str, in_comment: &mut bool) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+
+    loop {
+        if *in_comment {
+            match rest.find("*/") {
+                Some(end) => {
+                    rest = &rest[end + 2..];
+                    *in_comment = false;
+                    out.push(' ');
+                },
+                None => return out,
+            }
+        }
+
+        let line_comment = rest.find("//");
+        let block_comment = rest.find("/*");
+        match (line_comment, block_comment) {
+            (Some(l), Some(b)) if l < b => {
+                out.push_str(&rest[..l]);
+                return out;
+            },
+            (Some(l), None) => {
+                out.push_str(&rest[..l]);
+                return out;
+            },
+            (_, Some(b)) => {
+                out.push_str(&rest[..b]);
+                rest = &rest[b + 2..];
+                *in_comment = true;
+            },
+            (None, None) => {
+                out.push_str(rest);
+                return out;
+            },
+        }
+    }
+}
+
+/// Parse the `#define`s of a resource header.
+///
+/// A define that redefines an earlier name, or a conditional block that is
+/// never closed, is an error; anything that is merely not a constant is skipped.
+pub fn parse(text: &str) -> Result<Header, HeaderError> {
+    let mut header = Header::default();
+    let mut known = BTreeMap::new();
+    let mut depth = 0usize;
+    let mut in_comment = false;
+
+    let mut lines = text.lines().enumerate();
+    while let Some((index, raw)) = lines.next() {
+        let line_number = index + 1;
+        let mut line = strip_comments(raw, &mut in_comment);
+
+        // Line continuations
+        while line.trim_end().ends_with('\\') {
+            let trimmed = line.trim_end().len() - 1;
+            line.truncate(trimmed);
+            match lines.next() {
+                Some((_, next)) => line.push_str(&strip_comments(next, &mut in_comment)),
+                None => break,
+            }
+        }
+
+        let line = line.trim_start();
+        if !line.starts_with('#') {
+            continue;
+        }
+        let directive = line[1..].trim_start();
+        let keyword_len = directive.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(directive.len());
+        let (keyword, rest) = directive.split_at(keyword_len);
+
+        match keyword {
+            "if" | "ifdef" | "ifndef" => depth += 1,
+            "endif" => {
+                depth = depth.checked_sub(1).ok_or_else(|| HeaderError {
+                    line: line_number,
+                    message: "#endif without #if".to_owned(),
+                })?;
+            },
+            "define" if depth == 0 => {
+                let rest = rest.trim_start();
+                let name_len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                let (name, value) = rest.split_at(name_len);
+                if name.is_empty() {
+                    return Err(HeaderError { line: line_number, message: "#define without a name".to_owned() });
+                }
+
+                let skip = |reason: String| Skipped { name: name.to_owned(), line: line_number, reason };
+                if value.starts_with('(') {
+                    header.skipped.push(skip("function-like macro".to_owned()));
+                    continue;
+                }
+
+                let alias_of = match tokenize(value).as_deref() {
+                    Ok([Token::Ident(other)]) if known.contains_key(other) => Some(other.clone()),
+                    _ => None,
+                };
+                match evaluate(value, &known) {
+                    Ok(value) => {
+                        if known.insert(name.to_owned(), value).is_some() {
+                            return Err(HeaderError { line: line_number, message: format!("`{}` is defined twice", name) });
+                        }
+                        header.defines.push(Define { name: name.to_owned(), value, line: line_number, alias_of });
+                    },
+                    Err(reason) => header.skipped.push(skip(reason)),
+                }
+            },
+            _ => {},
+        }
+    }
+
+    if depth != 0 {
+        return Err(HeaderError { line: text.lines().count(), message: "unterminated #if block".to_owned() });
+    }
+
+    Ok(header)
+}
+
+/// The category of a resource id: its prefix up to and including the first
+/// underscore, e.g. `IDC_` for controls, `IDM_` for menu items and `ID_` for
+/// commands. Ids of different categories live in different namespaces and
+/// are free to share values.
+pub fn category(name: &str) -> &str {
+    match name.find('_') {
+        Some(underscore) => &name[..=underscore],
+        None => name,
+    }
+}
+
+// The values a Rust or Windows integer type can hold, for the types ids are
+// usually given
+fn type_range(ty: &str) -> Option<(i64, i64)> {
+    match ty {
+        "i8" | "CHAR" => Some((i8::MIN as i64, i8::MAX as i64)),
+        "u8" | "BYTE" => Some((0, u8::MAX as i64)),
+        "i16" | "SHORT" => Some((i16::MIN as i64, i16::MAX as i64)),
+        "u16" | "WORD" | "USHORT" => Some((0, u16::MAX as i64)),
+        "i32" | "INT" | "LONG" | "c_int" | "c_long" => Some((i32::MIN as i64, i32::MAX as i64)),
+        "u32" | "UINT" | "DWORD" | "ULONG" | "c_uint" => Some((0, u32::MAX as i64)),
+        "i64" | "isize" => Some((i64::MIN, i64::MAX)),
+        "u64" | "usize" => Some((0, i64::MAX)),
+        _ => None,
+    }
+}
+
+impl Header {
+    /// Values used by more than one define of the same category, in value
+    /// order. Aliases are left out, being the same id under another name.
+    pub fn collisions(&self) -> Vec<Collision> {
+        let mut by_value: BTreeMap<(&str, i64), Vec<&str>> = BTreeMap::new();
+        for define in self.defines.iter().filter(|define| define.alias_of.is_none()) {
+            by_value.entry((category(&define.name), define.value))
+                .or_default()
+                .push(&define.name);
+        }
+
+        by_value.into_iter()
+            .filter(|(_key, names)| names.len() > 1)
+            .map(|((category, value), names)| Collision {
+                category: category.to_owned(),
+                value,
+                names: names.into_iter().map(str::to_owned).collect(),
+            })
+            .collect()
+    }
+
+    /// `pub const NAME: ty = value;` for every define, in header order.
+    ///
+    /// A value out of the range of `ty` is an error rather than a constant
+    /// that fails to compile, or is silently truncated by a cast, e.g.
+    /// `0xFFFFFFFF` for `INT`. `ty` may be a primitive integer or one of the
+    /// Windows integer types; the values of any other type are not checked.
+    pub fn to_rust(&self, ty: &str) -> Result<String, HeaderError> {
+        let range = type_range(ty);
+        let mut rust = String::new();
+        rust.push_str("// Generated by hidpi-build from a resource header. Do not edit.\n\n");
+        for define in &self.defines {
+            if let Some((min, max)) = range {
+                if define.value < min || define.value > max {
+                    return Err(HeaderError {
+                        line: define.line,
+                        message: format!("`{}` is {}, which does not fit in {} ({}..={})", define.name, define.value, ty, min, max),
+                    });
+                }
+            }
+            let _ = writeln!(rust, "pub const {}: {} = {};", define.name, ty, define.value);
+        }
+        Ok(rust)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUI_HEADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../hidpi-gui/src/Resource.h"));
+    const PLUGIN_HEADER: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../dll_plugin/src/resource.h"));
+
+    fn value(header: &Header, name: &str) -> Option<i64> {
+        header.defines.iter().find(|define| define.name == name).map(|define| define.value)
+    }
+
+    fn evaluated(expression: &str) -> Result<i64, String> {
+        let known = [("IDC_FIRST".to_owned(), 1000)].iter().cloned().collect();
+        evaluate(expression, &known)
+    }
+
+    #[test]
+    fn the_gui_header() {
+        let header = parse(GUI_HEADER).unwrap();
+        assert_eq!(header.skipped, Vec::new());
+        assert_eq!(header.defines.len(), 37);
+        assert_eq!(value(&header, "IDD_DIALOG1"), Some(129));
+        assert_eq!(value(&header, "IDC_BUTTON_UNAWARE"), Some(1000));
+        assert_eq!(value(&header, "IDC_BUTTON_1803"), Some(1005));
+        assert_eq!(value(&header, "ID_EDITITEM1_EDITSUBITEM3"), Some(32788));
+        assert_eq!(value(&header, "IDC_STATIC"), Some(-1));
+        // APSTUDIO_INVOKED bookkeeping
+        assert_eq!(value(&header, "_APS_NEXT_CONTROL_VALUE"), None);
+
+        let alias = header.defines.iter().find(|define| define.name == "IDC_BUTTON_81").unwrap();
+        assert_eq!(alias.alias_of.as_deref(), Some("IDC_BUTTON3"));
+        assert_eq!(header.collisions(), Vec::new());
+
+        let rust = header.to_rust("INT").unwrap();
+        assert!(rust.contains("pub const IDC_BUTTON_UNAWARE: INT = 1000;\n"));
+        assert!(rust.contains("pub const IDC_STATIC: INT = -1;\n"));
+        assert_eq!(rust.lines().filter(|line| line.starts_with("pub const ")).count(), 37);
+    }
+
+    #[test]
+    fn the_plugin_header() {
+        let header = parse(PLUGIN_HEADER).unwrap();
+        assert_eq!(header.skipped, Vec::new());
+        assert_eq!(header.defines.iter().map(|define| (define.name.as_str(), define.value)).collect::<Vec<_>>(), vec![("IDB_BITMAP1", 101)]);
+        assert_eq!(header.collisions(), Vec::new());
+        assert_eq!(header.to_rust("INT").unwrap(), "// Generated by hidpi-build from a resource header. Do not edit.\n\npub const IDB_BITMAP1: INT = 101;\n");
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(evaluated("1000"), Ok(1000));
+        assert_eq!(evaluated("0x3E8"), Ok(1000));
+        assert_eq!(evaluated("01750"), Ok(1000));
+        assert_eq!(evaluated("1000UL"), Ok(1000));
+        assert_eq!(evaluated("0"), Ok(0));
+        assert_eq!(evaluated("0xFFFFFFFF"), Ok(0xFFFF_FFFF));
+        assert!(evaluated("09").is_err());
+        assert!(evaluated("0xG").is_err());
+        assert!(evaluated("").is_err());
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(evaluated("(IDC_FIRST + 1)"), Ok(1001));
+        assert_eq!(evaluated("IDC_FIRST + 2 * 3"), Ok(1006));
+        assert_eq!(evaluated("(IDC_FIRST + 2) * 3"), Ok(3006));
+        assert_eq!(evaluated("1 << 4 | 1"), Ok(17));
+        assert_eq!(evaluated("-1"), Ok(-1));
+        assert_eq!(evaluated("~0"), Ok(-1));
+        assert_eq!(evaluated("!IDC_FIRST"), Ok(0));
+        assert_eq!(evaluated("7 % 4 - 10 / 3"), Ok(0));
+        assert!(evaluated("IDC_OTHER").is_err());
+        assert!(evaluated("1 / 0").is_err());
+        assert!(evaluated("(1").is_err());
+        assert!(evaluated("1 2").is_err());
+        assert!(evaluated("\"text\"").is_err());
+    }
+
+    #[test]
+    fn comments_continuations_and_conditionals() {
+        let header = parse("#define A 1 // one\n#define B /* two */ 2\n/* #define C 3\n#define D 4 */\n#define E \\\n  5\n#ifdef X\n#define F 6\n#endif\n  #  define G (A + B)\n").unwrap();
+        let defines: Vec<_> = header.defines.iter().map(|define| (define.name.as_str(), define.value, define.line)).collect();
+        assert_eq!(defines, vec![("A", 1, 1), ("B", 2, 2), ("E", 5, 5), ("G", 3, 10)]);
+    }
+
+    #[test]
+    fn skipped_and_invalid_defines() {
+        let header = parse("#define MAX(a, b) a\n#define NAME \"text\"\n#define OTHER WM_USER\n#define OK 1\n").unwrap();
+        let skipped: Vec<_> = header.skipped.iter().map(|skipped| skipped.name.as_str()).collect();
+        assert_eq!(skipped, vec!["MAX", "NAME", "OTHER"]);
+        assert_eq!(header.defines.len(), 1);
+
+        assert_eq!(parse("#define A 1\n#define A 2\n").unwrap_err(), HeaderError { line: 2, message: "`A` is defined twice".to_owned() });
+        assert_eq!(parse("#endif\n").unwrap_err().line, 1);
+        assert_eq!(parse("#if 1\n#define A 1\n").unwrap_err().message, "unterminated #if block");
+        assert!(parse("#define\n").is_err());
+    }
+
+    #[test]
+    fn collisions_are_per_category() {
+        let header = parse("#define IDC_A 1000\n#define IDC_B 1000\n#define IDM_A 1000\n#define IDC_C 1001\n#define IDC_D 1000\n").unwrap();
+        let collisions = header.collisions();
+        assert_eq!(collisions, vec![Collision { category: "IDC_".to_owned(), value: 1000, names: vec!["IDC_A".to_owned(), "IDC_B".to_owned(), "IDC_D".to_owned()] }]);
+        assert_eq!(collisions[0].to_string(), "IDC_A, IDC_B, IDC_D share the IDC_ value 1000");
+    }
+
+    #[test]
+    fn aliases_are_not_collisions() {
+        let header = parse("#define IDC_A 1000\n#define IDC_B IDC_A\n#define IDC_C (IDC_A)\n#define IDC_D 1000\n").unwrap();
+        let aliases: Vec<_> = header.defines.iter().map(|define| define.alias_of.as_deref()).collect();
+        // only a bare name is an alias; anything else is a value that happens to match
+        assert_eq!(aliases, vec![None, Some("IDC_A"), None, None]);
+        assert_eq!(header.collisions()[0].names, vec!["IDC_A", "IDC_C", "IDC_D"]);
+
+        // an alias of an alias is still the same id
+        let header = parse("#define IDC_A 1000\n#define IDC_B IDC_A\n#define IDC_C IDC_B\n").unwrap();
+        assert_eq!(header.collisions(), Vec::new());
+    }
+
+    #[test]
+    fn values_must_fit_the_type() {
+        let header = parse("#define IDC_LOW -1\n#define IDC_HIGH 0xFFFFFFFF\n").unwrap();
+        let error = header.to_rust("INT").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(error.message, "`IDC_HIGH` is 4294967295, which does not fit in INT (-2147483648..=2147483647)");
+        assert_eq!(header.to_rust("UINT").unwrap_err().line, 1);
+        assert!(header.to_rust("i64").unwrap().contains("pub const IDC_HIGH: i64 = 4294967295;\n"));
+        // unknown types are left to the compiler
+        assert!(header.to_rust("MyId").is_ok());
+
+        let header = parse("#define A 2147483647\n#define B -2147483648\n#define C 65535\n").unwrap();
+        assert!(header.to_rust("INT").is_ok());
+        assert!(header.to_rust("i32").is_ok());
+        assert_eq!(header.to_rust("WORD").unwrap_err().line, 1);
+        assert_eq!(header.to_rust("u16").unwrap_err().line, 1);
+    }
+}
@@ -0,0 +1,403 @@
+//! Images with a variant per display scale, packed into the resources so
+//! `hidpi::image` can load the one that fits the DPI a window is drawn at.
+//!
+//! The lowest-scale variant is the image's own resource, which stays in the
+//! resource script, so `LoadBitmapW(h_inst, IDB_LOGO)` still finds it. The
+//! others are added with generated names (`DPI_IDB_LOGO_150`), and a
+//! `DPIIMAGE` resource with the image's id lists them all:
+//!
+//! ```text
+//! u16 version (1), u16 count, then per variant:
+//! u16 scale (percent), u16 format (1 bitmap, 2 icon, 3 PNG),
+//! u32 width, u32 height (pixels), NUL terminated UTF-16 name ("" for the image's own id)
+//! ```
+//!
+//! Bitmaps are `BITMAP` resources, icons `ICON` groups and PNGs a custom
+//! `PNG` type.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::rc::res::ResWriter;
+use crate::rc_path;
+
+/// The scales Windows offers in the display settings, up to 200%.
+pub const SCALES: [u16; 4] = [100, 125, 150, 200];
+
+/// The resource type of the variant tables.
+pub const RT_DPI_IMAGE: &str = "DPIIMAGE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Bitmap,
+    Icon,
+    Png,
+}
+
+impl ImageFormat {
+    fn code(self) -> u16 {
+        match self {
+            ImageFormat::Bitmap => 1,
+            ImageFormat::Icon => 2,
+            ImageFormat::Png => 3,
+        }
+    }
+
+    fn resource_type(self) -> &'static str {
+        match self {
+            ImageFormat::Bitmap => "BITMAP",
+            ImageFormat::Icon => "ICON",
+            ImageFormat::Png => "PNG",
+        }
+    }
+}
+
+/// The format and size of an image file. For icons, the size of the largest
+/// image in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Read the format and size from the headers of a `.bmp`, `.ico` or `.png`
+/// file, or `None` for anything else.
+pub fn image_info(data: &[u8]) -> Option<ImageInfo> {
+    let u16_le = |offset: usize| data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_le = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    let u32_be = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+
+    if data.starts_with(b"BM") {
+        let (width, height) = if u32_le(14)? == 12 {
+            // BITMAPCOREHEADER
+            (u16_le(18)? as u32, u16_le(20)? as u32)
+        } else {
+            // a top-down bitmap has a negative height
+            (u32_le(18)?, (u32_le(22)? as i32).unsigned_abs())
+        };
+        Some(ImageInfo { format: ImageFormat::Bitmap, width, height })
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") && data.get(12..16) == Some(b"IHDR") {
+        Some(ImageInfo { format: ImageFormat::Png, width: u32_be(16)?, height: u32_be(20)? })
+    } else if u16_le(0)? == 0 && u16_le(2)? == 1 {
+        // a 0 in the directory means 256
+        let dimension = |offset: usize| data.get(offset).map(|&d| if d == 0 { 256 } else { d as u32 });
+        let count = u16_le(4)? as usize;
+        let mut largest: Option<(u32, u32)> = None;
+        for index in 0..count {
+            let (width, height) = (dimension(6 + index * 16)?, dimension(7 + index * 16)?);
+            match largest {
+                Some((w, h)) if w * h >= width * height => {},
+                _ => largest = Some((width, height)),
+            }
+        }
+        let (width, height) = largest?;
+        Some(ImageInfo { format: ImageFormat::Icon, width, height })
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageVariant {
+    /// Percent, 100 being 96 DPI.
+    pub scale: u16,
+    pub path: PathBuf,
+}
+
+/// One logical image, `id` being its name or id in the resource script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSet {
+    pub id: String,
+    pub variants: Vec<ImageVariant>,
+    /// The directory `scaled_files` looked in, watched for new variants.
+    pub search_dir: Option<PathBuf>,
+}
+
+impl ImageSet {
+    pub fn new(id: &str) -> ImageSet {
+        ImageSet { id: id.to_owned(), variants: Vec::new(), search_dir: None }
+    }
+
+    pub fn variant<P: Into<PathBuf>>(mut self, scale: u16, path: P) -> ImageSet {
+        self.variants.push(ImageVariant { scale, path: path.into() });
+        self
+    }
+
+    /// `base` as the 100% variant, and the files named after it the way
+    /// Windows apps name theirs, `<stem>.scale-<percent>.<ext>`, as the
+    /// others: `logo.bmp`, `logo.scale-150.bmp`, `logo.scale-200.bmp`.
+    pub fn scaled_files<P: AsRef<Path>>(id: &str, base: P) -> io::Result<ImageSet> {
+        let base = base.as_ref();
+        let dir = base.parent().unwrap_or_else(|| Path::new("."));
+        let stem = base.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let ext = base.extension().map(|ext| ext.to_string_lossy().into_owned()).unwrap_or_default();
+        let prefix = format!("{}.scale-", stem).to_lowercase();
+        let suffix = format!(".{}", ext).to_lowercase();
+
+        let mut set = ImageSet::new(id).variant(100, base);
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+            let scale = file_name.strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(&suffix))
+                .and_then(|scale| scale.parse().ok());
+            if let Some(scale) = scale {
+                set = set.variant(scale, path);
+            }
+        }
+        set.search_dir = Some(dir.to_path_buf());
+        Ok(set)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl Error for ImageError {}
+
+/// The resource statements for a list of `ImageSet`s and the variant tables
+/// they refer to.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PackedImages {
+    pub script: String,
+    /// Where each table is expected, and its contents.
+    pub tables: Vec<(PathBuf, Vec<u8>)>,
+    /// Every file that was read.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Read the variants of each image and lay out all but the lowest-scale one
+/// as resources, with the tables to be written to `table_dir`.
+///
+/// Each variant must be its scale times the size of the lowest-scale variant,
+/// give or take a pixel of rounding, and no scale may appear twice.
+pub fn pack(images: &[ImageSet], table_dir: &Path) -> Result<PackedImages, ImageError> {
+    let mut packed = PackedImages::default();
+    for set in images {
+        let set_error = |message: String| ImageError { path: PathBuf::from(&set.id), message };
+        let mut variants: Vec<(&ImageVariant, ImageInfo)> = Vec::new();
+        for variant in &set.variants {
+            let error = |message: String| ImageError { path: variant.path.clone(), message };
+            let data = fs::read(&variant.path).map_err(|e| error(e.to_string()))?;
+            let info = image_info(&data).ok_or_else(|| error("not a bitmap, icon or PNG".to_owned()))?;
+            if variant.scale == 0 {
+                return Err(error("a scale of 0%".to_owned()));
+            }
+            packed.dependencies.push(variant.path.clone());
+            variants.push((variant, info));
+        }
+        variants.sort_by_key(|(variant, _)| variant.scale);
+        if let Some(pair) = variants.windows(2).find(|pair| pair[0].0.scale == pair[1].0.scale) {
+            return Err(set_error(format!("two variants for {}%", pair[0].0.scale)));
+        }
+        let (base, base_info) = match variants.first() {
+            Some(&(base, info)) => (base, info),
+            None => return Err(set_error("no variants".to_owned())),
+        };
+
+        let symbol: String = set.id.to_uppercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let mut table = Vec::new();
+        table.push_u16(1);
+        table.push_u16(variants.len() as u16);
+        for (index, &(variant, info)) in variants.iter().enumerate() {
+            let ratio = variant.scale as f64 / base.scale as f64;
+            let (expected_width, expected_height) = (base_info.width as f64 * ratio, base_info.height as f64 * ratio);
+            if (info.width as f64 - expected_width).abs() > 1.0 || (info.height as f64 - expected_height).abs() > 1.0 {
+                return Err(ImageError {
+                    path: variant.path.clone(),
+                    message: format!("{}x{} at {}% should be about {:.0}x{:.0}, from {}x{} at {}%",
+                        info.width, info.height, variant.scale, expected_width, expected_height, base_info.width, base_info.height, base.scale),
+                });
+            }
+
+            let name = if index == 0 {
+                String::new()
+            } else {
+                let name = format!("DPI_{}_{}", symbol, variant.scale);
+                packed.script.push_str(&format!("{} {} \"{}\"\r\n", name, info.format.resource_type(), rc_path(&variant.path)));
+                name
+            };
+
+            table.push_u16(variant.scale);
+            table.push_u16(info.format.code());
+            table.push_u32(info.width);
+            table.push_u32(info.height);
+            table.push_wstr(&name);
+        }
+
+        let table_path = table_dir.join(format!("{}.dpiimage", symbol));
+        packed.script.push_str(&format!("{} {} \"{}\"\r\n", set.id, RT_DPI_IMAGE, rc_path(&table_path)));
+        packed.tables.push((table_path, table));
+    }
+    Ok(packed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGO_TABLE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/images/IDB_LOGO.dpiimage"));
+    const TINY_ICO: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tiny.ico"));
+
+    fn images() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/images")
+    }
+
+    fn scales(set: &ImageSet) -> Vec<(u16, String)> {
+        let mut scales: Vec<(u16, String)> = set.variants.iter()
+            .map(|variant| (variant.scale, variant.path.file_name().unwrap().to_string_lossy().into_owned()))
+            .collect();
+        scales.sort();
+        scales
+    }
+
+    #[test]
+    fn image_info_reads_the_headers() {
+        let read = |name: &str| image_info(&fs::read(images().join(name)).unwrap());
+        assert_eq!(read("logo.bmp"), Some(ImageInfo { format: ImageFormat::Bitmap, width: 8, height: 4 }));
+        assert_eq!(read("badge.scale-200.png"), Some(ImageInfo { format: ImageFormat::Png, width: 32, height: 32 }));
+        assert_eq!(read("logo.scale-300.txt"), None);
+        assert_eq!(image_info(TINY_ICO).map(|info| info.format), Some(ImageFormat::Icon));
+
+        // top-down, with a negative height
+        let mut top_down = fs::read(images().join("logo.bmp")).unwrap();
+        top_down[22..26].copy_from_slice(&(-4i32).to_le_bytes());
+        assert_eq!(image_info(&top_down).map(|info| info.height), Some(4));
+
+        // BITMAPCOREHEADER, with 16-bit dimensions
+        let mut core = b"BM".to_vec();
+        core.extend_from_slice(&[0; 12]);
+        core.extend_from_slice(&12u32.to_le_bytes());
+        core.extend_from_slice(&[7, 0, 3, 0, 1, 0, 24, 0]);
+        assert_eq!(image_info(&core), Some(ImageInfo { format: ImageFormat::Bitmap, width: 7, height: 3 }));
+
+        // the largest image of an icon, with 0 meaning 256
+        let mut icon = vec![0, 0, 1, 0, 2, 0];
+        icon.extend_from_slice(&[16, 16, 0, 0, 1, 0, 32, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        icon.extend_from_slice(&[0, 0, 0, 0, 1, 0, 32, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(image_info(&icon), Some(ImageInfo { format: ImageFormat::Icon, width: 256, height: 256 }));
+
+        assert_eq!(image_info(b"BM"), None);
+        assert_eq!(image_info(&icon[..20]), None);
+    }
+
+    #[test]
+    fn scaled_files_finds_the_variants_beside_the_base() {
+        let set = ImageSet::scaled_files("IDB_LOGO", images().join("logo.bmp")).unwrap();
+        assert_eq!(set.id, "IDB_LOGO");
+        assert_eq!(set.search_dir, Some(images()));
+        // without regard to case, but not with another extension or a scale
+        // that isn't a number
+        assert_eq!(scales(&set), vec![
+            (100, "logo.bmp".to_owned()),
+            (125, "logo.scale-125.bmp".to_owned()),
+            (150, "LOGO.Scale-150.BMP".to_owned()),
+            (200, "logo.scale-200.bmp".to_owned()),
+        ]);
+
+        let set = ImageSet::scaled_files("IDB_BADGE", images().join("badge.png")).unwrap();
+        assert_eq!(scales(&set), vec![(100, "badge.png".to_owned()), (200, "badge.scale-200.png".to_owned())]);
+
+        assert!(ImageSet::scaled_files("IDB_NONE", images().join("missing/none.bmp")).is_err());
+    }
+
+    #[test]
+    fn pack_adds_all_but_the_base_variant() {
+        let logo = ImageSet::scaled_files("IDB_LOGO", images().join("logo.bmp")).unwrap();
+        let badge = ImageSet::new("IDB_BADGE")
+            .variant(200, images().join("badge.scale-200.png"))
+            .variant(100, images().join("badge.png"));
+        let packed = pack(&[logo, badge], Path::new("out")).unwrap();
+
+        let file = |name: &str| rc_path(&images().join(name));
+        assert_eq!(packed.script, format!(concat!(
+            "DPI_IDB_LOGO_125 BITMAP \"{}\"\r\n",
+            "DPI_IDB_LOGO_150 BITMAP \"{}\"\r\n",
+            "DPI_IDB_LOGO_200 BITMAP \"{}\"\r\n",
+            "IDB_LOGO DPIIMAGE \"out/IDB_LOGO.dpiimage\"\r\n",
+            "DPI_IDB_BADGE_200 PNG \"{}\"\r\n",
+            "IDB_BADGE DPIIMAGE \"out/IDB_BADGE.dpiimage\"\r\n",
+        ), file("logo.scale-125.bmp"), file("LOGO.Scale-150.BMP"), file("logo.scale-200.bmp"), file("badge.scale-200.png")));
+
+        assert_eq!(packed.tables.len(), 2);
+        assert_eq!(packed.tables[0], (Path::new("out").join("IDB_LOGO.dpiimage"), LOGO_TABLE.to_vec()));
+
+        let mut badge_table = Vec::new();
+        badge_table.push_u16(1);
+        badge_table.push_u16(2);
+        for &(scale, size, name) in &[(100, 16, ""), (200, 32, "DPI_IDB_BADGE_200")] {
+            badge_table.push_u16(scale);
+            badge_table.push_u16(3);
+            badge_table.push_u32(size);
+            badge_table.push_u32(size);
+            badge_table.push_wstr(name);
+        }
+        assert_eq!(packed.tables[1].1, badge_table);
+
+        assert_eq!(packed.dependencies.len(), 6);
+    }
+
+    #[test]
+    fn packed_images_compile() {
+        use crate::rc::{compile_file, res::ResId, Options};
+
+        let dir = std::env::temp_dir().join(format!("hidpi-build-images-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let logo = ImageSet::scaled_files("IDB_LOGO", images().join("logo.bmp")).unwrap();
+        let packed = pack(&[logo], &dir).unwrap();
+        for (path, table) in &packed.tables {
+            fs::write(path, table).unwrap();
+        }
+        let script = format!("IDB_LOGO BITMAP \"{}\"\r\n{}", rc_path(&images().join("logo.bmp")), packed.script);
+        fs::write(dir.join("images.rc"), script).unwrap();
+
+        let options = Options { defines: vec![("IDB_LOGO".to_owned(), 7)], ..Options::default() };
+        let compiled = compile_file(dir.join("images.rc"), &options).unwrap();
+        let names: Vec<(ResId, ResId)> = compiled.entries.iter().map(|entry| (entry.type_id.clone(), entry.name.clone())).collect();
+        assert_eq!(names, vec![
+            (ResId::Ordinal(2), ResId::Ordinal(7)),
+            (ResId::Ordinal(2), ResId::Name("DPI_IDB_LOGO_125".to_owned())),
+            (ResId::Ordinal(2), ResId::Name("DPI_IDB_LOGO_150".to_owned())),
+            (ResId::Ordinal(2), ResId::Name("DPI_IDB_LOGO_200".to_owned())),
+            (ResId::Name(RT_DPI_IMAGE.to_owned()), ResId::Ordinal(7)),
+        ]);
+        assert_eq!(compiled.entries[4].data, LOGO_TABLE);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn pack_rejects_inconsistent_variants() {
+        let error = |set: ImageSet| pack(&[set], Path::new("out")).unwrap_err();
+        let logo = || ImageSet::new("IDB_LOGO").variant(100, images().join("logo.bmp"));
+
+        let wrong_size = error(logo().variant(200, images().join("odd.bmp")));
+        assert_eq!(wrong_size.path, images().join("odd.bmp"));
+        assert_eq!(wrong_size.message, "14x8 at 200% should be about 16x8, from 8x4 at 100%");
+
+        // 150% of 8x4 is 12x6; a pixel either way is rounding
+        assert!(pack(&[logo().variant(140, images().join("LOGO.Scale-150.BMP"))], Path::new("out")).is_ok());
+
+        assert_eq!(error(logo().variant(100, images().join("logo.bmp"))).message, "two variants for 100%");
+        assert_eq!(error(logo().variant(0, images().join("logo.bmp"))).message, "a scale of 0%");
+        assert_eq!(error(logo().variant(300, images().join("logo.scale-300.txt"))).message, "not a bitmap, icon or PNG");
+        assert_eq!(error(ImageSet::new("IDB_EMPTY")), ImageError { path: PathBuf::from("IDB_EMPTY"), message: "no variants".to_owned() });
+        assert_eq!(error(logo().variant(200, images().join("absent.bmp"))).path, images().join("absent.bmp"));
+    }
+}
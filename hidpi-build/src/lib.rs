@@ -0,0 +1,190 @@
+//! Build script helpers for applications using `hidpi`.
+//!
+//! ```ignore
+//! // build.rs
+//! let rc = hidpi_build::embed_manifest("src/App.rc", &Manifest::per_monitor_v2()).expect("write manifest");
+//! let mut res = winres::WindowsResource::new();
+//! res.set_resource_file(rc.to_str().unwrap());
+//! res.compile().expect("compile resources");
+//! ```
+//!
+//! or, without `winres` and the Windows SDK, with the built-in compiler:
+//!
+//! ```ignore
+//! let rc = hidpi_build::embed_manifest("src/App.rc", &Manifest::per_monitor_v2()).expect("write manifest");
+//! hidpi_build::compile_resources(&rc).expect("compile resources");
+//! ```
+//!
+//! Images with a variant per display scale are added the same way, before the
+//! manifest:
+//!
+//! ```ignore
+//! let images = [ImageSet::scaled_files("IDB_LOGO", "src/logo.bmp").expect("find images")];
+//! let rc = hidpi_build::embed_images("src/App.rc", &images).expect("pack images");
+//! ```
+
+pub mod header;
+pub mod images;
+pub mod manifest;
+pub mod rc;
+
+pub use header::{Collision, Define, Header, HeaderError};
+pub use images::{ImageError, ImageSet};
+pub use manifest::{DpiAwareness, Manifest, SupportedOs};
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// CREATEPROCESS_MANIFEST_RESOURCE_ID and RT_MANIFEST
+const MANIFEST_RESOURCE: &str = "1 24";
+
+fn out_dir() -> io::Result<PathBuf> {
+    env::var_os("OUT_DIR")
+        .map(PathBuf::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "OUT_DIR is not set, hidpi-build must be called from a build script"))
+}
+
+// rc accepts forward slashes, which need no escaping in string literals
+pub(crate) fn rc_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Write `manifest` to `OUT_DIR` along with a resource script that includes
+/// `rc_file` and adds the manifest as the application manifest resource.
+///
+/// Returns the path of the new script, to be compiled in place of `rc_file`
+/// (with `winres::WindowsResource::set_resource_file` for example). `rc_file`
+/// must not already contain a manifest resource.
+pub fn embed_manifest<P: AsRef<Path>>(rc_file: P, manifest: &Manifest) -> io::Result<PathBuf> {
+    let out_dir = out_dir()?;
+    // not canonicalize, which gives `\\?\` paths on Windows that rc cannot open
+    let rc_file = env::current_dir()?.join(rc_file.as_ref());
+    println!("cargo:rerun-if-changed={}", rc_file.display());
+
+    let stem = rc_file.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "app".to_owned());
+
+    let manifest_path = out_dir.join(format!("{}.manifest", stem));
+    fs::write(&manifest_path, manifest.to_xml())?;
+
+    let script_path = out_dir.join(format!("{}.manifest.rc", stem));
+    let script = format!("#include \"{}\"\r\n\r\n{} \"{}\"\r\n", rc_path(&rc_file), MANIFEST_RESOURCE, rc_path(&manifest_path));
+    fs::write(&script_path, script)?;
+
+    Ok(script_path)
+}
+
+/// Write a resource script to `OUT_DIR` that includes `rc_file` and adds
+/// `images`, each as its variants and the table listing them (see `images`).
+///
+/// Returns the path of the new script, which can be compiled in place of
+/// `rc_file` or passed on to `embed_manifest`. The lowest-scale variant of
+/// each image must be its resource in `rc_file`, such as
+/// `IDB_LOGO BITMAP "logo.bmp"`.
+pub fn embed_images<P: AsRef<Path>>(rc_file: P, images: &[ImageSet]) -> io::Result<PathBuf> {
+    let out_dir = out_dir()?;
+    let current_dir = env::current_dir()?;
+    let rc_file = current_dir.join(rc_file.as_ref());
+    println!("cargo:rerun-if-changed={}", rc_file.display());
+
+    // the new script is in OUT_DIR, so relative paths would no longer resolve
+    let images: Vec<ImageSet> = images.iter()
+        .map(|set| {
+            let mut set = set.clone();
+            for variant in &mut set.variants {
+                variant.path = current_dir.join(&variant.path);
+            }
+            set
+        })
+        .collect();
+    let packed = images::pack(&images, &out_dir)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    for dependency in &packed.dependencies {
+        println!("cargo:rerun-if-changed={}", dependency.display());
+    }
+    // so that adding a variant is picked up
+    for dir in images.iter().filter_map(|set| set.search_dir.as_ref()) {
+        println!("cargo:rerun-if-changed={}", current_dir.join(dir).display());
+    }
+    for (path, table) in &packed.tables {
+        fs::write(path, table)?;
+    }
+
+    let stem = rc_file.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "app".to_owned());
+    let script_path = out_dir.join(format!("{}.images.rc", stem));
+    fs::write(&script_path, images_script(&rc_file, &packed))?;
+
+    Ok(script_path)
+}
+
+// `rc_file` followed by the images' resources
+pub(crate) fn images_script(rc_file: &Path, packed: &images::PackedImages) -> String {
+    format!("#include \"{}\"\r\n\r\n{}", rc_path(rc_file), packed.script)
+}
+
+/// Compile `rc_file` to `OUT_DIR/<stem>.res` with the built-in compiler (see
+/// `rc::compile`) and link it into the crate's binaries.
+///
+/// The `.res` file is handed to the linker as is, which `link.exe` and
+/// `lld-link` accept, so this needs an MSVC target; for other targets it is
+/// only compiled, which still checks the script. Returns the path of the
+/// `.res` file.
+pub fn compile_resources<P: AsRef<Path>>(rc_file: P) -> io::Result<PathBuf> {
+    let rc_file = env::current_dir()?.join(rc_file.as_ref());
+    let compiled = rc::compile_file(&rc_file, &rc::Options::default())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    for dependency in &compiled.dependencies {
+        println!("cargo:rerun-if-changed={}", dependency.display());
+    }
+
+    let stem = rc_file.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "app".to_owned());
+    let res_path = out_dir()?.join(format!("{}.res", stem));
+    fs::write(&res_path, compiled.to_res())?;
+
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+    if target_os == "windows" {
+        if target_env != "msvc" {
+            return Err(io::Error::new(io::ErrorKind::Other, format!("linking a .res file needs an MSVC target, not `{}`; use winres instead", target_env)));
+        }
+        println!("cargo:rustc-link-arg={}", res_path.display());
+    }
+
+    Ok(res_path)
+}
+
+/// Generate `OUT_DIR/<out_name>` with a `pub const NAME: ty = value;` for each
+/// `#define` in the resource header `header`, to be pulled in with
+/// `include!(concat!(env!("OUT_DIR"), "/<out_name>"))`.
+///
+/// Ids of one category that share a value, and defines that were skipped, are
+/// reported as cargo warnings; a value that does not fit in `ty` is an error.
+/// Returns the path of the generated file.
+pub fn generate_resource_constants<P: AsRef<Path>>(header: P, out_name: &str, ty: &str) -> io::Result<PathBuf> {
+    let header = header.as_ref();
+    println!("cargo:rerun-if-changed={}", header.display());
+
+    let text = fs::read_to_string(header)?;
+    let parsed = header::parse(&text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", header.display(), e)))?;
+
+    for collision in parsed.collisions() {
+        println!("cargo:warning={}: {}", header.display(), collision);
+    }
+    for skipped in &parsed.skipped {
+        println!("cargo:warning={}:{}: skipped {}: {}", header.display(), skipped.line, skipped.name, skipped.reason);
+    }
+
+    let rust = parsed.to_rust(ty)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", header.display(), e)))?;
+    let path = out_dir()?.join(out_name);
+    fs::write(&path, rust)?;
+    Ok(path)
+}
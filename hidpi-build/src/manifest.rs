@@ -0,0 +1,230 @@
+//! Application manifests declaring DPI awareness and the other per-process
+//! settings Windows reads before any code runs.
+//!
+//! Declaring the awareness in the manifest is preferred to setting it at
+//! startup: it is in effect before the first window is created, and the
+//! `SetProcessDpiAwareness*` calls then fail with `ERROR_ACCESS_DENIED`
+//! instead of changing it.
+
+use std::fmt::Write;
+
+/// The process DPI awareness to declare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DpiAwareness {
+    Unaware,
+    System,
+    /// Per-monitor awareness on Windows 8.1 and later.
+    PerMonitor,
+    /// Per-monitor V2 on Windows 10 1703 and later, Per-Monitor before that.
+    PerMonitorV2,
+}
+
+impl DpiAwareness {
+    // `<dpiAware>`, read by Windows Vista to Windows 10 1511
+    fn dpi_aware(self) -> &'static str {
+        match self {
+            DpiAwareness::Unaware => "false",
+            DpiAwareness::System => "true",
+            DpiAwareness::PerMonitor | DpiAwareness::PerMonitorV2 => "true/pm",
+        }
+    }
+
+    // `<dpiAwareness>`, read by Windows 10 1607 and later in place of `<dpiAware>`.
+    // The first value the running version understands is used.
+    fn dpi_awareness(self) -> &'static str {
+        match self {
+            DpiAwareness::Unaware => "unaware",
+            DpiAwareness::System => "system",
+            DpiAwareness::PerMonitor => "permonitor",
+            DpiAwareness::PerMonitorV2 => "permonitorv2,permonitor",
+        }
+    }
+}
+
+/// A `<supportedOS>` entry. Declaring an OS opts into its behavior changes,
+/// and stops version queries from being capped at the newest declared OS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SupportedOs {
+    WindowsVista,
+    Windows7,
+    Windows8,
+    Windows81,
+    /// Windows 10 and 11.
+    Windows10,
+}
+
+impl SupportedOs {
+    pub const ALL: [SupportedOs; 5] = [
+        SupportedOs::WindowsVista,
+        SupportedOs::Windows7,
+        SupportedOs::Windows8,
+        SupportedOs::Windows81,
+        SupportedOs::Windows10,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            SupportedOs::WindowsVista => "{e2011457-1546-43c5-a5fe-008deee3d3f0}",
+            SupportedOs::Windows7 => "{35138b9a-5d96-4fbd-8e2d-a2440225f93a}",
+            SupportedOs::Windows8 => "{4a2f28e3-53b9-4441-ba9c-d69d4a4a6e38}",
+            SupportedOs::Windows81 => "{1f676c76-80e1-4239-95bb-83d0f6d0da78}",
+            SupportedOs::Windows10 => "{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}",
+        }
+    }
+}
+
+/// The settings written by `Manifest::to_xml`. Anything left at its default
+/// is omitted, leaving the Windows default in place.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Manifest {
+    /// `<assemblyIdentity>` name and version, e.g. `("Company.Product", "1.0.0.0")`.
+    pub identity: Option<(String, String)>,
+    pub dpi_awareness: Option<DpiAwareness>,
+    /// GDI scaling of DPI unaware applications (Windows 10 1703).
+    pub gdi_scaling: bool,
+    /// Paths longer than `MAX_PATH` (Windows 10 1607, when enabled system wide).
+    pub long_path_aware: bool,
+    pub supported_os: Vec<SupportedOs>,
+    /// Bind to version 6 of the common controls for themed controls.
+    pub common_controls_v6: bool,
+}
+
+const WINDOWS_SETTINGS_2005: &str = "http://schemas.microsoft.com/SMI/2005/WindowsSettings";
+const WINDOWS_SETTINGS_2016: &str = "http://schemas.microsoft.com/SMI/2016/WindowsSettings";
+const WINDOWS_SETTINGS_2017: &str = "http://schemas.microsoft.com/SMI/2017/WindowsSettings";
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Manifest {
+    /// Per-Monitor V2 awareness on every supported version of Windows, with
+    /// themed common controls.
+    pub fn per_monitor_v2() -> Manifest {
+        Manifest {
+            dpi_awareness: Some(DpiAwareness::PerMonitorV2),
+            supported_os: SupportedOs::ALL.to_vec(),
+            common_controls_v6: true,
+            ..Manifest::default()
+        }
+    }
+
+    /// The manifest XML. The output only depends on the settings, so it can be
+    /// compared byte for byte.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\r\n");
+        xml.push_str("<assembly xmlns=\"urn:schemas-microsoft-com:asm.v1\" manifestVersion=\"1.0\">\r\n");
+
+        if let Some((name, version)) = &self.identity {
+            let _ = write!(xml, "  <assemblyIdentity type=\"win32\" name=\"{}\" version=\"{}\"/>\r\n", escape(name), escape(version));
+        }
+
+        if self.common_controls_v6 {
+            xml.push_str("  <dependency>\r\n");
+            xml.push_str("    <dependentAssembly>\r\n");
+            xml.push_str("      <assemblyIdentity type=\"win32\" name=\"Microsoft.Windows.Common-Controls\" version=\"6.0.0.0\" processorArchitecture=\"*\" publicKeyToken=\"6595b64144ccf1df\" language=\"*\"/>\r\n");
+            xml.push_str("    </dependentAssembly>\r\n");
+            xml.push_str("  </dependency>\r\n");
+        }
+
+        if !self.supported_os.is_empty() {
+            let mut supported_os = self.supported_os.clone();
+            supported_os.sort();
+            supported_os.dedup();
+
+            xml.push_str("  <compatibility xmlns=\"urn:schemas-microsoft-com:compatibility.v1\">\r\n");
+            xml.push_str("    <application>\r\n");
+            for os in supported_os {
+                let _ = write!(xml, "      <supportedOS Id=\"{}\"/>\r\n", os.id());
+            }
+            xml.push_str("    </application>\r\n");
+            xml.push_str("  </compatibility>\r\n");
+        }
+
+        if self.dpi_awareness.is_some() || self.gdi_scaling || self.long_path_aware {
+            xml.push_str("  <application xmlns=\"urn:schemas-microsoft-com:asm.v3\">\r\n");
+            xml.push_str("    <windowsSettings>\r\n");
+            if let Some(awareness) = self.dpi_awareness {
+                let _ = write!(xml, "      <dpiAware xmlns=\"{}\">{}</dpiAware>\r\n", WINDOWS_SETTINGS_2005, awareness.dpi_aware());
+                let _ = write!(xml, "      <dpiAwareness xmlns=\"{}\">{}</dpiAwareness>\r\n", WINDOWS_SETTINGS_2016, awareness.dpi_awareness());
+            }
+            if self.gdi_scaling {
+                let _ = write!(xml, "      <gdiScaling xmlns=\"{}\">true</gdiScaling>\r\n", WINDOWS_SETTINGS_2017);
+            }
+            if self.long_path_aware {
+                let _ = write!(xml, "      <longPathAware xmlns=\"{}\">true</longPathAware>\r\n", WINDOWS_SETTINGS_2016);
+            }
+            xml.push_str("    </windowsSettings>\r\n");
+            xml.push_str("  </application>\r\n");
+        }
+
+        xml.push_str("</assembly>\r\n");
+        xml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The snapshots are checked in with CRLF line endings, as written
+    macro_rules! snapshot {
+        ($name:expr) => {
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/snapshots/", $name, ".manifest"))
+        };
+    }
+
+    fn awareness(dpi_awareness: DpiAwareness) -> Manifest {
+        Manifest { dpi_awareness: Some(dpi_awareness), ..Manifest::default() }
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(Manifest::default().to_xml(), snapshot!("empty"));
+    }
+
+    #[test]
+    fn per_monitor_v2() {
+        assert_eq!(Manifest::per_monitor_v2().to_xml(), snapshot!("per_monitor_v2"));
+    }
+
+    #[test]
+    fn each_awareness() {
+        assert_eq!(awareness(DpiAwareness::Unaware).to_xml(), snapshot!("unaware"));
+        assert_eq!(awareness(DpiAwareness::System).to_xml(), snapshot!("system"));
+        assert_eq!(awareness(DpiAwareness::PerMonitor).to_xml(), snapshot!("per_monitor"));
+    }
+
+    #[test]
+    fn every_setting() {
+        let manifest = Manifest {
+            identity: Some(("Tom & Jerry's <\"Sample\">".to_owned(), "1.2.3.4".to_owned())),
+            dpi_awareness: Some(DpiAwareness::Unaware),
+            gdi_scaling: true,
+            long_path_aware: true,
+            // written sorted and once each
+            supported_os: vec![SupportedOs::Windows10, SupportedOs::Windows7, SupportedOs::Windows10],
+            common_controls_v6: true,
+        };
+        assert_eq!(manifest.to_xml(), snapshot!("every_setting"));
+    }
+
+    #[test]
+    fn output_is_crlf() {
+        let xml = Manifest::per_monitor_v2().to_xml();
+        assert_eq!(xml.matches('\n').count(), xml.matches("\r\n").count());
+        assert!(xml.ends_with("</assembly>\r\n"));
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+
+/// A range of the source, in bytes, with the 1-based line and column of its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span { end: other.end, ..self }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// An integer expression, as used for ids, coordinates and styles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    /// A `#define`d name, or a constant from the Windows headers such as `WS_POPUP`.
+    Symbol(String),
+    Neg(Box<Expr>),
+    BitNot(Box<Expr>),
+    /// `NOT x` in a style: the bits of `x` are removed from the style rather
+    /// than complemented.
+    Not(Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// A resource or class named either by a string or by a number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameOrId {
+    Name(String),
+    Id(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceScript {
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Include { path: String, span: Span },
+    Define { name: String, value: String, span: Span },
+    Undef { name: String, span: Span },
+    Pragma { text: String, span: Span },
+    Conditional(Conditional),
+    Language { primary: Expr, sub: Expr, span: Span },
+    Resource(Resource),
+    StringTable { strings: Vec<(Expr, String)>, span: Span },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConditionKind {
+    If,
+    Ifdef,
+    Ifndef,
+}
+
+/// An `#if`/`#ifdef`/`#ifndef` block. `#elif` is represented as an `If` nested
+/// in `else_items`. The condition is kept as written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conditional {
+    pub kind: ConditionKind,
+    pub condition: String,
+    pub then_items: Vec<Item>,
+    pub else_items: Vec<Item>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    pub name: NameOrId,
+    pub kind: ResourceKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FileType {
+    Bitmap,
+    Icon,
+    Cursor,
+    /// A numbered type, e.g. 24 (`RT_MANIFEST`).
+    Numeric(u16),
+    /// A type given by a symbol such as `RT_MANIFEST`, or by a string.
+    Custom(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceKind {
+    Menu(Menu),
+    Dialog(Box<Dialog>),
+    VersionInfo(VersionInfo),
+    /// A resource whose data is the named file.
+    File { file_type: FileType, path: String },
+    /// The Visual Studio bookkeeping strings.
+    TextInclude(Vec<String>),
+    /// A resource type this parser does not model, such as `DESIGNINFO`.
+    /// Its body was skipped.
+    Other { type_name: String },
+}
+
+/// A `VERSIONINFO` resource: the fixed-info statements, then the
+/// `BLOCK`/`VALUE` tree.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VersionInfo {
+    /// Up to four parts, most significant first.
+    pub file_version: Vec<Expr>,
+    pub product_version: Vec<Expr>,
+    pub file_flags_mask: Option<Expr>,
+    pub file_flags: Option<Expr>,
+    pub file_os: Option<Expr>,
+    pub file_type: Option<Expr>,
+    pub file_subtype: Option<Expr>,
+    pub entries: Vec<VersionEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionEntry {
+    Block {
+        key: String,
+        entries: Vec<VersionEntry>,
+        span: Span,
+    },
+    Value {
+        key: String,
+        values: Vec<VersionValue>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionValue {
+    Str(String),
+    /// A 16-bit number, as in `VALUE "Translation", 0x409, 1200`.
+    Word(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Menu {
+    /// `MENUEX` rather than `MENU`.
+    pub extended: bool,
+    pub items: Vec<MenuItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuItem {
+    Separator {
+        span: Span,
+    },
+    /// For `MENU` the options are keywords such as `CHECKED` or `GRAYED`. For
+    /// `MENUEX` they are the type and state expressions, in order.
+    Item {
+        text: String,
+        id: Option<Expr>,
+        options: Vec<Expr>,
+        span: Span,
+    },
+    Popup {
+        text: String,
+        id: Option<Expr>,
+        options: Vec<Expr>,
+        items: Vec<MenuItem>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialogFont {
+    pub point_size: Expr,
+    pub face: String,
+    pub weight: Option<Expr>,
+    pub italic: Option<Expr>,
+    pub char_set: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dialog {
+    /// `DIALOGEX` rather than `DIALOG`.
+    pub extended: bool,
+    pub x: Expr,
+    pub y: Expr,
+    pub width: Expr,
+    pub height: Expr,
+    pub help_id: Option<Expr>,
+    pub style: Option<Expr>,
+    pub ex_style: Option<Expr>,
+    pub caption: Option<String>,
+    pub font: Option<DialogFont>,
+    pub menu: Option<NameOrId>,
+    pub class: Option<NameOrId>,
+    pub controls: Vec<Control>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControlKind {
+    LText,
+    RText,
+    CText,
+    PushButton,
+    DefPushButton,
+    PushBox,
+    CheckBox,
+    AutoCheckBox,
+    RadioButton,
+    AutoRadioButton,
+    State3,
+    Auto3State,
+    GroupBox,
+    EditText,
+    ListBox,
+    ComboBox,
+    ScrollBar,
+    Icon,
+    /// The generic `CONTROL` statement with an explicit class.
+    Control,
+}
+
+impl ControlKind {
+    pub(crate) fn from_keyword(keyword: &str) -> Option<ControlKind> {
+        Some(match keyword {
+            "LTEXT" => ControlKind::LText,
+            "RTEXT" => ControlKind::RText,
+            "CTEXT" => ControlKind::CText,
+            "PUSHBUTTON" => ControlKind::PushButton,
+            "DEFPUSHBUTTON" => ControlKind::DefPushButton,
+            "PUSHBOX" => ControlKind::PushBox,
+            "CHECKBOX" => ControlKind::CheckBox,
+            "AUTOCHECKBOX" => ControlKind::AutoCheckBox,
+            "RADIOBUTTON" => ControlKind::RadioButton,
+            "AUTORADIOBUTTON" => ControlKind::AutoRadioButton,
+            "STATE3" => ControlKind::State3,
+            "AUTO3STATE" => ControlKind::Auto3State,
+            "GROUPBOX" => ControlKind::GroupBox,
+            "EDITTEXT" => ControlKind::EditText,
+            "LISTBOX" => ControlKind::ListBox,
+            "COMBOBOX" => ControlKind::ComboBox,
+            "SCROLLBAR" => ControlKind::ScrollBar,
+            "ICON" => ControlKind::Icon,
+            "CONTROL" => ControlKind::Control,
+            _ => return None,
+        })
+    }
+
+    /// Whether the statement starts with the control's text.
+    pub fn has_text(self) -> bool {
+        !matches!(self, ControlKind::EditText | ControlKind::ListBox | ControlKind::ComboBox | ControlKind::ScrollBar)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Control {
+    pub kind: ControlKind,
+    pub text: Option<NameOrId>,
+    pub id: Expr,
+    /// Only for `CONTROL`: a predefined class keyword such as `BUTTON` (as a
+    /// `Symbol`), or a class name string.
+    pub class: Option<NameOrId>,
+    pub x: Expr,
+    pub y: Expr,
+    /// `None` only for an `ICON` without a size.
+    pub width: Option<Expr>,
+    pub height: Option<Expr>,
+    pub style: Option<Expr>,
+    pub ex_style: Option<Expr>,
+    pub help_id: Option<Expr>,
+    pub span: Span,
+}
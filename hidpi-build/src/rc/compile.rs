@@ -0,0 +1,912 @@
+//! Compiles resource scripts to `.res` entries the way `rc.exe` does, so the
+//! resources can be built without the Windows SDK.
+//!
+//! The preprocessor lines are evaluated: `#include`d headers supply their
+//! `#define`s, `#include`d `.rc`/`.rc2` files are compiled in place, and
+//! `#if`/`#ifdef` pick their branch. The SDK headers themselves are not read;
+//! the constants scripts use from them are built in.
+//!
+//! `MENU`, `MENUEX`, `DIALOG`, `DIALOGEX`, `STRINGTABLE`, `VERSIONINFO`,
+//! `BITMAP`, `ICON`, `TEXTINCLUDE` and file resources of other types, such as
+//! the manifest, are supported. Memory options such as `DISCARDABLE` are ignored.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::ast::*;
+use super::res::{self, Entry, ResId, ResWriter};
+use super::symbols::WINDOWS_SYMBOLS;
+use crate::header;
+
+const WS_POPUP: u32 = 0x8000_0000;
+const WS_CHILD: u32 = 0x4000_0000;
+const WS_VISIBLE: u32 = 0x1000_0000;
+const WS_CAPTION: u32 = 0x00C0_0000;
+const WS_BORDER: u32 = 0x0080_0000;
+const WS_SYSMENU: u32 = 0x0008_0000;
+const WS_GROUP: u32 = 0x0002_0000;
+const WS_TABSTOP: u32 = 0x0001_0000;
+const DS_SETFONT: u32 = 0x0040;
+
+const MF_GRAYED: u16 = 0x0001;
+const MF_DISABLED: u16 = 0x0002;
+const MF_CHECKED: u16 = 0x0008;
+const MF_POPUP: u16 = 0x0010;
+const MF_MENUBARBREAK: u16 = 0x0020;
+const MF_MENUBREAK: u16 = 0x0040;
+const MF_END: u16 = 0x0080;
+const MF_HELP: u16 = 0x4000;
+const MFT_SEPARATOR: u32 = 0x0800;
+
+// The atoms of the predefined control classes
+const BUTTON: u16 = 0x0080;
+const EDIT: u16 = 0x0081;
+const STATIC: u16 = 0x0082;
+const LISTBOX: u16 = 0x0083;
+const SCROLLBAR: u16 = 0x0084;
+const COMBOBOX: u16 = 0x0085;
+
+// US English, the default of `rc` without `/l`
+const DEFAULT_LANGUAGE: u16 = 0x0409;
+
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Settings for `compile_file`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Searched for `#include`s and resource files after the directory of
+    /// the script that names them.
+    pub include_dirs: Vec<PathBuf>,
+    /// Defined before the script is read, as with `rc /d NAME=value`.
+    pub defines: Vec<(String, i64)>,
+    /// The language of resources before the first `LANGUAGE` statement.
+    pub language: u16,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            include_dirs: Vec::new(),
+            defines: Vec::new(),
+            language: DEFAULT_LANGUAGE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    /// The script, header or resource file the error is in.
+    pub file: PathBuf,
+    /// Where in `file`, when it is a script.
+    pub span: Option<Span>,
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}:{}: {}", self.file.display(), span, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+impl Error for CompileError {}
+
+/// A compiled script.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Compiled {
+    pub entries: Vec<Entry>,
+    /// Every file that was read, for `cargo:rerun-if-changed`.
+    pub dependencies: Vec<PathBuf>,
+}
+
+impl Compiled {
+    /// The contents of the `.res` file.
+    pub fn to_res(&self) -> Vec<u8> {
+        res::to_bytes(&self.entries)
+    }
+}
+
+/// Compile the script at `path` and everything it includes.
+pub fn compile_file<P: AsRef<Path>>(path: P, options: &Options) -> Result<Compiled, CompileError> {
+    let mut values: BTreeMap<String, i64> = WINDOWS_SYMBOLS.iter()
+        .map(|&(name, value)| (name.to_owned(), value))
+        .collect();
+    values.insert("RC_INVOKED".to_owned(), 1);
+    values.insert("_WIN32".to_owned(), 1);
+    values.extend(options.defines.iter().cloned());
+
+    let mut compiler = Compiler {
+        options,
+        values,
+        defined: BTreeSet::new(),
+        language: options.language,
+        strings: Vec::new(),
+        next_icon_id: 1,
+        compiled: Compiled::default(),
+        file: PathBuf::new(),
+        depth: 0,
+    };
+    compiler.script(path.as_ref())?;
+
+    for ((language, block), strings) in compiler.strings {
+        let mut data = Vec::new();
+        for s in &strings {
+            let s = s.as_ref().map_or("", String::as_str);
+            data.push_u16(s.encode_utf16().count() as u16);
+            for unit in s.encode_utf16() {
+                data.push_u16(unit);
+            }
+        }
+        compiler.compiled.entries.push(Entry {
+            type_id: ResId::Ordinal(res::RT_STRING),
+            name: ResId::Ordinal(block),
+            language,
+            memory_flags: res::MOVEABLE | res::PURE | res::DISCARDABLE,
+            data,
+        });
+    }
+
+    Ok(compiler.compiled)
+}
+
+// The 16 strings of a string table block, keyed by language and block id
+type StringBlock = ((u16, u16), Vec<Option<String>>);
+
+struct Compiler<'a> {
+    options: &'a Options,
+    // Defines with a numeric value
+    values: BTreeMap<String, i64>,
+    // Defines without one, such as `APSTUDIO_READONLY_SYMBOLS`
+    defined: BTreeSet<String>,
+    language: u16,
+    // String table blocks in order of first use; rc writes them after all
+    // other resources
+    strings: Vec<StringBlock>,
+    // The ordinal of the next RT_ICON image, numbered across the whole script
+    next_icon_id: u16,
+    compiled: Compiled,
+    // The script being compiled, for errors
+    file: PathBuf,
+    depth: usize,
+}
+
+impl<'a> Compiler<'a> {
+    fn error(&self, span: Span, message: String) -> CompileError {
+        CompileError { file: self.file.clone(), span: Some(span), message }
+    }
+
+    fn script(&mut self, path: &Path) -> Result<(), CompileError> {
+        let file_error = |message: String| CompileError { file: path.to_owned(), span: None, message };
+
+        if self.depth == MAX_INCLUDE_DEPTH {
+            return Err(file_error("#include nested too deeply".to_owned()));
+        }
+        let text = super::read_script(path).map_err(|e| file_error(e.to_string()))?;
+        self.compiled.dependencies.push(path.to_owned());
+        let script = super::parse(&text)
+            .map_err(|e| CompileError { file: path.to_owned(), span: Some(e.span), message: e.message })?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("")).to_owned();
+        let outer = std::mem::replace(&mut self.file, path.to_owned());
+        self.depth += 1;
+        let result = self.items(&script.items, &dir);
+        self.depth -= 1;
+        self.file = outer;
+        result
+    }
+
+    fn header(&mut self, path: &Path) -> Result<(), CompileError> {
+        let file_error = |message: String| CompileError { file: path.to_owned(), span: None, message };
+
+        let text = fs::read_to_string(path).map_err(|e| file_error(e.to_string()))?;
+        self.compiled.dependencies.push(path.to_owned());
+        let parsed = header::parse(&text).map_err(|e| file_error(e.to_string()))?;
+
+        for define in parsed.defines {
+            self.values.insert(define.name, define.value);
+        }
+        for skipped in parsed.skipped {
+            self.defined.insert(skipped.name);
+        }
+        Ok(())
+    }
+
+    // `name` in the directory of the script, then in the include directories.
+    // Names are matched without regard to case, as on Windows.
+    fn find_file(&self, dir: &Path, name: &str) -> Option<PathBuf> {
+        let name = name.replace('\\', "/");
+        let dirs = std::iter::once(dir).chain(self.options.include_dirs.iter().map(PathBuf::as_path));
+
+        for dir in dirs {
+            let path = dir.join(&name);
+            if path.is_file() {
+                return Some(path);
+            }
+
+            let (parent, file_name) = match (path.parent(), path.file_name()) {
+                (Some(parent), Some(file_name)) => (parent, file_name.to_string_lossy().into_owned()),
+                _ => continue,
+            };
+            let found = fs::read_dir(parent).ok().and_then(|entries| {
+                entries.filter_map(Result::ok)
+                    .find(|entry| entry.file_name().to_string_lossy().eq_ignore_ascii_case(&file_name))
+                    .map(|entry| entry.path())
+            });
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    // A resource file, found as `find_file` does
+    fn read_file(&mut self, dir: &Path, name: &str) -> Result<(PathBuf, Vec<u8>), String> {
+        let found = self.find_file(dir, name).ok_or_else(|| format!("cannot find `{}`", name))?;
+        let data = fs::read(&found).map_err(|e| format!("{}: {}", found.display(), e))?;
+        self.compiled.dependencies.push(found.clone());
+        Ok((found, data))
+    }
+
+    fn is_defined(&self, name: &str) -> bool {
+        self.values.contains_key(name) || self.defined.contains(name)
+    }
+
+    fn items(&mut self, items: &[Item], dir: &Path) -> Result<(), CompileError> {
+        for item in items {
+            match item {
+                Item::Include { path, span } => {
+                    let is_script = Path::new(path).extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("rc") || ext.eq_ignore_ascii_case("rc2"));
+                    match self.find_file(dir, path) {
+                        Some(found) if is_script => self.script(&found)?,
+                        Some(found) => self.header(&found)?,
+                        None if is_script => return Err(self.error(*span, format!("cannot find `{}`", path))),
+                        // an SDK header, whose constants are built in
+                        None => {},
+                    }
+                },
+                Item::Define { name, value, .. } => {
+                    match header::evaluate(value, &self.values) {
+                        Ok(value) => {
+                            self.values.insert(name.clone(), value);
+                        },
+                        Err(_) => {
+                            self.values.remove(name);
+                            self.defined.insert(name.clone());
+                        },
+                    }
+                },
+                Item::Undef { name, .. } => {
+                    self.values.remove(name);
+                    self.defined.remove(name);
+                },
+                // code_page: scripts are decoded by `read_script`
+                Item::Pragma { .. } => {},
+                Item::Conditional(conditional) => {
+                    let condition = conditional.condition.trim();
+                    let taken = match conditional.kind {
+                        ConditionKind::Ifdef => self.is_defined(condition),
+                        ConditionKind::Ifndef => !self.is_defined(condition),
+                        ConditionKind::If => header::evaluate_condition(condition, &self.values, &|name| self.is_defined(name))
+                            .map_err(|e| self.error(conditional.span, e))?,
+                    };
+                    let branch = if taken { &conditional.then_items } else { &conditional.else_items };
+                    self.items(branch, dir)?;
+                },
+                Item::Language { primary, sub, span } => {
+                    let primary = self.eval(primary).map_err(|e| self.error(*span, e))?;
+                    let sub = self.eval(sub).map_err(|e| self.error(*span, e))?;
+                    self.language = ((sub as u16) << 10) | (primary as u16 & 0x3FF);
+                },
+                Item::Resource(resource) => {
+                    let entries = match &resource.kind {
+                        ResourceKind::File { file_type: FileType::Icon, path } => self.icon(resource, path, dir),
+                        _ => self.resource(resource, dir).map(|entry| vec![entry]),
+                    };
+                    self.compiled.entries.extend(entries.map_err(|e| self.error(resource.span, e))?);
+                },
+                Item::StringTable { strings, span } => {
+                    for (id, s) in strings {
+                        let id = self.eval(id).map_err(|e| self.error(*span, e))? as u16;
+                        self.add_string(id, s).map_err(|e| self.error(*span, e))?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn add_string(&mut self, id: u16, s: &str) -> Result<(), String> {
+        let key = (self.language, id / 16 + 1);
+        let index = match self.strings.iter().position(|(block, _)| *block == key) {
+            Some(index) => index,
+            None => {
+                self.strings.push((key, vec![None; 16]));
+                self.strings.len() - 1
+            },
+        };
+
+        let slot = &mut self.strings[index].1[(id % 16) as usize];
+        if slot.is_some() {
+            return Err(format!("string {} is defined twice", id));
+        }
+        *slot = Some(s.to_owned());
+        Ok(())
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<i64, String> {
+        Ok(match expr {
+            Expr::Number(value) => *value,
+            Expr::Symbol(name) => *self.values.get(name)
+                .ok_or_else(|| format!("`{}` is not defined", name))?,
+            Expr::Neg(inner) => self.eval(inner)?.wrapping_neg(),
+            Expr::BitNot(inner) | Expr::Not(inner) => !self.eval(inner)?,
+            Expr::Binary(lhs, op, rhs) => {
+                let (lhs, rhs) = (self.eval(lhs)?, self.eval(rhs)?);
+                match op {
+                    BinaryOp::Or => lhs | rhs,
+                    BinaryOp::And => lhs & rhs,
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                    BinaryOp::Div if rhs == 0 => return Err("division by zero".to_owned()),
+                    BinaryOp::Div => lhs.wrapping_div(rhs),
+                }
+            },
+        })
+    }
+
+    // A style added to `style`, where `NOT x` terms remove bits instead
+    fn apply_style(&self, expr: &Expr, style: u32) -> Result<u32, String> {
+        match expr {
+            Expr::Binary(lhs, BinaryOp::Or, rhs) => {
+                let style = self.apply_style(lhs, style)?;
+                self.apply_style(rhs, style)
+            },
+            Expr::Not(inner) => Ok(style & !(self.eval(inner)? as u32)),
+            _ => Ok(style | self.eval(expr)? as u32),
+        }
+    }
+
+    fn eval_or_zero(&self, expr: &Option<Expr>) -> Result<i64, String> {
+        expr.as_ref().map_or(Ok(0), |expr| self.eval(expr))
+    }
+
+    // A resource name or type: an undefined symbol is a name, like a string
+    fn res_id(&self, name: &NameOrId) -> Result<ResId, String> {
+        match name {
+            NameOrId::Name(name) => Ok(ResId::Name(name.to_uppercase())),
+            NameOrId::Id(Expr::Symbol(symbol)) if !self.values.contains_key(symbol) => Ok(ResId::Name(symbol.to_uppercase())),
+            NameOrId::Id(expr) => Ok(ResId::Ordinal(self.eval(expr)? as u16)),
+        }
+    }
+
+    fn resource(&mut self, resource: &Resource, dir: &Path) -> Result<Entry, String> {
+        let name = self.res_id(&resource.name)?;
+        let (type_id, memory_flags, data) = match &resource.kind {
+            ResourceKind::Menu(menu) => (ResId::Ordinal(res::RT_MENU), res::MOVEABLE | res::PURE | res::DISCARDABLE, self.menu(menu)?),
+            ResourceKind::Dialog(dialog) => (ResId::Ordinal(res::RT_DIALOG), res::MOVEABLE | res::PURE | res::DISCARDABLE, self.dialog(dialog)?),
+            ResourceKind::VersionInfo(info) => (ResId::Ordinal(res::RT_VERSION), res::MOVEABLE | res::PURE, self.version_info(info)?),
+            ResourceKind::TextInclude(lines) => {
+                let data = lines.iter()
+                    .flat_map(|line| line.chars())
+                    .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+                    .collect();
+                (ResId::Name("TEXTINCLUDE".to_owned()), res::MOVEABLE | res::PURE, data)
+            },
+            ResourceKind::File { file_type, path } => {
+                let (found, data) = self.read_file(dir, path)?;
+                let type_id = match file_type {
+                    FileType::Bitmap => ResId::Ordinal(res::RT_BITMAP),
+                    FileType::Icon => unreachable!("compiled by `icon`"),
+                    FileType::Cursor => return Err("CURSOR resources are not supported".to_owned()),
+                    FileType::Numeric(type_id) => ResId::Ordinal(*type_id),
+                    FileType::Custom(type_name) => self.res_id(&NameOrId::Id(Expr::Symbol(type_name.clone())))?,
+                };
+                let data = if *file_type == FileType::Bitmap {
+                    // without the BITMAPFILEHEADER
+                    if data.len() < 14 || !data.starts_with(b"BM") {
+                        return Err(format!("{} is not a bitmap", found.display()));
+                    }
+                    data[14..].to_vec()
+                } else {
+                    data
+                };
+                (type_id, res::MOVEABLE | res::PURE, data)
+            },
+            ResourceKind::Other { type_name } => return Err(format!("{} resources are not supported", type_name)),
+        };
+
+        Ok(Entry { type_id, name, language: self.language, memory_flags, data })
+    }
+
+    // An .ico file becomes an RT_ICON per image and an RT_GROUP_ICON directory
+    // of them, which refers to the images by ordinal instead of file offset
+    fn icon(&mut self, resource: &Resource, path: &str, dir: &Path) -> Result<Vec<Entry>, String> {
+        let name = self.res_id(&resource.name)?;
+        let (found, data) = self.read_file(dir, path)?;
+        let not_an_icon = || format!("{} is not an icon", found.display());
+
+        let u16_at = |offset: usize| data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+        let u32_at = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        if u16_at(0) != Some(0) || u16_at(2) != Some(1) {
+            return Err(not_an_icon());
+        }
+        let count = u16_at(4).ok_or_else(not_an_icon)?;
+
+        let mut entries = Vec::new();
+        let mut group = Vec::new();
+        group.push_u16(0); // idReserved
+        group.push_u16(1); // idType
+        group.push_u16(count);
+        for index in 0..count as usize {
+            let entry = 6 + index * 16;
+            let header = data.get(entry..entry + 8).ok_or_else(not_an_icon)?;
+            let size = u32_at(entry + 8).ok_or_else(not_an_icon)? as usize;
+            let offset = u32_at(entry + 12).ok_or_else(not_an_icon)? as usize;
+            let image = data.get(offset..offset + size).ok_or_else(not_an_icon)?;
+
+            // The planes and bit count in .ico directories are often 0; rc
+            // takes them from the BITMAPINFOHEADER. PNG images keep them.
+            let (planes, bit_count) = match image.get(0..16) {
+                Some(info) if !image.starts_with(b"\x89PNG") => (u16::from_le_bytes([info[12], info[13]]), u16::from_le_bytes([info[14], info[15]])),
+                _ => (u16::from_le_bytes([header[4], header[5]]), u16::from_le_bytes([header[6], header[7]])),
+            };
+
+            let id = self.next_icon_id;
+            self.next_icon_id += 1;
+            group.extend_from_slice(&header[0..4]); // width, height, colors, reserved
+            group.push_u16(planes);
+            group.push_u16(bit_count);
+            group.push_u32(size as u32);
+            group.push_u16(id);
+
+            entries.push(Entry {
+                type_id: ResId::Ordinal(res::RT_ICON),
+                name: ResId::Ordinal(id),
+                language: self.language,
+                memory_flags: res::MOVEABLE | res::DISCARDABLE,
+                data: image.to_vec(),
+            });
+        }
+        entries.push(Entry {
+            type_id: ResId::Ordinal(res::RT_GROUP_ICON),
+            name,
+            language: self.language,
+            memory_flags: res::MOVEABLE | res::PURE | res::DISCARDABLE,
+            data: group,
+        });
+        Ok(entries)
+    }
+
+    fn menu(&self, menu: &Menu) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        if menu.extended {
+            data.push_u16(1); // wVersion
+            data.push_u16(4); // wOffset, to the items
+            data.push_u32(0); // dwHelpId
+            self.menuex_items(&menu.items, &mut data)?;
+        } else {
+            data.push_u16(0);
+            data.push_u16(0);
+            self.menu_items(&menu.items, &mut data)?;
+        }
+        Ok(data)
+    }
+
+    fn menu_flags(&self, options: &[Expr]) -> Result<u16, String> {
+        let mut flags = 0;
+        for option in options {
+            flags |= match option {
+                Expr::Symbol(option) if option == "CHECKED" => MF_CHECKED,
+                Expr::Symbol(option) if option == "GRAYED" => MF_GRAYED,
+                Expr::Symbol(option) if option == "HELP" => MF_HELP,
+                Expr::Symbol(option) if option == "INACTIVE" => MF_DISABLED,
+                Expr::Symbol(option) if option == "MENUBARBREAK" => MF_MENUBARBREAK,
+                Expr::Symbol(option) if option == "MENUBREAK" => MF_MENUBREAK,
+                _ => self.eval(option)? as u16,
+            };
+        }
+        Ok(flags)
+    }
+
+    fn menu_items(&self, items: &[MenuItem], data: &mut Vec<u8>) -> Result<(), String> {
+        for (index, item) in items.iter().enumerate() {
+            let end = if index + 1 == items.len() { MF_END } else { 0 };
+            match item {
+                MenuItem::Separator { .. } => {
+                    data.push_u16(end);
+                    data.push_u16(0);
+                    data.push_wstr("");
+                },
+                MenuItem::Item { text, id, options, .. } => {
+                    data.push_u16(self.menu_flags(options)? | end);
+                    data.push_u16(self.eval_or_zero(id)? as u16);
+                    data.push_wstr(text);
+                },
+                MenuItem::Popup { text, options, items, .. } => {
+                    data.push_u16(self.menu_flags(options)? | MF_POPUP | end);
+                    data.push_wstr(text);
+                    self.menu_items(items, data)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn menuex_items(&self, items: &[MenuItem], data: &mut Vec<u8>) -> Result<(), String> {
+        for (index, item) in items.iter().enumerate() {
+            let end = if index + 1 == items.len() { 0x80 } else { 0 };
+            data.align_to(4);
+            match item {
+                MenuItem::Separator { .. } => {
+                    data.push_u32(MFT_SEPARATOR);
+                    data.push_u32(0);
+                    data.push_u32(0);
+                    data.push_u16(end);
+                    data.push_wstr("");
+                },
+                MenuItem::Item { text, id, options, .. } | MenuItem::Popup { text, id, options, .. } => {
+                    let option = |index: usize| self.eval_or_zero(&options.get(index).cloned());
+                    data.push_u32(option(0)? as u32); // dwType
+                    data.push_u32(option(1)? as u32); // dwState
+                    data.push_u32(self.eval_or_zero(id)? as u32);
+                    if let MenuItem::Popup { items, .. } = item {
+                        data.push_u16(end | 0x01);
+                        data.push_wstr(text);
+                        data.align_to(4);
+                        data.push_u32(option(2)? as u32); // dwHelpId
+                        self.menuex_items(items, data)?;
+                    } else {
+                        data.push_u16(end);
+                        data.push_wstr(text);
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn dialog(&self, dialog: &Dialog) -> Result<Vec<u8>, String> {
+        let mut style = match &dialog.style {
+            Some(style) => self.apply_style(style, 0)?,
+            None => WS_POPUP | WS_BORDER | WS_SYSMENU,
+        };
+        if dialog.caption.is_some() {
+            style |= WS_CAPTION;
+        }
+        if dialog.font.is_some() {
+            style |= DS_SETFONT;
+        }
+        let ex_style = self.eval_or_zero(&dialog.ex_style)? as u32;
+
+        let mut data = Vec::new();
+        if dialog.extended {
+            data.push_u16(1); // dlgVer
+            data.push_u16(0xFFFF); // signature
+            data.push_u32(self.eval_or_zero(&dialog.help_id)? as u32);
+            data.push_u32(ex_style);
+            data.push_u32(style);
+        } else {
+            data.push_u32(style);
+            data.push_u32(ex_style);
+        }
+        data.push_u16(dialog.controls.len() as u16);
+        for coordinate in &[&dialog.x, &dialog.y, &dialog.width, &dialog.height] {
+            data.push_u16(self.eval(coordinate)? as u16);
+        }
+
+        match &dialog.menu {
+            Some(menu) => data.push_res_id(&self.res_id(menu)?),
+            None => data.push_u16(0),
+        }
+        match &dialog.class {
+            Some(NameOrId::Name(class)) => data.push_wstr(class),
+            Some(NameOrId::Id(id)) => data.push_res_id(&ResId::Ordinal(self.eval(id)? as u16)),
+            None => data.push_u16(0),
+        }
+        data.push_wstr(dialog.caption.as_ref().map_or("", String::as_str));
+
+        if let Some(font) = &dialog.font {
+            data.push_u16(self.eval(&font.point_size)? as u16);
+            if dialog.extended {
+                data.push_u16(self.eval_or_zero(&font.weight)? as u16);
+                data.push(self.eval_or_zero(&font.italic)? as u8);
+                data.push(font.char_set.as_ref().map_or(Ok(1), |char_set| self.eval(char_set))? as u8);
+            }
+            data.push_wstr(&font.face);
+        }
+
+        for control in &dialog.controls {
+            data.align_to(4);
+            self.control(control, dialog.extended, &mut data)?;
+        }
+        Ok(data)
+    }
+
+    fn control(&self, control: &Control, extended: bool, data: &mut Vec<u8>) -> Result<(), String> {
+        // The styles rc.exe gives each statement, as reproduced by llvm-rc.
+        // Radio buttons and combo boxes are not tab stops, whatever the
+        // documentation says.
+        let (class, default_style) = match control.kind {
+            ControlKind::LText => (ResId::Ordinal(STATIC), WS_GROUP),
+            ControlKind::RText => (ResId::Ordinal(STATIC), 0x0002 | WS_GROUP),
+            ControlKind::CText => (ResId::Ordinal(STATIC), 0x0001 | WS_GROUP),
+            ControlKind::PushButton => (ResId::Ordinal(BUTTON), WS_TABSTOP),
+            ControlKind::DefPushButton => (ResId::Ordinal(BUTTON), 0x0001 | WS_TABSTOP),
+            ControlKind::PushBox => (ResId::Ordinal(BUTTON), 0x000A | WS_TABSTOP),
+            ControlKind::CheckBox => (ResId::Ordinal(BUTTON), 0x0002 | WS_TABSTOP),
+            ControlKind::AutoCheckBox => (ResId::Ordinal(BUTTON), 0x0003 | WS_TABSTOP),
+            ControlKind::RadioButton => (ResId::Ordinal(BUTTON), 0x0004),
+            ControlKind::AutoRadioButton => (ResId::Ordinal(BUTTON), 0x0009),
+            ControlKind::State3 => (ResId::Ordinal(BUTTON), 0x0005 | WS_TABSTOP),
+            ControlKind::Auto3State => (ResId::Ordinal(BUTTON), 0x0006 | WS_TABSTOP),
+            ControlKind::GroupBox => (ResId::Ordinal(BUTTON), 0x0007),
+            ControlKind::EditText => (ResId::Ordinal(EDIT), WS_BORDER | WS_TABSTOP),
+            ControlKind::ListBox => (ResId::Ordinal(LISTBOX), 0x0001 | WS_BORDER),
+            ControlKind::ComboBox => (ResId::Ordinal(COMBOBOX), 0),
+            ControlKind::ScrollBar => (ResId::Ordinal(SCROLLBAR), 0),
+            ControlKind::Icon => (ResId::Ordinal(STATIC), 0x0003),
+            ControlKind::Control => {
+                // only the keywords are atoms; a quoted "Button" stays a string
+                let class = match &control.class {
+                    Some(NameOrId::Name(class)) => ResId::Name(class.clone()),
+                    Some(NameOrId::Id(Expr::Symbol(class))) if !self.values.contains_key(class) => match class.to_ascii_uppercase().as_str() {
+                        "BUTTON" => ResId::Ordinal(BUTTON),
+                        "EDIT" => ResId::Ordinal(EDIT),
+                        "STATIC" => ResId::Ordinal(STATIC),
+                        "LISTBOX" => ResId::Ordinal(LISTBOX),
+                        "SCROLLBAR" => ResId::Ordinal(SCROLLBAR),
+                        "COMBOBOX" => ResId::Ordinal(COMBOBOX),
+                        _ => return Err(format!("unknown control class `{}`", class)),
+                    },
+                    Some(NameOrId::Id(id)) => ResId::Ordinal(self.eval(id)? as u16),
+                    None => return Err("CONTROL without a class".to_owned()),
+                };
+                (class, 0)
+            },
+        };
+
+        let base = WS_CHILD | WS_VISIBLE | default_style;
+        let style = match &control.style {
+            Some(style) => self.apply_style(style, base)?,
+            None => base,
+        };
+        let ex_style = self.eval_or_zero(&control.ex_style)? as u32;
+        let id = self.eval(&control.id)?;
+
+        if extended {
+            data.push_u32(self.eval_or_zero(&control.help_id)? as u32);
+            data.push_u32(ex_style);
+            data.push_u32(style);
+        } else {
+            data.push_u32(style);
+            data.push_u32(ex_style);
+        }
+        data.push_u16(self.eval(&control.x)? as u16);
+        data.push_u16(self.eval(&control.y)? as u16);
+        data.push_u16(self.eval_or_zero(&control.width)? as u16);
+        data.push_u16(self.eval_or_zero(&control.height)? as u16);
+        if extended {
+            data.push_u32(id as u32);
+        } else {
+            data.push_u16(id as u16);
+        }
+
+        data.push_res_id(&class);
+        match &control.text {
+            // the icon of an ICON control is a resource name
+            Some(text) if control.kind == ControlKind::Icon => data.push_res_id(&self.res_id(text)?),
+            Some(NameOrId::Name(text)) => data.push_wstr(text),
+            Some(NameOrId::Id(id)) => data.push_res_id(&ResId::Ordinal(self.eval(id)? as u16)),
+            None => data.push_wstr(""),
+        }
+        data.push_u16(0); // no creation data
+        Ok(())
+    }
+
+    fn version_info(&self, info: &VersionInfo) -> Result<Vec<u8>, String> {
+        let version = |parts: &[Expr]| -> Result<(u32, u32), String> {
+            let mut words = [0u32; 4];
+            for (word, part) in words.iter_mut().zip(parts) {
+                *word = self.eval(part)? as u32 & 0xFFFF;
+            }
+            Ok(((words[0] << 16) | words[1], (words[2] << 16) | words[3]))
+        };
+        let (file_ms, file_ls) = version(&info.file_version)?;
+        let (product_ms, product_ls) = version(&info.product_version)?;
+
+        // VS_FIXEDFILEINFO
+        let mut fixed = Vec::new();
+        fixed.push_u32(0xFEEF_04BD);
+        fixed.push_u32(0x0001_0000);
+        fixed.push_u32(file_ms);
+        fixed.push_u32(file_ls);
+        fixed.push_u32(product_ms);
+        fixed.push_u32(product_ls);
+        for field in &[&info.file_flags_mask, &info.file_flags, &info.file_os, &info.file_type, &info.file_subtype] {
+            fixed.push_u32(self.eval_or_zero(field)? as u32);
+        }
+        fixed.push_u32(0); // dwFileDateMS
+        fixed.push_u32(0); // dwFileDateLS
+
+        let children = info.entries.iter()
+            .map(|entry| self.version_entry(entry))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(version_node("VS_VERSION_INFO", &fixed, fixed.len() as u16, false, &children))
+    }
+
+    fn version_entry(&self, entry: &VersionEntry) -> Result<Vec<u8>, String> {
+        match entry {
+            VersionEntry::Block { key, entries, .. } => {
+                let children = entries.iter()
+                    .map(|entry| self.version_entry(entry))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(version_node(key, &[], 0, true, &children))
+            },
+            VersionEntry::Value { key, values, .. } => {
+                let mut value = Vec::new();
+                if values.iter().all(|value| matches!(value, VersionValue::Str(_))) {
+                    let mut text = String::new();
+                    for v in values {
+                        if let VersionValue::Str(s) = v {
+                            text.push_str(s);
+                        }
+                    }
+                    value.push_wstr(&text);
+                    // in characters, with the NUL
+                    Ok(version_node(key, &value, (value.len() / 2) as u16, true, &[]))
+                } else {
+                    for v in values {
+                        match v {
+                            VersionValue::Word(word) => value.push_u16(self.eval(word)? as u16),
+                            VersionValue::Str(_) => return Err(format!("VALUE \"{}\" mixes strings and numbers", key)),
+                        }
+                    }
+                    Ok(version_node(key, &value, value.len() as u16, false, &[]))
+                }
+            },
+        }
+    }
+}
+
+// A VS_VERSIONINFO node: wLength, wValueLength, wType, the key, the value and
+// the children, each starting on a DWORD boundary
+fn version_node(key: &str, value: &[u8], value_length: u16, text: bool, children: &[Vec<u8>]) -> Vec<u8> {
+    let mut node = Vec::new();
+    node.push_u16(0); // wLength, filled in below
+    node.push_u16(value_length);
+    node.push_u16(text as u16);
+    node.push_wstr(key);
+    node.align_to(4);
+    node.extend_from_slice(value);
+    for child in children {
+        node.align_to(4);
+        node.extend_from_slice(child);
+    }
+
+    let length = (node.len() as u16).to_le_bytes();
+    node[..2].copy_from_slice(&length);
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The `.res` files were made by llvm-rc from the scripts preprocessed by
+    // `cpp -P -undef -DRC_INVOKED -D_WIN32`, with a `windows.h` and a
+    // `winres.h` holding `WINDOWS_SYMBOLS`:
+    // `llvm-rc -no-cpp /FO <script>.res <preprocessed>.rc`
+    const GUI_RES: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/DpiAwarenessContext.res"));
+    const FEATURES_RES: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/features.res"));
+    const PLUGIN_RES: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/Resource.res"));
+
+    fn gui_script() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../hidpi-gui/src/DpiAwarenessContext.rc")
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    // Reports the first differing byte rather than two dumps of a few
+    // thousand numbers
+    fn assert_same_res(compiled: &[u8], expected: &[u8]) {
+        if let Some(offset) = compiled.iter().zip(expected).position(|(a, b)| a != b) {
+            panic!("the .res files differ at byte {:#x}: {:#04x} instead of {:#04x}", offset, compiled[offset], expected[offset]);
+        }
+        assert_eq!(compiled.len(), expected.len(), "the .res files have different lengths");
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hidpi-build-compile-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn gui_script_matches_llvm_rc() {
+        let compiled = compile_file(gui_script(), &Options::default()).unwrap();
+        assert_same_res(&compiled.to_res(), GUI_RES);
+    }
+
+    #[test]
+    fn features_script_matches_llvm_rc() {
+        let compiled = compile_file(fixture("features.rc"), &Options::default()).unwrap();
+        assert_same_res(&compiled.to_res(), FEATURES_RES);
+    }
+
+    #[test]
+    fn plugin_script_matches_llvm_rc() {
+        let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("../dll_plugin/src/Resource.rc");
+        let compiled = compile_file(script, &Options::default()).unwrap();
+        assert_same_res(&compiled.to_res(), PLUGIN_RES);
+    }
+
+    #[test]
+    fn every_file_read_is_a_dependency() {
+        let compiled = compile_file(fixture("features.rc"), &Options::default()).unwrap();
+        let names: Vec<String> = compiled.dependencies.iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        for name in &["features.rc", "features.h", "tiny.bmp", "tiny.ico", "tiny.manifest"] {
+            assert!(names.iter().any(|n| n == name), "{} is not in {:?}", name, names);
+        }
+    }
+
+    #[test]
+    fn options_apply_before_the_script() {
+        let dir = scratch_dir("options");
+        fs::create_dir_all(dir.join("include")).unwrap();
+        fs::write(dir.join("include/ids.h"), "#define IDR_DATA 7\n").unwrap();
+        fs::write(dir.join("options.rc"), concat!(
+            "#include \"ids.h\"\n",
+            "#if VARIANT == 2\n",
+            "IDR_DATA RCDATA \"two.bin\"\n",
+            "#else\n",
+            "IDR_DATA RCDATA \"one.bin\"\n",
+            "#endif\n",
+        )).unwrap();
+        fs::write(dir.join("one.bin"), [1]).unwrap();
+        fs::write(dir.join("include/two.bin"), [2]).unwrap();
+
+        let options = Options {
+            include_dirs: vec![dir.join("include")],
+            defines: vec![("VARIANT".to_owned(), 2)],
+            language: 0x0407,
+        };
+        let compiled = compile_file(dir.join("options.rc"), &options).unwrap();
+        assert_eq!(compiled.entries.len(), 1);
+        let entry = &compiled.entries[0];
+        assert_eq!((&entry.type_id, &entry.name, entry.language), (&ResId::Ordinal(10), &ResId::Ordinal(7), 0x0407));
+        assert_eq!(entry.data, [2]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors_name_the_file_and_place() {
+        let dir = scratch_dir("errors");
+        fs::write(dir.join("missing.rc"), "1 BITMAP \"absent.bmp\"\n").unwrap();
+        let error = compile_file(dir.join("missing.rc"), &Options::default()).unwrap_err();
+        assert_eq!(error.file, dir.join("missing.rc"));
+        assert_eq!(error.span.map(|span| (span.line, span.column)), Some((1, 1)));
+
+        fs::write(dir.join("undefined.rc"), "1 MENU\nBEGIN\n  MENUITEM \"A\", ID_UNDEFINED\nEND\n").unwrap();
+        let error = compile_file(dir.join("undefined.rc"), &Options::default()).unwrap_err();
+        assert_eq!(error.file, dir.join("undefined.rc"));
+        assert!(error.span.is_some());
+        assert!(error.message.contains("ID_UNDEFINED"), "{}", error.message);
+
+        let error = compile_file(dir.join("absent.rc"), &Options::default()).unwrap_err();
+        assert_eq!((error.file, error.span), (dir.join("absent.rc"), None));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
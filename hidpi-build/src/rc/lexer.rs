@@ -0,0 +1,247 @@
+use super::ast::Span;
+use super::ParseError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A preprocessor line: the directive name and the rest of the line,
+    /// with comments and line continuations removed.
+    Directive(String, String),
+    Ident(String),
+    Number(i64),
+    /// A decoded string literal; adjacent literals are not joined.
+    Str(String),
+    Punct(char),
+}
+
+pub struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+    line: usize,
+    column: usize,
+    // whether only whitespace has been seen since the last newline
+    at_line_start: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Lexer<'a> {
+        Lexer { source, pos: 0, line: 1, column: 1, at_line_start: true }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.source[self.pos..].chars().nth(offset)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+            self.at_line_start = true;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn span_from(&self, start: usize, line: usize, column: usize) -> Span {
+        Span { start, end: self.pos, line, column }
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            message,
+            span: Span { start: self.pos, end: self.pos, line: self.line, column: self.column },
+        }
+    }
+
+    fn skip_block_comment(&mut self) -> Result<(), ParseError> {
+        let start = self.error("unterminated comment".to_owned());
+        self.bump();
+        self.bump();
+        loop {
+            match self.bump() {
+                Some('*') if self.peek() == Some('/') => {
+                    self.bump();
+                    return Ok(());
+                },
+                Some(_) => {},
+                None => return Err(start),
+            }
+        }
+    }
+
+    fn skip_line_comment(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '\n' {
+                break;
+            }
+            self.bump();
+        }
+    }
+
+    // Skips whitespace and comments, keeping `at_line_start` up to date
+    fn skip_trivia(&mut self) -> Result<(), ParseError> {
+        loop {
+            match (self.peek(), self.peek_at(1)) {
+                (Some('/'), Some('/')) => self.skip_line_comment(),
+                (Some('/'), Some('*')) => self.skip_block_comment()?,
+                (Some(c), _) if c.is_whitespace() => {
+                    self.bump();
+                },
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn directive(&mut self) -> Result<Token, ParseError> {
+        self.bump();
+        let mut text = String::new();
+        loop {
+            match (self.peek(), self.peek_at(1)) {
+                (None, _) | (Some('\n'), _) => break,
+                (Some('\\'), Some('\n')) => {
+                    self.bump();
+                    self.bump();
+                    text.push(' ');
+                },
+                (Some('\\'), Some('\r')) if self.peek_at(2) == Some('\n') => {
+                    self.bump();
+                    self.bump();
+                    self.bump();
+                    text.push(' ');
+                },
+                (Some('/'), Some('/')) => self.skip_line_comment(),
+                (Some('/'), Some('*')) => {
+                    self.skip_block_comment()?;
+                    text.push(' ');
+                },
+                (Some(c), _) => {
+                    self.bump();
+                    text.push(c);
+                },
+            }
+        }
+
+        let text = text.trim();
+        let name_len = text.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(text.len());
+        Ok(Token::Directive(text[..name_len].to_owned(), text[name_len..].trim().to_owned()))
+    }
+
+    fn string(&mut self) -> Result<Token, ParseError> {
+        let unterminated = self.error("unterminated string".to_owned());
+        self.bump();
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err(unterminated),
+                Some('"') => {
+                    // "" is an embedded quote
+                    if self.peek() == Some('"') {
+                        self.bump();
+                        s.push('"');
+                    } else {
+                        return Ok(Token::Str(s));
+                    }
+                },
+                Some('\\') => match self.bump() {
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('a') => s.push('\u{7}'),
+                    Some('0') => s.push('\0'),
+                    Some('\\') => s.push('\\'),
+                    Some('"') => s.push('"'),
+                    Some('x') => {
+                        let mut value = 0u32;
+                        while let Some(digit) = self.peek().and_then(|c| c.to_digit(16)) {
+                            self.bump();
+                            value = value * 16 + digit;
+                        }
+                        s.push(std::char::from_u32(value).unwrap_or('\u{fffd}'));
+                    },
+                    Some(c) => {
+                        s.push('\\');
+                        s.push(c);
+                    },
+                    None => return Err(unterminated),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Token, ParseError> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !c.is_ascii_alphanumeric() {
+                break;
+            }
+            self.bump();
+        }
+
+        let literal = &self.source[start..self.pos];
+        let digits = literal.trim_end_matches(&['l', 'L', 'u', 'U'][..]);
+        let parsed = if digits.starts_with("0x") || digits.starts_with("0X") {
+            u64::from_str_radix(&digits[2..], 16)
+        } else {
+            digits.parse::<u64>()
+        };
+        parsed
+            .map(|value| Token::Number(value as i64))
+            .map_err(|_| self.error(format!("invalid number `{}`", literal)))
+    }
+
+    /// The next token and its span, or `None` at the end of the source.
+    pub fn next_token(&mut self) -> Result<Option<(Token, Span)>, ParseError> {
+        self.skip_trivia()?;
+
+        let (start, line, column) = (self.pos, self.line, self.column);
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let line_start = self.at_line_start;
+        self.at_line_start = false;
+        let token = if c == '#' && line_start {
+            self.directive()?
+        } else if c == '"' {
+            self.string()?
+        } else if (c == 'L' || c == 'l') && self.peek_at(1) == Some('"') {
+            // wide string literal
+            self.bump();
+            self.string()?
+        } else if c.is_ascii_digit() {
+            self.number()?
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let ident_start = self.pos;
+            while let Some(c) = self.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                self.bump();
+            }
+            Token::Ident(self.source[ident_start..self.pos].to_owned())
+        } else if ",|&+-*/~(){}".contains(c) {
+            self.bump();
+            Token::Punct(c)
+        } else {
+            return Err(self.error(format!("unexpected `{}`", c)));
+        };
+
+        Ok(Some((token, self.span_from(start, line, column))))
+    }
+
+    pub fn tokenize(mut self) -> Result<Vec<(Token, Span)>, ParseError> {
+        let mut tokens = Vec::new();
+        while let Some(token) = self.next_token()? {
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+}
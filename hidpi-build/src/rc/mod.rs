@@ -0,0 +1,353 @@
+//! A parser for the subset of resource scripts (`.rc`) used by these projects:
+//! preprocessor lines, `LANGUAGE`, `MENU`/`MENUEX`, `DIALOG`/`DIALOGEX` and their
+//! controls, `STRINGTABLE`, `VERSIONINFO`, file resources such as `BITMAP`,
+//! and `TEXTINCLUDE`.
+//!
+//! Preprocessor lines are kept in the tree rather than evaluated, and symbols
+//! are not resolved, so the script can be inspected as written. Every node
+//! carries the `Span` it was parsed from. `compile` evaluates them and turns
+//! the tree into a `.res` file.
+
+pub mod ast;
+pub mod compile;
+mod lexer;
+mod parser;
+pub mod res;
+mod symbols;
+
+pub use self::ast::*;
+pub use self::compile::{compile_file, CompileError, Compiled, Options};
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parse the text of a resource script.
+pub fn parse(source: &str) -> Result<ResourceScript, ParseError> {
+    parser::Parser::new(source)?.script()
+}
+
+/// Read a resource script, which Visual Studio saves as UTF-16 with a byte
+/// order mark, and as UTF-8 or the ANSI code page otherwise. Bytes that are
+/// not UTF-8 are read as Latin-1.
+pub fn read_script<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = bytes[2..].chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+            .collect();
+        return String::from_utf16(&units).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+    }
+
+    let bytes = if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) { bytes[3..].to_vec() } else { bytes };
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(e) => Ok(e.into_bytes().into_iter().map(char::from).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUI_SCRIPT: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../hidpi-gui/src/DpiAwarenessContext.rc"));
+    const FEATURES_SCRIPT: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/features.rc"));
+    const PLUGIN_SCRIPT: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../dll_plugin/src/Resource.rc"));
+
+    // Every item, with those in both branches of conditionals, in order
+    fn flatten(items: &[Item]) -> Vec<&Item> {
+        let mut flat = Vec::new();
+        for item in items {
+            flat.push(item);
+            if let Item::Conditional(conditional) = item {
+                flat.extend(flatten(&conditional.then_items));
+                flat.extend(flatten(&conditional.else_items));
+            }
+        }
+        flat
+    }
+
+    fn resources(script: &ResourceScript) -> Vec<&Resource> {
+        flatten(&script.items).into_iter()
+            .filter_map(|item| match item {
+                Item::Resource(resource) => Some(resource),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn symbol(name: &str) -> NameOrId {
+        NameOrId::Id(Expr::Symbol(name.to_owned()))
+    }
+
+    fn menu_texts(items: &[MenuItem]) -> Vec<String> {
+        items.iter()
+            .map(|item| match item {
+                MenuItem::Separator { .. } => "-".to_owned(),
+                MenuItem::Item { text, .. } => text.clone(),
+                MenuItem::Popup { text, items, .. } => format!("{} [{}]", text, menu_texts(items).join(", ")),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_gui_script() {
+        let script = parse(GUI_SCRIPT).unwrap();
+        assert!(matches!(&script.items[0], Item::Include { path, .. } if path == "resource.h"));
+
+        let resources = resources(&script);
+        let names: Vec<_> = resources.iter().map(|resource| &resource.name).collect();
+        assert_eq!(names, vec![
+            &symbol("IDC_MAINMENU"),
+            &NameOrId::Id(Expr::Number(1)),
+            &NameOrId::Id(Expr::Number(2)),
+            &NameOrId::Id(Expr::Number(3)),
+            &symbol("IDD_DIALOG1"),
+            &symbol("GUIDELINES"),
+        ]);
+
+        match &resources[0].kind {
+            ResourceKind::Menu(menu) => {
+                assert!(!menu.extended);
+                assert_eq!(menu_texts(&menu.items), vec![
+                    "&File [Item 1 [Sub Item 1, Sub Item 2, Sub Item 3], Item 2, Item 3, E&xit]",
+                    "Edit [Edit Item 1 [Edit Sub Item 1, Edit Sub Item 2, Edit Sub Item 3], Edit Item 2, Edit Item 3]",
+                    "View [View Item 1 [View Sub Item 1, View Sub Item 2, View Sub Item 3], View Item 2, View Item 3]",
+                ]);
+            },
+            kind => panic!("{:?}", kind),
+        }
+
+        match &resources[2].kind {
+            ResourceKind::TextInclude(lines) => assert_eq!(lines.concat(), "#ifndef APSTUDIO_INVOKED\r\n#include \"targetver.h\"\r\n#endif\r\n#define APSTUDIO_HIDDEN_SYMBOLS\r\n#include \"windows.h\"\r\n#undef APSTUDIO_HIDDEN_SYMBOLS\r\n\0"),
+            kind => panic!("{:?}", kind),
+        }
+
+        match &resources[4].kind {
+            ResourceKind::Dialog(dialog) => {
+                assert!(dialog.extended);
+                assert_eq!((&dialog.x, &dialog.y, &dialog.width, &dialog.height), (&Expr::Number(0), &Expr::Number(0), &Expr::Number(304), &Expr::Number(339)));
+                assert_eq!(dialog.caption.as_deref(), Some("High DPI Awareness Sample"));
+                let font = dialog.font.as_ref().unwrap();
+                assert_eq!((&font.point_size, font.face.as_str()), (&Expr::Number(9), "Segoe UI"));
+                let controls: Vec<_> = dialog.controls.iter().map(|control| (control.kind, &control.id)).collect();
+                assert_eq!(controls, vec![
+                    (ControlKind::PushButton, &Expr::Symbol("IDC_BUTTON_UNAWARE".to_owned())),
+                    (ControlKind::PushButton, &Expr::Symbol("IDC_BUTTON_SYSTEM".to_owned())),
+                    (ControlKind::PushButton, &Expr::Symbol("IDC_BUTTON_81".to_owned())),
+                    (ControlKind::PushButton, &Expr::Symbol("IDC_BUTTON_1607".to_owned())),
+                    (ControlKind::PushButton, &Expr::Symbol("IDC_BUTTON_1703".to_owned())),
+                    (ControlKind::EditText, &Expr::Symbol("IDC_EDIT1".to_owned())),
+                    (ControlKind::PushButton, &Expr::Symbol("IDC_BUTTON_1803".to_owned())),
+                ]);
+                // NOT removes the border from the edit control's default style
+                let edit_style = dialog.controls[5].style.as_ref().unwrap();
+                assert!(matches!(edit_style, Expr::Binary(_, BinaryOp::Or, not) if **not == Expr::Not(Box::new(Expr::Symbol("WS_BORDER".to_owned())))));
+                assert_eq!(dialog.controls[3].span.line, 115);
+            },
+            kind => panic!("{:?}", kind),
+        }
+
+        assert!(matches!(&resources[5].kind, ResourceKind::Other { type_name } if type_name == "DESIGNINFO"));
+    }
+
+    #[test]
+    fn the_features_script() {
+        let script = parse(FEATURES_SCRIPT).unwrap();
+        let resources = resources(&script);
+
+        let kinds: Vec<_> = match &resources[0].kind {
+            ResourceKind::Dialog(dialog) => {
+                assert!(!dialog.extended);
+                dialog.controls.iter().map(|control| control.kind).collect()
+            },
+            kind => panic!("{:?}", kind),
+        };
+        assert_eq!(kinds, vec![
+            ControlKind::LText, ControlKind::RText, ControlKind::CText, ControlKind::DefPushButton, ControlKind::PushButton,
+            ControlKind::CheckBox, ControlKind::AutoCheckBox, ControlKind::RadioButton, ControlKind::AutoRadioButton,
+            ControlKind::State3, ControlKind::Auto3State, ControlKind::GroupBox, ControlKind::EditText, ControlKind::ListBox,
+            ControlKind::ComboBox, ControlKind::ScrollBar, ControlKind::Control, ControlKind::Control,
+        ]);
+
+        match &resources[1].kind {
+            ResourceKind::Dialog(dialog) => {
+                assert!(dialog.extended);
+                assert_eq!(dialog.help_id, Some(Expr::Number(77)));
+                assert_eq!(dialog.class, Some(NameOrId::Name("ExtendedClass".to_owned())));
+                assert_eq!(dialog.controls[0].help_id, Some(Expr::Number(42)));
+                assert_eq!(dialog.controls[2].text, Some(symbol("IDI_TINY")));
+            },
+            kind => panic!("{:?}", kind),
+        }
+
+        match &resources[2].kind {
+            ResourceKind::Menu(menu) => assert_eq!(menu_texts(&menu.items), vec!["&File [&Open, &Close, -, E&xit]", "&Help"]),
+            kind => panic!("{:?}", kind),
+        }
+
+        let files: Vec<_> = resources.iter()
+            .filter_map(|resource| match &resource.kind {
+                ResourceKind::File { file_type, path } => Some((&resource.name, file_type.clone(), path.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(files, vec![
+            (&symbol("IDB_TINY"), FileType::Bitmap, "tiny.bmp"),
+            (&symbol("IDI_TINY"), FileType::Icon, "tiny.ico"),
+            // RCDATA is the predefined RT_RCDATA
+            (&symbol("NAMED"), FileType::Numeric(10), "tiny.manifest"),
+            (&NameOrId::Id(Expr::Number(1)), FileType::Numeric(24), "tiny.manifest"),
+        ]);
+
+        match &resources.last().unwrap().kind {
+            ResourceKind::VersionInfo(info) => {
+                assert_eq!(info.file_version, vec![Expr::Number(1), Expr::Number(2), Expr::Number(3), Expr::Number(4)]);
+                assert_eq!(info.file_os, Some(Expr::Number(0x40004)));
+                assert_eq!(info.entries.len(), 2);
+            },
+            kind => panic!("{:?}", kind),
+        }
+
+        let tables: Vec<_> = flatten(&script.items).into_iter()
+            .filter_map(|item| match item {
+                Item::StringTable { strings, .. } => Some(strings.iter().map(|(_id, text)| text.as_str()).collect::<Vec<_>>()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(tables, vec![vec!["Hello", "World\tand \"quotes\"", "Far away"], vec!["Hallo"]]);
+    }
+
+    #[test]
+    fn the_plugin_script() {
+        let script = parse(PLUGIN_SCRIPT).unwrap();
+        let includes: Vec<_> = flatten(&script.items).into_iter()
+            .filter_map(|item| match item {
+                Item::Include { path, .. } => Some(path.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(includes, vec!["resource.h", "winres.h"]);
+
+        let plugin_resources = resources(&script);
+        let names: Vec<_> = plugin_resources.iter().map(|resource| &resource.name).collect();
+        assert_eq!(names, vec![
+            &NameOrId::Id(Expr::Number(1)),
+            &NameOrId::Id(Expr::Number(2)),
+            &NameOrId::Id(Expr::Number(3)),
+            &symbol("IDB_BITMAP1"),
+        ]);
+        match &plugin_resources[1].kind {
+            ResourceKind::TextInclude(lines) => assert_eq!(lines.concat(), "#include \"winres.h\"\r\n\0"),
+            kind => panic!("{:?}", kind),
+        }
+        assert_eq!(plugin_resources[3].kind, ResourceKind::File { file_type: FileType::Bitmap, path: "PC.bmp".to_owned() });
+
+        // and as the plugin's build script compiles it, with the variants of
+        // its bitmap and their table
+        let plugin_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../dll_plugin/src");
+        let images = [crate::ImageSet::scaled_files("IDB_BITMAP1", plugin_dir.join("PC.bmp")).unwrap()];
+        let packed = crate::images::pack(&images, Path::new("out")).unwrap();
+        let script = parse(&crate::images_script(&plugin_dir.join("Resource.rc"), &packed)).unwrap();
+        assert!(matches!(&script.items[0], Item::Include { path, .. } if path.ends_with("dll_plugin/src/Resource.rc")));
+        let files: Vec<_> = resources(&script).iter()
+            .map(|resource| match &resource.kind {
+                ResourceKind::File { file_type, path } => (&resource.name, file_type.clone(), path.rsplit('/').next().unwrap()),
+                kind => panic!("{:?}", kind),
+            })
+            .collect();
+        assert_eq!(files, vec![
+            (&symbol("DPI_IDB_BITMAP1_125"), FileType::Bitmap, "PC.scale-125.bmp"),
+            (&symbol("DPI_IDB_BITMAP1_150"), FileType::Bitmap, "PC.scale-150.bmp"),
+            (&symbol("DPI_IDB_BITMAP1_200"), FileType::Bitmap, "PC.scale-200.bmp"),
+            (&symbol("IDB_BITMAP1"), FileType::Custom("DPIIMAGE".to_owned()), "IDB_BITMAP1.dpiimage"),
+        ]);
+    }
+
+    #[test]
+    fn types_are_keywords_or_names() {
+        let script = parse("A rcdata \"a.bin\"\nB Html \"b.htm\"\nC \"RCDATA\" \"c.bin\"\nD MYTYPE \"d.bin\"\n").unwrap();
+        let types: Vec<_> = resources(&script).iter()
+            .map(|resource| match &resource.kind {
+                ResourceKind::File { file_type, .. } => file_type.clone(),
+                kind => panic!("{:?}", kind),
+            })
+            .collect();
+        assert_eq!(types, vec![
+            FileType::Numeric(10),
+            FileType::Numeric(23),
+            FileType::Custom("RCDATA".to_owned()),
+            FileType::Custom("MYTYPE".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn conditionals_keep_both_branches() {
+        let script = parse("#ifdef A\n1 MENU BEGIN END\n#elif B > 1\n2 MENU BEGIN END\n#else\n3 MENU BEGIN END\n#endif\n").unwrap();
+        let conditional = match &script.items[..] {
+            [Item::Conditional(conditional)] => conditional,
+            items => panic!("{:?}", items),
+        };
+        assert_eq!((conditional.kind, conditional.condition.as_str()), (ConditionKind::Ifdef, "A"));
+        assert_eq!(resources(&script).len(), 3);
+        match &conditional.else_items[..] {
+            [Item::Conditional(elif)] => {
+                assert_eq!((elif.kind, elif.condition.as_str()), (ConditionKind::If, "B > 1"));
+                assert_eq!(elif.else_items.len(), 1);
+            },
+            items => panic!("{:?}", items),
+        }
+    }
+
+    #[test]
+    fn errors_say_where() {
+        let error = parse("1 MENU\nBEGIN\n    MENUITEM 5, 1\nEND\n").unwrap_err();
+        assert_eq!((error.span.line, error.span.column), (3, 14));
+
+        let error = parse("#ifdef A\n1 MENU BEGIN END\n").unwrap_err();
+        assert_eq!(error.message, "missing #endif");
+
+        let error = parse("\n  #endif\n").unwrap_err();
+        assert_eq!((error.span.line, error.span.column), (2, 3));
+        assert_eq!(error.to_string(), "2:3: #endif without #if");
+
+        assert!(parse("1 DIALOG 0, 0, 10\nBEGIN\nEND\n").is_err());
+        assert!(parse("\"unterminated\n").is_err());
+    }
+
+    #[test]
+    fn scripts_are_read_as_utf16_or_utf8() {
+        let dir = std::env::temp_dir().join(format!("hidpi-build-read-script-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let utf16: Vec<u8> = [0xFEFF_u16].iter().cloned().chain("1 MENU \u{e9}".encode_utf16()).flat_map(u16::to_le_bytes).collect();
+        fs::write(dir.join("utf16.rc"), utf16).unwrap();
+        assert_eq!(read_script(dir.join("utf16.rc")).unwrap(), "1 MENU \u{e9}");
+
+        fs::write(dir.join("utf8.rc"), b"\xEF\xBB\xBF1 MENU \xC3\xA9").unwrap();
+        assert_eq!(read_script(dir.join("utf8.rc")).unwrap(), "1 MENU \u{e9}");
+
+        // the ANSI code page, read as Latin-1
+        fs::write(dir.join("ansi.rc"), b"1 MENU \xE9").unwrap();
+        assert_eq!(read_script(dir.join("ansi.rc")).unwrap(), "1 MENU \u{e9}");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,646 @@
+use super::ast::*;
+use super::lexer::{Lexer, Token};
+use super::ParseError;
+
+// Load and memory attributes accepted, and ignored, after a resource type
+const MEMORY_OPTIONS: &[&str] = &[
+    "PRELOAD", "LOADONCALL", "FIXED", "MOVEABLE", "DISCARDABLE", "PURE", "IMPURE", "SHARED", "NONSHARED",
+];
+
+const MENU_OPTIONS: &[&str] = &["CHECKED", "GRAYED", "HELP", "INACTIVE", "MENUBARBREAK", "MENUBREAK"];
+
+// The type keywords that stand for a predefined `RT_` ordinal
+const PREDEFINED_TYPES: &[(&str, u16)] = &[
+    ("FONTDIR", 7), ("FONT", 8), ("RCDATA", 10), ("MESSAGETABLE", 11), ("DLGINCLUDE", 17),
+    ("PLUGPLAY", 19), ("VXD", 20), ("ANICURSOR", 21), ("ANIICON", 22), ("HTML", 23),
+];
+
+pub struct Parser {
+    tokens: Vec<(Token, Span)>,
+    pos: usize,
+    eof: Span,
+}
+
+impl Parser {
+    pub fn new(source: &str) -> Result<Parser, ParseError> {
+        let tokens = Lexer::new(source).tokenize()?;
+        let last_line = source.rfind('\n').map_or(0, |newline| newline + 1);
+        let eof = Span {
+            start: source.len(),
+            end: source.len(),
+            line: source.matches('\n').count() + 1,
+            column: source[last_line..].chars().count() + 1,
+        };
+        Ok(Parser { tokens, pos: 0, eof })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _span)| token)
+    }
+
+    fn peek_span(&self) -> Span {
+        self.tokens.get(self.pos).map_or(self.eof, |(_token, span)| *span)
+    }
+
+    fn prev_span(&self) -> Span {
+        self.pos.checked_sub(1)
+            .and_then(|prev| self.tokens.get(prev))
+            .map_or(self.eof, |(_token, span)| *span)
+    }
+
+    fn next(&mut self) -> Result<(Token, Span), ParseError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            },
+            None => Err(self.error_here("unexpected end of script")),
+        }
+    }
+
+    fn error_here(&self, message: &str) -> ParseError {
+        ParseError { message: message.to_owned(), span: self.peek_span() }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(ident)) => ident.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_punct(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(c));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_comma(&mut self) -> Result<(), ParseError> {
+        if self.eat_punct(',') {
+            Ok(())
+        } else {
+            Err(self.error_here("expected `,`"))
+        }
+    }
+
+    fn is_begin(&self) -> bool {
+        self.is_keyword("BEGIN") || self.peek() == Some(&Token::Punct('{'))
+    }
+
+    fn is_end(&self) -> bool {
+        self.is_keyword("END") || self.peek() == Some(&Token::Punct('}'))
+    }
+
+    fn expect_begin(&mut self) -> Result<(), ParseError> {
+        if self.is_begin() {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error_here("expected BEGIN"))
+        }
+    }
+
+    fn expect_string(&mut self) -> Result<String, ParseError> {
+        match self.peek() {
+            Some(Token::Str(_)) => match self.next()? {
+                (Token::Str(s), _) => Ok(s),
+                _ => unreachable!(),
+            },
+            _ => Err(self.error_here("expected a string")),
+        }
+    }
+
+    fn skip_memory_options(&mut self) {
+        while MEMORY_OPTIONS.iter().any(|option| self.is_keyword(option)) {
+            self.pos += 1;
+        }
+    }
+
+    // Skips a BEGIN ... END block, including nested blocks
+    fn skip_block(&mut self) -> Result<(), ParseError> {
+        self.expect_begin()?;
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_begin() {
+                depth += 1;
+            } else if self.is_end() {
+                depth -= 1;
+            }
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    pub fn script(&mut self) -> Result<ResourceScript, ParseError> {
+        let items = self.items(false)?;
+        Ok(ResourceScript { items })
+    }
+
+    fn items(&mut self, in_conditional: bool) -> Result<Vec<Item>, ParseError> {
+        let mut items = Vec::new();
+
+        loop {
+            let directive = match self.peek() {
+                None if in_conditional => return Err(self.error_here("missing #endif")),
+                None => break,
+                Some(Token::Directive(name, _rest)) => Some(name.clone()),
+                Some(_) => None,
+            };
+
+            let directive = match directive {
+                Some(directive) => directive,
+                None => {
+                    items.push(self.statement()?);
+                    continue;
+                },
+            };
+
+            match directive.as_str() {
+                "else" | "elif" | "endif" if in_conditional => break,
+                "else" | "elif" | "endif" => return Err(self.error_here(&format!("#{} without #if", directive))),
+                "if" | "ifdef" | "ifndef" => items.push(Item::Conditional(self.conditional()?)),
+                _ => {
+                    let (rest, span) = match self.next()? {
+                        (Token::Directive(_name, rest), span) => (rest, span),
+                        _ => unreachable!(),
+                    };
+                    items.push(match directive.as_str() {
+                        "include" => Item::Include {
+                            path: rest.trim_matches(|c| c == '"' || c == '<' || c == '>').to_owned(),
+                            span,
+                        },
+                        "define" => {
+                            let name_len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+                            Item::Define { name: rest[..name_len].to_owned(), value: rest[name_len..].trim().to_owned(), span }
+                        },
+                        "undef" => Item::Undef { name: rest, span },
+                        "pragma" => Item::Pragma { text: rest, span },
+                        _ => return Err(ParseError { message: format!("unsupported directive #{}", directive), span }),
+                    });
+                },
+            }
+        }
+
+        Ok(items)
+    }
+
+    fn conditional(&mut self) -> Result<Conditional, ParseError> {
+        let (kind, condition, span) = match self.next()? {
+            (Token::Directive(name, condition), span) => {
+                let kind = match name.as_str() {
+                    "if" => ConditionKind::If,
+                    "ifdef" => ConditionKind::Ifdef,
+                    _ => ConditionKind::Ifndef,
+                };
+                (kind, condition, span)
+            },
+            _ => unreachable!(),
+        };
+        self.conditional_body(kind, condition, span)
+    }
+
+    // Everything after the opening directive, up to and including the #endif
+    fn conditional_body(&mut self, kind: ConditionKind, condition: String, span: Span) -> Result<Conditional, ParseError> {
+        let then_items = self.items(true)?;
+        let mut else_items = Vec::new();
+
+        match self.next()? {
+            (Token::Directive(ref name, _), _) if name == "else" => {
+                else_items = self.items(true)?;
+                match self.next()? {
+                    (Token::Directive(ref name, _), _) if name == "endif" => {},
+                    (_, span) => return Err(ParseError { message: "expected #endif".to_owned(), span }),
+                }
+            },
+            (Token::Directive(ref name, ref elif_condition), elif_span) if name == "elif" => {
+                let nested = self.conditional_body(ConditionKind::If, elif_condition.clone(), elif_span)?;
+                else_items.push(Item::Conditional(nested));
+            },
+            _ => {},
+        }
+
+        Ok(Conditional { kind, condition, then_items, else_items, span: span.to(self.prev_span()) })
+    }
+
+    fn statement(&mut self) -> Result<Item, ParseError> {
+        let start = self.peek_span();
+
+        if self.eat_keyword("LANGUAGE") {
+            let primary = self.expr()?;
+            self.expect_comma()?;
+            let sub = self.expr()?;
+            return Ok(Item::Language { primary, sub, span: start.to(self.prev_span()) });
+        }
+
+        if self.eat_keyword("STRINGTABLE") {
+            self.skip_memory_options();
+            self.optional_statements()?;
+            self.expect_begin()?;
+            let mut strings = Vec::new();
+            while !self.is_end() {
+                let id = self.expr()?;
+                self.eat_punct(',');
+                strings.push((id, self.expect_string()?));
+            }
+            self.next()?;
+            return Ok(Item::StringTable { strings, span: start.to(self.prev_span()) });
+        }
+
+        let name = self.name_or_id()?;
+        let (type_token, type_span) = self.next()?;
+        let kind = match type_token {
+            Token::Ident(type_name) => match type_name.to_ascii_uppercase().as_str() {
+                "MENU" => ResourceKind::Menu(self.menu(false)?),
+                "MENUEX" => ResourceKind::Menu(self.menu(true)?),
+                "DIALOG" => ResourceKind::Dialog(Box::new(self.dialog(false)?)),
+                "DIALOGEX" => ResourceKind::Dialog(Box::new(self.dialog(true)?)),
+                "TEXTINCLUDE" => {
+                    self.skip_memory_options();
+                    self.expect_begin()?;
+                    let mut lines = Vec::new();
+                    while !self.is_end() {
+                        lines.push(self.expect_string()?);
+                    }
+                    self.next()?;
+                    ResourceKind::TextInclude(lines)
+                },
+                "VERSIONINFO" => ResourceKind::VersionInfo(self.version_info()?),
+                "BITMAP" => self.file(FileType::Bitmap)?,
+                "ICON" => self.file(FileType::Icon)?,
+                "CURSOR" => self.file(FileType::Cursor)?,
+                _ => {
+                    self.skip_memory_options();
+                    match self.peek() {
+                        Some(Token::Str(_)) => match PREDEFINED_TYPES.iter().find(|(keyword, _)| type_name.eq_ignore_ascii_case(keyword)) {
+                            Some(&(_, type_id)) => self.file(FileType::Numeric(type_id))?,
+                            None => self.file(FileType::Custom(type_name))?,
+                        },
+                        _ => self.other(type_name)?,
+                    }
+                },
+            },
+            Token::Str(type_name) => self.file(FileType::Custom(type_name))?,
+            Token::Number(number) if (0..=0xFFFF).contains(&number) => {
+                self.skip_memory_options();
+                match self.peek() {
+                    Some(Token::Str(_)) => ResourceKind::File { file_type: FileType::Numeric(number as u16), path: self.expect_string()? },
+                    _ => self.other(number.to_string())?,
+                }
+            },
+            _ => return Err(ParseError { message: "expected a resource type".to_owned(), span: type_span }),
+        };
+
+        Ok(Item::Resource(Resource { name, kind, span: start.to(self.prev_span()) }))
+    }
+
+    fn file(&mut self, file_type: FileType) -> Result<ResourceKind, ParseError> {
+        self.skip_memory_options();
+        Ok(ResourceKind::File { file_type, path: self.expect_string()? })
+    }
+
+    fn other(&mut self, type_name: String) -> Result<ResourceKind, ParseError> {
+        self.skip_memory_options();
+        self.optional_statements()?;
+        self.skip_block()?;
+        Ok(ResourceKind::Other { type_name })
+    }
+
+    fn version_info(&mut self) -> Result<VersionInfo, ParseError> {
+        let mut info = VersionInfo::default();
+
+        while !self.is_begin() {
+            let keyword = match self.next()? {
+                (Token::Ident(keyword), _) => keyword.to_ascii_uppercase(),
+                (_, span) => return Err(ParseError { message: "expected a VERSIONINFO statement or BEGIN".to_owned(), span }),
+            };
+            let field = match keyword.as_str() {
+                "FILEVERSION" | "PRODUCTVERSION" => {
+                    let mut parts = vec![self.expr()?];
+                    parts.extend(self.optional_args(3)?);
+                    if keyword == "FILEVERSION" {
+                        info.file_version = parts;
+                    } else {
+                        info.product_version = parts;
+                    }
+                    continue;
+                },
+                "FILEFLAGSMASK" => &mut info.file_flags_mask,
+                "FILEFLAGS" => &mut info.file_flags,
+                "FILEOS" => &mut info.file_os,
+                "FILETYPE" => &mut info.file_type,
+                "FILESUBTYPE" => &mut info.file_subtype,
+                _ => return Err(ParseError { message: format!("unknown VERSIONINFO statement `{}`", keyword), span: self.prev_span() }),
+            };
+            *field = Some(self.expr()?);
+        }
+
+        info.entries = self.version_entries()?;
+        Ok(info)
+    }
+
+    fn version_entries(&mut self) -> Result<Vec<VersionEntry>, ParseError> {
+        self.expect_begin()?;
+        let mut entries = Vec::new();
+
+        while !self.is_end() {
+            let start = self.peek_span();
+            if self.eat_keyword("BLOCK") {
+                let key = self.expect_string()?;
+                let children = self.version_entries()?;
+                entries.push(VersionEntry::Block { key, entries: children, span: start.to(self.prev_span()) });
+            } else if self.eat_keyword("VALUE") {
+                let key = self.expect_string()?;
+                let mut values = Vec::new();
+                while self.eat_punct(',') {
+                    values.push(match self.peek() {
+                        Some(Token::Str(_)) => VersionValue::Str(self.expect_string()?),
+                        _ => VersionValue::Word(self.expr()?),
+                    });
+                }
+                entries.push(VersionEntry::Value { key, values, span: start.to(self.prev_span()) });
+            } else {
+                return Err(self.error_here("expected BLOCK or VALUE"));
+            }
+        }
+
+        self.next()?;
+        Ok(entries)
+    }
+
+    // LANGUAGE, CHARACTERISTICS and VERSION before a resource body
+    fn optional_statements(&mut self) -> Result<(), ParseError> {
+        loop {
+            if self.eat_keyword("LANGUAGE") {
+                self.expr()?;
+                self.expect_comma()?;
+                self.expr()?;
+            } else if self.eat_keyword("CHARACTERISTICS") || self.eat_keyword("VERSION") {
+                self.expr()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name_or_id(&mut self) -> Result<NameOrId, ParseError> {
+        match self.peek() {
+            Some(Token::Str(_)) => Ok(NameOrId::Name(self.expect_string()?)),
+            _ => Ok(NameOrId::Id(self.expr()?)),
+        }
+    }
+
+    fn menu(&mut self, extended: bool) -> Result<Menu, ParseError> {
+        self.skip_memory_options();
+        self.optional_statements()?;
+        let items = self.menu_items(extended)?;
+        Ok(Menu { extended, items })
+    }
+
+    fn menu_items(&mut self, extended: bool) -> Result<Vec<MenuItem>, ParseError> {
+        self.expect_begin()?;
+        let mut items = Vec::new();
+
+        while !self.is_end() {
+            let start = self.peek_span();
+            let popup = if self.eat_keyword("POPUP") {
+                true
+            } else if self.eat_keyword("MENUITEM") {
+                false
+            } else {
+                return Err(self.error_here("expected MENUITEM or POPUP"));
+            };
+
+            if !popup && self.eat_keyword("SEPARATOR") {
+                items.push(MenuItem::Separator { span: start.to(self.prev_span()) });
+                continue;
+            }
+
+            let text = self.expect_string()?;
+            let mut id = None;
+            let mut options = Vec::new();
+
+            if extended {
+                // "text" [, id [, type [, state [, help id]]]], any of which may be empty
+                let mut index = 0;
+                while self.eat_punct(',') {
+                    if self.peek() != Some(&Token::Punct(',')) && !self.is_begin() {
+                        let value = self.expr()?;
+                        if index == 0 {
+                            id = Some(value);
+                        } else {
+                            options.push(value);
+                        }
+                    }
+                    index += 1;
+                }
+            } else {
+                if !popup {
+                    self.expect_comma()?;
+                    id = Some(self.expr()?);
+                }
+                loop {
+                    let comma = self.eat_punct(',');
+                    match self.peek() {
+                        Some(Token::Ident(option)) if MENU_OPTIONS.iter().any(|known| option.eq_ignore_ascii_case(known)) => {
+                            options.push(Expr::Symbol(option.to_ascii_uppercase()));
+                            self.pos += 1;
+                        },
+                        _ if comma => return Err(self.error_here("expected a menu option")),
+                        _ => break,
+                    }
+                }
+            }
+
+            if popup {
+                let children = self.menu_items(extended)?;
+                items.push(MenuItem::Popup { text, id, options, items: children, span: start.to(self.prev_span()) });
+            } else {
+                items.push(MenuItem::Item { text, id, options, span: start.to(self.prev_span()) });
+            }
+        }
+
+        self.next()?;
+        Ok(items)
+    }
+
+    fn dialog(&mut self, extended: bool) -> Result<Dialog, ParseError> {
+        self.skip_memory_options();
+        let x = self.expr()?;
+        self.expect_comma()?;
+        let y = self.expr()?;
+        self.expect_comma()?;
+        let width = self.expr()?;
+        self.expect_comma()?;
+        let height = self.expr()?;
+        let help_id = if self.eat_punct(',') { Some(self.expr()?) } else { None };
+
+        let mut dialog = Dialog {
+            extended, x, y, width, height, help_id,
+            style: None,
+            ex_style: None,
+            caption: None,
+            font: None,
+            menu: None,
+            class: None,
+            controls: Vec::new(),
+        };
+
+        while !self.is_begin() {
+            if self.eat_keyword("STYLE") {
+                dialog.style = Some(self.expr()?);
+            } else if self.eat_keyword("EXSTYLE") {
+                dialog.ex_style = Some(self.expr()?);
+            } else if self.eat_keyword("CAPTION") {
+                dialog.caption = Some(self.expect_string()?);
+            } else if self.eat_keyword("FONT") {
+                let point_size = self.expr()?;
+                self.expect_comma()?;
+                let face = self.expect_string()?;
+                let mut rest = self.optional_args(3)?.into_iter();
+                dialog.font = Some(DialogFont { point_size, face, weight: rest.next(), italic: rest.next(), char_set: rest.next() });
+            } else if self.eat_keyword("MENU") {
+                dialog.menu = Some(self.name_or_id()?);
+            } else if self.eat_keyword("CLASS") {
+                dialog.class = Some(self.name_or_id()?);
+            } else if self.is_keyword("LANGUAGE") || self.is_keyword("CHARACTERISTICS") || self.is_keyword("VERSION") {
+                self.optional_statements()?;
+            } else {
+                return Err(self.error_here("expected a dialog statement or BEGIN"));
+            }
+        }
+
+        self.expect_begin()?;
+        while !self.is_end() {
+            dialog.controls.push(self.control()?);
+        }
+        self.next()?;
+
+        Ok(dialog)
+    }
+
+    // Up to `max` further comma separated expressions
+    fn optional_args(&mut self, max: usize) -> Result<Vec<Expr>, ParseError> {
+        let mut args = Vec::new();
+        while args.len() < max && self.eat_punct(',') {
+            args.push(self.expr()?);
+        }
+        Ok(args)
+    }
+
+    fn control(&mut self) -> Result<Control, ParseError> {
+        let start = self.peek_span();
+        let kind = match self.next()? {
+            (Token::Ident(keyword), span) => ControlKind::from_keyword(&keyword.to_ascii_uppercase())
+                .ok_or_else(|| ParseError { message: format!("unknown control `{}`", keyword), span })?,
+            (_, span) => return Err(ParseError { message: "expected a control".to_owned(), span }),
+        };
+
+        let text = if kind.has_text() {
+            let text = self.name_or_id()?;
+            self.expect_comma()?;
+            Some(text)
+        } else {
+            None
+        };
+        let id = self.expr()?;
+        self.expect_comma()?;
+
+        let mut class = None;
+        let mut style = None;
+        if kind == ControlKind::Control {
+            class = Some(self.name_or_id()?);
+            self.expect_comma()?;
+            style = Some(self.expr()?);
+            self.expect_comma()?;
+        }
+
+        let x = self.expr()?;
+        self.expect_comma()?;
+        let y = self.expr()?;
+
+        let (width, height) = if kind == ControlKind::Icon && !self.eat_punct(',') {
+            (None, None)
+        } else {
+            if kind != ControlKind::Icon {
+                self.expect_comma()?;
+            }
+            let width = self.expr()?;
+            self.expect_comma()?;
+            (Some(width), Some(self.expr()?))
+        };
+
+        let mut rest = self.optional_args(if kind == ControlKind::Control { 2 } else { 3 })?.into_iter();
+        if kind != ControlKind::Control {
+            style = rest.next();
+        }
+        let ex_style = rest.next();
+        let help_id = rest.next();
+
+        // DIALOGEX controls may carry creation data
+        if self.is_begin() {
+            self.skip_block()?;
+        }
+
+        Ok(Control { kind, text, id, class, x, y, width, height, style, ex_style, help_id, span: start.to(self.prev_span()) })
+    }
+
+    pub fn expr(&mut self) -> Result<Expr, ParseError> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+
+        loop {
+            let (op, precedence) = match self.peek() {
+                Some(Token::Punct('|')) => (BinaryOp::Or, 1),
+                Some(Token::Punct('&')) => (BinaryOp::And, 2),
+                Some(Token::Punct('+')) => (BinaryOp::Add, 3),
+                Some(Token::Punct('-')) => (BinaryOp::Sub, 3),
+                Some(Token::Punct('*')) => (BinaryOp::Mul, 4),
+                Some(Token::Punct('/')) => (BinaryOp::Div, 4),
+                _ => break,
+            };
+            if precedence <= min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(precedence)?;
+            lhs = Expr::Binary(Box::new(lhs), op, Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+
+        match self.next()? {
+            (Token::Number(value), _) => Ok(Expr::Number(value)),
+            (Token::Ident(name), _) => Ok(Expr::Symbol(name)),
+            (Token::Punct('-'), _) => Ok(Expr::Neg(Box::new(self.unary()?))),
+            (Token::Punct('+'), _) => self.unary(),
+            (Token::Punct('~'), _) => Ok(Expr::BitNot(Box::new(self.unary()?))),
+            (Token::Punct('('), _) => {
+                let inner = self.expr()?;
+                if !self.eat_punct(')') {
+                    return Err(self.error_here("expected `)`"));
+                }
+                Ok(inner)
+            },
+            (_, span) => Err(ParseError { message: "expected a value".to_owned(), span }),
+        }
+    }
+}
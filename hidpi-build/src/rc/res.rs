@@ -0,0 +1,123 @@
+//! The binary `.res` format written by `rc.exe`, which `link.exe` and
+//! `lld-link` accept in place of an object file.
+//!
+//! A `.res` file is a sequence of entries, each a `RESOURCEHEADER` followed by
+//! the resource data padded to a `DWORD`, starting with an empty entry that
+//! marks the file as 32-bit.
+
+/// The memory flags `rc` gives each resource. They are ignored since 32-bit
+/// Windows but still written.
+pub const MOVEABLE: u16 = 0x0010;
+pub const PURE: u16 = 0x0020;
+pub const PRELOAD: u16 = 0x0040;
+pub const DISCARDABLE: u16 = 0x1000;
+
+/// The predefined resource types.
+pub const RT_CURSOR: u16 = 1;
+pub const RT_BITMAP: u16 = 2;
+pub const RT_ICON: u16 = 3;
+pub const RT_MENU: u16 = 4;
+pub const RT_DIALOG: u16 = 5;
+pub const RT_STRING: u16 = 6;
+pub const RT_RCDATA: u16 = 10;
+pub const RT_GROUP_CURSOR: u16 = 12;
+pub const RT_GROUP_ICON: u16 = 14;
+pub const RT_VERSION: u16 = 16;
+pub const RT_MANIFEST: u16 = 24;
+
+/// A resource type or name: a 16-bit ordinal, or a string that `rc` stores
+/// in upper case.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResId {
+    Ordinal(u16),
+    Name(String),
+}
+
+/// One compiled resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub type_id: ResId,
+    pub name: ResId,
+    /// `MAKELANGID(primary, sub)`.
+    pub language: u16,
+    pub memory_flags: u16,
+    pub data: Vec<u8>,
+}
+
+/// Little-endian helpers for building resource data.
+pub trait ResWriter {
+    fn push_u16(&mut self, value: u16);
+    fn push_u32(&mut self, value: u32);
+    /// A NUL terminated UTF-16 string.
+    fn push_wstr(&mut self, s: &str);
+    /// An ordinal as `0xFFFF, id`, or a string.
+    fn push_res_id(&mut self, id: &ResId);
+    /// Zero padding up to a multiple of `alignment` bytes.
+    fn align_to(&mut self, alignment: usize);
+}
+
+impl ResWriter for Vec<u8> {
+    fn push_u16(&mut self, value: u16) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_u32(&mut self, value: u32) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_wstr(&mut self, s: &str) {
+        for unit in s.encode_utf16() {
+            self.push_u16(unit);
+        }
+        self.push_u16(0);
+    }
+
+    fn push_res_id(&mut self, id: &ResId) {
+        match id {
+            ResId::Ordinal(ordinal) => {
+                self.push_u16(0xFFFF);
+                self.push_u16(*ordinal);
+            },
+            ResId::Name(name) => self.push_wstr(name),
+        }
+    }
+
+    fn align_to(&mut self, alignment: usize) {
+        let padding = (alignment - self.len() % alignment) % alignment;
+        self.resize(self.len() + padding, 0);
+    }
+}
+
+fn write_entry(out: &mut Vec<u8>, entry: &Entry) {
+    let mut header = Vec::new();
+    header.push_res_id(&entry.type_id);
+    header.push_res_id(&entry.name);
+    header.align_to(4);
+    header.push_u32(0); // DataVersion
+    header.push_u16(entry.memory_flags);
+    header.push_u16(entry.language);
+    header.push_u32(0); // Version
+    header.push_u32(0); // Characteristics
+
+    out.push_u32(entry.data.len() as u32);
+    out.push_u32(8 + header.len() as u32);
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&entry.data);
+    out.align_to(4);
+}
+
+/// The `.res` file holding `entries`, in order.
+pub fn to_bytes(entries: &[Entry]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_entry(&mut out, &Entry {
+        type_id: ResId::Ordinal(0),
+        name: ResId::Ordinal(0),
+        language: 0,
+        memory_flags: 0,
+        data: Vec::new(),
+    });
+    for entry in entries {
+        write_entry(&mut out, entry);
+    }
+    out
+}
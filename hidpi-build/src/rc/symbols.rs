@@ -0,0 +1,269 @@
+// The constants of `windows.h`/`winres.h` that resource scripts use, standing
+// in for the SDK headers, which are not read.
+pub const WINDOWS_SYMBOLS: &[(&str, i64)] = &[
+    // Window styles
+    ("WS_OVERLAPPED", 0x0000_0000),
+    ("WS_TILED", 0x0000_0000),
+    ("WS_POPUP", 0x8000_0000),
+    ("WS_CHILD", 0x4000_0000),
+    ("WS_CHILDWINDOW", 0x4000_0000),
+    ("WS_MINIMIZE", 0x2000_0000),
+    ("WS_ICONIC", 0x2000_0000),
+    ("WS_VISIBLE", 0x1000_0000),
+    ("WS_DISABLED", 0x0800_0000),
+    ("WS_CLIPSIBLINGS", 0x0400_0000),
+    ("WS_CLIPCHILDREN", 0x0200_0000),
+    ("WS_MAXIMIZE", 0x0100_0000),
+    ("WS_CAPTION", 0x00C0_0000),
+    ("WS_BORDER", 0x0080_0000),
+    ("WS_DLGFRAME", 0x0040_0000),
+    ("WS_VSCROLL", 0x0020_0000),
+    ("WS_HSCROLL", 0x0010_0000),
+    ("WS_SYSMENU", 0x0008_0000),
+    ("WS_THICKFRAME", 0x0004_0000),
+    ("WS_SIZEBOX", 0x0004_0000),
+    ("WS_GROUP", 0x0002_0000),
+    ("WS_TABSTOP", 0x0001_0000),
+    ("WS_MINIMIZEBOX", 0x0002_0000),
+    ("WS_MAXIMIZEBOX", 0x0001_0000),
+    ("WS_OVERLAPPEDWINDOW", 0x00CF_0000),
+    ("WS_TILEDWINDOW", 0x00CF_0000),
+    ("WS_POPUPWINDOW", 0x8088_0000),
+    // Extended window styles
+    ("WS_EX_DLGMODALFRAME", 0x0000_0001),
+    ("WS_EX_NOPARENTNOTIFY", 0x0000_0004),
+    ("WS_EX_TOPMOST", 0x0000_0008),
+    ("WS_EX_ACCEPTFILES", 0x0000_0010),
+    ("WS_EX_TRANSPARENT", 0x0000_0020),
+    ("WS_EX_MDICHILD", 0x0000_0040),
+    ("WS_EX_TOOLWINDOW", 0x0000_0080),
+    ("WS_EX_WINDOWEDGE", 0x0000_0100),
+    ("WS_EX_CLIENTEDGE", 0x0000_0200),
+    ("WS_EX_CONTEXTHELP", 0x0000_0400),
+    ("WS_EX_RIGHT", 0x0000_1000),
+    ("WS_EX_LEFT", 0x0000_0000),
+    ("WS_EX_RTLREADING", 0x0000_2000),
+    ("WS_EX_LTRREADING", 0x0000_0000),
+    ("WS_EX_LEFTSCROLLBAR", 0x0000_4000),
+    ("WS_EX_RIGHTSCROLLBAR", 0x0000_0000),
+    ("WS_EX_CONTROLPARENT", 0x0001_0000),
+    ("WS_EX_STATICEDGE", 0x0002_0000),
+    ("WS_EX_APPWINDOW", 0x0004_0000),
+    ("WS_EX_OVERLAPPEDWINDOW", 0x0000_0300),
+    ("WS_EX_PALETTEWINDOW", 0x0000_0188),
+    ("WS_EX_LAYERED", 0x0008_0000),
+    ("WS_EX_NOINHERITLAYOUT", 0x0010_0000),
+    ("WS_EX_LAYOUTRTL", 0x0040_0000),
+    ("WS_EX_COMPOSITED", 0x0200_0000),
+    ("WS_EX_NOACTIVATE", 0x0800_0000),
+    // Dialog styles
+    ("DS_ABSALIGN", 0x0001),
+    ("DS_SYSMODAL", 0x0002),
+    ("DS_3DLOOK", 0x0004),
+    ("DS_FIXEDSYS", 0x0008),
+    ("DS_NOFAILCREATE", 0x0010),
+    ("DS_LOCALEDIT", 0x0020),
+    ("DS_SETFONT", 0x0040),
+    ("DS_MODALFRAME", 0x0080),
+    ("DS_NOIDLEMSG", 0x0100),
+    ("DS_SETFOREGROUND", 0x0200),
+    ("DS_CONTROL", 0x0400),
+    ("DS_CENTER", 0x0800),
+    ("DS_CENTERMOUSE", 0x1000),
+    ("DS_CONTEXTHELP", 0x2000),
+    ("DS_SHELLFONT", 0x0048),
+    // Button styles
+    ("BS_PUSHBUTTON", 0x0000),
+    ("BS_DEFPUSHBUTTON", 0x0001),
+    ("BS_CHECKBOX", 0x0002),
+    ("BS_AUTOCHECKBOX", 0x0003),
+    ("BS_RADIOBUTTON", 0x0004),
+    ("BS_3STATE", 0x0005),
+    ("BS_AUTO3STATE", 0x0006),
+    ("BS_GROUPBOX", 0x0007),
+    ("BS_USERBUTTON", 0x0008),
+    ("BS_AUTORADIOBUTTON", 0x0009),
+    ("BS_PUSHBOX", 0x000A),
+    ("BS_OWNERDRAW", 0x000B),
+    ("BS_TYPEMASK", 0x000F),
+    ("BS_LEFTTEXT", 0x0020),
+    ("BS_RIGHTBUTTON", 0x0020),
+    ("BS_TEXT", 0x0000),
+    ("BS_ICON", 0x0040),
+    ("BS_BITMAP", 0x0080),
+    ("BS_LEFT", 0x0100),
+    ("BS_RIGHT", 0x0200),
+    ("BS_CENTER", 0x0300),
+    ("BS_TOP", 0x0400),
+    ("BS_BOTTOM", 0x0800),
+    ("BS_VCENTER", 0x0C00),
+    ("BS_PUSHLIKE", 0x1000),
+    ("BS_MULTILINE", 0x2000),
+    ("BS_NOTIFY", 0x4000),
+    ("BS_FLAT", 0x8000),
+    // Edit styles
+    ("ES_LEFT", 0x0000),
+    ("ES_CENTER", 0x0001),
+    ("ES_RIGHT", 0x0002),
+    ("ES_MULTILINE", 0x0004),
+    ("ES_UPPERCASE", 0x0008),
+    ("ES_LOWERCASE", 0x0010),
+    ("ES_PASSWORD", 0x0020),
+    ("ES_AUTOVSCROLL", 0x0040),
+    ("ES_AUTOHSCROLL", 0x0080),
+    ("ES_NOHIDESEL", 0x0100),
+    ("ES_OEMCONVERT", 0x0400),
+    ("ES_READONLY", 0x0800),
+    ("ES_WANTRETURN", 0x1000),
+    ("ES_NUMBER", 0x2000),
+    // Static styles
+    ("SS_LEFT", 0x0000),
+    ("SS_CENTER", 0x0001),
+    ("SS_RIGHT", 0x0002),
+    ("SS_ICON", 0x0003),
+    ("SS_BLACKRECT", 0x0004),
+    ("SS_GRAYRECT", 0x0005),
+    ("SS_WHITERECT", 0x0006),
+    ("SS_BLACKFRAME", 0x0007),
+    ("SS_GRAYFRAME", 0x0008),
+    ("SS_WHITEFRAME", 0x0009),
+    ("SS_USERITEM", 0x000A),
+    ("SS_SIMPLE", 0x000B),
+    ("SS_LEFTNOWORDWRAP", 0x000C),
+    ("SS_OWNERDRAW", 0x000D),
+    ("SS_BITMAP", 0x000E),
+    ("SS_ENHMETAFILE", 0x000F),
+    ("SS_ETCHEDHORZ", 0x0010),
+    ("SS_ETCHEDVERT", 0x0011),
+    ("SS_ETCHEDFRAME", 0x0012),
+    ("SS_REALSIZECONTROL", 0x0040),
+    ("SS_NOPREFIX", 0x0080),
+    ("SS_NOTIFY", 0x0100),
+    ("SS_CENTERIMAGE", 0x0200),
+    ("SS_RIGHTJUST", 0x0400),
+    ("SS_REALSIZEIMAGE", 0x0800),
+    ("SS_SUNKEN", 0x1000),
+    ("SS_EDITCONTROL", 0x2000),
+    ("SS_ENDELLIPSIS", 0x4000),
+    ("SS_PATHELLIPSIS", 0x8000),
+    ("SS_WORDELLIPSIS", 0xC000),
+    // List box, combo box and scroll bar styles
+    ("LBS_NOTIFY", 0x0001),
+    ("LBS_SORT", 0x0002),
+    ("LBS_NOREDRAW", 0x0004),
+    ("LBS_MULTIPLESEL", 0x0008),
+    ("LBS_OWNERDRAWFIXED", 0x0010),
+    ("LBS_OWNERDRAWVARIABLE", 0x0020),
+    ("LBS_HASSTRINGS", 0x0040),
+    ("LBS_USETABSTOPS", 0x0080),
+    ("LBS_NOINTEGRALHEIGHT", 0x0100),
+    ("LBS_MULTICOLUMN", 0x0200),
+    ("LBS_WANTKEYBOARDINPUT", 0x0400),
+    ("LBS_EXTENDEDSEL", 0x0800),
+    ("LBS_DISABLENOSCROLL", 0x1000),
+    ("LBS_NODATA", 0x2000),
+    ("LBS_NOSEL", 0x4000),
+    ("LBS_STANDARD", 0x00A0_0003),
+    ("CBS_SIMPLE", 0x0001),
+    ("CBS_DROPDOWN", 0x0002),
+    ("CBS_DROPDOWNLIST", 0x0003),
+    ("CBS_OWNERDRAWFIXED", 0x0010),
+    ("CBS_OWNERDRAWVARIABLE", 0x0020),
+    ("CBS_AUTOHSCROLL", 0x0040),
+    ("CBS_OEMCONVERT", 0x0080),
+    ("CBS_SORT", 0x0100),
+    ("CBS_HASSTRINGS", 0x0200),
+    ("CBS_NOINTEGRALHEIGHT", 0x0400),
+    ("CBS_DISABLENOSCROLL", 0x0800),
+    ("CBS_UPPERCASE", 0x2000),
+    ("CBS_LOWERCASE", 0x4000),
+    ("SBS_HORZ", 0x0000),
+    ("SBS_VERT", 0x0001),
+    // Menu item types and states
+    ("MFT_STRING", 0x0000),
+    ("MFT_BITMAP", 0x0004),
+    ("MFT_MENUBARBREAK", 0x0020),
+    ("MFT_MENUBREAK", 0x0040),
+    ("MFT_OWNERDRAW", 0x0100),
+    ("MFT_RADIOCHECK", 0x0200),
+    ("MFT_SEPARATOR", 0x0800),
+    ("MFT_RIGHTORDER", 0x2000),
+    ("MFT_RIGHTJUSTIFY", 0x4000),
+    ("MFS_GRAYED", 0x0003),
+    ("MFS_DISABLED", 0x0003),
+    ("MFS_CHECKED", 0x0008),
+    ("MFS_HILITE", 0x0080),
+    ("MFS_ENABLED", 0x0000),
+    ("MFS_UNCHECKED", 0x0000),
+    ("MFS_UNHILITE", 0x0000),
+    ("MFS_DEFAULT", 0x1000),
+    // Dialog box command ids
+    ("IDOK", 1),
+    ("IDCANCEL", 2),
+    ("IDABORT", 3),
+    ("IDRETRY", 4),
+    ("IDIGNORE", 5),
+    ("IDYES", 6),
+    ("IDNO", 7),
+    ("IDCLOSE", 8),
+    ("IDHELP", 9),
+    ("IDC_STATIC", -1),
+    // Languages
+    ("LANG_NEUTRAL", 0x00),
+    ("LANG_INVARIANT", 0x7F),
+    ("LANG_CHINESE", 0x04),
+    ("LANG_GERMAN", 0x07),
+    ("LANG_ENGLISH", 0x09),
+    ("LANG_SPANISH", 0x0A),
+    ("LANG_FRENCH", 0x0C),
+    ("LANG_ITALIAN", 0x10),
+    ("LANG_JAPANESE", 0x11),
+    ("LANG_KOREAN", 0x12),
+    ("LANG_DUTCH", 0x13),
+    ("LANG_PORTUGUESE", 0x16),
+    ("LANG_RUSSIAN", 0x19),
+    ("SUBLANG_NEUTRAL", 0x00),
+    ("SUBLANG_DEFAULT", 0x01),
+    ("SUBLANG_SYS_DEFAULT", 0x02),
+    ("SUBLANG_ENGLISH_US", 0x01),
+    ("SUBLANG_ENGLISH_UK", 0x02),
+    ("SUBLANG_GERMAN", 0x01),
+    ("SUBLANG_FRENCH", 0x01),
+    ("SUBLANG_JAPANESE_JAPAN", 0x01),
+    // Version information
+    ("VS_VERSION_INFO", 1),
+    ("VS_FFI_FILEFLAGSMASK", 0x3F),
+    ("VS_FF_DEBUG", 0x01),
+    ("VS_FF_PRERELEASE", 0x02),
+    ("VS_FF_PATCHED", 0x04),
+    ("VS_FF_PRIVATEBUILD", 0x08),
+    ("VS_FF_INFOINFERRED", 0x10),
+    ("VS_FF_SPECIALBUILD", 0x20),
+    ("VOS_UNKNOWN", 0x0000_0000),
+    ("VOS_DOS", 0x0001_0000),
+    ("VOS_NT", 0x0004_0000),
+    ("VOS__WINDOWS32", 0x0000_0004),
+    ("VOS_NT_WINDOWS32", 0x0004_0004),
+    ("VFT_UNKNOWN", 0),
+    ("VFT_APP", 1),
+    ("VFT_DLL", 2),
+    ("VFT_DRV", 3),
+    ("VFT_FONT", 4),
+    ("VFT_VXD", 5),
+    ("VFT_STATIC_LIB", 7),
+    ("VFT2_UNKNOWN", 0),
+    // Resource types and manifest ids
+    ("RT_CURSOR", 1),
+    ("RT_BITMAP", 2),
+    ("RT_ICON", 3),
+    ("RT_MENU", 4),
+    ("RT_DIALOG", 5),
+    ("RT_STRING", 6),
+    ("RT_RCDATA", 10),
+    ("RT_VERSION", 16),
+    ("RT_HTML", 23),
+    ("RT_MANIFEST", 24),
+    ("CREATEPROCESS_MANIFEST_RESOURCE_ID", 1),
+    ("ISOLATIONAWARE_MANIFEST_RESOURCE_ID", 2),
+    ("ISOLATIONAWARE_NOSTATICIMPORT_MANIFEST_RESOURCE_ID", 3),
+];
@@ -0,0 +1,11 @@
+#define IDD_PLAIN       100
+#define IDD_EXTENDED    101
+#define IDM_CLASSIC     102
+#define IDB_TINY        104
+#define IDI_TINY        105
+#define IDC_FIRST       1000
+#define IDC_SECOND      (IDC_FIRST + 1)
+#define IDC_THIRD       (IDC_FIRST + 2)
+#define IDS_HELLO       1
+#define IDS_WORLD       3
+#define IDS_FAR         4097
@@ -1,11 +1,31 @@
 use std::env;
 use std::path::Path;
 
+use hidpi_build::Manifest;
+
 fn main() {
     let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
-    
+
+    hidpi_build::generate_resource_constants(Path::new(&crate_dir).join("src/Resource.h"), "res.rs", "INT")
+        .expect("generate resource constants");
+
+    // Per-Monitor V2 for the process; main() then switches its own thread to System aware
+    let rc_file = hidpi_build::embed_manifest(Path::new(&crate_dir).join("src/DpiAwarenessContext.rc"), &Manifest::per_monitor_v2())
+        .expect("write manifest");
+
+    compile_resources(&rc_file);
+}
+
+#[cfg(feature = "winres")]
+fn compile_resources(rc_file: &Path) {
     let mut res = winres::WindowsResource::new();
-    res.set_resource_file(Path::new(&crate_dir).join("src/DpiAwarenessContext.rc").to_str().unwrap());
+    res.set_resource_file(rc_file.to_str().unwrap());
     res.compile()
         .expect("compile resources");
-}
\ No newline at end of file
+}
+
+#[cfg(not(feature = "winres"))]
+fn compile_resources(rc_file: &Path) {
+    hidpi_build::compile_resources(rc_file)
+        .expect("compile resources");
+}
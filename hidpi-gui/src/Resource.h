@@ -8,17 +8,17 @@
 #define IDC_MAINMENU                    109
 #define IDD_DIALOG1                     129
 #define IDC_BUTTON1                     1000
-#define IDC_BUTTON_UNAWARE              1000
+#define IDC_BUTTON_UNAWARE              IDC_BUTTON1
 #define IDC_BUTTON2                     1001
-#define IDC_BUTTON_SYSTEM               1001
+#define IDC_BUTTON_SYSTEM               IDC_BUTTON2
 #define IDC_BUTTON3                     1002
-#define IDC_BUTTON_81                   1002
+#define IDC_BUTTON_81                   IDC_BUTTON3
 #define IDC_BUTTON4                     1003
-#define IDC_BUTTON_1607                 1003
+#define IDC_BUTTON_1607                 IDC_BUTTON4
 #define IDC_BUTTON5                     1004
-#define IDC_BUTTON_1703                 1004
+#define IDC_BUTTON_1703                 IDC_BUTTON5
 #define IDC_BUTTON6                     1005
-#define IDC_BUTTON_1803                 1005
+#define IDC_BUTTON_1803                 IDC_BUTTON6
 #define IDC_EDIT1                       1007
 #define ID_FILE_ITEM2                   32771
 #define ID_FILE_ITEM3                   32772
@@ -4,14 +4,17 @@ extern crate hidpi;
 
 use std::ptr;
 use std::mem;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::env;
+use std::rc::Rc;
+use std::time::Instant;
 use libc::{
     size_t,
 };
 use winapi::{
     ctypes::{
         c_void,
-        wchar_t,
     },
 	shared::{
         minwindef::{
@@ -35,26 +38,22 @@ use winapi::{
             HMENU,
             HBRUSH,
             DPI_AWARENESS_CONTEXT,
-            DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
-            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE,
-            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
-            DPI_AWARENESS_CONTEXT_UNAWARE,
-            DPI_HOSTING_BEHAVIOR,
-            DPI_HOSTING_BEHAVIOR_MIXED,
+            DPI_AWARENESS_SYSTEM_AWARE,
+            DPI_AWARENESS_PER_MONITOR_AWARE,
         }
     },
 	um::{
         winuser,
-        winbase,
         wingdi,
         commdlg,
         winnt::{
             LPWSTR,
-            LPCWSTR,
         },
         consoleapi,
         wincon,
         libloaderapi,
+        debugapi,
+        shellscalingapi::MDT_EFFECTIVE_DPI,
     },
 };
 
@@ -67,21 +66,54 @@ use hidpi::{
     get_stock_brush,
     get_thread_dpi_awareness,
     get_thread_dpi_awareness_context,
+    get_window_dpi_awareness_context,
     set_thread_dpi_awareness_context,
-    set_thread_dpi_hosting_behavior,
     get_maybe_dpi_by_awareness,
+    get_dpi_for_system,
+    get_dpi_for_window,
+    get_dpi_for_monitor,
     get_hinstance_for_h_wnd,
+    enable_non_client_dpi_scaling,
     awareness_to_str,
     awareness_context_to_str,
+    DpiAwarenessContext,
+    DpiHostingBehavior,
+    ThreadDpiScope,
+    Dpi,
+    Logical,
+    Physical,
+    Rect,
+    DpiFontCache,
+    FontDescription,
+    GdiFonts,
+    DialogTemplate,
+    DialogControl,
+    DialogFont,
+    Menu,
+    MenuPopup,
+    MenuCommand,
+    Accelerator,
+};
+use dll_plugin_import::{
+    DpiIsolation,
+    Host,
+    HostDpi,
+    LogLevel,
+    PluginRegistry,
+    RegisteredPlugin,
+    Reload,
+    Supervision,
+    Supervisor,
+    Watch,
 };
 
-pub const WINDOW_WIDTH96: INT = 500;
-pub const WINDOW_HEIGHT96: INT = 700;
+pub const WINDOW_WIDTH96: Logical<INT> = Logical(500);
+pub const WINDOW_HEIGHT96: Logical<INT> = Logical(700);
 pub const DEFAULT_CHAR_BUFFER: size_t = 150;
-pub const DEFAULT_PADDING96: UINT = 20;
-pub const DEFAULT_BUTTON_HEIGHT96: INT = 25;
-pub const DEFAULT_BUTTON_WIDTH96: INT = 100;
-pub const SAMPLE_STATIC_HEIGHT96: UINT = 50;
+pub const DEFAULT_PADDING96: Logical<INT> = Logical(20);
+pub const DEFAULT_BUTTON_HEIGHT96: Logical<INT> = Logical(25);
+pub const DEFAULT_BUTTON_WIDTH96: Logical<INT> = Logical(100);
+pub const SAMPLE_STATIC_HEIGHT96: Logical<INT> = Logical(50);
 pub const WINDOWCLASSNAME: &'static str = "SetThreadDpiAwarenessContextSample";
 pub const HWND_NAME_CHECKBOX: &'static str = "CHECKBOX";
 pub const HWND_NAME_RADIO: &'static str = "RADIO";
@@ -89,10 +121,21 @@ pub const HWND_NAME_STATIC: &'static str = "Static";
 pub const HWND_NAME_DIALOG: &'static str = "Open a System Dialog";
 pub const HWND_NAME_EXTERNAL: &'static str = "External Content";
 pub const PLUGINWINDOWCLASSNAME: &'static str = "Plugin Window Class";
-pub const EXTERNAL_CONTENT_WIDTH96: INT = 400;
-pub const EXTERNAL_CONTENT_HEIGHT96: INT = 400;
+pub const EXTERNAL_CONTENT_WIDTH96: Logical<INT> = Logical(400);
+pub const EXTERNAL_CONTENT_HEIGHT96: Logical<INT> = Logical(400);
 pub const GA_PARENT: UINT = 1;
 pub const PROP_DPIISOLATION: &'static str = "PROP_ISOLATION";
+pub const PROP_EXTERNALCONTENT: &'static str = "PROP_EXTERNAL_CONTENT";
+// The DPI the plugin was last told its content is at
+pub const PROP_EXTERNALCONTENTDPI: &'static str = "PROP_EXTERNAL_CONTENT_DPI";
+// Posted to a sample window whose plugin asked for it to be laid out again
+pub const WM_RELAYOUT: UINT = winuser::WM_APP;
+// The host dialog's timer for looking for rebuilt plugins and dead plugin
+// helpers
+pub const PLUGIN_TIMER_ID: usize = 1;
+pub const PLUGIN_POLL_MS: UINT = 500;
+// Set to run the first plugin in a helper process
+pub const OUT_OF_PROCESS_VAR: &'static str = "HIDPI_GUI_OUT_OF_PROCESS";
 
 pub mod res;
 use res::*;
@@ -113,18 +156,264 @@ impl Default for CreateParams {
     }
 }
 
+/// What the plugins call back into. Settings are kept per plugin for as long
+/// as the sample runs, so they outlive a plugin being reloaded.
+#[derive(Default)]
+pub struct SampleHost {
+    // The sample window content is being created for, see current_dpi
+    creating_for: Cell<Option<HWND>>,
+    settings: RefCell<HashMap<(String, String), String>>,
+}
+
+impl Host for SampleHost {
+    // Everything goes to the debugger, and warnings and errors to the host
+    // dialog's description too
+    fn log(&self, plugin: &str, level: LogLevel, message: &str) {
+        let line = format!("{} {}: {}", plugin, level, message);
+        let debug_line = to_wstring(format!("{}\n", line));
+        unsafe { debugapi::OutputDebugStringW(debug_line.as_ptr()) };
+        if level <= LogLevel::Warn {
+            PLUGIN_LOG.with(|log| log.borrow_mut().push(line));
+        }
+    }
+
+    fn setting(&self, plugin: &str, key: &str) -> Option<String> {
+        self.settings.borrow().get(&(plugin.to_owned(), key.to_owned())).cloned()
+    }
+
+    fn set_setting(&self, plugin: &str, key: &str, value: &str) -> bool {
+        self.settings.borrow_mut().insert((plugin.to_owned(), key.to_owned()), value.to_owned());
+        true
+    }
+
+    // The plugin's pointers are std's c_void rather than winapi's
+    fn current_dpi(&self, content: *mut std::os::raw::c_void) -> u32 {
+        if !content.is_null() {
+            // GetDpiForWindow answers for the window's own awareness
+            return get_dpi_for_window(content as HWND);
+        }
+        // Inside create_content the thread has the content's awareness, and
+        // per-monitor content will be at the DPI of its window's monitor
+        // whatever the window's own awareness
+        match (self.creating_for.get(), get_thread_dpi_awareness()) {
+            (Some(h_wnd), Some(DPI_AWARENESS_PER_MONITOR_AWARE)) => {
+                let h_monitor = unsafe { winuser::MonitorFromWindow(h_wnd, winuser::MONITOR_DEFAULTTONEAREST) };
+                get_dpi_for_monitor(h_monitor, MDT_EFFECTIVE_DPI).ok().flatten()
+                    .map_or_else(get_dpi_for_system, |(dpi_x, _dpi_y)| dpi_x)
+            },
+            (_, Some(DPI_AWARENESS_SYSTEM_AWARE)) => get_dpi_for_system(),
+            _ => Dpi::DEFAULT.0,
+        }
+    }
+
+    fn request_relayout(&self, content: *mut std::os::raw::c_void) {
+        let h_wnd = match self.creating_for.get() {
+            Some(h_wnd) => h_wnd,
+            None => unsafe { winuser::GetParent(content as HWND) },
+        };
+        if !h_wnd.is_null() {
+            unsafe { winuser::PostMessageW(h_wnd, WM_RELAYOUT, 0, 0) };
+        }
+    }
+}
+
 thread_local! {
     pub static CREATE_PARAMS: RefCell<CreateParams> = RefCell::new(CreateParams::default());
+    // Fonts sent to the child controls. They stay alive until the thread exits
+    // because windows at any DPI may still be using them.
+    pub static FONTS: RefCell<DpiFontCache<GdiFonts>> = RefCell::new(DpiFontCache::new(GdiFonts));
+    // What the sample windows do for their menu items and accelerators
+    pub static SAMPLE_COMMANDS: CommandTable = sample_commands();
+    // What the plugins call back into
+    pub static HOST: Rc<SampleHost> = Rc::new(SampleHost::default());
+    // The plugins beside the executable and in its plugins directory. The
+    // content of the first is shown in each sample window.
+    pub static PLUGINS: RefCell<PluginRegistry> = RefCell::new(load_plugins());
+    // In debug builds, the plugins' libraries, to swap in new builds of them
+    // while the sample windows stay open
+    pub static WATCHES: RefCell<Vec<Watch>> = RefCell::new(PLUGINS.with(|plugins| watch_plugins(&plugins.borrow())));
+    // What happened to the plugins since they were found, for the host
+    // dialog's description
+    pub static PLUGIN_LOG: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    // The helper process the first plugin's content runs in, if it runs in
+    // one
+    pub static PLUGIN_HELPER: RefCell<Option<Supervisor>> = RefCell::new(start_plugin_helper());
+}
+
+fn load_plugins() -> PluginRegistry {
+    let mut registry = PluginRegistry::new().host(HOST.with(|host| host.clone()));
+    if let Some(dir) = env::current_exe().ok().as_ref().and_then(|exe| exe.parent()) {
+        registry = registry.dir(dir).dir(dir.join("plugins"));
+    }
+    // Debug builds load copies of the libraries, so they can be rebuilt
+    // while the sample is running
+    if cfg!(debug_assertions) {
+        registry = registry.shadow(env::temp_dir().join("hidpi-gui-plugins"));
+    }
+    registry.scan();
+    registry
+}
+
+fn watch_plugins(registry: &PluginRegistry) -> Vec<Watch> {
+    match &registry.shadow {
+        // A helper process keeps the library it loaded, so isn't reloaded
+        Some(_) if env::var_os(OUT_OF_PROCESS_VAR).is_some() => Vec::new(),
+        Some(shadow) => registry.plugins().iter().map(|found| Watch::new(found, shadow)).collect(),
+        None => Vec::new(),
+    }
+}
+
+// With OUT_OF_PROCESS_VAR set, the first plugin's content runs in the
+// plugin_host helper beside the executable, so a plugin that crashes only
+// takes the helper down. It runs in this process if the helper can't start.
+fn start_plugin_helper() -> Option<Supervisor> {
+    env::var_os(OUT_OF_PROCESS_VAR)?;
+    let helper = env::current_exe().ok()?.with_file_name(format!("plugin_host{}", env::consts::EXE_SUFFIX));
+    let source = PLUGINS.with(|plugins| plugins.borrow().plugins().first().map(|found| found.source.clone()))?;
+    let mut supervisor = Supervisor::new(helper, source);
+    match supervisor.start() {
+        Ok(_) => Some(supervisor),
+        Err(error) => {
+            let line = format!("couldn't start the plugin helper, running the plugin in process: {}", error);
+            PLUGIN_LOG.with(|log| log.borrow_mut().push(line));
+            None
+        },
+    }
+}
+
+/// The host dialog's description, with the plugins found and what happened
+/// to them since.
+fn app_description() -> String {
+    let mut description = format!("{}\r\n{}",
+    r#"This sample app lets you create windows with different DPI Awareness modes so
+    that you can observe how Win32 windows behave under these modes.
+    Each window will show different behaviors depending on the mode (will be blurry or
+    crisp, non-client area will scale differently, etc.).
+    \r\n\r\n
+    The best way to observe these differences is to move each window to a display with a
+    different display scaling (DPI) value. On single-display devices you can simulate
+    this by changing the display scaling value of your display (the "Change the size
+    of text, apps, and other items" setting in the Display settings page of the Settings
+    app, as of Windows 10, 1703). Make these settings changes while the app is still
+    running to observe the different DPI-scaling behavior.
+    "#,
+        PLUGINS.with(|plugins| plugin_summary(&plugins.borrow())));
+    PLUGIN_HELPER.with(|helper| if let Some(remote) = helper.borrow().as_ref().and_then(Supervisor::remote) {
+        description.push_str(&format!("\r\n    {} runs in helper process {}", remote.name(), remote.process_id()));
+    });
+    PLUGIN_LOG.with(|log| for line in log.borrow().iter() {
+        description.push_str(&format!("\r\n    {}", line));
+    });
+    description
+}
+
+/// A line for each plugin found, and for each library or manifest skipped.
+fn plugin_summary(registry: &PluginRegistry) -> String {
+    let mut summary = String::from("Plugins:");
+    for found in registry.plugins() {
+        summary.push_str(&format!("\r\n    {}", found.name()));
+        if let Some(version) = found.version() {
+            summary.push_str(&format!(" {}", version));
+        }
+        summary.push_str(&format!(" ({})", found.source.display()));
+        summary.push_str(&format!(", written for {}", found.dpi_awareness()));
+    }
+    if registry.plugins().is_empty() {
+        summary.push_str("\r\n    none found");
+    }
+    for problem in registry.problems() {
+        summary.push_str(&format!("\r\n    skipped {}", problem));
+    }
+    summary
+}
+
+/// The awareness of a sample window, and whether it was created with mixed
+/// hosting so plugin content can keep its own.
+fn host_dpi(h_wnd: HWND) -> HostDpi {
+    let awareness = get_window_dpi_awareness_context(h_wnd)
+        .or_else(|| get_thread_dpi_awareness_context().and_then(DpiAwarenessContext::from_raw))
+        .unwrap_or(DpiAwarenessContext::Unaware);
+    let prop_dpi_isolation = to_wstring(PROP_DPIISOLATION);
+    let mixed = unsafe { winuser::GetPropW(h_wnd, prop_dpi_isolation.as_ptr()) } as BOOL == TRUE;
+    HostDpi::new(awareness, mixed)
+}
+
+/// Maps `WM_COMMAND` ids, from menus, accelerators or buttons, to handlers
+/// taking the window that received the command.
+#[derive(Default)]
+pub struct CommandTable {
+    handlers: HashMap<INT, Box<dyn Fn(HWND)>>,
+}
+
+impl CommandTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on<F>(mut self, id: INT, handler: F) -> Self where F: Fn(HWND) + 'static {
+        self.handlers.insert(id, Box::new(handler));
+        self
+    }
+
+    /// Run the handler for `id`, returning false if there isn't one.
+    pub fn dispatch(&self, id: INT, h_wnd: HWND) -> bool {
+        match self.handlers.get(&id) {
+            Some(handler) => {
+                handler(h_wnd);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+fn sample_commands() -> CommandTable {
+    CommandTable::new()
+        .on(IDM_SHOWDIALOG, |h_wnd| show_file_open_dialog(h_wnd))
+        .on(IDM_EXIT, |h_wnd| unsafe { winuser::DestroyWindow(h_wnd); })
+}
+
+/// The sample window menu, `IDC_MAINMENU` in DpiAwarenessContext.rc, plus the
+/// system dialog and its accelerator.
+fn sample_menu() -> Menu {
+    Menu::new()
+        .popup(MenuPopup::new("&File")
+            .popup(MenuPopup::new("Item 1")
+                .command(MenuCommand::new("Sub Item 1", ID_ITEM1_SUBITEM1))
+                .command(MenuCommand::new("Sub Item 2", ID_ITEM1_SUBITEM2))
+                .command(MenuCommand::new("Sub Item 3", ID_ITEM1_SUBITEM3)))
+            .command(MenuCommand::new("Item 2", ID_FILE_ITEM2))
+            .command(MenuCommand::new("Item 3", ID_FILE_ITEM3))
+            .separator()
+            .command(MenuCommand::new("&Open a System Dialog...", IDM_SHOWDIALOG)
+                .accelerator(Accelerator::new(b'O' as u16).with_ctrl()))
+            .separator()
+            .command(MenuCommand::new("E&xit", IDM_EXIT)))
+        .popup(MenuPopup::new("Edit")
+            .popup(MenuPopup::new("Edit Item 1")
+                .command(MenuCommand::new("Edit Sub Item 1", ID_EDITITEM1_EDITSUBITEM1))
+                .command(MenuCommand::new("Edit Sub Item 2", ID_EDITITEM1_EDITSUBITEM2))
+                .command(MenuCommand::new("Edit Sub Item 3", ID_EDITITEM1_EDITSUBITEM3)))
+            .command(MenuCommand::new("Edit Item 2", ID_EDIT_EDITITEM2))
+            .command(MenuCommand::new("Edit Item 3", ID_EDIT_EDITITEM3)))
+        .popup(MenuPopup::new("View")
+            .popup(MenuPopup::new("View Item 1")
+                .command(MenuCommand::new("View Sub Item 1", ID_VIEWITEM1_VIEWSUBITEM1))
+                .command(MenuCommand::new("View Sub Item 2", ID_VIEWITEM1_VIEWSUBITEM2))
+                .command(MenuCommand::new("View Sub Item 3", ID_VIEWITEM1_VIEWSUBITEM3)))
+            .command(MenuCommand::new("View Item 2", ID_VIEW_VIEWITEM2))
+            .command(MenuCommand::new("View Item 3", ID_VIEW_VIEWITEM3)))
 }
 
 #[no_mangle]
 pub extern "C" fn update_dpi_string(h_wnd: HWND, u_dpi: UINT) {
     let awareness = get_thread_dpi_awareness();
-    let context = get_thread_dpi_awareness_context();
+    let context = get_thread_dpi_awareness_context()
+        .and_then(DpiAwarenessContext::from_raw);
 
     let s = format!("DPI Awareness: {}\nDPI Awareness Context: {}\nGetDpiForWindow(.....): {}",
-        awareness_to_str(awareness),
-        awareness_context_to_str(context),
+        awareness.map(awareness_to_str).unwrap_or("DPI_AWARENESS_UNAWARE"),
+        context.map(awareness_context_to_str).unwrap_or("DPI_AWARENESS_CONTEXT_UNAWARE"),
         u_dpi
     );
 
@@ -138,7 +427,8 @@ pub extern "C" fn update_and_dpi_scale_child_windows(h_wnd: HWND, u_dpi: UINT) {
     let h_wnd_dialog: HWND;
     let h_wnd_radio: HWND;
 
-    let u_padding = unsafe { winbase::MulDiv(DEFAULT_PADDING96 as i32, u_dpi as i32, 96) };
+    let dpi = Dpi(u_dpi);
+    let Physical(u_padding) = DEFAULT_PADDING96.scale(dpi);
     let mut rc_client = RECT { left: 0, bottom: 0, top: 0, right: 0 };
     unsafe { winuser::GetClientRect(h_wnd, &mut rc_client); }
 
@@ -150,7 +440,7 @@ pub extern "C" fn update_and_dpi_scale_child_windows(h_wnd: HWND, u_dpi: UINT) {
     }
 
     let u_width = (rc_client.right - rc_client.left) - 2 * u_padding;
-    let u_height = unsafe { winbase::MulDiv(SAMPLE_STATIC_HEIGHT96 as i32, u_dpi as i32, 96) };
+    let Physical(u_height) = SAMPLE_STATIC_HEIGHT96.scale(dpi);
     unsafe { winuser::SetWindowPos(
         h_wnd_static,
         ptr::null_mut(),
@@ -176,8 +466,8 @@ pub extern "C" fn update_and_dpi_scale_child_windows(h_wnd: HWND, u_dpi: UINT) {
         ptr::null_mut(),
         u_padding,
         rc_client.bottom + u_padding,
-        winbase::MulDiv(DEFAULT_BUTTON_WIDTH96, u_dpi as i32, 96),
-        winbase::MulDiv(DEFAULT_BUTTON_HEIGHT96, u_dpi as i32, 96), winuser::SWP_NOZORDER | winuser::SWP_NOACTIVATE); }
+        DEFAULT_BUTTON_WIDTH96.scale(dpi).0,
+        DEFAULT_BUTTON_HEIGHT96.scale(dpi).0, winuser::SWP_NOZORDER | winuser::SWP_NOACTIVATE); }
 
     // Size and position the radio button
     let radio_str = to_wstring(HWND_NAME_RADIO);
@@ -188,8 +478,8 @@ pub extern "C" fn update_and_dpi_scale_child_windows(h_wnd: HWND, u_dpi: UINT) {
     }
     get_parent_relative_window_rect(h_wnd_checkbox, &mut rc_client);
     unsafe { winuser::SetWindowPos(h_wnd_radio, ptr::null_mut(), rc_client.right + u_padding, rc_client.top,
-        winbase::MulDiv(DEFAULT_BUTTON_WIDTH96, u_dpi as i32, 96),
-        winbase::MulDiv(DEFAULT_BUTTON_HEIGHT96, u_dpi as i32, 96),
+        DEFAULT_BUTTON_WIDTH96.scale(dpi).0,
+        DEFAULT_BUTTON_HEIGHT96.scale(dpi).0,
         winuser::SWP_NOZORDER | winuser::SWP_NOACTIVATE); }
 
     // Size and position the dialog button
@@ -197,8 +487,8 @@ pub extern "C" fn update_and_dpi_scale_child_windows(h_wnd: HWND, u_dpi: UINT) {
     h_wnd_dialog = unsafe { winuser::FindWindowExW(h_wnd, ptr::null_mut(), button_str.as_ptr(), dialog_str.as_ptr()) };
     get_parent_relative_window_rect(h_wnd_checkbox, &mut rc_client);
     unsafe { winuser::SetWindowPos(h_wnd_dialog, ptr::null_mut(), u_padding, rc_client.bottom + u_padding,
-        winbase::MulDiv(DEFAULT_BUTTON_WIDTH96 * 2, u_dpi as i32, 96), // Make this one twice as wide as the others
-        winbase::MulDiv(DEFAULT_BUTTON_HEIGHT96, u_dpi as i32, 96),
+        (DEFAULT_BUTTON_WIDTH96 + DEFAULT_BUTTON_WIDTH96).scale(dpi).0, // Make this one twice as wide as the others
+        DEFAULT_BUTTON_HEIGHT96.scale(dpi).0,
         winuser::SWP_NOZORDER | winuser::SWP_NOACTIVATE); }
 
     // Size and position the external content HWND
@@ -207,23 +497,60 @@ pub extern "C" fn update_and_dpi_scale_child_windows(h_wnd: HWND, u_dpi: UINT) {
     let  h_wnd_external = unsafe { winuser::FindWindowExW(h_wnd, ptr::null_mut(), window_class_name_str.as_ptr(), h_wnd_name_external.as_ptr()) };
     get_parent_relative_window_rect(h_wnd_dialog, &mut rc_client);
     unsafe { winuser::SetWindowPos(h_wnd_external, h_wnd_dialog, u_padding, rc_client.bottom + u_padding,
-        winbase::MulDiv(EXTERNAL_CONTENT_WIDTH96, u_dpi as i32, 96),
-        winbase::MulDiv(EXTERNAL_CONTENT_HEIGHT96, u_dpi as i32, 96),
+        EXTERNAL_CONTENT_WIDTH96.scale(dpi).0,
+        EXTERNAL_CONTENT_HEIGHT96.scale(dpi).0,
         winuser::SWP_NOZORDER | winuser::SWP_NOACTIVATE); }
+    if h_wnd_external != ptr::null_mut()
+    {
+        // Content in a helper process hears of its new size too
+        let remote = PLUGIN_HELPER.with(|helper| match helper.borrow_mut().as_mut() {
+            Some(supervisor) => {
+                let _ = supervisor.resize(h_wnd as u64, EXTERNAL_CONTENT_WIDTH96.scale(dpi).0, EXTERNAL_CONTENT_HEIGHT96.scale(dpi).0);
+                true
+            },
+            None => false,
+        });
+
+        // The plugin hears of a new DPI as its content's awareness sees it,
+        // which under isolation needn't be this window's
+        let u_dpi_external = get_dpi_for_window(h_wnd_external);
+        let prop_external_content_dpi = to_wstring(PROP_EXTERNALCONTENTDPI);
+        let u_dpi_told = unsafe { winuser::GetPropW(h_wnd, prop_external_content_dpi.as_ptr()) } as usize as UINT;
+        if u_dpi_external != u_dpi_told
+        {
+            unsafe { winuser::SetPropW(h_wnd, prop_external_content_dpi.as_ptr(), u_dpi_external as usize as HANDLE) };
+            if remote
+            {
+                PLUGIN_HELPER.with(|helper| if let Some(supervisor) = helper.borrow_mut().as_mut() {
+                    let _ = supervisor.dpi_changed(h_wnd as u64, u_dpi_external);
+                });
+            }
+            else
+            {
+                let host = host_dpi(h_wnd);
+                PLUGINS.with(|plugins| if let Some(found) = plugins.borrow().plugins().first() {
+                    found.dpi_changed(host, h_wnd_external as _, u_dpi_external);
+                });
+            }
+        }
+    }
 
     // Send a new font to all child controls (the 'plugin' content is subclassed to ignore WM_SETFONT)
-    let h_font_old = get_window_font(h_wnd_static);
-    let mut lf_text = wingdi::LOGFONTW::default();
-    unsafe { winuser::SystemParametersInfoForDpi(winuser::SPI_GETICONTITLELOGFONT, mem::size_of::<wingdi::LOGFONTW>() as u32, &mut lf_text as *mut wingdi::LOGFONTW as *mut c_void, 0, u_dpi); }
-    let h_font_new = unsafe { wingdi::CreateFontIndirectW(&lf_text) };
-    if h_font_new != ptr::null_mut()
+    let h_font_new = match FontDescription::icon_title() {
+        Some(description) => FONTS.with(|fonts| fonts.borrow_mut().acquire(&description, dpi)),
+        None => return,
+    };
+    if let Some(h_font_new) = h_font_new
     {
-        unsafe { wingdi::DeleteObject(h_font_old as *mut c_void); }
+        // The window's use of the font it had ends once the children have
+        // the new one
+        let h_font_old = get_window_font(h_wnd_static);
         enum_child_windows(h_wnd, move |h_wnd, _l_param|
         {
             unsafe { winuser::SendMessageW(h_wnd, winuser::WM_SETFONT, h_font_new as usize, make_l_param(TRUE as u16, 0) as isize); }
             return TRUE;
         });
+        FONTS.with(|fonts| fonts.borrow_mut().release(h_font_old));
     }
 }
 
@@ -239,11 +566,12 @@ pub extern "C" fn do_initial_window_setup(h_wnd: HWND) -> LRESULT
     // Determine the DPI to use, according to the DPI awareness mode
 	let _dpi_awareness = get_thread_dpi_awareness();
 
-	let u_dpi = get_maybe_dpi_by_awareness(h_wnd).unwrap_or(96);
+	let u_dpi = get_maybe_dpi_by_awareness(h_wnd).unwrap_or(Dpi::DEFAULT.0);
+    let dpi = Dpi(u_dpi);
 
     unsafe { winuser::GetWindowRect(h_wnd, &mut rc_window); }
-    rc_window.right = rc_window.left + unsafe { winbase::MulDiv(WINDOW_WIDTH96, u_dpi as i32, 96) };
-    rc_window.bottom = rc_window.top + unsafe { winbase::MulDiv(WINDOW_HEIGHT96, u_dpi as i32, 96) };
+    rc_window.right = rc_window.left + WINDOW_WIDTH96.scale(dpi).0;
+    rc_window.bottom = rc_window.top + WINDOW_HEIGHT96.scale(dpi).0;
     unsafe {
         winuser::SetWindowPos(h_wnd, 
             ptr::null_mut(), 
@@ -294,39 +622,205 @@ pub extern "C" fn do_initial_window_setup(h_wnd: HWND) -> LRESULT
     // When child-HWND DPI isolation is enabled, Windows will try to let that HWND run in its native
     // DPI scaling mode (which might or might not have been defined explicitly). 
 
-    // First, determine if we are in the correct mode to use this feature
-    let prop_dpi_isolation = to_wstring(PROP_DPIISOLATION);
-    let b_dpi_isolation: BOOL = unsafe { winuser::GetPropW(h_wnd, prop_dpi_isolation.as_ptr()) as BOOL };
+    attach_external_content(h_wnd);
 
-    let mut previous_dpi_context = ptr::null_mut();
-	let mut previous_dpi_hosting_behavior = DPI_HOSTING_BEHAVIOR::default();
-    if b_dpi_isolation == TRUE
+    // DPI scale child-windows
+    //UpdateAndDpiScaleChildWindows(h_wnd, u_dpi);
+	update_and_dpi_scale_child_windows(h_wnd, u_dpi);
+
+    return 0;
+}
+
+// Create the first plugin's content and parent it to a sample window.
+//
+// Each plugin says which awareness its content is written for. The host
+// window's awareness and hosting behavior decide whether it can have it:
+// the plugin's calls run with the thread switched to that awareness only
+// when the window was created with mixed hosting, and otherwise with the
+// window's own, so the content can always be reparented.
+//
+// Content in a helper process is created the same way there, and embedded
+// by handle.
+fn attach_external_content(h_wnd: HWND)
+{
+    let h_inst = get_hinstance_for_h_wnd(h_wnd);
+    let host = host_dpi(h_wnd);
+    let h_wnd_external = PLUGINS.with(|plugins| match plugins.borrow().plugins().first() {
+        Some(found) => PLUGIN_HELPER.with(|helper| match helper.borrow_mut().as_mut() {
+            Some(supervisor) => {
+                // The helper has no awareness of ours to fall back on, so it's
+                // given the content's even where that is ours
+                let isolation = found.isolation(host);
+                let isolation = DpiIsolation { context: Some(isolation.content_awareness(host)), ..isolation };
+                supervisor.create(h_wnd as u64, EXTERNAL_CONTENT_WIDTH96.0, EXTERNAL_CONTENT_HEIGHT96.0, isolation)
+                    .unwrap_or(0) as usize as HWND
+            },
+            None => HOST.with(|sample_host| {
+                sample_host.creating_for.set(Some(h_wnd));
+                let h_wnd_external = unsafe {
+                    found.create_content(host, h_inst as _, EXTERNAL_CONTENT_WIDTH96.0, EXTERNAL_CONTENT_HEIGHT96.0) as HWND
+                };
+                sample_host.creating_for.set(None);
+                h_wnd_external
+            }),
+        }),
+        None => ptr::null_mut(),
+    });
+    embed_external_content(h_wnd, h_wnd_external);
+}
+
+// The external content was created with the awareness it runs in, reparent it
+fn embed_external_content(h_wnd: HWND, h_wnd_external: HWND)
+{
+    if h_wnd_external != ptr::null_mut()
     {
-        previous_dpi_hosting_behavior = set_thread_dpi_hosting_behavior(DPI_HOSTING_BEHAVIOR_MIXED);
+        let _h_wnd_result = unsafe { winuser::SetParent(h_wnd_external, h_wnd) };
+        let prop_external_content = to_wstring(PROP_EXTERNALCONTENT);
+        unsafe { winuser::SetPropW(h_wnd, prop_external_content.as_ptr(), h_wnd_external as HANDLE) };
+        // New content hasn't been told of any DPI
+        let prop_external_content_dpi = to_wstring(PROP_EXTERNALCONTENTDPI);
+        unsafe { winuser::RemovePropW(h_wnd, prop_external_content_dpi.as_ptr()) };
+    }
+}
 
-        // For this example, we'll have the external content run with System-DPI awareness
-		previous_dpi_context = set_thread_dpi_awareness_context(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE);
+// Hand a sample window's external content back to its plugin
+fn detach_external_content(h_wnd: HWND)
+{
+    let prop_external_content = to_wstring(PROP_EXTERNALCONTENT);
+    let h_wnd_external = unsafe { winuser::RemovePropW(h_wnd, prop_external_content.as_ptr()) };
+    let prop_external_content_dpi = to_wstring(PROP_EXTERNALCONTENTDPI);
+    unsafe { winuser::RemovePropW(h_wnd, prop_external_content_dpi.as_ptr()) };
+    if h_wnd_external != ptr::null_mut()
+    {
+        let remote = PLUGIN_HELPER.with(|helper| match helper.borrow_mut().as_mut() {
+            Some(supervisor) => {
+                // a helper that died took the content with it
+                let _ = supervisor.destroy(h_wnd as u64);
+                true
+            },
+            None => false,
+        });
+        if !remote
+        {
+            let host = host_dpi(h_wnd);
+            PLUGINS.with(|plugins| if let Some(found) = plugins.borrow().plugins().first() {
+                unsafe { found.destroy_content(host, h_wnd_external as _) };
+            });
+        }
     }
+}
 
-    let h_wnd_external = unsafe {
-         dll_plugin_import::create_content_hwnd(h_inst, EXTERNAL_CONTENT_WIDTH96, EXTERNAL_CONTENT_HEIGHT96)
-    };
+// Look for rebuilt plugins, and swap in the ones that have settled
+fn reload_plugins(h_wnd_dlg: HWND)
+{
+    let now = Instant::now();
+    let reloads: Vec<Reload> = WATCHES.with(|watches| watches.borrow_mut().iter_mut().map(|watch| watch.poll(now)).collect());
+    let mut changed = false;
+    for reload in reloads {
+        match reload {
+            Reload::Reloaded(found) => {
+                let line = format!("reloaded {} from {}", found.name(), found.source.display());
+                swap_plugin(h_wnd_dlg, found);
+                PLUGIN_LOG.with(|log| log.borrow_mut().push(line));
+            },
+            Reload::Failed(error) => {
+                PLUGIN_LOG.with(|log| log.borrow_mut().push(format!("couldn't reload {}", error)));
+            },
+            Reload::Unchanged | Reload::Settling => continue,
+        }
+        changed = true;
+    }
 
-    // Return the thread context and hosting behavior to its previous value, if using DPI-isolation
-	if b_dpi_isolation == TRUE
-	{
-		set_thread_dpi_awareness_context(previous_dpi_context);
-		set_thread_dpi_hosting_behavior(previous_dpi_hosting_behavior);
-	}
+    if changed
+    {
+        let app_description = to_wstring(app_description());
+        unsafe { winuser::SetDlgItemTextW(h_wnd_dlg, IDC_EDIT1, app_description.as_ptr()); }
+    }
+}
 
-    // After the external content HWND was create with a system-DPI awareness context, reparent it
-    let _h_wnd_result = unsafe { winuser::SetParent(h_wnd_external, h_wnd) };
+// Check on the plugin helper, embedding the content again if it had to be
+// restarted, and note what it logged
+fn supervise_plugin_helper(h_wnd_dlg: HWND)
+{
+    let (supervision, logs, relayouts) = PLUGIN_HELPER.with(|helper| match helper.borrow_mut().as_mut() {
+        Some(supervisor) => (supervisor.poll(Instant::now()), supervisor.take_logs(), supervisor.take_relayouts()),
+        None => (Supervision::Stopped, Vec::new(), Vec::new()),
+    });
+    // The ids are the sample windows
+    for id in relayouts {
+        unsafe { winuser::PostMessageW(id as usize as HWND, WM_RELAYOUT, 0, 0) };
+    }
+    let mut lines: Vec<String> = logs.into_iter()
+        .filter(|(level, _)| *level <= LogLevel::Warn)
+        .map(|(level, message)| format!("plugin {}: {}", level, message))
+        .collect();
+
+    let prop_external_content = to_wstring(PROP_EXTERNALCONTENT);
+    match supervision {
+        Supervision::Restarted { error, windows } => {
+            lines.push(format!("restarted the plugin helper: {}", error));
+            for (id, window) in windows {
+                let h_wnd = id as usize as HWND;
+                unsafe { winuser::RemovePropW(h_wnd, prop_external_content.as_ptr()) };
+                embed_external_content(h_wnd, window as usize as HWND);
+                update_and_dpi_scale_child_windows(h_wnd, get_maybe_dpi_by_awareness(h_wnd).unwrap_or(Dpi::DEFAULT.0));
+            }
+        },
+        Supervision::GaveUp(error) => {
+            lines.push(format!("gave up on the plugin helper: {}", error));
+            for h_wnd in sample_windows(h_wnd_dlg) {
+                unsafe { winuser::RemovePropW(h_wnd, prop_external_content.as_ptr()) };
+            }
+        },
+        Supervision::Running | Supervision::Stopped => {},
+    }
 
-    // DPI scale child-windows
-    //UpdateAndDpiScaleChildWindows(h_wnd, u_dpi);
-	update_and_dpi_scale_child_windows(h_wnd, u_dpi);
+    if !lines.is_empty()
+    {
+        PLUGIN_LOG.with(|log| log.borrow_mut().extend(lines));
+        let app_description = to_wstring(app_description());
+        unsafe { winuser::SetDlgItemTextW(h_wnd_dlg, IDC_EDIT1, app_description.as_ptr()); }
+    }
+}
 
-    return 0;
+// Replace a plugin with a new build of it. The sample windows are left as
+// they are, only the content of the old build is destroyed and created again
+// with the new one, then laid out at the window's DPI.
+fn swap_plugin(h_wnd_dlg: HWND, found: RegisteredPlugin)
+{
+    let shows_content = PLUGINS.with(|plugins| {
+        plugins.borrow().plugins().first().is_some_and(|first| first.name() == found.name())
+    });
+    let h_wnds = if shows_content { sample_windows(h_wnd_dlg) } else { Vec::new() };
+
+    for &h_wnd in &h_wnds {
+        detach_external_content(h_wnd);
+    }
+    let previous = PLUGINS.with(|plugins| plugins.borrow_mut().replace(found));
+    drop(previous);
+    for &h_wnd in &h_wnds {
+        attach_external_content(h_wnd);
+        update_and_dpi_scale_child_windows(h_wnd, get_maybe_dpi_by_awareness(h_wnd).unwrap_or(Dpi::DEFAULT.0));
+    }
+}
+
+// The sample windows the host dialog opened
+fn sample_windows(h_wnd_dlg: HWND) -> Vec<HWND>
+{
+    let class_name_str = to_wstring(WINDOWCLASSNAME);
+    let mut h_wnds = Vec::new();
+    let mut h_wnd = ptr::null_mut();
+    loop {
+        h_wnd = unsafe { winuser::FindWindowExW(ptr::null_mut(), h_wnd, class_name_str.as_ptr(), ptr::null()) };
+        if h_wnd == ptr::null_mut()
+        {
+            return h_wnds;
+        }
+        if unsafe { winuser::GetWindow(h_wnd, winuser::GW_OWNER) } == h_wnd_dlg
+        {
+            h_wnds.push(h_wnd);
+        }
+    }
 }
 
 // DPI Change handler. on WM_DPICHANGE resize the window and
@@ -341,13 +835,14 @@ pub extern "C" fn handle_dpi_change(h_wnd: HWND, w_param: WPARAM, l_param: LPARA
     {
         let u_dpi = HIWORD(w_param as u32) as UINT;
 
-        // Resize the window
+        // Resize the window to the suggested rect, see hidpi::sim for how
+        // Windows picks it
         let lprc_new_scale: *const RECT = l_param as *const RECT;
-        let new_scale = unsafe { &*lprc_new_scale };
+        let new_scale: Rect = unsafe { *lprc_new_scale }.into();
 
         unsafe {
             winuser::SetWindowPos(h_wnd, ptr::null_mut(), new_scale.left, new_scale.top,
-                new_scale.right - new_scale.left, new_scale.bottom - new_scale.top,
+                new_scale.width(), new_scale.height(),
                 winuser::SWP_NOZORDER | winuser::SWP_NOACTIVATE);
         }
 
@@ -368,8 +863,20 @@ pub extern "C" fn create_sample_window(h_wnd_dlg: HWND,
 {
     let h_inst = get_hinstance_for_h_wnd(h_wnd_dlg);
 
-    // Store the current thread's DPI-awareness context
-    let previous_dpi_context = set_thread_dpi_awareness_context(context);
+    // Windows 10 (1803) supports child-HWND DPI-mode isolation. This enables
+    // child HWNDs to run in DPI-scaling modes that are isolated from that of 
+    // their parent (or host) HWND. Without child-HWND DPI isolation, all HWNDs 
+    // in an HWND tree must have the same DPI-scaling mode.
+    let hosting = if b_child_window_dpi_isolation == TRUE
+    {
+        Some(DpiHostingBehavior::Mixed)
+    } else {
+        None
+    };
+
+    // Switch the current thread's DPI-awareness context (and hosting behavior, if
+    // requested) until the end of this function
+    let _dpi_scope = ThreadDpiScope::enter(DpiAwarenessContext::from_raw(context), hosting);
 
     // Create the window. Initially create it using unscaled (96 DPI)
     // sizes. We'll resize the window after it's created
@@ -379,36 +886,16 @@ pub extern "C" fn create_sample_window(h_wnd_dlg: HWND,
         b_child_window_dpi_isolation: b_child_window_dpi_isolation,
     };
 
-    // Windows 10 (1803) supports child-HWND DPI-mode isolation. This enables
-    // child HWNDs to run in DPI-scaling modes that are isolated from that of 
-    // their parent (or host) HWND. Without child-HWND DPI isolation, all HWNDs 
-    // in an HWND tree must have the same DPI-scaling mode.
-	let previous_dpi_hosting_behavior = if b_child_window_dpi_isolation == TRUE
-	{
-		set_thread_dpi_hosting_behavior(DPI_HOSTING_BEHAVIOR_MIXED)
-	} else {
-        DPI_HOSTING_BEHAVIOR::default()
-    };
-
-    let h_menu = unsafe { winuser::LoadMenuW(h_inst, IDC_MAINMENU as *mut wchar_t) };
+    let h_menu = sample_menu().create();
 
     let empty_str = to_wstring("");
     let class_name_str = to_wstring(WINDOWCLASSNAME);
     let h_wnd = unsafe { winuser::CreateWindowExW(0, class_name_str.as_ptr(), empty_str.as_ptr(), 
         winuser::WS_OVERLAPPEDWINDOW | winuser::WS_HSCROLL | winuser::WS_VSCROLL,
-        winuser::CW_USEDEFAULT, 0, WINDOW_WIDTH96, WINDOW_HEIGHT96, h_wnd_dlg, h_menu,
+        winuser::CW_USEDEFAULT, 0, WINDOW_WIDTH96.0, WINDOW_HEIGHT96.0, h_wnd_dlg, h_menu,
         h_inst, &mut create_params as *mut CreateParams as *mut c_void) };
 
     unsafe { winuser::ShowWindow(h_wnd, winuser::SW_SHOWNORMAL) };
-
-    // Restore the current thread's DPI awareness context
-    set_thread_dpi_awareness_context(previous_dpi_context);
-
-	// Restore the current thread DPI hosting behavior, if we changed it.
-	if b_child_window_dpi_isolation == TRUE
-	{
-		set_thread_dpi_hosting_behavior(previous_dpi_hosting_behavior);
-	}
 }
 
 // The dialog procedure for the sample host window
@@ -423,46 +910,42 @@ pub extern "system" fn host_dialog_proc(h_wnd_dlg: HWND, message: UINT, w_param:
             return get_stock_brush(wingdi::WHITE_BRUSH) as LRESULT;
         },
         winuser::WM_INITDIALOG => { 
-            let app_description = to_wstring(
-    r#"This sample app lets you create windows with different DPI Awareness modes so
-    that you can observe how Win32 windows behave under these modes.
-    Each window will show different behaviors depending on the mode (will be blurry or
-    crisp, non-client area will scale differently, etc.).
-    \r\n\r\n
-    The best way to observe these differences is to move each window to a display with a
-    different display scaling (DPI) value. On single-display devices you can simulate
-    this by changing the display scaling value of your display (the "Change the size
-    of text, apps, and other items" setting in the Display settings page of the Settings
-    app, as of Windows 10, 1703). Make these settings changes while the app is still
-    running to observe the different DPI-scaling behavior.
-    "#
-            );
+            let app_description = to_wstring(app_description());
             unsafe { winuser::SetDlgItemTextW(h_wnd_dlg, IDC_EDIT1, app_description.as_ptr()); }
+            if WATCHES.with(|watches| !watches.borrow().is_empty()) || PLUGIN_HELPER.with(|helper| helper.borrow().is_some())
+            {
+                unsafe { winuser::SetTimer(h_wnd_dlg, PLUGIN_TIMER_ID, PLUGIN_POLL_MS, None) };
+            }
             return 0;
         },
+        winuser::WM_TIMER if w_param == PLUGIN_TIMER_ID => {
+            reload_plugins(h_wnd_dlg);
+            supervise_plugin_helper(h_wnd_dlg);
+            return TRUE as LRESULT;
+        },
         winuser::WM_COMMAND => {
-            let mut context: DPI_AWARENESS_CONTEXT = ptr::null_mut();
+            let mut context = None;
             let mut b_non_client_scaling = FALSE;
             let mut b_child_window_dpi_isolation = FALSE;
             match LOWORD(w_param as u32) as i32 {
                 IDC_BUTTON_UNAWARE => {
-                    context = DPI_AWARENESS_CONTEXT_UNAWARE;
+                    context = Some(DpiAwarenessContext::Unaware);
                 },
                 IDC_BUTTON_SYSTEM => {
-                    context = DPI_AWARENESS_CONTEXT_SYSTEM_AWARE;
+                    context = Some(DpiAwarenessContext::System);
                 },
                 IDC_BUTTON_81 => {
-                    context = DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE;
+                    context = Some(DpiAwarenessContext::PerMonitor);
                 },
                 IDC_BUTTON_1607 => {
-                    context = DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE;
+                    context = Some(DpiAwarenessContext::PerMonitor);
                     b_non_client_scaling = TRUE;
                 },
                 IDC_BUTTON_1703 => {
-                    context = DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2;
+                    context = Some(DpiAwarenessContext::PerMonitorV2);
                 },
                 IDC_BUTTON_1803 => {
-                    context = DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2;
+                    context = Some(DpiAwarenessContext::PerMonitorV2);
                     b_child_window_dpi_isolation = TRUE;
                 },
                 IDM_EXIT => {
@@ -472,9 +955,9 @@ pub extern "system" fn host_dialog_proc(h_wnd_dlg: HWND, message: UINT, w_param:
                 _ => {}
             }
 
-            if context != ptr::null_mut()
+            if let Some(context) = context
             {
-                create_sample_window(h_wnd_dlg, context, b_non_client_scaling, b_child_window_dpi_isolation);
+                create_sample_window(h_wnd_dlg, context.to_raw(), b_non_client_scaling, b_child_window_dpi_isolation);
             }
             return TRUE as LRESULT;
 
@@ -518,7 +1001,7 @@ pub extern "system" fn wnd_proc(h_wnd: HWND, message: UINT, w_param: WPARAM, l_p
 
             if create_params.b_enable_non_client_dpi_scaling == TRUE
             {
-                unsafe { winuser::EnableNonClientDpiScaling(h_wnd); }
+                enable_non_client_dpi_scaling(h_wnd);
             }
 
             // Store a flag on the window to note that it'll run its child in a different awareness
@@ -546,6 +1029,12 @@ pub extern "system" fn wnd_proc(h_wnd: HWND, message: UINT, w_param: WPARAM, l_p
             return handle_dpi_change(h_wnd, w_param, l_param);
         },
 
+        // The plugin asked for its content to be laid out again
+        WM_RELAYOUT => {
+            update_and_dpi_scale_child_windows(h_wnd, get_maybe_dpi_by_awareness(h_wnd).unwrap_or(Dpi::DEFAULT.0));
+            return 0;
+        },
+
         winuser::WM_CLOSE => {
             unsafe { winuser::DestroyWindow(h_wnd); }
             return 0;
@@ -554,19 +1043,17 @@ pub extern "system" fn wnd_proc(h_wnd: HWND, message: UINT, w_param: WPARAM, l_p
         winuser::WM_COMMAND => {
             let wm_id = LOWORD(w_param as u32) as i32;
             // Parse the menu selections:
-            match wm_id{
-                res::IDM_SHOWDIALOG => {
-                    show_file_open_dialog(h_wnd);
-                    return 0;
-                },
-
-                _ => {
-                    return unsafe { winuser::DefWindowProcW(h_wnd, message, w_param, l_param) };
-                }
+            if SAMPLE_COMMANDS.with(|commands| commands.dispatch(wm_id, h_wnd)) {
+                return 0;
             }
+
+            return unsafe { winuser::DefWindowProcW(h_wnd, message, w_param, l_param) };
         },
 
         winuser::WM_DESTROY => {
+            // Hand the external content back to its plugin before Windows
+            // destroys it along with this window
+            detach_external_content(h_wnd);
             delete_window_font(h_wnd);
 
             return 0;
@@ -578,13 +1065,36 @@ pub extern "system" fn wnd_proc(h_wnd: HWND, message: UINT, w_param: WPARAM, l_p
     }
 }
 
+/// The host dialog, `IDD_DIALOG1` in DpiAwarenessContext.rc, in dialog units,
+/// with a scroll bar on the description since it lists the plugins.
+fn host_dialog_template() -> DialogTemplate {
+    let button = |text: &str, id, y, height| DialogControl::push_button(text, id, 7, y, 290, height)
+        .style(winuser::BS_MULTILINE);
+
+    DialogTemplate::new(0, 0, 304, 339)
+        .style(winuser::DS_MODALFRAME | winuser::DS_CENTER | winuser::WS_POPUP | winuser::WS_SYSMENU)
+        .caption("High DPI Awareness Sample")
+        .font(DialogFont::new(9, "Segoe UI"))
+        .control(button("Create a DPI unaware window\r\n(DPI_AWARENESS_CONTEXT_UNAWARE)", IDC_BUTTON_UNAWARE, 111, 30))
+        .control(button("Create a System DPI aware window\r\n(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE)", IDC_BUTTON_SYSTEM, 148, 30))
+        .control(button("Create a Windows 8.1 era Per-Monitor DPI aware window\r\n(DPI_AWARENESS_CONTEXT_PER_MONITOR)", IDC_BUTTON_81, 186, 30))
+        .control(button("Create a Windows 10 (1607) Per-Monitor DPI aware window with non-client scaling\r\n(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE + EnableNonClientDpiScaling())", IDC_BUTTON_1607, 223, 33))
+        .control(button("Create a Windows 10 (1703) Per-Monitor DPI aware v2 window\r\n(DPI_AWARENESS_CONTEXT_PER_MONITOR_V2)", IDC_BUTTON_1703, 264, 30))
+        .control(DialogControl::edit_text(IDC_EDIT1, 7, 7, 290, 95)
+            .style(winuser::ES_MULTILINE | winuser::ES_READONLY | winuser::WS_VSCROLL)
+            .without_style(winuser::WS_BORDER))
+        .control(button("Create a Windows 10 (1803) Per-Monitor DPI aware v2 window with mixed DPI hosting\r\n(DPI_AWARENESS_CONTEXT_PER_MONITOR_V2 + DPI_HOSTING_BEHAVIOR_MIXED)", IDC_BUTTON_1803, 302, 30))
+}
+
 pub fn main() {
 /*#[allow(non_snake_case)]
 #[no_mangle]
 pub extern "system" fn wWinMain(h_inst: HINSTANCE, _h_inst_2: HINSTANCE, _str: LPWSTR, n_cmd_show: INT) -> INT
 {*/
     //show_console_window();
-    hidpi::win::set_thread_dpi_awareness_context(winapi::shared::windef::DPI_AWARENESS_CONTEXT_SYSTEM_AWARE);
+    // The process is Per-Monitor V2 aware from its manifest (see build.rs),
+    // the host window itself is System aware
+    set_thread_dpi_awareness_context(DpiAwarenessContext::System);
 
     let mut wcex = winuser::WNDCLASSEXW::default();
     let wcex_classname = to_wstring(WINDOWCLASSNAME);
@@ -604,7 +1114,7 @@ pub extern "system" fn wWinMain(h_inst: HINSTANCE, _h_inst_2: HINSTANCE, _str: L
     //g_hInst = hInstance; // Store instance handle in our global variable
 
     // Create the host window
-    let h_host_dlg = unsafe { winuser::CreateDialogParamW(h_inst, IDD_DIALOG1 as LPCWSTR, ptr::null_mut(), Some(host_dialog_proc), 0) };
+    let h_host_dlg = host_dialog_template().create(h_inst, ptr::null_mut(), Some(host_dialog_proc), 0);
     if h_host_dlg == ptr::null_mut()
     {
         std::process::exit(0);
@@ -613,12 +1123,18 @@ pub extern "system" fn wWinMain(h_inst: HINSTANCE, _h_inst_2: HINSTANCE, _str: L
     unsafe { winuser::ShowWindow(h_host_dlg, n_cmd_show); }
 
     let mut msg = winuser::MSG::default();
+    let h_accel = sample_menu().create_accelerator_table();
 
     // Main message loop:
     while unsafe { winuser::GetMessageW(&mut msg, ptr::null_mut(), 0, 0) == TRUE }
     {
-        unsafe { winuser::TranslateMessage(&msg) };
-        unsafe { winuser::DispatchMessageW(&msg) };
+        // Accelerators go to the top-level window, wherever the focus is in it
+        let h_wnd_root = unsafe { winuser::GetAncestor(msg.hwnd, winuser::GA_ROOT) };
+        if unsafe { winuser::TranslateAcceleratorW(h_wnd_root, h_accel, &mut msg) } == 0
+        {
+            unsafe { winuser::TranslateMessage(&msg) };
+            unsafe { winuser::DispatchMessageW(&msg) };
+        }
     }
 
     std::process::exit(msg.wParam as i32);
@@ -659,8 +1175,8 @@ pub extern "C" fn show_file_open_dialog(h_wnd: HWND)
     unsafe { commdlg::GetOpenFileNameW(&mut ofn); }
 }
 
-// Find the child static control, get the font for the control, then
-// delete it
+// Find the child static control and take the font away from it. The font
+// itself belongs to FONTS, which may delete it once no window uses it.
 #[no_mangle]
 pub extern "C" fn delete_window_font(h_wnd: HWND)
 {
@@ -670,7 +1186,6 @@ pub extern "C" fn delete_window_font(h_wnd: HWND)
         return;
     }
 
-    // Get a handle to the font
     let h_font = get_window_font(h_wnd_static);
     if h_font == ptr::null_mut()
     {
@@ -678,7 +1193,7 @@ pub extern "C" fn delete_window_font(h_wnd: HWND)
     }
 
     set_window_font(h_wnd_static, ptr::null_mut(), FALSE);
-    unsafe { wingdi::DeleteObject(h_font as *mut c_void) };
+    FONTS.with(|fonts| fonts.borrow_mut().release(h_font));
 }
 
 pub struct EnumChildWindowProcContext {
@@ -1,39 +1,4 @@
 use winapi::shared::minwindef::INT;
 
-pub const IDM_EXIT: INT                  = 103;
-pub const IDM_DPIUNAWARE: INT            = 104;
-pub const IDM_SHOWDIALOG: INT            = 105;
-pub const IDC_MAINMENU: INT              = 109;
-pub const IDD_DIALOG1: INT               = 129;
-pub const IDC_BUTTON1: INT               = 1000;
-pub const IDC_BUTTON_UNAWARE: INT        = 1000;
-pub const IDC_BUTTON2: INT               = 1001;
-pub const IDC_BUTTON_SYSTEM: INT         = 1001;
-pub const IDC_BUTTON3: INT               = 1002;
-pub const IDC_BUTTON_81: INT             = 1002;
-pub const IDC_BUTTON4: INT               = 1003;
-pub const IDC_BUTTON_1607: INT           = 1003;
-pub const IDC_BUTTON5: INT               = 1004;
-pub const IDC_BUTTON_1703: INT           = 1004;
-pub const IDC_BUTTON6: INT               = 1005;
-pub const IDC_BUTTON_1803: INT           = 1005;
-pub const IDC_EDIT1: INT                 = 1007;
-pub const ID_FILE_ITEM2: INT             = 32771;
-pub const ID_FILE_ITEM3: INT             = 32772;
-pub const ID_ITEM1_SUBITEM1: INT         = 32773;
-pub const ID_ITEM1_SUBITEM2: INT         = 32774;
-pub const ID_ITEM1_SUBITEM3: INT         = 32775;
-pub const ID_ITEM1_SUBITEM4: INT         = 32776;
-pub const ID_EDIT_EDITITEM1: INT         = 32777;
-pub const ID_EDIT_EDITITEM2: INT         = 32778;
-pub const ID_EDIT_EDITITEM3: INT         = 32779;
-pub const ID_VIEW_VIEWITEM1: INT         = 32780;
-pub const ID_VIEW_VIEWITEM2: INT         = 32781;
-pub const ID_VIEW_VIEWITEM3: INT         = 32782;
-pub const ID_VIEWITEM1_VIEWSUBITEM1: INT = 32783;
-pub const ID_VIEWITEM1_VIEWSUBITEM2: INT = 32784;
-pub const ID_VIEWITEM1_VIEWSUBITEM3: INT = 32785;
-pub const ID_EDITITEM1_EDITSUBITEM1: INT = 32786;
-pub const ID_EDITITEM1_EDITSUBITEM2: INT = 32787;
-pub const ID_EDITITEM1_EDITSUBITEM3: INT = 32788;
-pub const IDC_STATIC: INT                = -1;
\ No newline at end of file
+// The #defines of Resource.h, generated by build.rs
+include!(concat!(env!("OUT_DIR"), "/res.rs"));
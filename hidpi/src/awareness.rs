@@ -0,0 +1,306 @@
+use std::fmt;
+use std::io;
+
+use crate::sys::{
+    ERROR_ACCESS_DENIED,
+    E_ACCESSDENIED,
+};
+
+use crate::backend::{self, DpiBackend};
+use crate::win::WinDpiAwareness;
+use crate::DpiAwarenessContext;
+
+/// The process-wide DPI awareness APIs, newest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AwarenessApi {
+    /// `SetProcessDpiAwarenessContext` (user32, Windows 10 1703).
+    SetProcessDpiAwarenessContext,
+    /// `SetProcessDpiAwareness` (shcore, Windows 8.1).
+    SetProcessDpiAwareness,
+    /// `SetProcessDPIAware` (user32, Windows Vista).
+    SetProcessDPIAware,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttemptError {
+    /// The function is not exported by this version of Windows.
+    NotExported,
+    /// The function exists, but has no way to express this awareness level.
+    LevelUnavailable,
+    /// The awareness has already been set, by the manifest or an earlier call.
+    AccessDenied,
+    /// Any other failure, with the OS error code where one was reported.
+    Failed(Option<i32>),
+}
+
+impl fmt::Display for AttemptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttemptError::NotExported => f.write_str("not available on this version of Windows"),
+            AttemptError::LevelUnavailable => f.write_str("awareness level not supported by this API"),
+            AttemptError::AccessDenied => f.write_str("dpi awareness has already been set for this process"),
+            AttemptError::Failed(Some(code)) => write!(f, "failed with error {:#x}", code),
+            AttemptError::Failed(None) => f.write_str("failed"),
+        }
+    }
+}
+
+impl From<io::Error> for AttemptError {
+    fn from(e: io::Error) -> AttemptError {
+        match e.raw_os_error() {
+            Some(code) if code == ERROR_ACCESS_DENIED as i32 || code == E_ACCESSDENIED => AttemptError::AccessDenied,
+            code => AttemptError::Failed(code),
+        }
+    }
+}
+
+/// One failed call made by `enable_dpi_awareness`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    pub context: DpiAwarenessContext,
+    pub api: AwarenessApi,
+    pub error: AttemptError,
+}
+
+/// The outcome of `enable_dpi_awareness`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Applied {
+    /// The awareness now in effect for the process, or `None` if no call
+    /// succeeded and the process is still running with its default awareness.
+    pub context: Option<DpiAwarenessContext>,
+    /// The API that applied `context`.
+    pub api: Option<AwarenessApi>,
+    /// Every call that failed before one succeeded, in the order they were made.
+    pub failed: Vec<Attempt>,
+}
+
+// The ladder walked by `enable_dpi_awareness`: each awareness level with the
+// APIs able to set it, newest first.
+const LADDER: &[(DpiAwarenessContext, &[AwarenessApi])] = &[
+    (DpiAwarenessContext::PerMonitorV2, &[AwarenessApi::SetProcessDpiAwarenessContext]),
+    (DpiAwarenessContext::PerMonitor, &[AwarenessApi::SetProcessDpiAwarenessContext, AwarenessApi::SetProcessDpiAwareness]),
+    (DpiAwarenessContext::System, &[AwarenessApi::SetProcessDpiAwarenessContext, AwarenessApi::SetProcessDpiAwareness, AwarenessApi::SetProcessDPIAware]),
+    (DpiAwarenessContext::UnawareGdiScaled, &[AwarenessApi::SetProcessDpiAwarenessContext]),
+    (DpiAwarenessContext::Unaware, &[AwarenessApi::SetProcessDpiAwarenessContext, AwarenessApi::SetProcessDpiAwareness]),
+];
+
+fn rank(context: DpiAwarenessContext) -> usize {
+    LADDER.iter()
+        .position(|(level, _)| *level == context)
+        .expect("every context is on the ladder")
+}
+
+fn try_apply(b: &dyn DpiBackend, context: DpiAwarenessContext, api: AwarenessApi) -> Result<(), AttemptError> {
+    let applied = match api {
+        AwarenessApi::SetProcessDpiAwarenessContext => {
+            b.set_process_dpi_awareness_context(context.to_raw())?
+        },
+        AwarenessApi::SetProcessDpiAwareness => {
+            let awareness = match context {
+                DpiAwarenessContext::PerMonitor => WinDpiAwareness::ProcessPerMonitorDpiAware,
+                DpiAwarenessContext::System => WinDpiAwareness::ProcessSystemDpiAware,
+                DpiAwarenessContext::Unaware => WinDpiAwareness::ProcessDpiUnaware,
+                _ => return Err(AttemptError::LevelUnavailable),
+            };
+            b.set_process_dpi_awareness(awareness)?
+        },
+        AwarenessApi::SetProcessDPIAware => {
+            if context != DpiAwarenessContext::System {
+                return Err(AttemptError::LevelUnavailable);
+            }
+            match b.set_process_dpi_aware() {
+                Some(true) => true,
+                Some(false) => return Err(AttemptError::Failed(None)),
+                None => false,
+            }
+        },
+    };
+
+    if applied {
+        Ok(())
+    } else {
+        Err(AttemptError::NotExported)
+    }
+}
+
+/// Make the process as DPI aware as the running version of Windows allows,
+/// without going beyond `preferred`.
+///
+/// Starting at `preferred` this works down through Per-Monitor V2, Per-Monitor
+/// and System, trying at each level the newest API first
+/// (`SetProcessDpiAwarenessContext`, then shcore's `SetProcessDpiAwareness`,
+/// then the legacy `SetProcessDPIAware`). It stops at the first call that
+/// succeeds. Unaware is only set when it is `preferred`: when every aware level
+/// fails the process is left unaware by default, without locking it there
+/// for a later call. If the awareness was already fixed by the application
+/// manifest every call fails, with `AttemptError::AccessDenied` from the APIs
+/// that report it, and `context` is `None`.
+pub fn enable_dpi_awareness(preferred: DpiAwarenessContext) -> Applied {
+    backend::current(|b| {
+        let mut failed = Vec::new();
+
+        for (context, apis) in &LADDER[rank(preferred)..] {
+            // GDI scaling only makes sense if it was asked for, and setting
+            // Unaware only locks in the default
+            let unaware = *context == DpiAwarenessContext::UnawareGdiScaled || *context == DpiAwarenessContext::Unaware;
+            if unaware && *context != preferred {
+                continue;
+            }
+
+            for api in apis.iter() {
+                match try_apply(b, *context, *api) {
+                    Ok(()) => return Applied { context: Some(*context), api: Some(*api), failed },
+                    Err(error) => failed.push(Attempt { context: *context, api: *api, error }),
+                }
+            }
+        }
+
+        Applied { context: None, api: None, failed }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::with_backend;
+    use crate::mock::{MockBackend, Release};
+    use crate::DpiAwarenessContext::*;
+    use self::AwarenessApi::*;
+
+    fn enable_on(backend: &MockBackend, preferred: DpiAwarenessContext) -> Applied {
+        with_backend(backend.clone(), || enable_dpi_awareness(preferred))
+    }
+
+    fn failed(context: DpiAwarenessContext, api: AwarenessApi, error: AttemptError) -> Attempt {
+        Attempt { context, api, error }
+    }
+
+    #[test]
+    fn windows_7_is_system_aware_through_the_legacy_api() {
+        let backend = MockBackend::new(Release::Windows7);
+        let applied = enable_on(&backend, PerMonitorV2);
+        assert_eq!(applied.context, Some(System));
+        assert_eq!(applied.api, Some(SetProcessDPIAware));
+        assert_eq!(applied.failed, [
+            failed(PerMonitorV2, SetProcessDpiAwarenessContext, AttemptError::NotExported),
+            failed(PerMonitor, SetProcessDpiAwarenessContext, AttemptError::NotExported),
+            failed(PerMonitor, SetProcessDpiAwareness, AttemptError::NotExported),
+            failed(System, SetProcessDpiAwarenessContext, AttemptError::NotExported),
+            failed(System, SetProcessDpiAwareness, AttemptError::NotExported),
+        ]);
+        assert_eq!(backend.process_context(), Some(System));
+    }
+
+    #[test]
+    fn windows_8_1_and_10_1607_are_per_monitor_through_shcore() {
+        for &release in &[Release::Windows81, Release::Windows10_1607] {
+            let backend = MockBackend::new(release);
+            let applied = enable_on(&backend, PerMonitorV2);
+            assert_eq!(applied.context, Some(PerMonitor), "{:?}", release);
+            assert_eq!(applied.api, Some(SetProcessDpiAwareness));
+            assert_eq!(applied.failed, [
+                failed(PerMonitorV2, SetProcessDpiAwarenessContext, AttemptError::NotExported),
+                failed(PerMonitor, SetProcessDpiAwarenessContext, AttemptError::NotExported),
+            ]);
+            assert_eq!(backend.process_context(), Some(PerMonitor));
+        }
+    }
+
+    #[test]
+    fn windows_10_1703_and_1803_are_per_monitor_v2() {
+        for &release in &[Release::Windows10_1703, Release::Windows10_1803] {
+            let backend = MockBackend::new(release);
+            let applied = enable_on(&backend, PerMonitorV2);
+            assert_eq!(applied, Applied { context: Some(PerMonitorV2), api: Some(SetProcessDpiAwarenessContext), failed: vec![] });
+            assert_eq!(backend.calls(), ["SetProcessDpiAwarenessContext(PerMonitorV2)"]);
+        }
+    }
+
+    #[test]
+    fn never_goes_beyond_the_preferred_level() {
+        let backend = MockBackend::new(Release::Windows10_1803);
+        let applied = enable_on(&backend, System);
+        assert_eq!(applied.context, Some(System));
+        assert_eq!(backend.calls(), ["SetProcessDpiAwarenessContext(System)"]);
+
+        let backend = MockBackend::new(Release::Windows81);
+        let applied = enable_on(&backend, Unaware);
+        assert_eq!(applied.context, Some(Unaware));
+        assert_eq!(applied.api, Some(SetProcessDpiAwareness));
+    }
+
+    #[test]
+    fn gdi_scaling_is_only_tried_when_asked_for() {
+        let backend = MockBackend::new(Release::Windows10_1809);
+        assert_eq!(enable_on(&backend, UnawareGdiScaled).context, Some(UnawareGdiScaled));
+
+        // 1803 does not know the context yet, and leaves the default alone
+        let backend = MockBackend::new(Release::Windows10_1803);
+        let applied = enable_on(&backend, UnawareGdiScaled);
+        assert_eq!(applied.context, None);
+        assert_eq!(applied.failed, [failed(UnawareGdiScaled, SetProcessDpiAwarenessContext, AttemptError::Failed(Some(87)))]);
+        assert_eq!(backend.calls().len(), 1);
+        assert_eq!(backend.process_context(), None);
+    }
+
+    #[test]
+    fn unaware_is_only_set_when_asked_for() {
+        let backend = MockBackend::new(Release::Windows10_1803);
+        let applied = enable_on(&backend, Unaware);
+        assert_eq!(applied, Applied { context: Some(Unaware), api: Some(SetProcessDpiAwarenessContext), failed: vec![] });
+        assert_eq!(backend.process_context(), Some(Unaware));
+
+        // a process that can't be made aware stays at the default, free to be
+        // set later
+        let backend = MockBackend::new(Release::Windows10_1803).with_manifest(PerMonitorV2);
+        let applied = enable_on(&backend, System);
+        assert_eq!(applied.context, None);
+        assert!(applied.failed.iter().all(|attempt| attempt.context == System), "{:?}", applied.failed);
+        assert!(backend.calls().iter().all(|call| !call.contains("Unaware")), "{:?}", backend.calls());
+    }
+
+    #[test]
+    fn a_manifest_awareness_cannot_be_changed() {
+        let backend = MockBackend::new(Release::Windows10_1803).with_manifest(PerMonitorV2);
+        let applied = enable_on(&backend, PerMonitorV2);
+        assert_eq!(applied.context, None);
+        assert_eq!(applied.api, None);
+        assert_eq!(applied.failed, [
+            failed(PerMonitorV2, SetProcessDpiAwarenessContext, AttemptError::AccessDenied),
+            failed(PerMonitor, SetProcessDpiAwarenessContext, AttemptError::AccessDenied),
+            failed(PerMonitor, SetProcessDpiAwareness, AttemptError::AccessDenied),
+            failed(System, SetProcessDpiAwarenessContext, AttemptError::AccessDenied),
+            failed(System, SetProcessDpiAwareness, AttemptError::AccessDenied),
+            failed(System, SetProcessDPIAware, AttemptError::Failed(None)),
+        ]);
+        assert_eq!(backend.process_context(), Some(PerMonitorV2));
+    }
+
+    #[test]
+    fn nothing_applies_without_windows() {
+        let applied = enable_on(&MockBackend::new(Release::None), PerMonitorV2);
+        assert_eq!(applied.context, None);
+        assert!(applied.failed.iter().all(|attempt| attempt.error == AttemptError::NotExported));
+        assert_eq!(applied.failed.len(), 6);
+    }
+
+    #[test]
+    fn levels_an_api_cannot_express_are_rejected_without_a_call() {
+        let backend = MockBackend::new(Release::Windows10_1803);
+        with_backend(backend.clone(), || {
+            backend::current(|b| {
+                assert_eq!(try_apply(b, PerMonitorV2, SetProcessDpiAwareness), Err(AttemptError::LevelUnavailable));
+                assert_eq!(try_apply(b, PerMonitor, SetProcessDPIAware), Err(AttemptError::LevelUnavailable));
+            })
+        });
+        assert!(backend.calls().is_empty());
+    }
+
+    #[test]
+    fn access_denied_is_recognised_from_both_error_spaces() {
+        assert_eq!(AttemptError::from(io::Error::from_raw_os_error(ERROR_ACCESS_DENIED as i32)), AttemptError::AccessDenied);
+        assert_eq!(AttemptError::from(io::Error::from_raw_os_error(E_ACCESSDENIED)), AttemptError::AccessDenied);
+        assert_eq!(AttemptError::from(io::Error::from_raw_os_error(87)), AttemptError::Failed(Some(87)));
+        assert_eq!(AttemptError::from(io::Error::new(io::ErrorKind::Other, "no code")), AttemptError::Failed(None));
+    }
+}
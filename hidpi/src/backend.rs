@@ -0,0 +1,194 @@
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use crate::sys::{
+    c_int,
+    BOOL,
+    DWORD,
+    UINT,
+    HANDLE,
+    DPI_AWARENESS,
+    DPI_AWARENESS_CONTEXT,
+    DPI_HOSTING_BEHAVIOR,
+    HMONITOR,
+    HWND,
+    POINT,
+    RECT,
+    MONITOR_DPI_TYPE,
+    LOGFONTW,
+};
+use crate::win::{self, WinDpiAwareness};
+
+/// The set of dynamically loaded DPI functions that the rest of the crate
+/// is written against. Every method returns `None` (or `Ok(false)` for the
+/// process-wide setters) when the underlying symbol is not available on the
+/// running system.
+///
+/// `SystemBackend` forwards to `hidpi::win`. Other implementations can be
+/// installed for the current thread with `with_backend` so that callers of
+/// this crate can be exercised without the real user32/shcore exports.
+pub trait DpiBackend {
+    fn is_process_dpi_aware(&self) -> Option<bool>;
+    fn set_process_dpi_aware(&self) -> Option<bool>;
+    fn get_process_dpi_awareness(&self) -> io::Result<Option<WinDpiAwareness>>;
+    fn set_process_dpi_awareness(&self, awareness: WinDpiAwareness) -> io::Result<bool>;
+    fn set_process_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> io::Result<bool>;
+    fn get_thread_dpi_awareness_context(&self) -> Option<DPI_AWARENESS_CONTEXT>;
+    fn set_thread_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<DPI_AWARENESS_CONTEXT>;
+    fn get_awareness_from_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<DPI_AWARENESS>;
+    fn are_dpi_awareness_contexts_equal(&self, a: DPI_AWARENESS_CONTEXT, b: DPI_AWARENESS_CONTEXT) -> Option<BOOL>;
+    fn get_thread_dpi_hosting_behavior(&self) -> Option<DPI_HOSTING_BEHAVIOR>;
+    fn set_thread_dpi_hosting_behavior(&self, behavior: DPI_HOSTING_BEHAVIOR) -> Option<DPI_HOSTING_BEHAVIOR>;
+    fn get_window_dpi_awareness_context(&self, h_wnd: HWND) -> Option<DPI_AWARENESS_CONTEXT>;
+    fn get_window_dpi_hosting_behavior(&self, h_wnd: HWND) -> Option<DPI_HOSTING_BEHAVIOR>;
+    fn get_dpi_from_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<UINT>;
+    fn is_valid_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<bool>;
+    fn get_dpi_for_system(&self) -> Option<UINT>;
+    fn get_dpi_for_window(&self, h_wnd: HWND) -> Option<UINT>;
+    fn get_dpi_for_monitor(&self, h_monitor: HMONITOR, dpi_type: MONITOR_DPI_TYPE) -> io::Result<Option<(UINT, UINT)>>;
+    fn get_system_dpi_for_process(&self, h_process: HANDLE) -> Option<UINT>;
+    fn adjust_window_rect_ex_for_dpi(&self, rect: &mut RECT, style: DWORD, menu: bool, ex_style: DWORD, dpi: UINT) -> Option<bool>;
+    fn get_system_metrics_for_dpi(&self, index: c_int, dpi: UINT) -> Option<c_int>;
+    fn get_icon_title_log_font_for_dpi(&self, dpi: UINT) -> Option<LOGFONTW>;
+    fn enable_non_client_dpi_scaling(&self, h_wnd: HWND) -> Option<bool>;
+    fn logical_to_physical_point_for_per_monitor_dpi(&self, h_wnd: HWND, point: &mut POINT) -> Option<bool>;
+}
+
+/// The default backend: the functions resolved from user32.dll and shcore.dll,
+/// none of which exist on other platforms.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemBackend;
+
+impl DpiBackend for SystemBackend {
+    fn is_process_dpi_aware(&self) -> Option<bool> {
+        win::is_process_dpi_aware()
+    }
+
+    fn set_process_dpi_aware(&self) -> Option<bool> {
+        win::set_process_dpi_aware()
+    }
+
+    fn get_process_dpi_awareness(&self) -> io::Result<Option<WinDpiAwareness>> {
+        win::get_process_dpi_awareness()
+    }
+
+    fn set_process_dpi_awareness(&self, awareness: WinDpiAwareness) -> io::Result<bool> {
+        win::set_process_dpi_awareness(awareness)
+    }
+
+    fn set_process_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> io::Result<bool> {
+        win::set_process_dpi_awareness_context(context)
+    }
+
+    fn get_thread_dpi_awareness_context(&self) -> Option<DPI_AWARENESS_CONTEXT> {
+        win::get_thread_dpi_awareness_context()
+    }
+
+    fn set_thread_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<DPI_AWARENESS_CONTEXT> {
+        win::set_thread_dpi_awareness_context(context)
+    }
+
+    fn get_awareness_from_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<DPI_AWARENESS> {
+        win::get_awareness_from_dpi_awareness_context(context)
+    }
+
+    fn are_dpi_awareness_contexts_equal(&self, a: DPI_AWARENESS_CONTEXT, b: DPI_AWARENESS_CONTEXT) -> Option<BOOL> {
+        win::are_dpi_awareness_contexts_equal(a, b)
+    }
+
+    fn get_thread_dpi_hosting_behavior(&self) -> Option<DPI_HOSTING_BEHAVIOR> {
+        win::get_thread_dpi_hosting_behavior()
+    }
+
+    fn set_thread_dpi_hosting_behavior(&self, behavior: DPI_HOSTING_BEHAVIOR) -> Option<DPI_HOSTING_BEHAVIOR> {
+        win::set_thread_dpi_hosting_behavior(behavior)
+    }
+
+    fn get_window_dpi_awareness_context(&self, h_wnd: HWND) -> Option<DPI_AWARENESS_CONTEXT> {
+        win::get_window_dpi_awareness_context(h_wnd)
+    }
+
+    fn get_window_dpi_hosting_behavior(&self, h_wnd: HWND) -> Option<DPI_HOSTING_BEHAVIOR> {
+        win::get_window_dpi_hosting_behavior(h_wnd)
+    }
+
+    fn get_dpi_from_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<UINT> {
+        win::get_dpi_from_dpi_awareness_context(context)
+    }
+
+    fn is_valid_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<bool> {
+        win::is_valid_dpi_awareness_context(context)
+    }
+
+    fn get_dpi_for_system(&self) -> Option<UINT> {
+        win::get_dpi_for_system()
+    }
+
+    fn get_dpi_for_window(&self, h_wnd: HWND) -> Option<UINT> {
+        win::get_dpi_for_window(h_wnd)
+    }
+
+    fn get_dpi_for_monitor(&self, h_monitor: HMONITOR, dpi_type: MONITOR_DPI_TYPE) -> io::Result<Option<(UINT, UINT)>> {
+        win::get_dpi_for_monitor(h_monitor, dpi_type)
+    }
+
+    fn get_system_dpi_for_process(&self, h_process: HANDLE) -> Option<UINT> {
+        win::get_system_dpi_for_process(h_process)
+    }
+
+    fn adjust_window_rect_ex_for_dpi(&self, rect: &mut RECT, style: DWORD, menu: bool, ex_style: DWORD, dpi: UINT) -> Option<bool> {
+        win::adjust_window_rect_ex_for_dpi(rect, style, menu, ex_style, dpi)
+    }
+
+    fn get_system_metrics_for_dpi(&self, index: c_int, dpi: UINT) -> Option<c_int> {
+        win::get_system_metrics_for_dpi(index, dpi)
+    }
+
+    fn get_icon_title_log_font_for_dpi(&self, dpi: UINT) -> Option<LOGFONTW> {
+        win::get_icon_title_log_font_for_dpi(dpi)
+    }
+
+    fn enable_non_client_dpi_scaling(&self, h_wnd: HWND) -> Option<bool> {
+        win::enable_non_client_dpi_scaling(h_wnd)
+    }
+
+    fn logical_to_physical_point_for_per_monitor_dpi(&self, h_wnd: HWND, point: &mut POINT) -> Option<bool> {
+        win::logical_to_physical_point_for_per_monitor_dpi(h_wnd, point)
+    }
+}
+
+thread_local! {
+    static BACKEND: RefCell<Option<Rc<dyn DpiBackend>>> = RefCell::new(None);
+}
+
+// Puts the previously installed backend back, even when the closure
+// passed to `with_backend` unwinds.
+struct Restore(Option<Rc<dyn DpiBackend>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        BACKEND.with(|b| *b.borrow_mut() = previous);
+    }
+}
+
+/// Run `f` with `backend` installed as the DPI backend for the current thread.
+/// Calls may be nested; the outer backend is reinstated when `f` returns or panics.
+pub fn with_backend<B, F, R>(backend: B, f: F) -> R
+    where B: DpiBackend + 'static, F: FnOnce() -> R
+{
+    let installed: Rc<dyn DpiBackend> = Rc::new(backend);
+    let previous = BACKEND.with(|b| b.replace(Some(installed)));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Call `f` with the backend active on the current thread.
+pub(crate) fn current<F, R>(f: F) -> R where F: FnOnce(&dyn DpiBackend) -> R {
+    let installed = BACKEND.with(|b| b.borrow().clone());
+    match installed {
+        Some(backend) => f(&*backend),
+        None => f(&SystemBackend),
+    }
+}
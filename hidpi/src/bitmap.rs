@@ -0,0 +1,632 @@
+//! Decoding device independent bitmaps and resampling them to a DPI.
+//!
+//! Windows stretches a bitmap drawn at one DPI to another with a plain
+//! `StretchBlt`, which looks blocky going up and drops detail going down.
+//! `Bitmap` decodes the DIB of an `RT_BITMAP` resource or a `.bmp` file
+//! itself, so it can be resampled with a proper filter and handed back to
+//! GDI as a DIB section at the exact size.
+//!
+//! Decoding covers 1, 4, 8, 16, 24 and 32 bits per pixel, `BI_RLE4`,
+//! `BI_RLE8` and `BI_BITFIELDS`, with core, info and V4/V5 headers, both
+//! bottom-up and top-down.
+
+use std::{
+    error::Error,
+    f32::consts::PI,
+    fmt,
+};
+
+use crate::units::Dpi;
+
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+const BI_BITFIELDS: u32 = 3;
+
+// no more pixels than a 16384 x 16384 image. The data has to be long enough
+// for them as well before they're allocated, so a corrupt header can't ask
+// for gigabytes
+const MAX_PIXELS: u64 = 1 << 28;
+
+// the most pixels one RLE code pair can set
+const RLE_RUN: u64 = 255;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DibError {
+    /// The data ends before the header, palette or pixels do, or is too short
+    /// for RLE data to cover the bitmap.
+    Truncated,
+    /// A header size, bit count, compression or combination of them this
+    /// decoder doesn't know.
+    Unsupported(String),
+    /// A zero or negative width, a zero height, or a size over the limit.
+    Size(i32, i32),
+    /// A `.bmp` file that doesn't start with `BM`.
+    NotBmp,
+}
+
+impl fmt::Display for DibError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DibError::Truncated => write!(f, "bitmap data ends early"),
+            DibError::Unsupported(what) => write!(f, "unsupported bitmap: {}", what),
+            DibError::Size(width, height) => write!(f, "bad bitmap size {}x{}", width, height),
+            DibError::NotBmp => write!(f, "not a .bmp file"),
+        }
+    }
+}
+
+impl Error for DibError {}
+
+/// How `Bitmap::resize` computes each pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// The source pixel the destination pixel's centre falls in. Keeps hard
+    /// edges, for pixel art and integer scales.
+    Nearest,
+    /// A tent over the neighbouring pixels, widened when scaling down so every
+    /// source pixel counts.
+    Bilinear,
+    /// A windowed sinc over 3 pixels either side. Sharpest, at the cost of
+    /// slight ringing along hard edges.
+    Lanczos3,
+}
+
+impl Filter {
+    // the distance from the centre at which the kernel drops to 0
+    fn support(self) -> f32 {
+        match self {
+            Filter::Nearest => 0.5,
+            Filter::Bilinear => 1.0,
+            Filter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn kernel(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            Filter::Nearest => if x < 0.5 { 1.0 } else { 0.0 },
+            Filter::Bilinear => (1.0 - x).max(0.0),
+            Filter::Lanczos3 => if x < 3.0 { sinc(x) * sinc(x / 3.0) } else { 0.0 },
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        let x = x * PI;
+        x.sin() / x
+    }
+}
+
+/// A decoded image, rows top-down, pixels as `[blue, green, red, alpha]` like
+/// a 32 bits per pixel DIB. Alpha is straight, not premultiplied; formats
+/// without alpha are opaque, and pixels an RLE bitmap skips are transparent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Bitmap {
+    /// A transparent bitmap.
+    pub fn new(width: u32, height: u32) -> Bitmap {
+        Bitmap { width, height, pixels: vec![[0; 4]; width as usize * height as usize] }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Decode a packed DIB: a bitmap header, the colour masks and palette, then
+    /// the pixels, as an `RT_BITMAP` resource or `CF_DIB` clipboard data has.
+    pub fn from_dib(data: &[u8]) -> Result<Bitmap, DibError> {
+        decode(data, None)
+    }
+
+    /// Decode a `.bmp` file, a `BITMAPFILEHEADER` followed by a DIB whose
+    /// pixels start where the file header says.
+    pub fn from_bmp(data: &[u8]) -> Result<Bitmap, DibError> {
+        if !data.starts_with(b"BM") {
+            return Err(DibError::NotBmp);
+        }
+        let bits_offset = read_u32(data, 10)? as usize;
+        let dib = data.get(14..).ok_or(DibError::Truncated)?;
+        decode(dib, Some(bits_offset.checked_sub(14).ok_or(DibError::Truncated)?))
+    }
+
+    /// Resample to `width` by `height` pixels. Filters work on premultiplied
+    /// alpha, so transparent pixels don't bleed their colour into the edges.
+    pub fn resize(&self, width: u32, height: u32, filter: Filter) -> Bitmap {
+        if width == self.width && height == self.height {
+            return self.clone();
+        }
+        if width == 0 || height == 0 || self.width == 0 || self.height == 0 {
+            return Bitmap::new(width, height);
+        }
+
+        let premultiplied: Vec<[f32; 4]> = self.pixels.iter()
+            .map(|&[b, g, r, a]| {
+                let alpha = a as f32 / 255.0;
+                [b as f32 * alpha, g as f32 * alpha, r as f32 * alpha, a as f32]
+            })
+            .collect();
+
+        // horizontally into a width x self.height buffer, then vertically
+        let columns = weights(self.width, width, filter);
+        let mut wide = vec![[0.0; 4]; width as usize * self.height as usize];
+        for y in 0..self.height as usize {
+            let row = &premultiplied[y * self.width as usize..][..self.width as usize];
+            for (x, (first, taps)) in columns.iter().enumerate() {
+                wide[y * width as usize + x] = convolve(taps.iter().enumerate().map(|(i, &w)| (row[first + i], w)));
+            }
+        }
+
+        let rows = weights(self.height, height, filter);
+        let mut resized = Bitmap::new(width, height);
+        for (y, (first, taps)) in rows.iter().enumerate() {
+            for x in 0..width as usize {
+                let sum = convolve(taps.iter().enumerate().map(|(i, &w)| (wide[(first + i) * width as usize + x], w)));
+                resized.pixels[y * width as usize + x] = unpremultiply(sum);
+            }
+        }
+        resized
+    }
+
+    /// Resample a bitmap drawn for `from` to the size it should have at `to`.
+    pub fn scale(&self, from: Dpi, to: Dpi, filter: Filter) -> Bitmap {
+        self.resize(scale_length(self.width, from, to), scale_length(self.height, from, to), filter)
+    }
+}
+
+// At least 1, and at most u32::MAX rather than wrapping
+fn scale_length(length: u32, from: Dpi, to: Dpi) -> u32 {
+    let scaled = (length as u64 * to.0 as u64 + from.0 as u64 / 2) / from.0.max(1) as u64;
+    scaled.clamp(1, u32::MAX as u64) as u32
+}
+
+// For each destination pixel, the first source pixel it reads and the
+// weights of it and the ones after, summing to 1.
+fn weights(source: u32, destination: u32, filter: Filter) -> Vec<(usize, Vec<f32>)> {
+    let ratio = source as f32 / destination as f32;
+    // widen the kernel when scaling down, so it covers every source pixel
+    let stretch = ratio.max(1.0);
+    let support = filter.support() * stretch;
+    (0..destination)
+        .map(|x| {
+            let centre = (x as f32 + 0.5) * ratio;
+            if filter == Filter::Nearest {
+                return ((centre as usize).min(source as usize - 1), vec![1.0]);
+            }
+            let first = (centre - support).floor().max(0.0) as usize;
+            let last = ((centre + support).ceil() as usize).min(source as usize);
+            let mut taps: Vec<f32> = (first..last)
+                .map(|i| filter.kernel((i as f32 + 0.5 - centre) / stretch))
+                .collect();
+            let total: f32 = taps.iter().sum();
+            if total != 0.0 {
+                taps.iter_mut().for_each(|w| *w /= total);
+            }
+            (first, taps)
+        })
+        .collect()
+}
+
+fn convolve<I>(taps: I) -> [f32; 4] where I: Iterator<Item = ([f32; 4], f32)> {
+    taps.fold([0.0; 4], |mut sum, (pixel, weight)| {
+        for (s, p) in sum.iter_mut().zip(pixel.iter()) {
+            *s += p * weight;
+        }
+        sum
+    })
+}
+
+fn unpremultiply([b, g, r, a]: [f32; 4]) -> [u8; 4] {
+    let alpha = a.round().clamp(0.0, 255.0);
+    if alpha == 0.0 {
+        return [0; 4];
+    }
+    let channel = |c: f32| (c * 255.0 / alpha).round().clamp(0.0, 255.0) as u8;
+    [channel(b), channel(g), channel(r), alpha as u8]
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, DibError> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or(DibError::Truncated)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, DibError> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(DibError::Truncated)
+}
+
+// One channel of a BI_BITFIELDS pixel, scaled to 8 bits.
+#[derive(Clone, Copy)]
+struct Mask {
+    mask: u32,
+    shift: u32,
+    max: u32,
+}
+
+impl Mask {
+    fn new(mask: u32) -> Mask {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        Mask { mask, shift, max: mask >> shift }
+    }
+
+    fn get(self, pixel: u32) -> u8 {
+        if self.max == 0 {
+            return 0;
+        }
+        let value = (pixel & self.mask) >> self.shift;
+        ((value as u64 * 255 + self.max as u64 / 2) / self.max as u64) as u8
+    }
+}
+
+// `bits_offset`, from the start of `data`, for a file header that says where
+// the pixels are; else they follow the masks and palette
+fn decode(data: &[u8], bits_offset: Option<usize>) -> Result<Bitmap, DibError> {
+    let header_size = read_u32(data, 0)? as usize;
+    let (width, height, bit_count, compression, colors_used, palette_entry_size) = match header_size {
+        // BITMAPCOREHEADER
+        12 => (read_u16(data, 4)? as i32, read_u16(data, 6)? as i32, read_u16(data, 10)?, BI_RGB, 0, 3),
+        // BITMAPINFOHEADER, the undocumented V2 and V3 headers, BITMAPV4HEADER, BITMAPV5HEADER
+        40 | 52 | 56 | 108 | 124 => (
+            read_u32(data, 4)? as i32,
+            read_u32(data, 8)? as i32,
+            read_u16(data, 14)?,
+            read_u32(data, 16)?,
+            read_u32(data, 32)?,
+            4,
+        ),
+        size => return Err(DibError::Unsupported(format!("{} byte header", size))),
+    };
+
+    let top_down = height < 0;
+    let (width_px, height_px) = (width as i64, (height as i64).abs());
+    if width_px <= 0 || height_px == 0 || (width_px * height_px) as u64 > MAX_PIXELS {
+        return Err(DibError::Size(width, height));
+    }
+    let (width_px, height_px) = (width_px as u32, height_px as u32);
+
+    match (compression, bit_count) {
+        (BI_RGB, 1) | (BI_RGB, 4) | (BI_RGB, 8) | (BI_RGB, 16) | (BI_RGB, 24) | (BI_RGB, 32) => {},
+        (BI_RLE8, 8) | (BI_RLE4, 4) | (BI_BITFIELDS, 16) | (BI_BITFIELDS, 32) => {},
+        _ => return Err(DibError::Unsupported(format!("{} bits per pixel with compression {}", bit_count, compression))),
+    }
+    if top_down && (compression == BI_RLE8 || compression == BI_RLE4) {
+        return Err(DibError::Unsupported("a top-down RLE bitmap".to_owned()));
+    }
+
+    // the masks follow a BITMAPINFOHEADER, and are part of the larger headers
+    let mut offset = header_size;
+    let masks = if compression == BI_BITFIELDS {
+        let masks_at = if header_size == 40 { offset } else { 40 };
+        let alpha = if header_size >= 56 { read_u32(data, 52)? } else { 0 };
+        if header_size == 40 {
+            offset += 12;
+        }
+        [read_u32(data, masks_at)?, read_u32(data, masks_at + 4)?, read_u32(data, masks_at + 8)?, alpha]
+    } else if bit_count == 16 {
+        [0x7c00, 0x03e0, 0x001f, 0]
+    } else {
+        // the fourth byte of a 32 bits per pixel BI_RGB pixel is unused
+        [0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0]
+    };
+
+    let palette_size = if colors_used != 0 {
+        colors_used as usize
+    } else if bit_count <= 8 {
+        1 << bit_count
+    } else {
+        0
+    };
+    let palette = data.get(offset..offset + palette_size * palette_entry_size)
+        .ok_or(DibError::Truncated)?
+        .chunks(palette_entry_size)
+        .map(|entry| [entry[0], entry[1], entry[2], 255])
+        .collect::<Vec<_>>();
+    offset += palette_size * palette_entry_size;
+
+    let bits = data.get(bits_offset.unwrap_or(offset)..).ok_or(DibError::Truncated)?;
+    let color = |index: usize| palette.get(index).copied().unwrap_or([0, 0, 0, 255]);
+
+    if compression == BI_RLE8 || compression == BI_RLE4 {
+        // data that ends early ends the bitmap, but data too short to set
+        // every pixel in runs of the longest length isn't an RLE bitmap this size
+        if width_px as u64 * height_px as u64 > bits.len() as u64 / 2 * RLE_RUN {
+            return Err(DibError::Truncated);
+        }
+        let mut bitmap = Bitmap::new(width_px, height_px);
+        decode_rle(bits, compression == BI_RLE4, &mut bitmap, color);
+        return Ok(bitmap);
+    }
+
+    let stride = (width_px as u64 * bit_count as u64 + 31) / 32 * 4;
+    if (bits.len() as u64) < stride * height_px as u64 {
+        return Err(DibError::Truncated);
+    }
+    let stride = stride as usize;
+    let mut bitmap = Bitmap::new(width_px, height_px);
+    let [red, green, blue, alpha] = masks.map(Mask::new);
+    for (row_index, row) in bits.chunks(stride).take(height_px as usize).enumerate() {
+        let y = if top_down { row_index } else { height_px as usize - 1 - row_index };
+        let pixels = &mut bitmap.pixels[y * width_px as usize..][..width_px as usize];
+        for (x, pixel) in pixels.iter_mut().enumerate() {
+            *pixel = match bit_count {
+                1 | 4 | 8 => {
+                    let bit = x * bit_count as usize;
+                    let byte = row[bit / 8];
+                    let index = (byte >> (8 - bit_count as usize - bit % 8)) & ((1u16 << bit_count) - 1) as u8;
+                    color(index as usize)
+                },
+                24 => [row[x * 3], row[x * 3 + 1], row[x * 3 + 2], 255],
+                _ => {
+                    let value = if bit_count == 16 {
+                        u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32
+                    } else {
+                        u32::from_le_bytes([row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3]])
+                    };
+                    let a = if alpha.mask == 0 { 255 } else { alpha.get(value) };
+                    [blue.get(value), green.get(value), red.get(value), a]
+                },
+            };
+        }
+    }
+    Ok(bitmap)
+}
+
+// RLE bitmaps are bottom-up. Runs and deltas that go past the edge are
+// clipped, and data that ends without an end of bitmap marker ends it.
+fn decode_rle<F>(bits: &[u8], four_bit: bool, bitmap: &mut Bitmap, color: F) where F: Fn(usize) -> [u8; 4] {
+    let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+    let (mut x, mut y) = (0usize, 0usize);
+    let mut set = |x: usize, y: usize, index: u8| {
+        if x < width && y < height {
+            bitmap.pixels[(height - 1 - y) * width + x] = color(index as usize);
+        }
+    };
+    // the index of the nth pixel of a run, two to a byte for RLE4
+    let index = |byte: u8, n: usize| if !four_bit { byte } else if n % 2 == 0 { byte >> 4 } else { byte & 0xf };
+
+    let mut pos = 0;
+    while pos + 1 < bits.len() {
+        let (first, second) = (bits[pos], bits[pos + 1]);
+        pos += 2;
+        match (first, second) {
+            (0, 0) => {
+                x = 0;
+                y += 1;
+            },
+            (0, 1) => break,
+            (0, 2) => {
+                match bits.get(pos..pos + 2) {
+                    Some(delta) => {
+                        x += delta[0] as usize;
+                        y += delta[1] as usize;
+                    },
+                    None => break,
+                }
+                pos += 2;
+            },
+            (0, count) => {
+                // absolute mode, padded to a 16 bit boundary
+                let count = count as usize;
+                let len = if four_bit { (count + 1) / 2 } else { count };
+                let run = match bits.get(pos..pos + len) {
+                    Some(run) => run,
+                    None => break,
+                };
+                for n in 0..count {
+                    let byte = if four_bit { run[n / 2] } else { run[n] };
+                    set(x, y, index(byte, n));
+                    x += 1;
+                }
+                pos += len + len % 2;
+            },
+            (count, value) => {
+                for n in 0..count as usize {
+                    set(x, y, index(value, n));
+                    x += 1;
+                }
+            },
+        }
+        if y >= height {
+            break;
+        }
+    }
+}
+
+#[cfg(windows)]
+mod win {
+    use std::{mem, ptr, slice};
+
+    use winapi::{
+        shared::windef::HBITMAP,
+        um::wingdi::{self, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS},
+    };
+
+    use super::Bitmap;
+
+    impl Bitmap {
+        /// A top-down 32 bits per pixel DIB section with the pixels,
+        /// premultiplied as `AlphaBlend` expects. Opaque bitmaps draw the same
+        /// with `BitBlt`. Returns null if GDI can't allocate it.
+        pub fn to_dib_section(&self) -> HBITMAP {
+            let info = BITMAPINFO {
+                bmiHeader: BITMAPINFOHEADER {
+                    biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
+                    biWidth: self.width as i32,
+                    // negative for top-down
+                    biHeight: -(self.height as i32),
+                    biPlanes: 1,
+                    biBitCount: 32,
+                    biCompression: BI_RGB,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut bits = ptr::null_mut();
+            let h_bmp = unsafe { wingdi::CreateDIBSection(ptr::null_mut(), &info, DIB_RGB_COLORS, &mut bits, ptr::null_mut(), 0) };
+            if h_bmp.is_null() || bits.is_null() {
+                return ptr::null_mut();
+            }
+            let bits = unsafe { slice::from_raw_parts_mut(bits as *mut [u8; 4], self.pixels.len()) };
+            for (dst, &[b, g, r, a]) in bits.iter_mut().zip(self.pixels.iter()) {
+                let premultiply = |c: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+                *dst = [premultiply(b), premultiply(g), premultiply(r), a];
+            }
+            h_bmp
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    // The .bmp files and the pixels they hold, as PAM images, were written by
+    // a separate model of the formats and filters, in double precision
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/bitmaps").join(name);
+        fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+    }
+
+    // A PAM image with the RGB_ALPHA tuple type, as a `Bitmap`
+    fn golden(name: &str) -> Bitmap {
+        let data = fixture(name);
+        let end = data.windows(7).position(|w| w == b"ENDHDR\n").expect("PAM header") + 7;
+        let header = std::str::from_utf8(&data[..end]).unwrap();
+        let field = |key: &str| header.lines()
+            .find_map(|line| line.strip_prefix(key))
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .unwrap_or_else(|| panic!("{} has no {}", name, key));
+        assert!(header.contains("TUPLTYPE RGB_ALPHA"), "{}", name);
+        let (width, height) = (field("WIDTH"), field("HEIGHT"));
+        let pixels: Vec<[u8; 4]> = data[end..].chunks(4).map(|p| [p[2], p[1], p[0], p[3]]).collect();
+        assert_eq!(pixels.len(), (width * height) as usize, "{}", name);
+        Bitmap { width, height, pixels }
+    }
+
+    // Off by at most `tolerance` in any channel
+    fn assert_close(actual: &Bitmap, expected: &Bitmap, tolerance: u8, what: &str) {
+        assert_eq!((actual.width, actual.height), (expected.width, expected.height), "{}", what);
+        for y in 0..expected.height {
+            for x in 0..expected.width {
+                let (a, e) = (actual.pixel(x, y), expected.pixel(x, y));
+                let close = a.iter().zip(e.iter()).all(|(&a, &e)| (a as i16 - e as i16).abs() <= tolerance as i16);
+                assert!(close, "{}: pixel ({}, {}) is {:?} instead of {:?}", what, x, y, a, e);
+            }
+        }
+    }
+
+    #[test]
+    fn every_format_decodes_to_its_golden_image() {
+        for name in &[
+            "1bpp", "4bpp", "4bpp-core", "8bpp", "16bpp", "16bpp-565", "24bpp", "24bpp-top-down",
+            "32bpp", "32bpp-v5-alpha", "rle8", "rle4",
+        ] {
+            let file = fixture(&format!("{}.bmp", name));
+            let expected = golden(&format!("{}.pam", name));
+            assert_close(&Bitmap::from_bmp(&file).unwrap(), &expected, 0, name);
+            // the same DIB as a resource, with the pixels after the palette
+            assert_close(&Bitmap::from_dib(&file[14..]).unwrap(), &expected, 0, name);
+        }
+    }
+
+    #[test]
+    fn filters_resample_to_their_golden_images() {
+        let source = golden("32bpp-v5-alpha.pam");
+        for &(filter, name) in &[(Filter::Nearest, "nearest"), (Filter::Bilinear, "bilinear"), (Filter::Lanczos3, "lanczos3")] {
+            // single precision rounds a little differently
+            assert_close(&source.resize(11, 8, filter), &golden(&format!("{}-up.pam", name)), 1, name);
+            assert_close(&source.resize(3, 2, filter), &golden(&format!("{}-down.pam", name)), 1, name);
+        }
+    }
+
+    #[test]
+    fn scale_resizes_by_the_dpi_ratio() {
+        let source = golden("24bpp.pam");
+        assert_eq!(source.scale(Dpi(96), Dpi(144), Filter::Bilinear), source.resize(11, 8, Filter::Bilinear));
+        assert_eq!(source.scale(Dpi(96), Dpi(96), Filter::Lanczos3), source);
+        let tiny = source.scale(Dpi(192), Dpi(1), Filter::Nearest);
+        assert_eq!((tiny.width, tiny.height), (1, 1));
+        // a DPI of 0 is taken as 1 rather than dividing by it
+        assert_eq!(source.scale(Dpi(0), Dpi(1), Filter::Nearest).width, 7);
+    }
+
+    #[test]
+    fn scaled_lengths_saturate_instead_of_wrapping() {
+        assert_eq!(scale_length(7, Dpi(96), Dpi(144)), 11);
+        assert_eq!(scale_length(1, Dpi(1000), Dpi(1)), 1);
+        assert_eq!(scale_length(u32::MAX, Dpi(96), Dpi(192)), u32::MAX);
+        assert_eq!(scale_length(1 << 20, Dpi(0), Dpi(1 << 16)), u32::MAX);
+    }
+
+    #[test]
+    fn transparent_pixels_do_not_bleed() {
+        let mut source = Bitmap::new(2, 1);
+        source.pixels = vec![[0, 0, 255, 255], [255, 0, 0, 0]];
+        let resized = source.resize(4, 1, Filter::Bilinear);
+        // the colour stays red as it fades out, give or take the rounding of alpha
+        for pixel in &resized.pixels {
+            assert!(pixel[3] == 0 || (pixel[..2] == [0, 0] && pixel[2] >= 250), "{:?}", resized.pixels);
+        }
+    }
+
+    #[test]
+    fn malformed_bitmaps_are_errors() {
+        let file = fixture("8bpp.bmp");
+        assert_eq!(Bitmap::from_bmp(b"PNG"), Err(DibError::NotBmp));
+        for len in &[2, 13, 20, 53, 100, file.len() - 1] {
+            assert_eq!(Bitmap::from_bmp(&file[..*len]), Err(DibError::Truncated), "{} bytes", len);
+        }
+
+        let with = |offset: usize, bytes: &[u8]| {
+            let mut data = file.clone();
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            Bitmap::from_bmp(&data)
+        };
+        assert_eq!(with(14, &64u32.to_le_bytes()), Err(DibError::Unsupported("64 byte header".to_owned())));
+        assert_eq!(with(18, &0u32.to_le_bytes()), Err(DibError::Size(0, 5)));
+        assert_eq!(with(18, &(-7i32).to_le_bytes()), Err(DibError::Size(-7, 5)));
+        assert_eq!(with(18, &0x0400_0000u32.to_le_bytes()), Err(DibError::Size(0x0400_0000, 5)));
+        assert_eq!(with(28, &2u16.to_le_bytes()), Err(DibError::Unsupported("2 bits per pixel with compression 0".to_owned())));
+        assert_eq!(with(30, &2u32.to_le_bytes()), Err(DibError::Unsupported("8 bits per pixel with compression 2".to_owned())));
+
+        // a 16384 x 16384 header over a few bytes of pixels, caught before
+        // a gigabyte is allocated for it
+        for name in &["8bpp.bmp", "rle8.bmp", "rle4.bmp"] {
+            let mut huge = fixture(name);
+            huge[18..26].copy_from_slice(&[0, 0x40, 0, 0, 0, 0x40, 0, 0]);
+            assert_eq!(Bitmap::from_bmp(&huge), Err(DibError::Truncated), "{}", name);
+        }
+
+        let mut top_down_rle = fixture("rle8.bmp");
+        top_down_rle[22..26].copy_from_slice(&(-5i32).to_le_bytes());
+        assert_eq!(Bitmap::from_bmp(&top_down_rle), Err(DibError::Unsupported("a top-down RLE bitmap".to_owned())));
+    }
+
+    #[test]
+    fn rle_data_that_ends_early_ends_the_bitmap() {
+        let file = fixture("rle8.bmp");
+        let expected = golden("rle8.pam");
+        // cut after row 0 and the end of line
+        let offset = u32::from_le_bytes([file[10], file[11], file[12], file[13]]) as usize;
+        let cut = Bitmap::from_bmp(&file[..offset + 4]).unwrap();
+        for y in 0..5 {
+            for x in 0..7 {
+                let pixel = if y == 4 { expected.pixel(x, y) } else { [0; 4] };
+                assert_eq!(cut.pixel(x, y), pixel, "({}, {})", x, y);
+            }
+        }
+    }
+}
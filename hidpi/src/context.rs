@@ -0,0 +1,273 @@
+//! The predefined DPI awareness contexts and hosting behaviors, by name, and
+//! their conversions to and from the raw Win32 values.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::DpiAwareness;
+
+/// The predefined `DPI_AWARENESS_CONTEXT` values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DpiAwarenessContext {
+    Unaware,
+    /// Unaware, but with GDI content rendered at the monitor DPI (Windows 10 1809).
+    UnawareGdiScaled,
+    System,
+    /// Windows 8.1 era per-monitor awareness.
+    PerMonitor,
+    /// Per-monitor awareness with non-client and dialog scaling (Windows 10 1703).
+    PerMonitorV2,
+}
+
+impl DpiAwarenessContext {
+    pub const ALL: [DpiAwarenessContext; 5] = [
+        DpiAwarenessContext::Unaware,
+        DpiAwarenessContext::UnawareGdiScaled,
+        DpiAwarenessContext::System,
+        DpiAwarenessContext::PerMonitor,
+        DpiAwarenessContext::PerMonitorV2,
+    ];
+
+    pub fn awareness(self) -> DpiAwareness {
+        match self {
+            DpiAwarenessContext::Unaware | DpiAwarenessContext::UnawareGdiScaled => DpiAwareness::Unaware,
+            DpiAwarenessContext::System => DpiAwareness::System,
+            DpiAwarenessContext::PerMonitor | DpiAwarenessContext::PerMonitorV2 => DpiAwareness::PerMonitor,
+        }
+    }
+
+    /// The name of the matching `DPI_AWARENESS_CONTEXT_*` constant.
+    pub fn name(self) -> &'static str {
+        match self {
+            DpiAwarenessContext::Unaware => "DPI_AWARENESS_CONTEXT_UNAWARE",
+            DpiAwarenessContext::UnawareGdiScaled => "DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED",
+            DpiAwarenessContext::System => "DPI_AWARENESS_CONTEXT_SYSTEM_AWARE",
+            DpiAwarenessContext::PerMonitor => "DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE",
+            DpiAwarenessContext::PerMonitorV2 => "DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2",
+        }
+    }
+
+    fn short_name(self) -> &'static str {
+        match self {
+            DpiAwarenessContext::Unaware => "unaware",
+            DpiAwarenessContext::UnawareGdiScaled => "unaware-gdi-scaled",
+            DpiAwarenessContext::System => "system",
+            DpiAwarenessContext::PerMonitor => "per-monitor",
+            DpiAwarenessContext::PerMonitorV2 => "per-monitor-v2",
+        }
+    }
+}
+
+impl fmt::Display for DpiAwarenessContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDpiAwarenessContextError(String);
+
+impl fmt::Display for ParseDpiAwarenessContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown dpi awareness context {:?}", self.0)
+    }
+}
+
+impl Error for ParseDpiAwarenessContextError {}
+
+impl FromStr for DpiAwarenessContext {
+    type Err = ParseDpiAwarenessContextError;
+
+    /// Accepts either the constant name (`DPI_AWARENESS_CONTEXT_SYSTEM_AWARE`)
+    /// or the short form used in configuration files (`system`, `per-monitor-v2`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.iter()
+            .find(|known| known.name() == s || known.short_name().eq_ignore_ascii_case(s))
+            .cloned()
+            .ok_or_else(|| ParseDpiAwarenessContextError(s.to_owned()))
+    }
+}
+
+/// The `DPI_HOSTING_BEHAVIOR` values a thread can be switched to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DpiHostingBehavior {
+    /// Child windows must share the DPI awareness of their parent.
+    Default,
+    /// Child windows may have a different DPI awareness to their parent (Windows 10 1803).
+    Mixed,
+}
+
+mod raw {
+    use crate::sys::{
+        FALSE,
+        DPI_AWARENESS_CONTEXT,
+        DPI_AWARENESS_CONTEXT_UNAWARE,
+        DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED,
+        DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
+        DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE,
+        DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        DPI_AWARENESS_UNAWARE,
+        DPI_AWARENESS_SYSTEM_AWARE,
+        DPI_AWARENESS_PER_MONITOR_AWARE,
+        DPI_HOSTING_BEHAVIOR,
+        DPI_HOSTING_BEHAVIOR_DEFAULT,
+        DPI_HOSTING_BEHAVIOR_MIXED,
+    };
+
+    use super::{DpiAwarenessContext, DpiHostingBehavior};
+    use crate::backend;
+
+    impl DpiAwarenessContext {
+        pub fn to_raw(self) -> DPI_AWARENESS_CONTEXT {
+            match self {
+                DpiAwarenessContext::Unaware => DPI_AWARENESS_CONTEXT_UNAWARE,
+                DpiAwarenessContext::UnawareGdiScaled => DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED,
+                DpiAwarenessContext::System => DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
+                DpiAwarenessContext::PerMonitor => DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE,
+                DpiAwarenessContext::PerMonitorV2 => DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+            }
+        }
+
+        /// Identify a raw context.
+        ///
+        /// Contexts handed out by Windows (from `GetThreadDpiAwarenessContext` for
+        /// example) are not the predefined pseudo-handles, so after checking for
+        /// those this asks `AreDpiAwarenessContextsEqual`, and finally falls back to
+        /// the context's `DPI_AWARENESS`. Returns `None` for an invalid context.
+        pub fn from_raw(context: DPI_AWARENESS_CONTEXT) -> Option<DpiAwarenessContext> {
+            if let Some(known) = Self::ALL.iter().find(|known| known.to_raw() == context) {
+                return Some(*known);
+            }
+
+            backend::current(|b| {
+                let equal = Self::ALL.iter().find(|known| {
+                    b.are_dpi_awareness_contexts_equal(context, known.to_raw())
+                        .is_some_and(|equal| equal != FALSE)
+                });
+                if let Some(known) = equal {
+                    return Some(*known);
+                }
+
+                match b.get_awareness_from_dpi_awareness_context(context)? {
+                    DPI_AWARENESS_UNAWARE => Some(DpiAwarenessContext::Unaware),
+                    DPI_AWARENESS_SYSTEM_AWARE => Some(DpiAwarenessContext::System),
+                    DPI_AWARENESS_PER_MONITOR_AWARE => Some(DpiAwarenessContext::PerMonitor),
+                    _ => None,
+                }
+            })
+        }
+    }
+
+    impl From<DpiAwarenessContext> for DPI_AWARENESS_CONTEXT {
+        fn from(context: DpiAwarenessContext) -> DPI_AWARENESS_CONTEXT {
+            context.to_raw()
+        }
+    }
+
+    impl DpiHostingBehavior {
+        pub fn to_raw(self) -> DPI_HOSTING_BEHAVIOR {
+            match self {
+                DpiHostingBehavior::Default => DPI_HOSTING_BEHAVIOR_DEFAULT,
+                DpiHostingBehavior::Mixed => DPI_HOSTING_BEHAVIOR_MIXED,
+            }
+        }
+
+        pub fn from_raw(behavior: DPI_HOSTING_BEHAVIOR) -> Option<DpiHostingBehavior> {
+            match behavior {
+                DPI_HOSTING_BEHAVIOR_DEFAULT => Some(DpiHostingBehavior::Default),
+                DPI_HOSTING_BEHAVIOR_MIXED => Some(DpiHostingBehavior::Mixed),
+                _ => None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::with_backend;
+    use crate::mock::{MockBackend, Release};
+    use crate::sys::{
+        DPI_AWARENESS_CONTEXT,
+        DPI_AWARENESS_CONTEXT_UNAWARE,
+        DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED,
+        DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
+        DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE,
+        DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        DPI_HOSTING_BEHAVIOR_DEFAULT,
+        DPI_HOSTING_BEHAVIOR_INVALID,
+        DPI_HOSTING_BEHAVIOR_MIXED,
+    };
+
+    const TABLE: &[(DpiAwarenessContext, DPI_AWARENESS_CONTEXT, &str, &str, DpiAwareness)] = &[
+        (DpiAwarenessContext::Unaware, DPI_AWARENESS_CONTEXT_UNAWARE, "DPI_AWARENESS_CONTEXT_UNAWARE", "unaware", DpiAwareness::Unaware),
+        (DpiAwarenessContext::UnawareGdiScaled, DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED, "DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED", "unaware-gdi-scaled", DpiAwareness::Unaware),
+        (DpiAwarenessContext::System, DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, "DPI_AWARENESS_CONTEXT_SYSTEM_AWARE", "system", DpiAwareness::System),
+        (DpiAwarenessContext::PerMonitor, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE, "DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE", "per-monitor", DpiAwareness::PerMonitor),
+        (DpiAwarenessContext::PerMonitorV2, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, "DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2", "per-monitor-v2", DpiAwareness::PerMonitor),
+    ];
+
+    #[test]
+    fn every_context_is_in_the_table() {
+        assert_eq!(TABLE.len(), DpiAwarenessContext::ALL.len());
+        for (context, row) in DpiAwarenessContext::ALL.iter().zip(TABLE) {
+            assert_eq!(*context, row.0);
+        }
+    }
+
+    #[test]
+    fn pseudo_handles_convert_both_ways_without_windows() {
+        with_backend(MockBackend::new(Release::None), || {
+            for &(context, raw, ..) in TABLE {
+                assert_eq!(context.to_raw(), raw);
+                assert_eq!(DPI_AWARENESS_CONTEXT::from(context), raw);
+                assert_eq!(DpiAwarenessContext::from_raw(raw), Some(context));
+            }
+            assert_eq!(DpiAwarenessContext::from_raw(MockBackend::handle(DpiAwarenessContext::System)), None);
+            assert_eq!(DpiAwarenessContext::from_raw(std::ptr::null_mut()), None);
+        });
+    }
+
+    #[test]
+    fn handles_from_windows_are_identified() {
+        with_backend(MockBackend::new(Release::Windows10_1809), || {
+            for &(context, ..) in TABLE {
+                assert_eq!(DpiAwarenessContext::from_raw(MockBackend::handle(context)), Some(context));
+            }
+            assert_eq!(DpiAwarenessContext::from_raw(std::ptr::null_mut()), None);
+        });
+        // before 1703 there is no Per-Monitor V2 to compare with
+        with_backend(MockBackend::new(Release::Windows10_1607), || {
+            assert_eq!(DpiAwarenessContext::from_raw(MockBackend::handle(DpiAwarenessContext::PerMonitorV2)), None);
+            assert_eq!(DpiAwarenessContext::from_raw(MockBackend::handle(DpiAwarenessContext::PerMonitor)), Some(DpiAwarenessContext::PerMonitor));
+        });
+    }
+
+    #[test]
+    fn names_parse_and_display() {
+        for &(context, _, name, short, awareness) in TABLE {
+            assert_eq!(context.name(), name);
+            assert_eq!(context.to_string(), name);
+            assert_eq!(context.awareness(), awareness);
+            assert_eq!(name.parse(), Ok(context));
+            assert_eq!(short.parse(), Ok(context));
+            assert_eq!(short.to_ascii_uppercase().parse(), Ok(context));
+        }
+        // constant names are exact
+        assert!("dpi_awareness_context_unaware".parse::<DpiAwarenessContext>().is_err());
+        assert_eq!(
+            "per-monitor-v3".parse::<DpiAwarenessContext>().unwrap_err().to_string(),
+            "unknown dpi awareness context \"per-monitor-v3\"");
+    }
+
+    #[test]
+    fn hosting_behaviors_convert_both_ways() {
+        for &(behavior, raw) in &[(DpiHostingBehavior::Default, DPI_HOSTING_BEHAVIOR_DEFAULT), (DpiHostingBehavior::Mixed, DPI_HOSTING_BEHAVIOR_MIXED)] {
+            assert_eq!(behavior.to_raw(), raw);
+            assert_eq!(DpiHostingBehavior::from_raw(raw), Some(behavior));
+        }
+        assert_eq!(DpiHostingBehavior::from_raw(DPI_HOSTING_BEHAVIOR_INVALID), None);
+        assert_eq!(DpiHostingBehavior::from_raw(2), None);
+    }
+}
@@ -0,0 +1,536 @@
+//! Dialog templates described in code and serialized to the `DLGTEMPLATEEX`
+//! blob that `CreateDialogIndirectParamW` takes, so a dialog can be changed
+//! without editing a resource script.
+//!
+//! Geometry is in dialog units, as in a `.rc` file, and each control starts
+//! out with the default style of the matching resource statement. A template
+//! serializes to the same bytes `rc` produces for the equivalent `DIALOGEX`.
+//!
+//! ```ignore
+//! let template = DialogTemplate::new(0, 0, 200, 100)
+//!     .style(DS_MODALFRAME | DS_CENTER | WS_POPUP | WS_CAPTION | WS_SYSMENU)
+//!     .caption("Settings")
+//!     .font(DialogFont::new(9, "Segoe UI"))
+//!     .control(DialogControl::def_push_button("OK", IDOK, 143, 79, 50, 14));
+//! let h_dlg = template.create(h_inst, ptr::null_mut(), Some(dialog_proc), 0);
+//! ```
+
+// The styles rc adds on its own; the winapi constants are Windows only
+const WS_POPUP: u32 = 0x8000_0000;
+const WS_CHILD: u32 = 0x4000_0000;
+const WS_VISIBLE: u32 = 0x1000_0000;
+const WS_CAPTION: u32 = 0x00C0_0000;
+const WS_BORDER: u32 = 0x0080_0000;
+const WS_SYSMENU: u32 = 0x0008_0000;
+const WS_GROUP: u32 = 0x0002_0000;
+const WS_TABSTOP: u32 = 0x0001_0000;
+const DS_SETFONT: u32 = 0x0040;
+
+/// A string, or a 16-bit ordinal such as a resource id or a class atom.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TemplateId {
+    Ordinal(u16),
+    Name(String),
+}
+
+impl From<u16> for TemplateId {
+    fn from(ordinal: u16) -> TemplateId {
+        TemplateId::Ordinal(ordinal)
+    }
+}
+
+impl From<&str> for TemplateId {
+    fn from(name: &str) -> TemplateId {
+        TemplateId::Name(name.to_owned())
+    }
+}
+
+impl From<String> for TemplateId {
+    fn from(name: String) -> TemplateId {
+        TemplateId::Name(name)
+    }
+}
+
+/// The window class of a control. The predefined classes are stored as atoms.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ControlClass {
+    Button,
+    Edit,
+    Static,
+    ListBox,
+    ScrollBar,
+    ComboBox,
+    /// A registered class, e.g. `"SysListView32"`.
+    Named(String),
+}
+
+impl ControlClass {
+    fn id(&self) -> TemplateId {
+        match self {
+            ControlClass::Button => TemplateId::Ordinal(0x0080),
+            ControlClass::Edit => TemplateId::Ordinal(0x0081),
+            ControlClass::Static => TemplateId::Ordinal(0x0082),
+            ControlClass::ListBox => TemplateId::Ordinal(0x0083),
+            ControlClass::ScrollBar => TemplateId::Ordinal(0x0084),
+            ControlClass::ComboBox => TemplateId::Ordinal(0x0085),
+            ControlClass::Named(name) => TemplateId::Name(name.clone()),
+        }
+    }
+}
+
+/// The `FONT` of a dialog, in points at 96 DPI. Windows scales it, and the
+/// dialog units derived from it, to the DPI of the dialog.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DialogFont {
+    pub point_size: u16,
+    pub weight: u16,
+    pub italic: bool,
+    pub char_set: u8,
+    pub face: String,
+}
+
+impl DialogFont {
+    /// A regular weight font (`FW_NORMAL`) with `DEFAULT_CHARSET`.
+    pub fn new(point_size: u16, face: &str) -> DialogFont {
+        DialogFont { point_size, weight: 400, italic: false, char_set: 1, face: face.to_owned() }
+    }
+}
+
+/// A control of a `DialogTemplate`. Geometry is in dialog units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialogControl {
+    pub class: ControlClass,
+    pub text: TemplateId,
+    /// A `DWORD` in the template, but an `INT` everywhere else.
+    pub id: i32,
+    pub x: i16,
+    pub y: i16,
+    pub width: i16,
+    pub height: i16,
+    pub style: u32,
+    pub ex_style: u32,
+    pub help_id: u32,
+}
+
+impl DialogControl {
+    /// A control of any class, like the `CONTROL` statement: only
+    /// `WS_CHILD | WS_VISIBLE` are set.
+    pub fn new<T: Into<TemplateId>>(class: ControlClass, text: T, id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl {
+            class,
+            text: text.into(),
+            id,
+            x,
+            y,
+            width,
+            height,
+            style: WS_CHILD | WS_VISIBLE,
+            ex_style: 0,
+            help_id: 0,
+        }
+    }
+
+    /// `LTEXT`: `SS_LEFT | WS_GROUP`.
+    pub fn ltext(text: &str, id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Static, text, id, x, y, width, height).style(WS_GROUP)
+    }
+
+    /// `RTEXT`: `SS_RIGHT | WS_GROUP`.
+    pub fn rtext(text: &str, id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Static, text, id, x, y, width, height).style(0x0002 | WS_GROUP)
+    }
+
+    /// `CTEXT`: `SS_CENTER | WS_GROUP`.
+    pub fn ctext(text: &str, id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Static, text, id, x, y, width, height).style(0x0001 | WS_GROUP)
+    }
+
+    /// `PUSHBUTTON`: `BS_PUSHBUTTON | WS_TABSTOP`.
+    pub fn push_button(text: &str, id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Button, text, id, x, y, width, height).style(WS_TABSTOP)
+    }
+
+    /// `DEFPUSHBUTTON`: `BS_DEFPUSHBUTTON | WS_TABSTOP`.
+    pub fn def_push_button(text: &str, id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Button, text, id, x, y, width, height).style(0x0001 | WS_TABSTOP)
+    }
+
+    /// `AUTOCHECKBOX`: `BS_AUTOCHECKBOX | WS_TABSTOP`.
+    pub fn auto_check_box(text: &str, id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Button, text, id, x, y, width, height).style(0x0003 | WS_TABSTOP)
+    }
+
+    /// `AUTORADIOBUTTON`: `BS_AUTORADIOBUTTON`. Radio buttons are not tab
+    /// stops; the first of a group usually gets `WS_GROUP | WS_TABSTOP`.
+    pub fn auto_radio_button(text: &str, id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Button, text, id, x, y, width, height).style(0x0009)
+    }
+
+    /// `GROUPBOX`: `BS_GROUPBOX`.
+    pub fn group_box(text: &str, id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Button, text, id, x, y, width, height).style(0x0007)
+    }
+
+    /// `EDITTEXT`: `ES_LEFT | WS_BORDER | WS_TABSTOP`.
+    pub fn edit_text(id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Edit, "", id, x, y, width, height).style(WS_BORDER | WS_TABSTOP)
+    }
+
+    /// `LISTBOX`: `LBS_NOTIFY | WS_BORDER`.
+    pub fn list_box(id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::ListBox, "", id, x, y, width, height).style(0x0001 | WS_BORDER)
+    }
+
+    /// `COMBOBOX`: no style of its own, so pass the kind, such as
+    /// `CBS_DROPDOWNLIST`.
+    pub fn combo_box(id: i32, x: i16, y: i16, width: i16, height: i16) -> DialogControl {
+        DialogControl::new(ControlClass::ComboBox, "", id, x, y, width, height)
+    }
+
+    /// `ICON`: `SS_ICON`, showing the icon resource `icon`, sized to the icon.
+    pub fn icon<T: Into<TemplateId>>(icon: T, id: i32, x: i16, y: i16) -> DialogControl {
+        DialogControl::new(ControlClass::Static, icon, id, x, y, 0, 0).style(0x0003)
+    }
+
+    /// Add `style` to the default style.
+    pub fn style(mut self, style: u32) -> DialogControl {
+        self.style |= style;
+        self
+    }
+
+    /// Remove `style` from the style, like `NOT` in a resource script.
+    pub fn without_style(mut self, style: u32) -> DialogControl {
+        self.style &= !style;
+        self
+    }
+
+    pub fn ex_style(mut self, ex_style: u32) -> DialogControl {
+        self.ex_style |= ex_style;
+        self
+    }
+
+    pub fn help_id(mut self, help_id: u32) -> DialogControl {
+        self.help_id = help_id;
+        self
+    }
+}
+
+/// A dialog and its controls. Geometry is in dialog units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialogTemplate {
+    pub x: i16,
+    pub y: i16,
+    pub width: i16,
+    pub height: i16,
+    /// `WS_CAPTION` is added when there is a caption, and `DS_SETFONT` when
+    /// there is a font, as `rc` does.
+    pub style: u32,
+    pub ex_style: u32,
+    pub help_id: u32,
+    pub menu: Option<TemplateId>,
+    pub class: Option<TemplateId>,
+    pub caption: Option<String>,
+    pub font: Option<DialogFont>,
+    pub controls: Vec<DialogControl>,
+}
+
+impl DialogTemplate {
+    /// An empty dialog with the default style of `rc`,
+    /// `WS_POPUP | WS_BORDER | WS_SYSMENU`.
+    pub fn new(x: i16, y: i16, width: i16, height: i16) -> DialogTemplate {
+        DialogTemplate {
+            x,
+            y,
+            width,
+            height,
+            style: WS_POPUP | WS_BORDER | WS_SYSMENU,
+            ex_style: 0,
+            help_id: 0,
+            menu: None,
+            class: None,
+            caption: None,
+            font: None,
+            controls: Vec::new(),
+        }
+    }
+
+    /// Replace the style, like the `STYLE` statement.
+    pub fn style(mut self, style: u32) -> DialogTemplate {
+        self.style = style;
+        self
+    }
+
+    pub fn ex_style(mut self, ex_style: u32) -> DialogTemplate {
+        self.ex_style = ex_style;
+        self
+    }
+
+    pub fn help_id(mut self, help_id: u32) -> DialogTemplate {
+        self.help_id = help_id;
+        self
+    }
+
+    pub fn caption(mut self, caption: &str) -> DialogTemplate {
+        self.caption = Some(caption.to_owned());
+        self
+    }
+
+    pub fn font(mut self, font: DialogFont) -> DialogTemplate {
+        self.font = Some(font);
+        self
+    }
+
+    /// The menu resource to load into the dialog.
+    pub fn menu<T: Into<TemplateId>>(mut self, menu: T) -> DialogTemplate {
+        self.menu = Some(menu.into());
+        self
+    }
+
+    /// A registered window class for the dialog itself.
+    pub fn class<T: Into<TemplateId>>(mut self, class: T) -> DialogTemplate {
+        self.class = Some(class.into());
+        self
+    }
+
+    /// Add a control; the tab order is the order they are added in.
+    pub fn control(mut self, control: DialogControl) -> DialogTemplate {
+        self.controls.push(control);
+        self
+    }
+
+    /// The style that is written, with the implied `WS_CAPTION` and `DS_SETFONT`.
+    pub fn effective_style(&self) -> u32 {
+        let mut style = self.style;
+        if self.caption.is_some() {
+            style |= WS_CAPTION;
+        }
+        if self.font.is_some() {
+            style |= DS_SETFONT;
+        }
+        style
+    }
+
+    /// The `DLGTEMPLATEEX` and its `DLGITEMTEMPLATEEX`s.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        push_u16(&mut data, 1); // dlgVer
+        push_u16(&mut data, 0xFFFF); // signature
+        push_u32(&mut data, self.help_id);
+        push_u32(&mut data, self.ex_style);
+        push_u32(&mut data, self.effective_style());
+        push_u16(&mut data, self.controls.len() as u16);
+        for coordinate in &[self.x, self.y, self.width, self.height] {
+            push_u16(&mut data, *coordinate as u16);
+        }
+        push_optional_id(&mut data, &self.menu);
+        push_optional_id(&mut data, &self.class);
+        push_wstr(&mut data, self.caption.as_ref().map_or("", String::as_str));
+
+        if let Some(font) = &self.font {
+            push_u16(&mut data, font.point_size);
+            push_u16(&mut data, font.weight);
+            data.push(font.italic as u8);
+            data.push(font.char_set);
+            push_wstr(&mut data, &font.face);
+        }
+
+        for control in &self.controls {
+            align(&mut data);
+            push_u32(&mut data, control.help_id);
+            push_u32(&mut data, control.ex_style);
+            push_u32(&mut data, control.style);
+            for coordinate in &[control.x, control.y, control.width, control.height] {
+                push_u16(&mut data, *coordinate as u16);
+            }
+            push_u32(&mut data, control.id as u32);
+            push_id(&mut data, &control.class.id());
+            push_id(&mut data, &control.text);
+            push_u16(&mut data, 0); // no creation data
+        }
+        data
+    }
+}
+
+pub(crate) fn push_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn push_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) fn push_wstr(data: &mut Vec<u8>, s: &str) {
+    for unit in s.encode_utf16() {
+        push_u16(data, unit);
+    }
+    push_u16(data, 0);
+}
+
+fn push_id(data: &mut Vec<u8>, id: &TemplateId) {
+    match id {
+        TemplateId::Ordinal(ordinal) => {
+            push_u16(data, 0xFFFF);
+            push_u16(data, *ordinal);
+        },
+        TemplateId::Name(name) => push_wstr(data, name),
+    }
+}
+
+fn push_optional_id(data: &mut Vec<u8>, id: &Option<TemplateId>) {
+    match id {
+        Some(id) => push_id(data, id),
+        None => push_u16(data, 0),
+    }
+}
+
+// Items start on a DWORD boundary
+pub(crate) fn align(data: &mut Vec<u8>) {
+    let padding = (4 - data.len() % 4) % 4;
+    data.resize(data.len() + padding, 0);
+}
+
+/// A copy of a template in `DWORD`s, the alignment Windows requires of it.
+#[cfg(windows)]
+pub(crate) fn to_dwords(data: &[u8]) -> Vec<u32> {
+    data.chunks(4)
+        .map(|chunk| {
+            let mut dword = [0u8; 4];
+            dword[..chunk.len()].copy_from_slice(chunk);
+            u32::from_le_bytes(dword)
+        })
+        .collect()
+}
+
+#[cfg(windows)]
+mod win {
+    use winapi::{
+        shared::{
+            basetsd::INT_PTR,
+            minwindef::{HINSTANCE, LPARAM},
+            windef::HWND,
+        },
+        um::winuser::{self, DLGPROC, LPCDLGTEMPLATEW},
+    };
+
+    use super::{to_dwords, DialogTemplate};
+
+    impl DialogTemplate {
+        /// Create the dialog, modeless, with `CreateDialogIndirectParamW`.
+        /// The template is only needed for the duration of the call.
+        pub fn create(&self, h_inst: HINSTANCE, h_wnd_parent: HWND, dialog_proc: DLGPROC, init_param: LPARAM) -> HWND {
+            let template = to_dwords(&self.to_bytes());
+            unsafe { winuser::CreateDialogIndirectParamW(h_inst, template.as_ptr() as LPCDLGTEMPLATEW, h_wnd_parent, dialog_proc, init_param) }
+        }
+
+        /// Run the dialog modally with `DialogBoxIndirectParamW`, returning the
+        /// value passed to `EndDialog`.
+        pub fn dialog_box(&self, h_inst: HINSTANCE, h_wnd_parent: HWND, dialog_proc: DLGPROC, init_param: LPARAM) -> INT_PTR {
+            let template = to_dwords(&self.to_bytes());
+            unsafe { winuser::DialogBoxIndirectParamW(h_inst, template.as_ptr() as LPCDLGTEMPLATEW, h_wnd_parent, dialog_proc, init_param) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Compiled from the resource scripts by llvm-rc; see hidpi-build
+    const GUI_RES: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/../hidpi-build/tests/fixtures/DpiAwarenessContext.res"));
+    const FEATURES_RES: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/../hidpi-build/tests/fixtures/features.res"));
+
+    const RT_DIALOG: u16 = 5;
+
+    const DS_MODALFRAME: u32 = 0x0080;
+    const DS_CENTER: u32 = 0x0800;
+    const WS_EX_TRANSPARENT: u32 = 0x0020;
+    const WS_EX_TOOLWINDOW: u32 = 0x0080;
+    const BS_MULTILINE: u32 = 0x2000;
+    const ES_MULTILINE: u32 = 0x0004;
+    const ES_READONLY: u32 = 0x0800;
+
+    // The data of the resource of type `type_id` named by the ordinal `name`
+    // in a .res file, whose entries are all named by ordinals
+    fn res_entry(res: &[u8], type_id: u16, name: u16) -> &[u8] {
+        let u16_at = |offset: usize| u16::from_le_bytes([res[offset], res[offset + 1]]);
+        let u32_at = |offset: usize| u32::from_le_bytes([res[offset], res[offset + 1], res[offset + 2], res[offset + 3]]) as usize;
+
+        let mut offset = 0;
+        while offset < res.len() {
+            let (data_size, header_size) = (u32_at(offset), u32_at(offset + 4));
+            if u16_at(offset + 10) == type_id && u16_at(offset + 14) == name {
+                return &res[offset + header_size..offset + header_size + data_size];
+            }
+            offset += (header_size + data_size + 3) & !3;
+        }
+        panic!("no resource {} of type {}", name, type_id);
+    }
+
+    #[test]
+    fn host_dialog_matches_rc() {
+        let button = |text: &str, id, y, height| DialogControl::push_button(text, id, 7, y, 290, height).style(BS_MULTILINE);
+        let template = DialogTemplate::new(0, 0, 304, 339)
+            .style(DS_MODALFRAME | DS_CENTER | WS_POPUP | WS_SYSMENU)
+            .caption("High DPI Awareness Sample")
+            .font(DialogFont::new(9, "Segoe UI"))
+            .control(button("Create a DPI unaware window\r\n(DPI_AWARENESS_CONTEXT_UNAWARE)", 1000, 111, 30))
+            .control(button("Create a System DPI aware window\r\n(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE)", 1001, 148, 30))
+            .control(button("Create a Windows 8.1 era Per-Monitor DPI aware window\r\n(DPI_AWARENESS_CONTEXT_PER_MONITOR)", 1002, 186, 30))
+            .control(button("Create a Windows 10 (1607) Per-Monitor DPI aware window with non-client scaling\r\n(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE + EnableNonClientDpiScaling())", 1003, 223, 33))
+            .control(button("Create a Windows 10 (1703) Per-Monitor DPI aware v2 window\r\n(DPI_AWARENESS_CONTEXT_PER_MONITOR_V2)", 1004, 264, 30))
+            .control(DialogControl::edit_text(1007, 7, 7, 290, 95)
+                .style(ES_MULTILINE | ES_READONLY)
+                .without_style(WS_BORDER))
+            .control(button("Create a Windows 10 (1803) Per-Monitor DPI aware v2 window with mixed DPI hosting\r\n(DPI_AWARENESS_CONTEXT_PER_MONITOR_V2 + DPI_HOSTING_BEHAVIOR_MIXED)", 1005, 302, 30));
+
+        let expected = res_entry(GUI_RES, RT_DIALOG, 129);
+        assert_eq!(expected.len(), 1572);
+        assert_eq!(template.to_bytes(), expected);
+    }
+
+    #[test]
+    fn extended_dialog_matches_rc() {
+        let mut icon = DialogControl::icon(105, -1, 60, 7);
+        icon.width = 20;
+        icon.height = 20;
+        let template = DialogTemplate::new(0, 0, 160, 60)
+            .style(DS_CENTER | WS_CHILD)
+            .ex_style(WS_EX_TOOLWINDOW)
+            .help_id(77)
+            .caption("Extended")
+            .class("ExtendedClass")
+            .font(DialogFont { weight: 700, italic: true, ..DialogFont::new(9, "Segoe UI") })
+            .control(DialogControl::ltext("Text", -1, 7, 7, 50, 8).ex_style(WS_EX_TRANSPARENT).help_id(42))
+            .control(DialogControl::push_button("Push", 1000, 7, 20, 50, 14))
+            .control(icon);
+
+        assert_eq!(template.to_bytes(), res_entry(FEATURES_RES, RT_DIALOG, 101));
+    }
+
+    #[test]
+    fn statements_have_the_styles_rc_gives_them() {
+        let style = |control: DialogControl| control.style & !(WS_CHILD | WS_VISIBLE);
+        assert_eq!(style(DialogControl::auto_radio_button("", 1, 0, 0, 0, 0)), 0x0009);
+        assert_eq!(style(DialogControl::combo_box(1, 0, 0, 0, 0)), 0);
+        assert_eq!(style(DialogControl::edit_text(1, 0, 0, 0, 0)), WS_BORDER | WS_TABSTOP);
+        assert_eq!(style(DialogControl::ltext("", 1, 0, 0, 0, 0)), WS_GROUP);
+    }
+
+    #[test]
+    fn controls_start_on_a_dword_boundary() {
+        let template = DialogTemplate::new(0, 0, 10, 10)
+            .caption("A")
+            .control(DialogControl::ltext("B", 1, 0, 0, 5, 5))
+            .control(DialogControl::ltext("CD", 2, 0, 0, 5, 5));
+        let data = template.to_bytes();
+
+        // the header is 26 bytes, then no menu, no class and "A"
+        assert_eq!(&data[26..34], [0, 0, 0, 0, b'A', 0, 0, 0]);
+        // the first item starts at 36 and is 24 + 4 (class) + 4 ("B") + 2 bytes long
+        assert_eq!(&data[34..36], [0, 0]);
+        assert_eq!(u32::from_le_bytes([data[56], data[57], data[58], data[59]]), 1);
+        // so the second starts at 72
+        assert_eq!(u32::from_le_bytes([data[92], data[93], data[94], data[95]]), 2);
+        assert_eq!(data.len(), 72 + 24 + 4 + 6 + 2);
+    }
+}
@@ -0,0 +1,457 @@
+//! The C ABI exported by this crate.
+//!
+//! Every function here catches panics at the boundary. Functions that depend
+//! on dynamically loaded symbols return a `HidpiStatus` and write their result
+//! through an out-parameter, so a host running on an older version of Windows
+//! gets `HidpiStatus::NotSupported` rather than an abort.
+
+use std::panic::{self, UnwindSafe};
+use std::slice;
+use libc::size_t;
+#[cfg(windows)]
+use winapi::shared::{
+    minwindef::{
+        LRESULT,
+        HINSTANCE,
+    },
+    windef::{
+        RECT,
+        HFONT,
+        HBRUSH,
+    },
+};
+
+use crate::sys::{
+    c_char,
+    wchar_t,
+    BOOL,
+    TRUE,
+    FALSE,
+    UINT,
+    HWND,
+    DPI_AWARENESS,
+    DPI_AWARENESS_CONTEXT,
+    DPI_HOSTING_BEHAVIOR,
+};
+use crate::DpiAwarenessContext;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HidpiStatus {
+    Ok = 0,
+    /// The function is not exported by this version of Windows.
+    NotSupported = 1,
+    /// A required pointer argument was null.
+    InvalidArgument = 2,
+    /// The implementation panicked; nothing was written to the out-parameters.
+    Panicked = 3,
+}
+
+fn guard<F>(f: F) -> HidpiStatus where F: FnOnce() -> HidpiStatus + UnwindSafe {
+    panic::catch_unwind(f).unwrap_or(HidpiStatus::Panicked)
+}
+
+fn guard_or<F, T>(fallback: T, f: F) -> T where F: FnOnce() -> T + UnwindSafe {
+    panic::catch_unwind(f).unwrap_or(fallback)
+}
+
+/// # Safety
+///
+/// `out` must be null or valid for a write.
+unsafe fn write_out<T>(out: *mut T, value: Option<T>) -> HidpiStatus {
+    if out.is_null() {
+        return HidpiStatus::InvalidArgument;
+    }
+
+    match value {
+        Some(value) => {
+            *out = value;
+            HidpiStatus::Ok
+        },
+        None => HidpiStatus::NotSupported,
+    }
+}
+
+#[cfg(windows)]
+#[no_mangle]
+pub extern "C" fn get_parent_relative_window_rect(h_wnd: HWND, child_bounds: *mut RECT) -> BOOL {
+    if child_bounds.is_null() {
+        return FALSE;
+    }
+    guard_or(FALSE, || crate::get_parent_relative_window_rect(h_wnd, child_bounds))
+}
+
+#[cfg(windows)]
+#[no_mangle]
+pub extern "C" fn get_stock_brush(brush: UINT) -> HBRUSH {
+    guard_or(std::ptr::null_mut(), || crate::get_stock_brush(brush))
+}
+
+#[cfg(windows)]
+#[no_mangle]
+pub extern "C" fn get_hinstance_for_h_wnd(h_wnd: HWND) -> HINSTANCE {
+    guard_or(std::ptr::null_mut(), || crate::get_hinstance_for_h_wnd(h_wnd))
+}
+
+#[cfg(windows)]
+#[no_mangle]
+pub extern "C" fn get_window_font(h_wnd: HWND) -> HFONT {
+    guard_or(std::ptr::null_mut(), || crate::get_window_font(h_wnd))
+}
+
+#[cfg(windows)]
+#[no_mangle]
+pub extern "C" fn set_window_font(h_wnd: HWND, h_font: HFONT, f_redraw: BOOL) -> LRESULT {
+    guard_or(0, || crate::set_window_font(h_wnd, h_font, f_redraw))
+}
+
+/// Returns 0 on failure.
+#[no_mangle]
+pub extern "C" fn get_dpi_for_system() -> UINT {
+    guard_or(0, crate::get_dpi_for_system)
+}
+
+/// Returns 0 on failure.
+#[no_mangle]
+pub extern "C" fn get_dpi_for_window(h_wnd: HWND) -> UINT {
+    guard_or(0, || crate::get_dpi_for_window(h_wnd))
+}
+
+/// Writes the DPI appropriate to the thread's awareness mode, or returns FALSE
+/// when the thread is DPI unaware and no scaling should be applied.
+///
+/// # Safety
+///
+/// `ret` must be null or valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn get_dpi_by_awareness(h_wnd: HWND, ret: *mut UINT) -> BOOL {
+    if ret.is_null() {
+        return FALSE;
+    }
+    guard_or(FALSE, || {
+        if let Some(dpi) = crate::get_maybe_dpi_by_awareness(h_wnd) {
+            *ret = dpi;
+            TRUE
+        } else {
+            FALSE
+        }
+    })
+}
+
+/// # Safety
+///
+/// `equal` must be null or valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn are_dpi_awareness_contexts_equal(a: DPI_AWARENESS_CONTEXT, b: DPI_AWARENESS_CONTEXT, equal: *mut BOOL) -> HidpiStatus {
+    guard(|| write_out(equal, crate::are_dpi_awareness_contexts_equal(a, b).map(|e| if e { TRUE } else { FALSE })))
+}
+
+/// # Safety
+///
+/// `context` must be null or valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn get_thread_dpi_awareness_context(context: *mut DPI_AWARENESS_CONTEXT) -> HidpiStatus {
+    guard(|| write_out(context, crate::backend::current(|b| b.get_thread_dpi_awareness_context())))
+}
+
+/// # Safety
+///
+/// `awareness` must be null or valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn get_thread_dpi_awareness(awareness: *mut DPI_AWARENESS) -> HidpiStatus {
+    guard(|| write_out(awareness, crate::get_thread_dpi_awareness()))
+}
+
+/// # Safety
+///
+/// `awareness` must be null or valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn get_awareness_from_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT, awareness: *mut DPI_AWARENESS) -> HidpiStatus {
+    guard(|| write_out(awareness, crate::get_awareness_from_dpi_awareness_context(context)))
+}
+
+/// # Safety
+///
+/// `behavior` must be null or valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn get_thread_dpi_hosting_behavior(behavior: *mut DPI_HOSTING_BEHAVIOR) -> HidpiStatus {
+    guard(|| write_out(behavior, crate::backend::current(|b| b.get_thread_dpi_hosting_behavior())))
+}
+
+/// Sets the thread's hosting behavior and writes the previous one to `previous`.
+///
+/// # Safety
+///
+/// `previous` must be null or valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn set_thread_dpi_hosting_behavior(behavior: DPI_HOSTING_BEHAVIOR, previous: *mut DPI_HOSTING_BEHAVIOR) -> HidpiStatus {
+    if previous.is_null() {
+        return HidpiStatus::InvalidArgument;
+    }
+    guard(|| write_out(previous, crate::backend::current(|b| b.set_thread_dpi_hosting_behavior(behavior))))
+}
+
+/// Sets the thread's awareness context and writes the previous one to `previous`.
+///
+/// # Safety
+///
+/// `previous` must be null or valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn set_thread_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT, previous: *mut DPI_AWARENESS_CONTEXT) -> HidpiStatus {
+    if previous.is_null() {
+        return HidpiStatus::InvalidArgument;
+    }
+    guard(|| write_out(previous, crate::backend::current(|b| b.set_thread_dpi_awareness_context(context))))
+}
+
+// Both string formatters follow the Win32 sizing convention: on success they
+// return the number of characters written, excluding the terminator. When
+// `buffer` is null or `capacity` is too small nothing is written and the
+// required capacity, including the terminator, is returned instead. A result
+// of 0 means the call failed, which for the context formatters includes being
+// passed a context that is not valid.
+
+unsafe fn write_wide(buffer: *mut wchar_t, capacity: size_t, s: &str) -> size_t {
+    let out: &mut [u16] = if buffer.is_null() {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(buffer, capacity)
+    };
+    crate::copy_to_wide(out, s).unwrap_or_else(|required| required)
+}
+
+unsafe fn write_utf8(buffer: *mut c_char, capacity: size_t, s: &str) -> size_t {
+    let out: &mut [u8] = if buffer.is_null() {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(buffer as *mut u8, capacity)
+    };
+    crate::copy_to_utf8(out, s).unwrap_or_else(|required| required)
+}
+
+/// # Safety
+///
+/// `buffer` must be null or valid for writes of `capacity` elements.
+#[no_mangle]
+pub unsafe extern "C" fn format_awareness(buffer: *mut wchar_t, capacity: size_t, awareness: DPI_AWARENESS) -> size_t {
+    guard_or(0, || write_wide(buffer, capacity, crate::awareness_to_str(awareness)))
+}
+
+/// # Safety
+///
+/// `buffer` must be null or valid for writes of `capacity` elements.
+#[no_mangle]
+pub unsafe extern "C" fn format_awareness_utf8(buffer: *mut c_char, capacity: size_t, awareness: DPI_AWARENESS) -> size_t {
+    guard_or(0, || write_utf8(buffer, capacity, crate::awareness_to_str(awareness)))
+}
+
+/// # Safety
+///
+/// `buffer` must be null or valid for writes of `capacity` elements.
+#[no_mangle]
+pub unsafe extern "C" fn format_awareness_context(buffer: *mut wchar_t, capacity: size_t, context: DPI_AWARENESS_CONTEXT) -> size_t {
+    guard_or(0, || match DpiAwarenessContext::from_raw(context) {
+        Some(context) => write_wide(buffer, capacity, context.name()),
+        None => 0,
+    })
+}
+
+/// # Safety
+///
+/// `buffer` must be null or valid for writes of `capacity` elements.
+#[no_mangle]
+pub unsafe extern "C" fn format_awareness_context_utf8(buffer: *mut c_char, capacity: size_t, context: DPI_AWARENESS_CONTEXT) -> size_t {
+    guard_or(0, || match DpiAwarenessContext::from_raw(context) {
+        Some(context) => write_utf8(buffer, capacity, context.name()),
+        None => 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+    use crate::backend::with_backend;
+    use crate::mock::{MockBackend, Release};
+    use crate::sys::{
+        DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
+        DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        DPI_AWARENESS_SYSTEM_AWARE,
+        DPI_AWARENESS_PER_MONITOR_AWARE,
+        DPI_HOSTING_BEHAVIOR_DEFAULT,
+        DPI_HOSTING_BEHAVIOR_MIXED,
+    };
+
+    const UNTOUCHED: usize = 0xdead;
+
+    fn untouched_context() -> DPI_AWARENESS_CONTEXT {
+        UNTOUCHED as DPI_AWARENESS_CONTEXT
+    }
+
+    #[test]
+    fn missing_functions_are_not_supported() {
+        with_backend(MockBackend::new(Release::None), || {
+            let mut context = untouched_context();
+            assert_eq!(unsafe { get_thread_dpi_awareness_context(&mut context) }, HidpiStatus::NotSupported);
+            assert_eq!(unsafe { set_thread_dpi_awareness_context(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, &mut context) }, HidpiStatus::NotSupported);
+            assert_eq!(context, untouched_context());
+
+            let mut awareness = UNTOUCHED as DPI_AWARENESS;
+            assert_eq!(unsafe { get_thread_dpi_awareness(&mut awareness) }, HidpiStatus::NotSupported);
+            assert_eq!(unsafe { get_awareness_from_dpi_awareness_context(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, &mut awareness) }, HidpiStatus::NotSupported);
+            assert_eq!(awareness, UNTOUCHED as DPI_AWARENESS);
+
+            let mut behavior = UNTOUCHED as DPI_HOSTING_BEHAVIOR;
+            assert_eq!(unsafe { get_thread_dpi_hosting_behavior(&mut behavior) }, HidpiStatus::NotSupported);
+            assert_eq!(unsafe { set_thread_dpi_hosting_behavior(DPI_HOSTING_BEHAVIOR_MIXED, &mut behavior) }, HidpiStatus::NotSupported);
+            assert_eq!(behavior, UNTOUCHED as DPI_HOSTING_BEHAVIOR);
+
+            let mut equal = UNTOUCHED as BOOL;
+            assert_eq!(unsafe { are_dpi_awareness_contexts_equal(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, &mut equal) }, HidpiStatus::NotSupported);
+            assert_eq!(equal, UNTOUCHED as BOOL);
+
+            let mut dpi = UNTOUCHED as UINT;
+            assert_eq!(unsafe { get_dpi_by_awareness(ptr::null_mut(), &mut dpi) }, FALSE);
+            assert_eq!(dpi, UNTOUCHED as UINT);
+        });
+    }
+
+    #[test]
+    fn missing_dpi_functions_fall_back_to_the_system_dpi() {
+        with_backend(MockBackend::new(Release::None), || {
+            let system = get_dpi_for_system();
+            assert_ne!(system, 0);
+            assert_eq!(get_dpi_for_window(ptr::null_mut()), system);
+        });
+        // Windows 8.1 has the monitor DPI
+        with_backend(MockBackend::new(Release::Windows81).with_dpi(96, 96, 144), || {
+            assert_eq!(get_dpi_for_window(ptr::null_mut()), 144);
+        });
+    }
+
+    #[test]
+    fn null_out_parameters_are_rejected() {
+        with_backend(MockBackend::new(Release::Windows10_1803), || {
+            assert_eq!(unsafe { get_thread_dpi_awareness_context(ptr::null_mut()) }, HidpiStatus::InvalidArgument);
+            assert_eq!(unsafe { get_thread_dpi_awareness(ptr::null_mut()) }, HidpiStatus::InvalidArgument);
+            assert_eq!(unsafe { get_thread_dpi_hosting_behavior(ptr::null_mut()) }, HidpiStatus::InvalidArgument);
+            assert_eq!(unsafe { are_dpi_awareness_contexts_equal(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, ptr::null_mut()) }, HidpiStatus::InvalidArgument);
+            assert_eq!(unsafe { get_dpi_by_awareness(ptr::null_mut(), ptr::null_mut()) }, FALSE);
+        });
+
+        // the setters check before changing anything
+        let backend = MockBackend::new(Release::Windows10_1803);
+        with_backend(backend.clone(), || {
+            assert_eq!(unsafe { set_thread_dpi_awareness_context(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, ptr::null_mut()) }, HidpiStatus::InvalidArgument);
+            assert_eq!(unsafe { set_thread_dpi_hosting_behavior(DPI_HOSTING_BEHAVIOR_MIXED, ptr::null_mut()) }, HidpiStatus::InvalidArgument);
+        });
+        assert!(backend.calls().is_empty());
+    }
+
+    #[test]
+    fn available_functions_write_their_result() {
+        let backend = MockBackend::new(Release::Windows10_1803).with_dpi(120, 144, 144);
+        with_backend(backend.clone(), || {
+            let mut previous = ptr::null_mut();
+            assert_eq!(unsafe { set_thread_dpi_awareness_context(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, &mut previous) }, HidpiStatus::Ok);
+            assert_eq!(previous, MockBackend::handle(DpiAwarenessContext::Unaware));
+
+            let mut context = ptr::null_mut();
+            assert_eq!(unsafe { get_thread_dpi_awareness_context(&mut context) }, HidpiStatus::Ok);
+            let mut equal = FALSE;
+            assert_eq!(unsafe { are_dpi_awareness_contexts_equal(context, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, &mut equal) }, HidpiStatus::Ok);
+            assert_eq!(equal, TRUE);
+
+            let mut awareness = 0;
+            assert_eq!(unsafe { get_thread_dpi_awareness(&mut awareness) }, HidpiStatus::Ok);
+            assert_eq!(awareness, DPI_AWARENESS_PER_MONITOR_AWARE);
+            assert_eq!(unsafe { get_awareness_from_dpi_awareness_context(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE, &mut awareness) }, HidpiStatus::Ok);
+            assert_eq!(awareness, DPI_AWARENESS_SYSTEM_AWARE);
+
+            let mut dpi = 0;
+            assert_eq!(unsafe { get_dpi_by_awareness(ptr::null_mut(), &mut dpi) }, TRUE);
+            assert_eq!(dpi, 144);
+            assert_eq!(get_dpi_for_system(), 120);
+
+            let mut behavior = DPI_HOSTING_BEHAVIOR_MIXED;
+            assert_eq!(unsafe { set_thread_dpi_hosting_behavior(DPI_HOSTING_BEHAVIOR_MIXED, &mut behavior) }, HidpiStatus::Ok);
+            assert_eq!(behavior, DPI_HOSTING_BEHAVIOR_DEFAULT);
+            assert_eq!(unsafe { get_thread_dpi_hosting_behavior(&mut behavior) }, HidpiStatus::Ok);
+            assert_eq!(behavior, DPI_HOSTING_BEHAVIOR_MIXED);
+        });
+        assert_eq!(backend.thread_context(), MockBackend::handle(DpiAwarenessContext::PerMonitorV2));
+    }
+
+    #[test]
+    fn an_unaware_thread_gets_no_dpi() {
+        with_backend(MockBackend::new(Release::Windows10_1607).with_dpi(144, 144, 144), || {
+            let mut dpi = UNTOUCHED as UINT;
+            assert_eq!(unsafe { get_dpi_by_awareness(ptr::null_mut(), &mut dpi) }, FALSE);
+            assert_eq!(dpi, UNTOUCHED as UINT);
+        });
+    }
+
+    #[test]
+    fn write_wide_reports_the_capacity_for_a_null_buffer() {
+        for &capacity in &[0, 1, 100] {
+            assert_eq!(unsafe { write_wide(ptr::null_mut(), capacity, "€𝄞") }, 4);
+            assert_eq!(unsafe { write_utf8(ptr::null_mut(), capacity, "€𝄞") }, 8);
+        }
+    }
+
+    #[test]
+    fn write_wide_and_write_utf8_follow_the_sizing_convention() {
+        let s = "a€𝄞";
+        let wide = s.encode_utf16().count();
+        for capacity in 0..wide + 3 {
+            let mut buffer = vec![0xaaaa_u16; capacity + 1];
+            let result = unsafe { write_wide(buffer.as_mut_ptr(), capacity, s) };
+            if capacity > wide {
+                assert_eq!(result, wide);
+                assert_eq!(buffer[wide], 0);
+            } else {
+                assert_eq!(result, wide + 1);
+                assert!(buffer.iter().all(|&c| c == 0xaaaa));
+            }
+            // never past `capacity`
+            assert_eq!(buffer[capacity], 0xaaaa);
+        }
+
+        for capacity in 0..s.len() + 3 {
+            let mut buffer = vec![0x55 as c_char; capacity + 1];
+            let result = unsafe { write_utf8(buffer.as_mut_ptr(), capacity, s) };
+            if capacity > s.len() {
+                assert_eq!(result, s.len());
+                assert_eq!(buffer[s.len()], 0);
+            } else {
+                assert_eq!(result, s.len() + 1);
+                assert!(buffer.iter().all(|&c| c == 0x55));
+            }
+            assert_eq!(buffer[capacity], 0x55);
+        }
+    }
+
+    #[test]
+    fn format_awareness_round_trips_through_both_encodings() {
+        let name = "DPI_AWARENESS_PER_MONITOR_AWARE";
+        let mut wide = [0u16; 64];
+        let mut utf8 = [0 as c_char; 64];
+        // exact fit, then one short
+        assert_eq!(unsafe { format_awareness(wide.as_mut_ptr(), name.len() + 1, DPI_AWARENESS_PER_MONITOR_AWARE) }, name.len());
+        assert_eq!(String::from_utf16(&wide[..name.len()]).unwrap(), name);
+        assert_eq!(unsafe { format_awareness_utf8(utf8.as_mut_ptr(), name.len() + 1, DPI_AWARENESS_PER_MONITOR_AWARE) }, name.len());
+        assert_eq!(utf8[..name.len()].iter().map(|&c| c as u8 as char).collect::<String>(), name);
+        assert_eq!(unsafe { format_awareness(wide.as_mut_ptr(), name.len(), DPI_AWARENESS_PER_MONITOR_AWARE) }, name.len() + 1);
+        assert_eq!(unsafe { format_awareness_utf8(utf8.as_mut_ptr(), name.len(), DPI_AWARENESS_PER_MONITOR_AWARE) }, name.len() + 1);
+    }
+
+    #[test]
+    fn invalid_contexts_do_not_format() {
+        with_backend(MockBackend::new(Release::None), || {
+            let mut buffer = [0u16; 64];
+            assert_eq!(unsafe { format_awareness_context(buffer.as_mut_ptr(), buffer.len(), untouched_context()) }, 0);
+            assert_eq!(unsafe { format_awareness_context(buffer.as_mut_ptr(), buffer.len(), DPI_AWARENESS_CONTEXT_SYSTEM_AWARE) }, "DPI_AWARENESS_CONTEXT_SYSTEM_AWARE".len());
+        });
+    }
+}
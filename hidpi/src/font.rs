@@ -0,0 +1,492 @@
+//! Fonts created for a DPI and kept until they are no longer needed.
+//!
+//! Child controls keep using the `HFONT` they were sent with `WM_SETFONT`, so
+//! the font has to outlive every control it was sent to, and should be deleted
+//! once none of them use it any more. `DpiFontCache` owns the fonts, hands out
+//! the same handle every time a font is asked for at a DPI, counts who is
+//! using it, and deletes them when it is dropped.
+//!
+//! ```ignore
+//! let mut fonts = DpiFontCache::new(GdiFonts);
+//! let description = FontDescription::icon_title().unwrap();
+//! if let Some(h_font) = fonts.acquire(&description, dpi) {
+//!     let h_font_old = get_window_font(h_wnd_static);
+//!     set_window_font(h_wnd_static, h_font, TRUE);
+//!     fonts.release(h_font_old);
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use crate::units::{mul_div, Dpi};
+
+/// The fields of a `LOGFONT`, with the sizes in 96 DPI units.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct FontDescription {
+    /// Negative for the character height, positive for the cell height.
+    pub height: i32,
+    /// 0 for the aspect ratio of the font.
+    pub width: i32,
+    pub escapement: i32,
+    pub orientation: i32,
+    pub weight: i32,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike_out: bool,
+    pub char_set: u8,
+    pub out_precision: u8,
+    pub clip_precision: u8,
+    pub quality: u8,
+    pub pitch_and_family: u8,
+    pub face_name: String,
+}
+
+impl FontDescription {
+    /// `height` and `width` at `dpi`.
+    pub fn size_for_dpi(&self, dpi: Dpi) -> (i32, i32) {
+        let dpi = dpi.0 as i32;
+        let default = Dpi::DEFAULT.0 as i32;
+        (mul_div(self.height, dpi, default), mul_div(self.width, dpi, default))
+    }
+}
+
+/// Creates and deletes the fonts held by a `DpiFontCache`.
+pub trait FontBackend {
+    type Font: Copy + Eq;
+
+    /// Create the font described by `description` at `dpi`, or `None` on failure.
+    fn create_font(&self, description: &FontDescription, dpi: Dpi) -> Option<Self::Font>;
+
+    fn delete_font(&self, font: Self::Font);
+}
+
+struct Entry<F> {
+    font: F,
+    last_used: u64,
+    // acquire calls not yet matched by a release
+    uses: usize,
+}
+
+/// A cache of fonts keyed by description and DPI.
+///
+/// Every `acquire` counts as a use of the font until it is handed back with
+/// `release`, and a font in use is never deleted before the cache is cleared
+/// or dropped. Once `capacity` fonts exist, creating another one first
+/// deletes the least recently used fonts nobody is using; if every font is in
+/// use, the cache grows past its capacity instead.
+pub struct DpiFontCache<B: FontBackend> {
+    backend: B,
+    fonts: HashMap<(FontDescription, Dpi), Entry<B::Font>>,
+    capacity: usize,
+    clock: u64,
+}
+
+impl<B: FontBackend> DpiFontCache<B> {
+    pub const DEFAULT_CAPACITY: usize = 16;
+
+    pub fn new(backend: B) -> DpiFontCache<B> {
+        DpiFontCache::with_capacity(backend, Self::DEFAULT_CAPACITY)
+    }
+
+    /// A cache holding at most `capacity` fonts; a capacity of 0 is treated as 1.
+    pub fn with_capacity(backend: B, capacity: usize) -> DpiFontCache<B> {
+        DpiFontCache {
+            backend,
+            fonts: HashMap::new(),
+            capacity: capacity.max(1),
+            clock: 0,
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn len(&self) -> usize {
+        self.fonts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Whether the font is already cached, without creating it or counting as a use.
+    pub fn contains(&self, description: &FontDescription, dpi: Dpi) -> bool {
+        self.fonts.contains_key(&(description.clone(), dpi))
+    }
+
+    /// How many `acquire` calls for the font have not been released yet.
+    pub fn uses(&self, description: &FontDescription, dpi: Dpi) -> usize {
+        self.fonts.get(&(description.clone(), dpi)).map_or(0, |entry| entry.uses)
+    }
+
+    /// The font for `description` at `dpi`, created if it is not cached yet.
+    /// The handle is still owned by the cache and must not be deleted; hand it
+    /// back with `release` once nothing uses it.
+    pub fn acquire(&mut self, description: &FontDescription, dpi: Dpi) -> Option<B::Font> {
+        self.clock += 1;
+        let now = self.clock;
+
+        let key = (description.clone(), dpi);
+        if let Some(entry) = self.fonts.get_mut(&key) {
+            entry.last_used = now;
+            entry.uses += 1;
+            return Some(entry.font);
+        }
+
+        let font = self.backend.create_font(description, dpi)?;
+        while self.fonts.len() >= self.capacity && self.evict_least_recently_used() {}
+        self.fonts.insert(key, Entry { font, last_used: now, uses: 1 });
+        Some(font)
+    }
+
+    /// Stop using a font returned by `acquire`. It stays cached for the next
+    /// `acquire`, and may be deleted once it is unused. Returns `false` if the
+    /// font is not from this cache or is not in use, e.g. a null handle or a
+    /// stock font read back from a window.
+    pub fn release(&mut self, font: B::Font) -> bool {
+        match self.fonts.values_mut().find(|entry| entry.font == font && entry.uses > 0) {
+            Some(entry) => {
+                entry.uses -= 1;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Delete the least recently used font that is not in use, if there is one
+    fn evict_least_recently_used(&mut self) -> bool {
+        let oldest = self.fonts.iter()
+            .filter(|(_key, entry)| entry.uses == 0)
+            .min_by_key(|(_key, entry)| entry.last_used)
+            .map(|(key, _entry)| key.clone());
+        match oldest {
+            Some(key) => self.remove_key(&key),
+            None => false,
+        }
+    }
+
+    fn remove_key(&mut self, key: &(FontDescription, Dpi)) -> bool {
+        match self.fonts.get(key) {
+            Some(entry) if entry.uses == 0 => {
+                let font = entry.font;
+                self.fonts.remove(key);
+                self.backend.delete_font(font);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Delete one font. Returns `false` if it was not cached or is still in use.
+    pub fn remove(&mut self, description: &FontDescription, dpi: Dpi) -> bool {
+        self.remove_key(&(description.clone(), dpi))
+    }
+
+    /// Delete every unused font created for `dpi`, e.g. once no window uses that DPI.
+    pub fn remove_dpi(&mut self, dpi: Dpi) {
+        let keys: Vec<_> = self.fonts.keys()
+            .filter(|(_description, font_dpi)| *font_dpi == dpi)
+            .cloned()
+            .collect();
+        for key in &keys {
+            self.remove_key(key);
+        }
+    }
+
+    /// Delete every font, including those still in use, e.g. once the
+    /// windows they were sent to are destroyed.
+    pub fn clear(&mut self) {
+        for (_key, entry) in self.fonts.drain() {
+            self.backend.delete_font(entry.font);
+        }
+    }
+}
+
+impl<B: FontBackend> Drop for DpiFontCache<B> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(windows)]
+pub use self::gdi::GdiFonts;
+
+#[cfg(windows)]
+mod gdi {
+    use winapi::{
+        shared::{
+            minwindef::{BYTE, FALSE, TRUE},
+            windef::HFONT,
+        },
+        um::wingdi::{self, LF_FACESIZE, LOGFONTW},
+    };
+
+    use super::{FontBackend, FontDescription};
+    use crate::units::{mul_div, Dpi};
+
+    impl FontDescription {
+        /// Describe a `LOGFONTW` whose sizes are for `dpi`.
+        pub fn from_log_font(lf: &LOGFONTW, dpi: Dpi) -> FontDescription {
+            let dpi = dpi.0 as i32;
+            let default = Dpi::DEFAULT.0 as i32;
+            let len = lf.lfFaceName.iter().position(|c| *c == 0).unwrap_or(LF_FACESIZE);
+            FontDescription {
+                height: mul_div(lf.lfHeight, default, dpi),
+                width: mul_div(lf.lfWidth, default, dpi),
+                escapement: lf.lfEscapement,
+                orientation: lf.lfOrientation,
+                weight: lf.lfWeight,
+                italic: lf.lfItalic != FALSE as BYTE,
+                underline: lf.lfUnderline != FALSE as BYTE,
+                strike_out: lf.lfStrikeOut != FALSE as BYTE,
+                char_set: lf.lfCharSet,
+                out_precision: lf.lfOutPrecision,
+                clip_precision: lf.lfClipPrecision,
+                quality: lf.lfQuality,
+                pitch_and_family: lf.lfPitchAndFamily,
+                face_name: String::from_utf16_lossy(&lf.lfFaceName[..len]),
+            }
+        }
+
+        /// The `LOGFONTW` for this description at `dpi`. The face name is
+        /// truncated to fit `lfFaceName`.
+        pub fn to_log_font(&self, dpi: Dpi) -> LOGFONTW {
+            let (height, width) = self.size_for_dpi(dpi);
+            let mut lf = LOGFONTW {
+                lfHeight: height,
+                lfWidth: width,
+                lfEscapement: self.escapement,
+                lfOrientation: self.orientation,
+                lfWeight: self.weight,
+                lfItalic: if self.italic { TRUE as BYTE } else { FALSE as BYTE },
+                lfUnderline: if self.underline { TRUE as BYTE } else { FALSE as BYTE },
+                lfStrikeOut: if self.strike_out { TRUE as BYTE } else { FALSE as BYTE },
+                lfCharSet: self.char_set,
+                lfOutPrecision: self.out_precision,
+                lfClipPrecision: self.clip_precision,
+                lfQuality: self.quality,
+                lfPitchAndFamily: self.pitch_and_family,
+                lfFaceName: [0; LF_FACESIZE],
+            };
+            for (dst, src) in lf.lfFaceName[..LF_FACESIZE - 1].iter_mut().zip(self.face_name.encode_utf16()) {
+                *dst = src;
+            }
+            lf
+        }
+
+        /// The icon title font, the usual font for child controls.
+        pub fn icon_title() -> Option<FontDescription> {
+            crate::get_icon_title_log_font_for_dpi(Dpi::DEFAULT.0)
+                .map(|lf| FontDescription::from_log_font(&lf, Dpi::DEFAULT))
+        }
+    }
+
+    /// Fonts created with `CreateFontIndirectW`.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct GdiFonts;
+
+    impl FontBackend for GdiFonts {
+        type Font = HFONT;
+
+        fn create_font(&self, description: &FontDescription, dpi: Dpi) -> Option<HFONT> {
+            let lf = description.to_log_font(dpi);
+            let h_font = unsafe { wingdi::CreateFontIndirectW(&lf) };
+            if h_font.is_null() { None } else { Some(h_font) }
+        }
+
+        fn delete_font(&self, font: HFONT) {
+            unsafe { wingdi::DeleteObject(font as *mut _); }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use super::*;
+
+    // Fonts are numbered in creation order; deleting one twice, or one that
+    // was never created, fails the test.
+    #[derive(Default)]
+    struct FakeGdi {
+        created: Cell<u32>,
+        live: RefCell<Vec<u32>>,
+        deleted: RefCell<Vec<u32>>,
+        fail: Cell<bool>,
+    }
+
+    #[derive(Clone, Default)]
+    struct FakeFonts(Rc<FakeGdi>);
+
+    impl FontBackend for FakeFonts {
+        type Font = u32;
+
+        fn create_font(&self, _description: &FontDescription, _dpi: Dpi) -> Option<u32> {
+            if self.0.fail.get() {
+                return None;
+            }
+            let font = self.0.created.get() + 1;
+            self.0.created.set(font);
+            self.0.live.borrow_mut().push(font);
+            Some(font)
+        }
+
+        fn delete_font(&self, font: u32) {
+            let mut live = self.0.live.borrow_mut();
+            let index = live.iter().position(|f| *f == font).expect("deleted a font that is not live");
+            live.remove(index);
+            self.0.deleted.borrow_mut().push(font);
+        }
+    }
+
+    impl FakeFonts {
+        fn deleted(&self) -> Vec<u32> {
+            self.0.deleted.borrow().clone()
+        }
+
+        fn live(&self) -> Vec<u32> {
+            self.0.live.borrow().clone()
+        }
+    }
+
+    fn face(name: &str) -> FontDescription {
+        FontDescription { height: -12, weight: 400, face_name: name.to_owned(), ..FontDescription::default() }
+    }
+
+    #[test]
+    fn sizes_scale_from_96_dpi() {
+        let description = FontDescription { height: -12, width: 5, ..FontDescription::default() };
+        assert_eq!(description.size_for_dpi(Dpi(96)), (-12, 5));
+        assert_eq!(description.size_for_dpi(Dpi(144)), (-18, 8));
+        assert_eq!(description.size_for_dpi(Dpi(192)), (-24, 10));
+    }
+
+    #[test]
+    fn the_same_font_is_handed_out_for_a_description_and_dpi() {
+        let backend = FakeFonts::default();
+        let mut fonts = DpiFontCache::new(backend.clone());
+        assert_eq!(fonts.acquire(&face("Segoe UI"), Dpi(96)), Some(1));
+        assert_eq!(fonts.acquire(&face("Segoe UI"), Dpi(96)), Some(1));
+        assert_eq!(fonts.acquire(&face("Segoe UI"), Dpi(144)), Some(2));
+        assert_eq!(fonts.acquire(&face("Tahoma"), Dpi(96)), Some(3));
+        assert_eq!(fonts.len(), 3);
+        assert_eq!(fonts.uses(&face("Segoe UI"), Dpi(96)), 2);
+        assert_eq!(backend.live(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn fonts_in_use_are_never_evicted() {
+        let backend = FakeFonts::default();
+        let mut fonts = DpiFontCache::with_capacity(backend.clone(), 2);
+        for dpi in &[96, 120, 144, 192] {
+            fonts.acquire(&face("Segoe UI"), Dpi(*dpi));
+        }
+        // over capacity rather than pull a font out from under a window
+        assert_eq!(fonts.len(), 4);
+        assert_eq!(backend.deleted(), Vec::<u32>::new());
+
+        assert!(fonts.release(2));
+        assert!(fonts.release(1));
+        // both unused fonts go to make room, the older first
+        fonts.acquire(&face("Segoe UI"), Dpi(288));
+        assert_eq!(backend.deleted(), vec![1, 2]);
+        assert_eq!(backend.live(), vec![3, 4, 5]);
+        assert!(!fonts.contains(&face("Segoe UI"), Dpi(96)));
+        assert!(fonts.contains(&face("Segoe UI"), Dpi(144)));
+    }
+
+    #[test]
+    fn eviction_is_least_recently_used() {
+        let backend = FakeFonts::default();
+        let mut fonts = DpiFontCache::with_capacity(backend.clone(), 2);
+        let a = fonts.acquire(&face("a"), Dpi(96)).unwrap();
+        let b = fonts.acquire(&face("b"), Dpi(96)).unwrap();
+        fonts.release(a);
+        fonts.release(b);
+        // a is used again, so b is the least recently used
+        let a = fonts.acquire(&face("a"), Dpi(96)).unwrap();
+        fonts.release(a);
+        fonts.acquire(&face("c"), Dpi(96));
+        assert_eq!(backend.deleted(), vec![b]);
+        assert!(fonts.contains(&face("a"), Dpi(96)));
+    }
+
+    #[test]
+    fn release_counts_down_each_use() {
+        let backend = FakeFonts::default();
+        let mut fonts = DpiFontCache::with_capacity(backend.clone(), 1);
+        let font = fonts.acquire(&face("a"), Dpi(96)).unwrap();
+        fonts.acquire(&face("a"), Dpi(96));
+        assert!(fonts.release(font));
+        assert!(!fonts.remove(&face("a"), Dpi(96)));
+        assert!(fonts.release(font));
+        assert!(!fonts.release(font));
+        assert_eq!(fonts.uses(&face("a"), Dpi(96)), 0);
+        // still cached until something else needs the room
+        assert!(fonts.contains(&face("a"), Dpi(96)));
+        assert_eq!(backend.deleted(), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn fonts_from_elsewhere_are_not_released() {
+        let mut fonts = DpiFontCache::new(FakeFonts::default());
+        assert!(!fonts.release(0));
+        assert!(!fonts.release(42));
+    }
+
+    #[test]
+    fn removing_leaves_fonts_in_use() {
+        let backend = FakeFonts::default();
+        let mut fonts = DpiFontCache::new(backend.clone());
+        let a = fonts.acquire(&face("a"), Dpi(144)).unwrap();
+        let b = fonts.acquire(&face("b"), Dpi(144)).unwrap();
+        let c = fonts.acquire(&face("c"), Dpi(96)).unwrap();
+        fonts.release(b);
+        fonts.release(c);
+
+        fonts.remove_dpi(Dpi(144));
+        assert_eq!(backend.deleted(), vec![b]);
+        assert!(!fonts.remove(&face("a"), Dpi(144)));
+        assert!(!fonts.remove(&face("b"), Dpi(144)));
+        assert!(fonts.remove(&face("c"), Dpi(96)));
+        fonts.release(a);
+        assert!(fonts.remove(&face("a"), Dpi(144)));
+        assert_eq!(backend.deleted(), vec![b, c, a]);
+        assert!(fonts.is_empty());
+    }
+
+    #[test]
+    fn clearing_or_dropping_deletes_everything() {
+        let backend = FakeFonts::default();
+        let mut fonts = DpiFontCache::new(backend.clone());
+        fonts.acquire(&face("a"), Dpi(96));
+        fonts.acquire(&face("b"), Dpi(96));
+        fonts.clear();
+        assert!(fonts.is_empty());
+        assert_eq!(backend.live(), Vec::<u32>::new());
+
+        fonts.acquire(&face("c"), Dpi(96));
+        drop(fonts);
+        assert_eq!(backend.live(), Vec::<u32>::new());
+        assert_eq!(backend.0.created.get(), 3);
+    }
+
+    #[test]
+    fn failed_creation_is_not_cached() {
+        let backend = FakeFonts::default();
+        let mut fonts = DpiFontCache::with_capacity(backend.clone(), 0);
+        assert_eq!(fonts.capacity(), 1);
+        backend.0.fail.set(true);
+        assert_eq!(fonts.acquire(&face("a"), Dpi(96)), None);
+        assert!(fonts.is_empty());
+        backend.0.fail.set(false);
+        assert_eq!(fonts.acquire(&face("a"), Dpi(96)), Some(1));
+    }
+}
@@ -0,0 +1,331 @@
+//! Images with a variant per display scale, as packed by
+//! `hidpi_build::embed_images`, and picking the variant for a DPI.
+//!
+//! An image id names a `DPIIMAGE` resource listing the variants; see
+//! `hidpi_build::images` for the layout. The lowest-scale variant is stored
+//! under the image id itself, so plain `LoadBitmapW` still finds an image.
+
+use std::{
+    error::Error,
+    fmt,
+};
+
+use crate::units::Dpi;
+
+/// The resource type of the variant tables.
+pub const RT_DPI_IMAGE: &str = "DPIIMAGE";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    /// An `RT_BITMAP` resource.
+    Bitmap,
+    /// An `RT_GROUP_ICON` resource.
+    Icon,
+    /// A `PNG` resource, the file as is.
+    Png,
+}
+
+impl ImageFormat {
+    fn from_code(code: u16) -> Option<ImageFormat> {
+        match code {
+            1 => Some(ImageFormat::Bitmap),
+            2 => Some(ImageFormat::Icon),
+            3 => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageVariant {
+    /// Percent, 100 being 96 DPI.
+    pub scale: u16,
+    pub format: ImageFormat,
+    /// In pixels. For icons, of the largest image in the group.
+    pub width: u32,
+    pub height: u32,
+    /// The resource name, `None` for the image id itself.
+    pub name: Option<String>,
+}
+
+impl ImageVariant {
+    /// The DPI the variant is drawn for, e.g. 144 for 150%.
+    pub fn dpi(&self) -> Dpi {
+        Dpi(self.scale as u32 * 96 / 100)
+    }
+}
+
+/// The variants of one image, lowest scale first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageTable {
+    pub variants: Vec<ImageVariant>,
+}
+
+impl ImageTable {
+    /// Parse the data of a `DPIIMAGE` resource.
+    pub fn from_bytes(data: &[u8]) -> Result<ImageTable, ImageTableError> {
+        let mut pos = 0;
+        let mut read = |len: usize| -> Result<u32, ImageTableError> {
+            let bytes = data.get(pos..pos + len).ok_or(ImageTableError::Truncated)?;
+            pos += len;
+            Ok(bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u32))
+        };
+
+        let version = read(2)?;
+        if version != 1 {
+            return Err(ImageTableError::Version(version as u16));
+        }
+        let count = read(2)?;
+        let mut variants = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let scale = read(2)? as u16;
+            let format = read(2)? as u16;
+            let format = ImageFormat::from_code(format).ok_or(ImageTableError::Format(format))?;
+            let width = read(4)?;
+            let height = read(4)?;
+            let mut name = Vec::new();
+            loop {
+                match read(2)? {
+                    0 => break,
+                    unit => name.push(unit as u16),
+                }
+            }
+            let name = String::from_utf16(&name).map_err(|_| ImageTableError::Name)?;
+            variants.push(ImageVariant {
+                scale,
+                format,
+                width,
+                height,
+                name: if name.is_empty() { None } else { Some(name) },
+            });
+        }
+        variants.sort_by_key(|variant| variant.scale);
+        Ok(ImageTable { variants })
+    }
+
+    /// The variant to draw at `dpi`: the smallest that is at least as large,
+    /// since scaling down looks better than scaling up, or else the largest.
+    pub fn select(&self, dpi: Dpi) -> Option<&ImageVariant> {
+        select(self.variants.iter(), dpi)
+    }
+
+    /// As `select`, among the variants in `format`.
+    pub fn select_format(&self, dpi: Dpi, format: ImageFormat) -> Option<&ImageVariant> {
+        select(self.variants.iter().filter(|variant| variant.format == format), dpi)
+    }
+}
+
+fn select<'a, I>(variants: I, dpi: Dpi) -> Option<&'a ImageVariant> where I: Iterator<Item = &'a ImageVariant> + Clone {
+    // percent, rounded up so 120 DPI asks for 125%
+    let wanted = (dpi.0 * 100 + 95) / 96;
+    variants.clone()
+        .filter(|variant| variant.scale as u32 >= wanted)
+        .min_by_key(|variant| variant.scale)
+        .or_else(|| variants.max_by_key(|variant| variant.scale))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageTableError {
+    Truncated,
+    Version(u16),
+    Format(u16),
+    Name,
+}
+
+impl fmt::Display for ImageTableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageTableError::Truncated => write!(f, "image table ends early"),
+            ImageTableError::Version(version) => write!(f, "unknown image table version {}", version),
+            ImageTableError::Format(format) => write!(f, "unknown image format {}", format),
+            ImageTableError::Name => write!(f, "image resource name is not UTF-16"),
+        }
+    }
+}
+
+impl Error for ImageTableError {}
+
+#[cfg(windows)]
+pub use self::win::{image_table, load_bitmap, load_bitmap_scaled, load_icon, load_png, resource_data};
+
+#[cfg(windows)]
+mod win {
+    use std::{ptr, slice};
+
+    use winapi::{
+        shared::{
+            minwindef::HINSTANCE,
+            windef::{HBITMAP, HICON},
+        },
+        um::{
+            libloaderapi,
+            winuser::{self, IMAGE_BITMAP, IMAGE_ICON, LR_CREATEDIBSECTION, MAKEINTRESOURCEW, RT_BITMAP},
+        },
+    };
+
+    use super::{ImageFormat, ImageTable, ImageVariant, RT_DPI_IMAGE};
+    use crate::{bitmap::{Bitmap, Filter}, to_wstring, units::{Dpi, Logical}};
+
+    /// The data of a resource in `h_inst`. Resources are mapped for as long as
+    /// the module is loaded.
+    ///
+    /// # Safety
+    /// `h_inst` must stay loaded while the data is used.
+    pub unsafe fn resource_data(h_inst: HINSTANCE, name: *const u16, resource_type: *const u16) -> Option<&'static [u8]> {
+        let h_res = libloaderapi::FindResourceW(h_inst, name, resource_type);
+        if h_res.is_null() {
+            return None;
+        }
+        let h_global = libloaderapi::LoadResource(h_inst, h_res);
+        let data = libloaderapi::LockResource(h_global);
+        if data.is_null() {
+            return None;
+        }
+        Some(slice::from_raw_parts(data as *const u8, libloaderapi::SizeofResource(h_inst, h_res) as usize))
+    }
+
+    /// The variant table of image `id`, or `None` if it was not packed with
+    /// variants.
+    pub fn image_table(h_inst: HINSTANCE, id: u16) -> Option<ImageTable> {
+        let resource_type = to_wstring(RT_DPI_IMAGE);
+        let data = unsafe { resource_data(h_inst, MAKEINTRESOURCEW(id), resource_type.as_ptr()) }?;
+        ImageTable::from_bytes(data).ok()
+    }
+
+    // The variant of `id` in `format` to use at `dpi` and its resource name;
+    // the image id itself when there is no table
+    fn select_variant(h_inst: HINSTANCE, id: u16, dpi: Dpi, format: ImageFormat) -> (Option<ImageVariant>, Option<Vec<u16>>) {
+        let variant = image_table(h_inst, id)
+            .and_then(|table| table.select_format(dpi, format).cloned());
+        let name = variant.as_ref().and_then(|variant| variant.name.as_ref()).map(to_wstring);
+        (variant, name)
+    }
+
+    fn resource_name(id: u16, name: &Option<Vec<u16>>) -> *const u16 {
+        name.as_ref().map_or(MAKEINTRESOURCEW(id) as *const u16, |name| name.as_ptr())
+    }
+
+    /// Load the variant of bitmap `id` that fits `dpi`, as a DIB section.
+    /// Returns null if there is no such bitmap.
+    pub fn load_bitmap(h_inst: HINSTANCE, id: u16, dpi: Dpi) -> HBITMAP {
+        let (_, name) = select_variant(h_inst, id, dpi, ImageFormat::Bitmap);
+        let name = resource_name(id, &name);
+        unsafe { winuser::LoadImageW(h_inst, name, IMAGE_BITMAP, 0, 0, LR_CREATEDIBSECTION) as HBITMAP }
+    }
+
+    /// Decode the variant of bitmap `id` that fits `dpi` and resample it with
+    /// `filter` from the DPI it was drawn for to `dpi`, as a DIB section.
+    /// Returns null if there is no such bitmap or it can't be decoded.
+    pub fn load_bitmap_scaled(h_inst: HINSTANCE, id: u16, dpi: Dpi, filter: Filter) -> HBITMAP {
+        let (variant, name) = select_variant(h_inst, id, dpi, ImageFormat::Bitmap);
+        let name = resource_name(id, &name);
+        let bitmap = unsafe { resource_data(h_inst, name, RT_BITMAP) }
+            .and_then(|data| Bitmap::from_dib(data).ok());
+        match bitmap {
+            Some(bitmap) => {
+                let drawn_for = variant.map_or(Dpi::DEFAULT, |variant| variant.dpi());
+                bitmap.scale(drawn_for, dpi, filter).to_dib_section()
+            },
+            None => ptr::null_mut(),
+        }
+    }
+
+    /// Load the variant of icon `id` that fits `dpi`, at `size` scaled to
+    /// `dpi`. Returns null if there is no such icon.
+    pub fn load_icon(h_inst: HINSTANCE, id: u16, dpi: Dpi, size: Logical<i32>) -> HICON {
+        let (_, name) = select_variant(h_inst, id, dpi, ImageFormat::Icon);
+        let name = resource_name(id, &name);
+        let size = size.scale(dpi).0;
+        unsafe { winuser::LoadImageW(h_inst, name, IMAGE_ICON, size, size, 0) as HICON }
+    }
+
+    /// The file data of the PNG variant of image `id` that fits `dpi`.
+    pub fn load_png(h_inst: HINSTANCE, id: u16, dpi: Dpi) -> Option<(ImageVariant, &'static [u8])> {
+        let (variant, name) = select_variant(h_inst, id, dpi, ImageFormat::Png);
+        let variant = variant?;
+        let name = resource_name(id, &name);
+        let resource_type = to_wstring("PNG");
+        let data = unsafe { resource_data(h_inst, name, resource_type.as_ptr()) }?;
+        Some((variant, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // As packed by hidpi-build from logo.bmp and its 125, 150 and 200% variants
+    const LOGO_TABLE: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/../hidpi-build/tests/fixtures/images/IDB_LOGO.dpiimage"));
+
+    fn variant(scale: u16, format: ImageFormat) -> ImageVariant {
+        ImageVariant { scale, format, width: scale as u32, height: scale as u32, name: Some(format!("V{}", scale)) }
+    }
+
+    fn selected(table: &ImageTable, dpi: u32) -> Option<u16> {
+        table.select(Dpi(dpi)).map(|variant| variant.scale)
+    }
+
+    #[test]
+    fn tables_parse_as_packed() {
+        let table = ImageTable::from_bytes(LOGO_TABLE).unwrap();
+        assert_eq!(table.variants.len(), 4);
+        assert_eq!(table.variants[0], ImageVariant { scale: 100, format: ImageFormat::Bitmap, width: 8, height: 4, name: None });
+        assert_eq!(table.variants[2], ImageVariant {
+            scale: 150,
+            format: ImageFormat::Bitmap,
+            width: 12,
+            height: 6,
+            name: Some("DPI_IDB_LOGO_150".to_owned()),
+        });
+        assert_eq!(table.variants.iter().map(ImageVariant::dpi).collect::<Vec<_>>(), [Dpi(96), Dpi(120), Dpi(144), Dpi(192)]);
+    }
+
+    #[test]
+    fn malformed_tables_are_errors() {
+        for len in 0..LOGO_TABLE.len() {
+            assert_eq!(ImageTable::from_bytes(&LOGO_TABLE[..len]), Err(ImageTableError::Truncated), "{}", len);
+        }
+
+        let mut table = LOGO_TABLE.to_vec();
+        table[0] = 2;
+        assert_eq!(ImageTable::from_bytes(&table), Err(ImageTableError::Version(2)));
+
+        let mut table = LOGO_TABLE.to_vec();
+        table[6] = 4;
+        assert_eq!(ImageTable::from_bytes(&table), Err(ImageTableError::Format(4)));
+
+        // an unpaired surrogate in the name of the 125% variant
+        let mut table = LOGO_TABLE.to_vec();
+        table[36..38].copy_from_slice(&0xDC00_u16.to_le_bytes());
+        assert_eq!(ImageTable::from_bytes(&table), Err(ImageTableError::Name));
+    }
+
+    #[test]
+    fn the_smallest_variant_at_least_as_large_is_selected() {
+        let table = ImageTable::from_bytes(LOGO_TABLE).unwrap();
+        for &(dpi, scale) in &[(72, 100), (96, 100), (97, 125), (120, 125), (121, 150), (144, 150), (168, 200), (192, 200), (288, 200)] {
+            assert_eq!(selected(&table, dpi), Some(scale), "{} DPI", dpi);
+        }
+
+        // variants in any order, with gaps
+        let table = ImageTable { variants: vec![variant(300, ImageFormat::Png), variant(100, ImageFormat::Png), variant(175, ImageFormat::Png)] };
+        assert_eq!(selected(&table, 96), Some(100));
+        assert_eq!(selected(&table, 120), Some(175));
+        assert_eq!(selected(&table, 168), Some(175));
+        assert_eq!(selected(&table, 192), Some(300));
+        assert_eq!(selected(&table, 480), Some(300));
+
+        assert_eq!(selected(&ImageTable::default(), 96), None);
+    }
+
+    #[test]
+    fn select_format_ignores_other_formats() {
+        let table = ImageTable {
+            variants: vec![variant(100, ImageFormat::Bitmap), variant(150, ImageFormat::Icon), variant(200, ImageFormat::Bitmap)],
+        };
+        assert_eq!(table.select(Dpi(144)).map(|variant| variant.format), Some(ImageFormat::Icon));
+        assert_eq!(table.select_format(Dpi(144), ImageFormat::Bitmap).map(|variant| variant.scale), Some(200));
+        assert_eq!(table.select_format(Dpi(96), ImageFormat::Icon).map(|variant| variant.scale), Some(150));
+        assert_eq!(table.select_format(Dpi(96), ImageFormat::Png), None);
+    }
+}
@@ -1,52 +1,55 @@
+//! DPI queries and helpers.
+//!
+//! `get_system_dpi`, `get_window_dpi`, `get_scale_factor`, the `units`,
+//! `monitor` and `sim` models, the `context` names, the `font` cache, the
+//! `dialog` and `menu` templates, `image` variant selection and `bitmap`
+//! decoding work on every platform. So do the Win32 wrappers in the crate
+//! root and the `backend`, `ffi`, `scope` and `awareness` modules, written in
+//! the `sys` types: without Windows every function they load is missing
+//! unless a mock `backend::DpiBackend` is installed.
+
+pub mod sys;
 pub mod win;
+pub mod backend;
+pub mod ffi;
+pub mod scope;
+pub mod awareness;
+mod win32;
+#[cfg(test)]
+mod mock;
+#[cfg(target_os = "linux")]
+pub mod linux;
+pub mod units;
+pub mod context;
+pub mod monitor;
+pub mod sim;
+pub mod font;
+pub mod dialog;
+pub mod menu;
+pub mod image;
+pub mod bitmap;
+
+pub use scope::ThreadDpiScope;
+pub use awareness::{enable_dpi_awareness, Applied, Attempt, AttemptError, AwarenessApi};
+pub use win32::*;
+#[cfg(target_os = "linux")]
+pub use linux::NativeWindow;
+pub use units::{Dpi, Logical, Physical, Point, Size, Rect};
+pub use context::{DpiAwarenessContext, DpiHostingBehavior, ParseDpiAwarenessContextError};
+pub use monitor::{Monitor, VirtualDesktop};
+pub use font::{DpiFontCache, FontBackend, FontDescription};
+pub use dialog::{ControlClass, DialogControl, DialogFont, DialogTemplate, TemplateId};
+pub use menu::{Accelerator, Menu, MenuCommand, MenuError, MenuItem, MenuParseError, MenuPopup, ParseAcceleratorError};
+pub use image::{ImageFormat, ImageTable, ImageTableError, ImageVariant};
+pub use bitmap::{Bitmap, DibError, Filter};
+#[cfg(windows)]
+pub use font::GdiFonts;
+#[cfg(windows)]
+pub use monitor::{monitors, monitor_for_rect, virtual_desktop};
 
-use std::ffi::OsStr;
-use std::os::windows::ffi::OsStrExt;
 use std::iter::once;
-use std::ptr;
-use std::io;
-use libc::size_t;
-use winapi::{
-    ctypes::wchar_t,
-	shared::{
-        minwindef::{
-            BOOL,
-            TRUE,
-            FALSE,
-            UINT,
-            INT,
-            WPARAM,
-            LPARAM,
-            LRESULT,
-            HINSTANCE,
-         },
-        windef::{
-            POINT,
-            RECT,
-            HWND,
-            HFONT,
-            HBRUSH,
-            DPI_AWARENESS,
-            DPI_AWARENESS_INVALID,
-            DPI_AWARENESS_SYSTEM_AWARE,
-            DPI_AWARENESS_PER_MONITOR_AWARE,
-            DPI_AWARENESS_UNAWARE,
-            DPI_AWARENESS_CONTEXT,
-            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
-            DPI_AWARENESS_CONTEXT_UNAWARE,
-            DPI_HOSTING_BEHAVIOR,
-        }
-    },
-	um::{
-        winuser::{
-            self,
-            HWND_DESKTOP,
-        },
-        wingdi,
-    },
-};
 
-pub const GA_PARENT: UINT = 1;
+pub const USER_DEFAULT_SCREEN_DPI: u32 = 96;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DpiAwareness {
@@ -60,210 +63,166 @@ pub fn make_l_param(l: u16, h: u16) -> u32 {
     (l as u32) | ((h as u32) << 16)
 }
 
-pub fn is_process_dpi_aware() -> Option<bool> {
-	win::is_process_dpi_aware()
+/// The DPI of the primary display, which is what DPI unaware and system aware
+/// windows are laid out for.
+#[cfg(windows)]
+pub fn get_system_dpi() -> Dpi {
+    Dpi(get_dpi_for_system())
 }
 
-pub fn get_process_dpi_awareness() -> io::Result<Option<DpiAwareness>> {
-	Ok(win::get_process_dpi_awareness()?.map(|awareness| {
-		match awareness {
-			win::WinDpiAwareness::ProcessDpiUnaware => DpiAwareness::Unaware,
-			win::WinDpiAwareness::ProcessSystemDpiAware => DpiAwareness::System,
-			win::WinDpiAwareness::ProcessPerMonitorDpiAware => DpiAwareness::PerMonitor,
-			win::WinDpiAwareness::Unknown(_o) => DpiAwareness::Other,
-		}
-	}))
+/// The DPI of the primary display, see `linux` for where it comes from.
+#[cfg(target_os = "linux")]
+pub fn get_system_dpi() -> Dpi {
+    linux::system_dpi()
 }
 
-pub fn set_process_dpi_aware() -> Option<bool> {
-	win::set_process_dpi_aware()
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn get_system_dpi() -> Dpi {
+    Dpi::DEFAULT
 }
 
-pub fn set_process_dpi_awareness(awareness: DpiAwareness) -> io::Result<bool> {
-	let win_awareness = match awareness {
-		DpiAwareness::Unaware => Ok(win::WinDpiAwareness::ProcessDpiUnaware),
-		DpiAwareness::System => Ok(win::WinDpiAwareness::ProcessSystemDpiAware),
-		DpiAwareness::PerMonitor => Ok(win::WinDpiAwareness::ProcessPerMonitorDpiAware),
-		_ => Err(io::Error::new(io::ErrorKind::Other, format!("unsupported dpi awareness type {:?}", awareness)))
-	}?;
-
-	win::set_process_dpi_awareness(win_awareness)
+/// The DPI a window should render at.
+#[cfg(windows)]
+pub fn get_window_dpi(window: NativeWindow) -> Dpi {
+    Dpi(get_dpi_for_window(window))
 }
 
-#[no_mangle]
-pub extern "C" fn get_parent_relative_window_rect(h_wnd: HWND, child_bounds: *mut RECT) -> BOOL
-{
-    if FALSE == unsafe { winuser::GetWindowRect(h_wnd, child_bounds) }
-    {
-        return FALSE;
-    }
-    
-    unsafe { winuser::MapWindowPoints(HWND_DESKTOP, winuser::GetAncestor(h_wnd, GA_PARENT), child_bounds as *mut POINT, 2); }
-
-    return TRUE;
+/// The DPI a window should render at.
+#[cfg(target_os = "linux")]
+pub fn get_window_dpi(window: NativeWindow) -> Dpi {
+    linux::window_dpi(window)
 }
 
-#[no_mangle]
-pub extern "C" fn get_stock_brush(brush: UINT) -> HBRUSH {
-    unsafe { wingdi::GetStockObject(brush as INT) as HBRUSH }
-}
+/// The window handle `get_window_dpi` takes where there is no DPI source
+/// besides the system one.
+#[cfg(not(any(windows, target_os = "linux")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NativeWindow;
 
-#[no_mangle]
-pub extern "C" fn get_hinstance_for_h_wnd(h_wnd: HWND) -> HINSTANCE {
-    unsafe { winuser::GetWindowLongW(h_wnd, winuser::GWL_HINSTANCE) as HINSTANCE }
+/// The DPI a window should render at, which is always the system DPI here.
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn get_window_dpi(_window: NativeWindow) -> Dpi {
+    get_system_dpi()
 }
 
-#[no_mangle]
-pub extern "C" fn get_window_font(h_wnd: HWND) -> HFONT {
-    unsafe  {  winuser::SendMessageW(h_wnd, winuser::WM_GETFONT, 0, 0) as HFONT }
+/// The system DPI as a scale factor relative to 96 DPI, e.g. 1.5 for 144 DPI.
+pub fn get_scale_factor() -> f64 {
+    get_system_dpi().scale_factor()
 }
 
-#[no_mangle]
-pub extern "C" fn set_window_font(h_wnd: HWND, h_font: HFONT, f_redraw: BOOL) -> LRESULT {
-    unsafe { winuser::SendMessageW(h_wnd, winuser::WM_SETFONT, h_font as WPARAM, f_redraw as LPARAM) }
-}
-
-#[no_mangle]
-pub extern "C" fn get_dpi_for_system() -> UINT {
-    unsafe { winuser::GetDpiForSystem() }
-}
-
-#[no_mangle]
-pub extern "C" fn get_dpi_for_window(h_wnd: HWND) -> UINT {
-    unsafe { winuser::GetDpiForWindow(h_wnd) }
+pub fn to_wstring<S>(s: S) -> Vec<u16> where S: AsRef<str> {
+    s.as_ref()
+        .encode_utf16()
+        .chain(once(0))
+        .collect()
 }
 
-pub fn get_maybe_dpi_by_awareness(h_wnd: HWND) -> Option<UINT> {
-    match get_thread_dpi_awareness() {
-        DPI_AWARENESS_SYSTEM_AWARE => {
-            Some(get_dpi_for_system())
-        },
-        DPI_AWARENESS_PER_MONITOR_AWARE => {
-            Some(get_dpi_for_window(h_wnd))
-        },
-        _ => None
+/// Copy `s` into `buffer` as a NUL-terminated UTF-16 string.
+///
+/// Returns the number of code units written, not counting the terminator. If
+/// `buffer` is too small nothing is written and the required capacity,
+/// including the terminator, is returned as the error.
+pub fn copy_to_wide(buffer: &mut [u16], s: &str) -> Result<usize, usize> {
+    let len = s.encode_utf16().count();
+    if buffer.len() <= len {
+        return Err(len + 1);
     }
-}
 
-#[no_mangle]
-pub extern "C" fn get_dpi_by_awareness(h_wnd: HWND, ret: *mut UINT) -> BOOL {
-    if let Some(dpi) = get_maybe_dpi_by_awareness(h_wnd) {
-        unsafe {
-            *ret = dpi;
-            TRUE
-        }
-    } else {
-        FALSE
+    for (dst, src) in buffer.iter_mut().zip(s.encode_utf16()) {
+        *dst = src;
     }
-}
-
-#[no_mangle]
-pub extern "C" fn are_dpi_awareness_contexts_equal(a: DPI_AWARENESS_CONTEXT, b: DPI_AWARENESS_CONTEXT) -> BOOL {
-    win::are_dpi_awareness_contexts_equal(a, b)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "no supported"))
-        .expect("are_dpi_awareness_contexts_equal")
-}
-
-#[no_mangle]
-pub extern "C" fn get_thread_dpi_awareness_context() -> DPI_AWARENESS_CONTEXT {
-    win::get_thread_dpi_awareness_context()
-        .unwrap_or(DPI_AWARENESS_CONTEXT_UNAWARE)
-}
-
-#[no_mangle]
-pub extern "C" fn get_thread_dpi_awareness() -> DPI_AWARENESS {
-    win::get_thread_dpi_awareness_context()
-        .and_then(win::get_awareness_from_dpi_awareness_context)
-        .unwrap_or(DPI_AWARENESS_UNAWARE)
-}
+    buffer[len] = 0;
 
-#[no_mangle]
-pub extern "C" fn get_awareness_from_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT) -> DPI_AWARENESS {
-    win::get_awareness_from_dpi_awareness_context(context)
-        .unwrap_or(DPI_AWARENESS_UNAWARE)
+    Ok(len)
 }
 
-#[no_mangle]
-pub extern "C" fn get_thread_dpi_hosting_behavior() -> DPI_HOSTING_BEHAVIOR {
-    win::get_thread_dpi_hosting_behavior().unwrap()
-}
-
-#[no_mangle]
-pub extern "C" fn set_thread_dpi_hosting_behavior(behavior: DPI_HOSTING_BEHAVIOR) -> DPI_HOSTING_BEHAVIOR {
-    win::set_thread_dpi_hosting_behavior(behavior).unwrap()
-}
-
-#[no_mangle]
-pub extern "C" fn set_thread_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT) -> DPI_AWARENESS_CONTEXT {
-    win::set_thread_dpi_awareness_context(context).unwrap()
-}
+/// Copy `s` into `buffer` as a NUL-terminated UTF-8 string, with the same
+/// return convention as `copy_to_wide`.
+pub fn copy_to_utf8(buffer: &mut [u8], s: &str) -> Result<usize, usize> {
+    let bytes = s.as_bytes();
+    if buffer.len() <= bytes.len() {
+        return Err(bytes.len() + 1);
+    }
 
-pub fn awareness_to_str(awareness: DPI_AWARENESS) -> &'static str {
-    match awareness {
-        DPI_AWARENESS_INVALID => {
-           "DPI_AWARENESS_INVALID"
-        },
-        DPI_AWARENESS_SYSTEM_AWARE => {
-            "DPI_AWARENESS_SYSTEM_AWARE"
-        },
-        DPI_AWARENESS_PER_MONITOR_AWARE => {
-            "DPI_AWARENESS_PER_MONITOR_AWARE"
-        },
-        _ => {
-            "DPI_AWARENESS_UNAWARE"
+    buffer[..bytes.len()].copy_from_slice(bytes);
+    buffer[bytes.len()] = 0;
+
+    Ok(bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ASCII, two and three byte UTF-8, and a character outside the BMP that
+    // takes a surrogate pair in UTF-16, alone and at either end of a string
+    const STRINGS: &[&str] = &["", "a", "DPI_AWARENESS_UNAWARE", "é", "€uro", "𝄞", "x𝄞", "𝄞x", "aé€𝄞"];
+
+    const FILL: u16 = 0xaaaa;
+
+    #[test]
+    fn copy_to_wide_fits_or_reports_the_capacity() {
+        for s in STRINGS {
+            let wide: Vec<u16> = s.encode_utf16().collect();
+            for capacity in 0..wide.len() + 3 {
+                let mut buffer = vec![FILL; capacity];
+                match copy_to_wide(&mut buffer, s) {
+                    Ok(written) => {
+                        assert!(capacity > wide.len(), "{:?} into {}", s, capacity);
+                        assert_eq!(written, wide.len());
+                        assert_eq!(&buffer[..written], &wide[..]);
+                        assert_eq!(buffer[written], 0);
+                        assert!(buffer[written + 1..].iter().all(|&c| c == FILL));
+                    },
+                    Err(required) => {
+                        assert!(capacity <= wide.len(), "{:?} into {}", s, capacity);
+                        assert_eq!(required, wide.len() + 1);
+                        assert!(buffer.iter().all(|&c| c == FILL), "{:?} partly written", s);
+                    },
+                }
+            }
         }
     }
-}
-
-#[no_mangle]
-pub extern "C" fn format_awareness(lpwstr: *mut wchar_t, len: size_t, awareness: DPI_AWARENESS) -> i32 {
-    string_copy(lpwstr, len, awareness_to_str(awareness));
-    0
-}
 
-pub fn awareness_context_to_str(context: DPI_AWARENESS_CONTEXT) -> &'static str {
-    let awareness = get_awareness_from_dpi_awareness_context(context);
-    match awareness {
-        DPI_AWARENESS_SYSTEM_AWARE => {
-            "DPI_AWARENESS_CONTEXT_SYSTEM_AWARE"
-        },
-        DPI_AWARENESS_PER_MONITOR_AWARE => {
-            if let Some(isequal) = win::are_dpi_awareness_contexts_equal(context, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) {
-                if isequal == TRUE {
-                    "DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2"
-                } else {
-                    "DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE"
+    #[test]
+    fn copy_to_utf8_fits_or_reports_the_capacity() {
+        for s in STRINGS {
+            let bytes = s.as_bytes();
+            for capacity in 0..bytes.len() + 3 {
+                let mut buffer = vec![FILL as u8; capacity];
+                match copy_to_utf8(&mut buffer, s) {
+                    Ok(written) => {
+                        assert!(capacity > bytes.len(), "{:?} into {}", s, capacity);
+                        assert_eq!(written, bytes.len());
+                        assert_eq!(&buffer[..written], bytes);
+                        assert_eq!(buffer[written], 0);
+                        assert!(buffer[written + 1..].iter().all(|&c| c == FILL as u8));
+                    },
+                    Err(required) => {
+                        assert!(capacity <= bytes.len(), "{:?} into {}", s, capacity);
+                        assert_eq!(required, bytes.len() + 1);
+                        assert!(buffer.iter().all(|&c| c == FILL as u8), "{:?} partly written", s);
+                    },
                 }
-            } else { 
-                "DPI_AWARENESS_CONTEXT_UNAWARE"
             }
-        },
-        _ => {
-            "DPI_AWARENESS_CONTEXT_UNAWARE"
         }
     }
-}
 
-#[no_mangle]
-pub extern "C" fn format_awareness_context(lpwstr: *mut wchar_t, len: size_t, context: DPI_AWARENESS_CONTEXT) -> i32 {
-    string_copy(lpwstr, len, awareness_context_to_str(context));
-    0
-}
-
-pub fn to_wstring<S>(s: S) -> Vec<u16> where S: AsRef<str> {
-    OsStr::new(s.as_ref())
-        .encode_wide()
-        .chain(once(0))
-        .collect()
-}
+    #[test]
+    fn a_surrogate_pair_is_never_split() {
+        // room for the high surrogate but not the low one and the terminator
+        let mut buffer = [FILL; 2];
+        assert_eq!(copy_to_wide(&mut buffer, "𝄞"), Err(3));
+        assert_eq!(buffer, [FILL; 2]);
 
-pub extern "C" fn string_copy(lpwstr: *mut wchar_t, capacity: size_t, s: &str) -> size_t {
-    let w: Vec<u16> = to_wstring(s);
-    let len = std::cmp::min(capacity - 1, w.len());
-    
-    unsafe {
-        ptr::copy_nonoverlapping(w.as_ptr(), lpwstr, len + 1);
+        let mut buffer = [FILL; 3];
+        assert_eq!(copy_to_wide(&mut buffer, "𝄞"), Ok(2));
+        assert_eq!(buffer, [0xd834, 0xdd1e, 0]);
     }
 
-    len
-}
\ No newline at end of file
+    #[test]
+    fn to_wstring_is_terminated() {
+        assert_eq!(to_wstring(""), [0]);
+        assert_eq!(to_wstring("é𝄞"), [0xe9, 0xd834, 0xdd1e, 0]);
+    }
+}
@@ -0,0 +1,351 @@
+//! DPI detection for X11 and Wayland desktops.
+//!
+//! There is no single system setting on Linux, so the DPI is resolved from the
+//! following sources. The first one that is present and valid is used:
+//!
+//! 1. The window's Wayland `wp_fractional_scale_v1` preferred scale. The
+//!    compositor sends it per surface, so it is correct for the output the
+//!    window is on. It only exists for windows, never for the system DPI.
+//! 2. `GDK_SCALE`, an integer scale forced for GTK applications.
+//! 3. `QT_SCALE_FACTOR`, a fractional scale forced for Qt applications.
+//! 4. The `Xft.dpi` X resource, which desktop environments set from their
+//!    scaling or font DPI setting.
+//! 5. 96 DPI.
+//!
+//! The environment variables are explicit overrides from the user or session,
+//! so they win over `Xft.dpi`. A value that does not parse, or is not
+//! positive, is skipped as though it was not set.
+//!
+//! Where the values come from is abstracted behind `Environment` so that the
+//! rules can be exercised with any combination of them.
+
+use declare_macro::declare_functions;
+use std::env;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::sync::OnceLock;
+
+use crate::Dpi;
+
+/// The settings the DPI is resolved from.
+pub trait Environment {
+    /// An environment variable.
+    fn var(&self, name: &str) -> Option<String>;
+    /// An X resource from the `RESOURCE_MANAGER` database, e.g. `Xft.dpi`.
+    fn x_resource(&self, name: &str) -> Option<String>;
+}
+
+/// The process environment and the X server's resource database, the
+/// `RESOURCE_MANAGER` property of the root window that `xrdb -query` lists.
+/// No resources are read unless `DISPLAY` is set and libX11 can be loaded.
+///
+/// The database is queried the first time a resource is asked for and kept
+/// for the life of the `SystemEnvironment`. `system_dpi` and `window_dpi`
+/// share one for the whole process; make a new one to see a changed `Xft.dpi`.
+#[derive(Debug)]
+pub struct SystemEnvironment {
+    query: fn() -> Option<String>,
+    x_resources: OnceLock<Option<String>>,
+}
+
+impl SystemEnvironment {
+    pub fn new() -> SystemEnvironment {
+        SystemEnvironment::with_query(query_x_resources)
+    }
+
+    /// Reads the resource database with `query` instead of from the X server,
+    /// for a caller that has its own connection or a database of its own.
+    pub fn with_query(query: fn() -> Option<String>) -> SystemEnvironment {
+        SystemEnvironment { query, x_resources: OnceLock::new() }
+    }
+}
+
+impl Default for SystemEnvironment {
+    fn default() -> SystemEnvironment {
+        SystemEnvironment::new()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref LIBX11: Option<libloading::Library> = libloading::Library::new("libX11.so.6").ok();
+    static ref SYSTEM: SystemEnvironment = SystemEnvironment::new();
+}
+
+// Only the loaded symbols are called, so linking never needs libX11
+declare_functions! {
+    extern "C" {
+        #[library(LIBX11)]
+        #[allow(dead_code)]
+        pub(crate) fn XOpenDisplay(name: *const c_char) -> *mut c_void;
+        #[library(LIBX11)]
+        #[allow(dead_code)]
+        pub(crate) fn XResourceManagerString(display: *mut c_void) -> *mut c_char;
+        #[library(LIBX11)]
+        #[allow(dead_code)]
+        pub(crate) fn XCloseDisplay(display: *mut c_void) -> c_int;
+    }
+}
+
+// Xlib reads RESOURCE_MANAGER when it opens the display
+fn query_x_resources() -> Option<String> {
+    env::var_os("DISPLAY")?;
+    let open = dynamic::XOpenDisplay.as_ref()?;
+    let resources = dynamic::XResourceManagerString.as_ref()?;
+    let close = dynamic::XCloseDisplay.as_ref()?;
+
+    unsafe {
+        let display = open(ptr::null());
+        if display.is_null() {
+            return None;
+        }
+        let database = resources(display);
+        let database = if database.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(database).to_string_lossy().into_owned())
+        };
+        close(display);
+        database
+    }
+}
+
+impl Environment for SystemEnvironment {
+    fn var(&self, name: &str) -> Option<String> {
+        env::var(name).ok()
+    }
+
+    fn x_resource(&self, name: &str) -> Option<String> {
+        let database = self.x_resources.get_or_init(self.query).as_ref()?;
+        find_x_resource(database, name)
+    }
+}
+
+/// Look up `name` in resource database text of `name:\tvalue` lines.
+pub fn find_x_resource(database: &str, name: &str) -> Option<String> {
+    database.lines()
+        .filter_map(|line| {
+            let colon = line.find(':')?;
+            Some((line[..colon].trim(), line[colon + 1..].trim()))
+        })
+        .find(|(key, _value)| *key == name)
+        .map(|(_key, value)| value.to_owned())
+}
+
+/// A `wp_fractional_scale_v1.preferred_scale`: the scale multiplied by 120.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FractionalScale(pub u32);
+
+impl FractionalScale {
+    pub const DENOMINATOR: u32 = 120;
+
+    pub fn scale_factor(self) -> f64 {
+        self.0 as f64 / Self::DENOMINATOR as f64
+    }
+
+    pub fn dpi(self) -> Dpi {
+        Dpi::from_scale_factor(self.scale_factor())
+    }
+}
+
+/// What the window is showing on, as far as `get_window_dpi` needs to know.
+///
+/// There is no portable way to ask a Wayland compositor for a surface's scale
+/// after the fact, so the toolkit that owns the surface records the last
+/// `preferred_scale` event here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NativeWindow {
+    pub fractional_scale: Option<FractionalScale>,
+}
+
+/// Which source a DPI was resolved from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScaleSource {
+    WaylandFractionalScale,
+    GdkScale,
+    QtScaleFactor,
+    XftDpi,
+    Default,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolved {
+    pub dpi: Dpi,
+    pub source: ScaleSource,
+}
+
+fn gdk_scale(env: &dyn Environment) -> Option<Dpi> {
+    let scale: u32 = env.var("GDK_SCALE")?.trim().parse().ok()?;
+    if scale == 0 {
+        return None;
+    }
+    Some(Dpi(Dpi::DEFAULT.0.saturating_mul(scale)))
+}
+
+fn positive_dpi(value: &str, per_unit: f64) -> Option<Dpi> {
+    let value: f64 = value.trim().parse().ok()?;
+    if !value.is_finite() || value <= 0.0 {
+        return None;
+    }
+    match Dpi::from_scale_factor(value * per_unit) {
+        Dpi(0) => None,
+        dpi => Some(dpi),
+    }
+}
+
+fn qt_scale_factor(env: &dyn Environment) -> Option<Dpi> {
+    positive_dpi(&env.var("QT_SCALE_FACTOR")?, 1.0)
+}
+
+fn xft_dpi(env: &dyn Environment) -> Option<Dpi> {
+    positive_dpi(&env.x_resource("Xft.dpi")?, 1.0 / Dpi::DEFAULT.0 as f64)
+}
+
+/// Resolve the DPI following the priority rules in the module documentation.
+/// `surface` is the window's fractional scale, or `None` for the system DPI.
+pub fn resolve(env: &dyn Environment, surface: Option<FractionalScale>) -> Resolved {
+    let wayland = surface
+        .filter(|scale| scale.0 > 0)
+        .map(|scale| (scale.dpi(), ScaleSource::WaylandFractionalScale));
+
+    let (dpi, source) = wayland
+        .or_else(|| gdk_scale(env).map(|dpi| (dpi, ScaleSource::GdkScale)))
+        .or_else(|| qt_scale_factor(env).map(|dpi| (dpi, ScaleSource::QtScaleFactor)))
+        .or_else(|| xft_dpi(env).map(|dpi| (dpi, ScaleSource::XftDpi)))
+        .unwrap_or((Dpi::DEFAULT, ScaleSource::Default));
+
+    Resolved { dpi, source }
+}
+
+pub fn system_dpi() -> Dpi {
+    resolve(&*SYSTEM, None).dpi
+}
+
+pub fn window_dpi(window: NativeWindow) -> Dpi {
+    resolve(&*SYSTEM, window.fractional_scale).dpi
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeEnvironment {
+        vars: HashMap<&'static str, &'static str>,
+        resources: HashMap<&'static str, &'static str>,
+        resource_reads: Cell<usize>,
+    }
+
+    impl FakeEnvironment {
+        fn var(mut self, name: &'static str, value: &'static str) -> FakeEnvironment {
+            self.vars.insert(name, value);
+            self
+        }
+
+        fn xft_dpi(mut self, value: &'static str) -> FakeEnvironment {
+            self.resources.insert("Xft.dpi", value);
+            self
+        }
+    }
+
+    impl Environment for FakeEnvironment {
+        fn var(&self, name: &str) -> Option<String> {
+            self.vars.get(name).map(|value| value.to_string())
+        }
+
+        fn x_resource(&self, name: &str) -> Option<String> {
+            self.resource_reads.set(self.resource_reads.get() + 1);
+            self.resources.get(name).map(|value| value.to_string())
+        }
+    }
+
+    fn resolved(dpi: u32, source: ScaleSource) -> Resolved {
+        Resolved { dpi: Dpi(dpi), source }
+    }
+
+    #[test]
+    fn nothing_set_is_96_dpi() {
+        assert_eq!(resolve(&FakeEnvironment::default(), None), resolved(96, ScaleSource::Default));
+    }
+
+    #[test]
+    fn xft_dpi_is_used_as_is() {
+        assert_eq!(resolve(&FakeEnvironment::default().xft_dpi("144"), None), resolved(144, ScaleSource::XftDpi));
+        assert_eq!(resolve(&FakeEnvironment::default().xft_dpi(" 120.4 "), None), resolved(120, ScaleSource::XftDpi));
+        for bad in &["", "0", "-96", "NaN", "inf", "ninety-six"] {
+            assert_eq!(resolve(&FakeEnvironment::default().xft_dpi(bad), None), resolved(96, ScaleSource::Default), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn gdk_scale_is_a_whole_multiple() {
+        let env = FakeEnvironment::default().var("GDK_SCALE", "2").xft_dpi("144");
+        assert_eq!(resolve(&env, None), resolved(192, ScaleSource::GdkScale));
+        assert_eq!(env.resource_reads.get(), 0);
+
+        for bad in &["0", "1.5", "-2", "two"] {
+            let env = FakeEnvironment::default().var("GDK_SCALE", bad).xft_dpi("120");
+            assert_eq!(resolve(&env, None), resolved(120, ScaleSource::XftDpi), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn qt_scale_factor_is_fractional_and_below_gdk_scale() {
+        let env = FakeEnvironment::default().var("QT_SCALE_FACTOR", "1.25").xft_dpi("192");
+        assert_eq!(resolve(&env, None), resolved(120, ScaleSource::QtScaleFactor));
+
+        let env = FakeEnvironment::default().var("QT_SCALE_FACTOR", "1.25").var("GDK_SCALE", "3");
+        assert_eq!(resolve(&env, None), resolved(288, ScaleSource::GdkScale));
+
+        let env = FakeEnvironment::default().var("QT_SCALE_FACTOR", "0");
+        assert_eq!(resolve(&env, None), resolved(96, ScaleSource::Default));
+    }
+
+    #[test]
+    fn the_wayland_surface_scale_wins_for_windows() {
+        let env = FakeEnvironment::default().var("GDK_SCALE", "2").var("QT_SCALE_FACTOR", "3").xft_dpi("144");
+        // 180 / 120 = 1.5
+        assert_eq!(resolve(&env, Some(FractionalScale(180))), resolved(144, ScaleSource::WaylandFractionalScale));
+        assert_eq!(resolve(&env, Some(FractionalScale(150))), resolved(120, ScaleSource::WaylandFractionalScale));
+        // a scale of 0 is not one
+        assert_eq!(resolve(&env, Some(FractionalScale(0))), resolved(192, ScaleSource::GdkScale));
+        // and the system DPI never has one
+        assert_eq!(resolve(&env, None), resolved(192, ScaleSource::GdkScale));
+    }
+
+    #[test]
+    fn resources_are_found_by_exact_name() {
+        let database = "Xft.antialias:\t1\nXft.dpi:\t144\nXft.dpi.extra: 1\n*customization:\t-color\n";
+        assert_eq!(find_x_resource(database, "Xft.dpi"), Some("144".to_owned()));
+        assert_eq!(find_x_resource(database, "*customization"), Some("-color".to_owned()));
+        assert_eq!(find_x_resource(database, "Xft"), None);
+        assert_eq!(find_x_resource("", "Xft.dpi"), None);
+    }
+
+    static QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+    fn query() -> Option<String> {
+        QUERIES.fetch_add(1, Ordering::SeqCst);
+        Some("Xft.antialias:\t1\nXft.dpi:\t120\n".to_owned())
+    }
+
+    #[test]
+    fn the_resource_database_is_read_once() {
+        let env = SystemEnvironment::with_query(query);
+        assert_eq!(QUERIES.load(Ordering::SeqCst), 0);
+        assert_eq!(env.x_resource("Xft.dpi"), Some("120".to_owned()));
+        assert_eq!(env.x_resource("Xft.antialias"), Some("1".to_owned()));
+        assert_eq!(env.x_resource("Xft.rgba"), None);
+        assert_eq!(QUERIES.load(Ordering::SeqCst), 1);
+        assert_eq!(resolve(&env, None), resolved(120, ScaleSource::XftDpi));
+
+        // nor is a missing one asked for again
+        let env = SystemEnvironment::with_query(|| None);
+        assert_eq!(env.x_resource("Xft.dpi"), None);
+        assert_eq!(env.x_resource("Xft.dpi"), None);
+    }
+}
@@ -0,0 +1,790 @@
+//! Menus described in code and serialized to the `MENUEX_TEMPLATE` blob that
+//! `LoadMenuIndirectW` takes, with a parser for the same format.
+//!
+//! Accelerators are shown after a tab in the item text, the way `rc` menus
+//! spell them (`"&Open...\tCtrl+O"`), and `Menu::accelerators` collects them
+//! for an accelerator table so the two can't disagree.
+//!
+//! ```ignore
+//! let menu = Menu::new()
+//!     .popup(MenuPopup::new("&File")
+//!         .command(MenuCommand::new("&Open...", IDM_OPEN).accelerator(Accelerator::new(b'O' as u16).with_ctrl()))
+//!         .separator()
+//!         .command(MenuCommand::new("E&xit", IDM_EXIT)));
+//! let h_menu = menu.create();
+//! ```
+
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt,
+    str::FromStr,
+};
+
+use crate::dialog::{align, push_u16, push_u32, push_wstr};
+
+const MFT_RADIOCHECK: u32 = 0x0200;
+const MFT_SEPARATOR: u32 = 0x0800;
+const MFS_DISABLED: u32 = 0x0003;
+const MFS_CHECKED: u32 = 0x0008;
+
+// MENUEX_TEMPLATE_ITEM.wFlags
+const LAST_ITEM: u16 = 0x80;
+const POPUP: u16 = 0x01;
+
+const KEY_NAMES: &[(u16, &str)] = &[
+    (0x08, "Backspace"),
+    (0x09, "Tab"),
+    (0x0D, "Enter"),
+    (0x1B, "Esc"),
+    (0x20, "Space"),
+    (0x21, "PgUp"),
+    (0x22, "PgDn"),
+    (0x23, "End"),
+    (0x24, "Home"),
+    (0x25, "Left"),
+    (0x26, "Up"),
+    (0x27, "Right"),
+    (0x28, "Down"),
+    (0x2D, "Ins"),
+    (0x2E, "Del"),
+];
+
+/// A key combination, by virtual-key code. Letters and digits are their
+/// upper case ASCII codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Accelerator {
+    pub key: u16,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Accelerator {
+    pub fn new(key: u16) -> Accelerator {
+        Accelerator { key, ctrl: false, shift: false, alt: false }
+    }
+
+    pub fn with_ctrl(mut self) -> Accelerator {
+        self.ctrl = true;
+        self
+    }
+
+    pub fn with_shift(mut self) -> Accelerator {
+        self.shift = true;
+        self
+    }
+
+    pub fn with_alt(mut self) -> Accelerator {
+        self.alt = true;
+        self
+    }
+}
+
+/// As shown in a menu: `Ctrl+Shift+S`, `Alt+F4`, `Del`.
+impl fmt::Display for Accelerator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            f.write_str("Ctrl+")?;
+        }
+        if self.shift {
+            f.write_str("Shift+")?;
+        }
+        if self.alt {
+            f.write_str("Alt+")?;
+        }
+        match self.key {
+            0x30..=0x39 | 0x41..=0x5A => write!(f, "{}", self.key as u8 as char),
+            0x70..=0x87 => write!(f, "F{}", self.key - 0x6F),
+            key => match KEY_NAMES.iter().find(|&&(code, _)| code == key) {
+                Some((_, name)) => f.write_str(name),
+                None => write!(f, "0x{:02X}", key),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAcceleratorError(String);
+
+impl fmt::Display for ParseAcceleratorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown accelerator {:?}", self.0)
+    }
+}
+
+impl Error for ParseAcceleratorError {}
+
+impl FromStr for Accelerator {
+    type Err = ParseAcceleratorError;
+
+    /// Accepts what `Display` writes, with the modifiers in any order and case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseAcceleratorError(s.to_owned());
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().ok_or_else(error)?;
+
+        let key = if key.len() == 1 && key.as_bytes()[0].is_ascii_alphanumeric() {
+            key.as_bytes()[0].to_ascii_uppercase() as u16
+        } else if let Some(hex) = key.strip_prefix("0x") {
+            u16::from_str_radix(hex, 16).map_err(|_| error())?
+        } else if let Some(n) = key.strip_prefix(&['F', 'f'][..]).and_then(|n| n.parse::<u16>().ok()) {
+            if !(1..=24).contains(&n) {
+                return Err(error());
+            }
+            0x6F + n
+        } else {
+            KEY_NAMES.iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(key))
+                .map(|&(code, _)| code)
+                .ok_or_else(error)?
+        };
+
+        let mut accelerator = Accelerator::new(key);
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => accelerator.ctrl = true,
+                "shift" => accelerator.shift = true,
+                "alt" => accelerator.alt = true,
+                _ => return Err(error()),
+            }
+        }
+        Ok(accelerator)
+    }
+}
+
+/// A menu item that sends `WM_COMMAND` with its `id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuCommand {
+    /// The label, without the accelerator.
+    pub text: String,
+    pub id: i32,
+    pub accelerator: Option<Accelerator>,
+    pub checked: bool,
+    /// Show the check mark as a bullet (`MFT_RADIOCHECK`).
+    pub radio: bool,
+    pub disabled: bool,
+}
+
+impl MenuCommand {
+    pub fn new(text: &str, id: i32) -> MenuCommand {
+        MenuCommand {
+            text: text.to_owned(),
+            id,
+            accelerator: None,
+            checked: false,
+            radio: false,
+            disabled: false,
+        }
+    }
+
+    pub fn accelerator(mut self, accelerator: Accelerator) -> MenuCommand {
+        self.accelerator = Some(accelerator);
+        self
+    }
+
+    pub fn checked(mut self, checked: bool) -> MenuCommand {
+        self.checked = checked;
+        self
+    }
+
+    pub fn radio(mut self, radio: bool) -> MenuCommand {
+        self.radio = radio;
+        self
+    }
+
+    pub fn disabled(mut self, disabled: bool) -> MenuCommand {
+        self.disabled = disabled;
+        self
+    }
+}
+
+/// A submenu. Windows requires it to have at least one item, and a menu with
+/// an empty one can't be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuPopup {
+    pub text: String,
+    /// Usually 0; `MENUEX` lets a popup have an id like a command.
+    pub id: i32,
+    pub help_id: u32,
+    pub disabled: bool,
+    pub items: Vec<MenuItem>,
+}
+
+impl MenuPopup {
+    pub fn new(text: &str) -> MenuPopup {
+        MenuPopup { text: text.to_owned(), id: 0, help_id: 0, disabled: false, items: Vec::new() }
+    }
+
+    pub fn id(mut self, id: i32) -> MenuPopup {
+        self.id = id;
+        self
+    }
+
+    pub fn help_id(mut self, help_id: u32) -> MenuPopup {
+        self.help_id = help_id;
+        self
+    }
+
+    pub fn disabled(mut self, disabled: bool) -> MenuPopup {
+        self.disabled = disabled;
+        self
+    }
+
+    pub fn command(mut self, command: MenuCommand) -> MenuPopup {
+        self.items.push(MenuItem::Command(command));
+        self
+    }
+
+    pub fn popup(mut self, popup: MenuPopup) -> MenuPopup {
+        self.items.push(MenuItem::Popup(popup));
+        self
+    }
+
+    pub fn separator(mut self) -> MenuPopup {
+        self.items.push(MenuItem::Separator);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuItem {
+    Command(MenuCommand),
+    Popup(MenuPopup),
+    Separator,
+}
+
+/// A menu bar, or the items of a context menu.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Menu {
+    pub help_id: u32,
+    pub items: Vec<MenuItem>,
+}
+
+impl Menu {
+    pub fn new() -> Menu {
+        Menu::default()
+    }
+
+    pub fn help_id(mut self, help_id: u32) -> Menu {
+        self.help_id = help_id;
+        self
+    }
+
+    pub fn command(mut self, command: MenuCommand) -> Menu {
+        self.items.push(MenuItem::Command(command));
+        self
+    }
+
+    pub fn popup(mut self, popup: MenuPopup) -> Menu {
+        self.items.push(MenuItem::Popup(popup));
+        self
+    }
+
+    pub fn separator(mut self) -> Menu {
+        self.items.push(MenuItem::Separator);
+        self
+    }
+
+    /// Every command with an accelerator, in menu order. An accelerator sends
+    /// a 16 bit command, so an id that doesn't fit is an error.
+    pub fn accelerators(&self) -> Result<Vec<(Accelerator, u16)>, MenuError> {
+        fn collect(items: &[MenuItem], accelerators: &mut Vec<(Accelerator, u16)>) -> Result<(), MenuError> {
+            for item in items {
+                match item {
+                    MenuItem::Command(MenuCommand { accelerator: Some(accelerator), id, .. }) => {
+                        let id = u16::try_from(*id).map_err(|_| MenuError::AcceleratorId(*id))?;
+                        accelerators.push((*accelerator, id));
+                    },
+                    MenuItem::Popup(popup) => collect(&popup.items, accelerators)?,
+                    _ => {},
+                }
+            }
+            Ok(())
+        }
+
+        let mut accelerators = Vec::new();
+        collect(&self.items, &mut accelerators)?;
+        Ok(accelerators)
+    }
+
+    /// The command with `id`, searching the popups too.
+    pub fn find_command(&self, id: i32) -> Option<&MenuCommand> {
+        fn find(items: &[MenuItem], id: i32) -> Option<&MenuCommand> {
+            items.iter().find_map(|item| match item {
+                MenuItem::Command(command) if command.id == id => Some(command),
+                MenuItem::Popup(popup) => find(&popup.items, id),
+                _ => None,
+            })
+        }
+
+        find(&self.items, id)
+    }
+
+    /// The `MENUEX_TEMPLATE_HEADER` and its `MENUEX_TEMPLATE_ITEM`s. An empty
+    /// menu is the header alone, but a popup's items end with the one marked
+    /// last, so an empty popup is an error.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MenuError> {
+        let mut data = Vec::new();
+        push_u16(&mut data, 1); // wVersion
+        push_u16(&mut data, 4); // wOffset, to the items
+        push_u32(&mut data, self.help_id);
+        write_items(&self.items, &mut data)?;
+        Ok(data)
+    }
+
+    /// Parse a `MENUEX_TEMPLATE`, such as `to_bytes` or `rc` writes for a
+    /// `MENUEX` resource. Types and states the model has no field for are
+    /// rejected rather than dropped.
+    pub fn from_bytes(data: &[u8]) -> Result<Menu, MenuParseError> {
+        let mut reader = Reader { data, pos: 0 };
+        let version = reader.u16()?;
+        if version != 1 {
+            return Err(reader.error(format!("not a MENUEX template, version {}", version)));
+        }
+        let offset = reader.u16()? as usize;
+        let help_id = reader.u32()?;
+        if offset < 4 {
+            return Err(MenuParseError { offset: 2, message: format!("items at offset {} overlap the header", offset) });
+        }
+        reader.pos = 4 + offset;
+        let items = if reader.pos == data.len() { Vec::new() } else { read_items(&mut reader)? };
+        Ok(Menu { help_id, items })
+    }
+}
+
+fn write_items(items: &[MenuItem], data: &mut Vec<u8>) -> Result<(), MenuError> {
+    for (index, item) in items.iter().enumerate() {
+        let last = if index + 1 == items.len() { LAST_ITEM } else { 0 };
+        align(data);
+        match item {
+            MenuItem::Separator => {
+                push_u32(data, MFT_SEPARATOR);
+                push_u32(data, 0);
+                push_u32(data, 0);
+                push_u16(data, last);
+                push_wstr(data, "");
+            },
+            MenuItem::Command(command) => {
+                push_u32(data, if command.radio { MFT_RADIOCHECK } else { 0 });
+                push_u32(data, state(command.checked, command.disabled));
+                push_u32(data, command.id as u32);
+                push_u16(data, last);
+                match &command.accelerator {
+                    Some(accelerator) => push_wstr(data, &format!("{}\t{}", command.text, accelerator)),
+                    None => push_wstr(data, &command.text),
+                }
+            },
+            MenuItem::Popup(popup) => {
+                if popup.items.is_empty() {
+                    return Err(MenuError::EmptyPopup(popup.text.clone()));
+                }
+                push_u32(data, 0);
+                push_u32(data, state(false, popup.disabled));
+                push_u32(data, popup.id as u32);
+                push_u16(data, last | POPUP);
+                push_wstr(data, &popup.text);
+                align(data);
+                push_u32(data, popup.help_id);
+                write_items(&popup.items, data)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+fn state(checked: bool, disabled: bool) -> u32 {
+    let mut state = 0;
+    if checked {
+        state |= MFS_CHECKED;
+    }
+    if disabled {
+        state |= MFS_DISABLED;
+    }
+    state
+}
+
+fn read_items(reader: &mut Reader) -> Result<Vec<MenuItem>, MenuParseError> {
+    let mut items = Vec::new();
+    loop {
+        reader.align();
+        let start = reader.pos;
+        let item_type = reader.u32()?;
+        let state = reader.u32()?;
+        let id = reader.u32()? as i32;
+        let flags = reader.u16()?;
+        let text = reader.wstr()?;
+
+        if item_type & !(MFT_RADIOCHECK | MFT_SEPARATOR) != 0 || state & !(MFS_CHECKED | MFS_DISABLED) != 0 {
+            return Err(MenuParseError {
+                offset: start,
+                message: format!("unsupported item type 0x{:X} or state 0x{:X}", item_type, state),
+            });
+        }
+        // MFS_GRAYED and MFS_DISABLED are the same thing since Windows 95
+        let disabled = state & MFS_DISABLED != 0;
+
+        let item = if flags & POPUP != 0 {
+            reader.align();
+            let help_id = reader.u32()?;
+            let items = read_items(reader)?;
+            MenuItem::Popup(MenuPopup { text, id, help_id, disabled, items })
+        } else if item_type & MFT_SEPARATOR != 0 {
+            MenuItem::Separator
+        } else {
+            let (text, accelerator) = match text.rfind('\t') {
+                Some(tab) => match text[tab + 1..].parse() {
+                    Ok(accelerator) => (text[..tab].to_owned(), Some(accelerator)),
+                    Err(_) => (text, None),
+                },
+                None => (text, None),
+            };
+            MenuItem::Command(MenuCommand {
+                text,
+                id,
+                accelerator,
+                checked: state & MFS_CHECKED != 0,
+                radio: item_type & MFT_RADIOCHECK != 0,
+                disabled,
+            })
+        };
+        items.push(item);
+
+        if flags & LAST_ITEM != 0 {
+            return Ok(items);
+        }
+    }
+}
+
+/// Why a `Menu` can't be turned into a template or accelerator table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuError {
+    /// A popup, by its text, with no items.
+    EmptyPopup(String),
+    /// A command with an accelerator whose id doesn't fit in 16 bits.
+    AcceleratorId(i32),
+}
+
+impl fmt::Display for MenuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MenuError::EmptyPopup(text) => write!(f, "popup {:?} has no items", text),
+            MenuError::AcceleratorId(id) => write!(f, "command {} has an accelerator but doesn't fit in 16 bits", id),
+        }
+    }
+}
+
+impl Error for MenuError {}
+
+/// Where a `MENUEX_TEMPLATE` stopped making sense.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuParseError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MenuParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "offset {}: {}", self.offset, self.message)
+    }
+}
+
+impl Error for MenuParseError {}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: String) -> MenuParseError {
+        MenuParseError { offset: self.pos, message }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], MenuParseError> {
+        let bytes = self.data.get(self.pos..self.pos + len)
+            .ok_or_else(|| self.error("unexpected end of template".to_owned()))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, MenuParseError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MenuParseError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn wstr(&mut self) -> Result<String, MenuParseError> {
+        let start = self.pos;
+        let mut units = Vec::new();
+        loop {
+            match self.u16()? {
+                0 => break,
+                unit => units.push(unit),
+            }
+        }
+        String::from_utf16(&units).map_err(|_| MenuParseError { offset: start, message: "invalid UTF-16 text".to_owned() })
+    }
+
+    // Not past the end, so a template cut in the padding is reported where it ends
+    fn align(&mut self) {
+        self.pos = ((self.pos + 3) & !3).min(self.data.len());
+    }
+}
+
+#[cfg(windows)]
+mod win {
+    use std::ptr;
+
+    use winapi::{
+        ctypes::c_int,
+        shared::windef::{HACCEL, HMENU},
+        um::winuser::{self, ACCEL, FALT, FCONTROL, FSHIFT, FVIRTKEY, MENUTEMPLATEW},
+    };
+
+    use crate::dialog::to_dwords;
+    use super::Menu;
+
+    impl Menu {
+        /// Create the menu with `LoadMenuIndirectW`. It belongs to the window it
+        /// is given to, or has to be freed with `DestroyMenu`. Returns null if
+        /// `to_bytes` fails.
+        pub fn create(&self) -> HMENU {
+            let template = match self.to_bytes() {
+                Ok(bytes) => to_dwords(&bytes),
+                Err(_) => return ptr::null_mut(),
+            };
+            unsafe { winuser::LoadMenuIndirectW(template.as_ptr() as *const MENUTEMPLATEW) }
+        }
+
+        /// An accelerator table for `accelerators`, to pass to
+        /// `TranslateAcceleratorW`. Free it with `DestroyAcceleratorTable`.
+        /// Returns null if `accelerators` fails.
+        pub fn create_accelerator_table(&self) -> HACCEL {
+            let accelerators = match self.accelerators() {
+                Ok(accelerators) => accelerators,
+                Err(_) => return ptr::null_mut(),
+            };
+            let mut table: Vec<ACCEL> = accelerators
+                .into_iter()
+                .map(|(accelerator, id)| {
+                    let mut virt = FVIRTKEY;
+                    if accelerator.ctrl {
+                        virt |= FCONTROL;
+                    }
+                    if accelerator.shift {
+                        virt |= FSHIFT;
+                    }
+                    if accelerator.alt {
+                        virt |= FALT;
+                    }
+                    ACCEL { fVirt: virt, key: accelerator.key, cmd: id }
+                })
+                .collect();
+            unsafe { winuser::CreateAcceleratorTableW(table.as_mut_ptr(), table.len() as c_int) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Menu {
+        Menu::new()
+            .help_id(7)
+            .popup(MenuPopup::new("&File")
+                .command(MenuCommand::new("&Open...", 100).accelerator(Accelerator::new(b'O' as u16).with_ctrl()))
+                .separator()
+                .popup(MenuPopup::new("&Recent").id(5).help_id(9).command(MenuCommand::new("(none)", 101).disabled(true)))
+                .command(MenuCommand::new("E&xit", 102).accelerator(Accelerator::new(0x73).with_alt())))
+            .popup(MenuPopup::new("&View")
+                .command(MenuCommand::new("&Large", 200).radio(true).checked(true))
+                .command(MenuCommand::new("&Small", 201).radio(true)))
+            .command(MenuCommand::new("&Help", -1))
+    }
+
+    // A deterministic stand-in for a property test: menus of every shape
+    // from a small linear congruential generator
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: u32) -> u32 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % bound as u64) as u32
+        }
+
+        fn text(&mut self) -> String {
+            let len = self.next(6);
+            (0..len).map(|_| ['a', 'Z', '&', ' ', '\u{e9}', '\u{1F600}'][self.next(6) as usize]).collect()
+        }
+
+        fn accelerator(&mut self) -> Option<Accelerator> {
+            let key = [b'A' as u16, b'7' as u16, 0x70, 0x87, 0x2E, 0x0D, 0x61, 0xBA][self.next(8) as usize];
+            let accelerator = Accelerator { key, ctrl: self.next(2) == 1, shift: self.next(2) == 1, alt: self.next(2) == 1 };
+            if self.next(3) == 0 { Some(accelerator) } else { None }
+        }
+
+        fn items(&mut self, depth: u32, min: u32) -> Vec<MenuItem> {
+            (0..min + self.next(4))
+                .map(|_| match self.next(if depth == 0 { 2 } else { 3 }) {
+                    0 => MenuItem::Command(MenuCommand {
+                        text: self.text(),
+                        id: self.next(70000) as i32 - 2,
+                        accelerator: self.accelerator(),
+                        checked: self.next(2) == 1,
+                        radio: self.next(2) == 1,
+                        disabled: self.next(2) == 1,
+                    }),
+                    1 => MenuItem::Separator,
+                    _ => MenuItem::Popup(MenuPopup {
+                        text: self.text(),
+                        id: self.next(10) as i32,
+                        help_id: self.next(10),
+                        disabled: self.next(2) == 1,
+                        items: self.items(depth - 1, 0),
+                    }),
+                })
+                .collect()
+        }
+    }
+
+    // The text of the first empty popup, depth first
+    fn empty_popup(items: &[MenuItem]) -> Option<&str> {
+        items.iter().find_map(|item| match item {
+            MenuItem::Popup(popup) if popup.items.is_empty() => Some(popup.text.as_str()),
+            MenuItem::Popup(popup) => empty_popup(&popup.items),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn menus_round_trip() {
+        assert_eq!(Menu::from_bytes(&sample().to_bytes().unwrap()), Ok(sample()));
+
+        let (mut rng, mut empty) = (Lcg(42), 0);
+        for _ in 0..500 {
+            let menu = Menu { help_id: rng.next(100), items: rng.items(3, 0) };
+            match empty_popup(&menu.items) {
+                Some(text) => {
+                    assert_eq!(menu.to_bytes(), Err(MenuError::EmptyPopup(text.to_owned())), "{:?}", menu);
+                    empty += 1;
+                },
+                None => assert_eq!(Menu::from_bytes(&menu.to_bytes().unwrap()).as_ref(), Ok(&menu), "{:?}", menu),
+            }
+        }
+        // both kinds were generated
+        assert!(empty > 0 && empty < 500, "{}", empty);
+    }
+
+    #[test]
+    fn empty_popups_are_not_written() {
+        let menu = Menu::new().popup(MenuPopup::new("&File").popup(MenuPopup::new("&Recent")));
+        assert_eq!(menu.to_bytes(), Err(MenuError::EmptyPopup("&Recent".to_owned())));
+        let menu = Menu::new().command(MenuCommand::new("&Open", 1)).popup(MenuPopup::new("&Edit"));
+        assert_eq!(menu.to_bytes(), Err(MenuError::EmptyPopup("&Edit".to_owned())));
+    }
+
+    #[test]
+    fn an_empty_menu_is_the_header_alone() {
+        let bytes = Menu::new().help_id(3).to_bytes().unwrap();
+        assert_eq!(bytes, [1, 0, 4, 0, 3, 0, 0, 0]);
+        assert_eq!(Menu::from_bytes(&bytes), Ok(Menu::new().help_id(3)));
+    }
+
+    #[test]
+    fn templates_have_the_menuex_layout() {
+        let menu = Menu::new()
+            .popup(MenuPopup::new("F").command(MenuCommand::new("O", 5).checked(true)))
+            .separator();
+
+        let mut expected = vec![1, 0, 4, 0, 0, 0, 0, 0];
+        // the popup: type, state, id, flags, "F", padding and its help id
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0, b'F', 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        // its only, and so last, item: checked, id 5, "O", padded
+        expected.extend_from_slice(&[0, 0, 0, 0, 8, 0, 0, 0, 5, 0, 0, 0, 0x80, 0, b'O', 0, 0, 0, 0, 0]);
+        // the last top level item: MFT_SEPARATOR with no text
+        expected.extend_from_slice(&[0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x80, 0, 0, 0]);
+        assert_eq!(menu.to_bytes().unwrap(), expected);
+    }
+
+    #[test]
+    fn truncated_templates_are_errors() {
+        let bytes = sample().to_bytes().unwrap();
+        for len in (0..bytes.len()).filter(|&len| len != 8) {
+            // the header alone is an empty menu; any other cut is an error
+            let error = Menu::from_bytes(&bytes[..len]).unwrap_err();
+            assert!(error.offset <= len, "{} at {}", error, len);
+            assert!(error.message.contains("unexpected end"), "{} at {}", error, len);
+        }
+    }
+
+    #[test]
+    fn malformed_templates_are_errors() {
+        let mut bytes = sample().to_bytes().unwrap();
+        bytes[0] = 0;
+        assert_eq!(Menu::from_bytes(&bytes).unwrap_err().message, "not a MENUEX template, version 0");
+
+        let mut bytes = sample().to_bytes().unwrap();
+        bytes[2] = 2;
+        assert_eq!(Menu::from_bytes(&bytes).unwrap_err().offset, 2);
+
+        // MFT_OWNERDRAW on the first item
+        let mut bytes = sample().to_bytes().unwrap();
+        bytes[9] = 0x01;
+        let error = Menu::from_bytes(&bytes).unwrap_err();
+        assert_eq!(error.offset, 8);
+        assert!(error.message.starts_with("unsupported item type"), "{}", error);
+
+        // an unpaired surrogate in the text of the first item
+        let mut bytes = sample().to_bytes().unwrap();
+        bytes[22..24].copy_from_slice(&0xD800_u16.to_le_bytes());
+        assert_eq!(Menu::from_bytes(&bytes).unwrap_err(), MenuParseError { offset: 22, message: "invalid UTF-16 text".to_owned() });
+    }
+
+    #[test]
+    fn accelerators_parse_what_they_display() {
+        for (text, accelerator) in &[
+            ("Ctrl+O", Accelerator::new(b'O' as u16).with_ctrl()),
+            ("Ctrl+Shift+S", Accelerator::new(b'S' as u16).with_ctrl().with_shift()),
+            ("Alt+F4", Accelerator::new(0x73).with_alt()),
+            ("Del", Accelerator::new(0x2E)),
+            ("0xBA", Accelerator::new(0xBA)),
+        ] {
+            assert_eq!(accelerator.to_string(), *text);
+            assert_eq!(text.parse::<Accelerator>().as_ref(), Ok(accelerator));
+        }
+        assert_eq!("shift + ctrl + o".parse(), Ok(Accelerator::new(b'O' as u16).with_ctrl().with_shift()));
+        assert!("Ctrl+F25".parse::<Accelerator>().is_err());
+        assert!("Meta+A".parse::<Accelerator>().is_err());
+        assert!("".parse::<Accelerator>().is_err());
+    }
+
+    #[test]
+    fn accelerators_and_commands_are_found_in_popups() {
+        let menu = sample();
+        assert_eq!(menu.accelerators(), Ok(vec![
+            (Accelerator::new(b'O' as u16).with_ctrl(), 100),
+            (Accelerator::new(0x73).with_alt(), 102),
+        ]));
+        assert_eq!(menu.find_command(101).map(|command| command.disabled), Some(true));
+        assert!(menu.find_command(5).is_none());
+    }
+
+    #[test]
+    fn accelerator_ids_fit_in_16_bits() {
+        let with_id = |id: i32| Menu::new()
+            .command(MenuCommand::new("&Help", -1))
+            .popup(MenuPopup::new("&File").command(MenuCommand::new("&Open", id).accelerator(Accelerator::new(0x70))));
+        assert_eq!(with_id(0xFFFF).accelerators(), Ok(vec![(Accelerator::new(0x70), 0xFFFF)]));
+        assert_eq!(with_id(0).accelerators(), Ok(vec![(Accelerator::new(0x70), 0)]));
+        assert_eq!(with_id(0x10000).accelerators(), Err(MenuError::AcceleratorId(0x10000)));
+        assert_eq!(with_id(-1).accelerators(), Err(MenuError::AcceleratorId(-1)));
+        // the template itself takes any id
+        assert!(with_id(0x10000).to_bytes().is_ok());
+    }
+}
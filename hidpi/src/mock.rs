@@ -0,0 +1,421 @@
+//! A `DpiBackend` standing in for a given release of Windows in tests.
+//!
+//! Functions the release does not export report themselves missing, the rest
+//! work on the state kept here. Like Windows, the thread awareness context
+//! handed back is a handle of its own rather than the pseudo-handle that was
+//! set, so callers have to compare contexts the way they would on Windows.
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::ptr;
+use std::rc::Rc;
+
+use crate::backend::DpiBackend;
+use crate::sys::{
+    c_int,
+    BOOL,
+    DWORD,
+    FALSE,
+    TRUE,
+    UINT,
+    HANDLE,
+    DPI_AWARENESS,
+    DPI_AWARENESS_CONTEXT,
+    DPI_AWARENESS_INVALID,
+    DPI_AWARENESS_PER_MONITOR_AWARE,
+    DPI_AWARENESS_SYSTEM_AWARE,
+    DPI_AWARENESS_UNAWARE,
+    DPI_HOSTING_BEHAVIOR,
+    DPI_HOSTING_BEHAVIOR_DEFAULT,
+    DPI_HOSTING_BEHAVIOR_INVALID,
+    DPI_HOSTING_BEHAVIOR_MIXED,
+    E_ACCESSDENIED,
+    ERROR_ACCESS_DENIED,
+    HMONITOR,
+    HWND,
+    POINT,
+    RECT,
+    MONITOR_DPI_TYPE,
+    LOGFONTW,
+};
+use crate::win::WinDpiAwareness;
+use crate::DpiAwarenessContext;
+
+const ERROR_INVALID_PARAMETER: i32 = 87;
+
+// A System aware context created for another system DPI: valid, but equal to
+// none of the names
+const FOREIGN: usize = 0x30;
+
+/// The releases that added DPI functions, oldest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Release {
+    /// Not Windows at all: nothing is exported.
+    None,
+    /// `SetProcessDPIAware` and `IsProcessDPIAware`.
+    Windows7,
+    /// shcore's process awareness and `GetDpiForMonitor`.
+    Windows81,
+    /// Thread awareness contexts and the `*ForDpi` functions.
+    Windows10_1607,
+    /// `SetProcessDpiAwarenessContext` and Per-Monitor V2.
+    Windows10_1703,
+    /// Thread hosting behavior and `GetDpiFromDpiAwarenessContext`.
+    Windows10_1803,
+    /// `DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED`.
+    Windows10_1809,
+}
+
+struct State {
+    release: Release,
+    process: Cell<Option<DpiAwarenessContext>>,
+    thread: Cell<DPI_AWARENESS_CONTEXT>,
+    hosting: Cell<DPI_HOSTING_BEHAVIOR>,
+    system_dpi: Cell<UINT>,
+    window_dpi: Cell<UINT>,
+    monitor_dpi: Cell<UINT>,
+    calls: RefCell<Vec<String>>,
+}
+
+/// Clones share their state, so a test can keep one while another is
+/// installed with `with_backend`.
+#[derive(Clone)]
+pub(crate) struct MockBackend(Rc<State>);
+
+impl MockBackend {
+    pub(crate) fn new(release: Release) -> MockBackend {
+        MockBackend(Rc::new(State {
+            release,
+            process: Cell::new(None),
+            thread: Cell::new(Self::handle(DpiAwarenessContext::Unaware)),
+            hosting: Cell::new(DPI_HOSTING_BEHAVIOR_DEFAULT),
+            system_dpi: Cell::new(96),
+            window_dpi: Cell::new(96),
+            monitor_dpi: Cell::new(96),
+            calls: RefCell::new(Vec::new()),
+        }))
+    }
+
+    pub(crate) fn with_dpi(self, system: UINT, window: UINT, monitor: UINT) -> MockBackend {
+        self.0.system_dpi.set(system);
+        self.0.window_dpi.set(window);
+        self.0.monitor_dpi.set(monitor);
+        self
+    }
+
+    /// As if the application manifest had already fixed the process awareness.
+    pub(crate) fn with_manifest(self, context: DpiAwarenessContext) -> MockBackend {
+        self.0.process.set(Some(context));
+        self.0.thread.set(Self::handle(context));
+        self
+    }
+
+    /// As if the thread had been given a context none of the names match.
+    pub(crate) fn with_foreign_thread_context(self) -> MockBackend {
+        self.0.thread.set(Self::foreign());
+        self
+    }
+
+    pub(crate) fn process_context(&self) -> Option<DpiAwarenessContext> {
+        self.0.process.get()
+    }
+
+    pub(crate) fn thread_context(&self) -> DPI_AWARENESS_CONTEXT {
+        self.0.thread.get()
+    }
+
+    pub(crate) fn thread_hosting(&self) -> DPI_HOSTING_BEHAVIOR {
+        self.0.hosting.get()
+    }
+
+    /// Every call made, with the contexts it was passed by name.
+    pub(crate) fn calls(&self) -> Vec<String> {
+        self.0.calls.borrow().clone()
+    }
+
+    /// The handle the thread context functions hand out for `context`.
+    pub(crate) fn handle(context: DpiAwarenessContext) -> DPI_AWARENESS_CONTEXT {
+        let index = DpiAwarenessContext::ALL.iter().position(|known| *known == context).unwrap();
+        (0x11 + index) as DPI_AWARENESS_CONTEXT
+    }
+
+    pub(crate) fn foreign() -> DPI_AWARENESS_CONTEXT {
+        FOREIGN as DPI_AWARENESS_CONTEXT
+    }
+
+    fn has(&self, since: Release) -> bool {
+        self.0.release >= since
+    }
+
+    fn record(&self, call: String) {
+        self.0.calls.borrow_mut().push(call);
+    }
+
+    // A pseudo-handle or one of our handles, if the release knows the context
+    fn identify(&self, context: DPI_AWARENESS_CONTEXT) -> Option<DpiAwarenessContext> {
+        let known = DpiAwarenessContext::ALL.iter()
+            .find(|known| known.to_raw() == context || Self::handle(**known) == context)
+            .cloned()?;
+        let since = match known {
+            DpiAwarenessContext::PerMonitorV2 => Release::Windows10_1703,
+            DpiAwarenessContext::UnawareGdiScaled => Release::Windows10_1809,
+            _ => Release::Windows10_1607,
+        };
+        if self.has(since) { Some(known) } else { None }
+    }
+
+    fn is_foreign(&self, context: DPI_AWARENESS_CONTEXT) -> bool {
+        context == Self::foreign() && self.has(Release::Windows10_1607)
+    }
+
+    fn describe(&self, context: DPI_AWARENESS_CONTEXT) -> String {
+        match self.identify(context) {
+            Some(known) => format!("{:?}", known),
+            None if self.is_foreign(context) => "Foreign".to_owned(),
+            None => format!("{}", context as isize),
+        }
+    }
+
+    fn set_process(&self, context: DpiAwarenessContext) -> Result<(), ()> {
+        if self.0.process.get().is_some() {
+            return Err(());
+        }
+        self.0.process.set(Some(context));
+        self.0.thread.set(Self::handle(context));
+        Ok(())
+    }
+}
+
+impl DpiBackend for MockBackend {
+    fn is_process_dpi_aware(&self) -> Option<bool> {
+        if !self.has(Release::Windows7) {
+            return None;
+        }
+        self.record("IsProcessDPIAware".to_owned());
+        Some(self.0.process.get().is_some_and(|c| c.awareness() != crate::DpiAwareness::Unaware))
+    }
+
+    fn set_process_dpi_aware(&self) -> Option<bool> {
+        if !self.has(Release::Windows7) {
+            return None;
+        }
+        self.record("SetProcessDPIAware".to_owned());
+        // succeeds when already system aware, like Windows
+        match self.0.process.get() {
+            Some(DpiAwarenessContext::System) => Some(true),
+            _ => Some(self.set_process(DpiAwarenessContext::System).is_ok()),
+        }
+    }
+
+    fn get_process_dpi_awareness(&self) -> io::Result<Option<WinDpiAwareness>> {
+        if !self.has(Release::Windows81) {
+            return Ok(None);
+        }
+        self.record("GetProcessDpiAwareness".to_owned());
+        Ok(Some(match self.0.process.get().map(DpiAwarenessContext::awareness) {
+            Some(crate::DpiAwareness::System) => WinDpiAwareness::ProcessSystemDpiAware,
+            Some(crate::DpiAwareness::PerMonitor) => WinDpiAwareness::ProcessPerMonitorDpiAware,
+            _ => WinDpiAwareness::ProcessDpiUnaware,
+        }))
+    }
+
+    fn set_process_dpi_awareness(&self, awareness: WinDpiAwareness) -> io::Result<bool> {
+        if !self.has(Release::Windows81) {
+            return Ok(false);
+        }
+        self.record(format!("SetProcessDpiAwareness({:?})", awareness));
+        let context = match awareness {
+            WinDpiAwareness::ProcessDpiUnaware => DpiAwarenessContext::Unaware,
+            WinDpiAwareness::ProcessSystemDpiAware => DpiAwarenessContext::System,
+            WinDpiAwareness::ProcessPerMonitorDpiAware => DpiAwarenessContext::PerMonitor,
+            WinDpiAwareness::Unknown(_) => return Err(io::Error::from_raw_os_error(ERROR_INVALID_PARAMETER)),
+        };
+        self.set_process(context)
+            .map(|()| true)
+            .map_err(|()| io::Error::from_raw_os_error(E_ACCESSDENIED))
+    }
+
+    fn set_process_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> io::Result<bool> {
+        if !self.has(Release::Windows10_1703) {
+            return Ok(false);
+        }
+        let known = self.identify(context);
+        self.record(format!("SetProcessDpiAwarenessContext({})", self.describe(context)));
+        let known = known.ok_or_else(|| io::Error::from_raw_os_error(ERROR_INVALID_PARAMETER))?;
+        self.set_process(known)
+            .map(|()| true)
+            .map_err(|()| io::Error::from_raw_os_error(ERROR_ACCESS_DENIED as i32))
+    }
+
+    fn get_thread_dpi_awareness_context(&self) -> Option<DPI_AWARENESS_CONTEXT> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        self.record("GetThreadDpiAwarenessContext".to_owned());
+        Some(self.0.thread.get())
+    }
+
+    fn set_thread_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<DPI_AWARENESS_CONTEXT> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        self.record(format!("SetThreadDpiAwarenessContext({})", self.describe(context)));
+        // NULL for a context this release does not know
+        let handle = match self.identify(context) {
+            Some(known) => Self::handle(known),
+            None if self.is_foreign(context) => context,
+            None => return Some(ptr::null_mut()),
+        };
+        Some(self.0.thread.replace(handle))
+    }
+
+    fn get_awareness_from_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<DPI_AWARENESS> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        if self.is_foreign(context) {
+            return Some(DPI_AWARENESS_SYSTEM_AWARE);
+        }
+        Some(match self.identify(context).map(DpiAwarenessContext::awareness) {
+            Some(crate::DpiAwareness::Unaware) => DPI_AWARENESS_UNAWARE,
+            Some(crate::DpiAwareness::System) => DPI_AWARENESS_SYSTEM_AWARE,
+            Some(crate::DpiAwareness::PerMonitor) => DPI_AWARENESS_PER_MONITOR_AWARE,
+            _ => DPI_AWARENESS_INVALID,
+        })
+    }
+
+    fn are_dpi_awareness_contexts_equal(&self, a: DPI_AWARENESS_CONTEXT, b: DPI_AWARENESS_CONTEXT) -> Option<BOOL> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        match (self.identify(a), self.identify(b)) {
+            (Some(a), Some(b)) if a == b => Some(TRUE),
+            _ if a == b && self.is_foreign(a) => Some(TRUE),
+            _ => Some(FALSE),
+        }
+    }
+
+    fn get_thread_dpi_hosting_behavior(&self) -> Option<DPI_HOSTING_BEHAVIOR> {
+        if !self.has(Release::Windows10_1803) {
+            return None;
+        }
+        self.record("GetThreadDpiHostingBehavior".to_owned());
+        Some(self.0.hosting.get())
+    }
+
+    fn set_thread_dpi_hosting_behavior(&self, behavior: DPI_HOSTING_BEHAVIOR) -> Option<DPI_HOSTING_BEHAVIOR> {
+        if !self.has(Release::Windows10_1803) {
+            return None;
+        }
+        self.record(format!("SetThreadDpiHostingBehavior({})", behavior as i32));
+        match behavior {
+            DPI_HOSTING_BEHAVIOR_DEFAULT | DPI_HOSTING_BEHAVIOR_MIXED => Some(self.0.hosting.replace(behavior)),
+            _ => Some(DPI_HOSTING_BEHAVIOR_INVALID),
+        }
+    }
+
+    fn get_window_dpi_awareness_context(&self, _h_wnd: HWND) -> Option<DPI_AWARENESS_CONTEXT> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        Some(self.0.thread.get())
+    }
+
+    fn get_window_dpi_hosting_behavior(&self, _h_wnd: HWND) -> Option<DPI_HOSTING_BEHAVIOR> {
+        if !self.has(Release::Windows10_1803) {
+            return None;
+        }
+        Some(self.0.hosting.get())
+    }
+
+    fn get_dpi_from_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<UINT> {
+        if !self.has(Release::Windows10_1803) {
+            return None;
+        }
+        match self.identify(context) {
+            Some(DpiAwarenessContext::System) => Some(self.0.system_dpi.get()),
+            _ => Some(0),
+        }
+    }
+
+    fn is_valid_dpi_awareness_context(&self, context: DPI_AWARENESS_CONTEXT) -> Option<bool> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        Some(self.identify(context).is_some() || self.is_foreign(context))
+    }
+
+    fn get_dpi_for_system(&self) -> Option<UINT> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        Some(self.0.system_dpi.get())
+    }
+
+    fn get_dpi_for_window(&self, _h_wnd: HWND) -> Option<UINT> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        Some(self.0.window_dpi.get())
+    }
+
+    fn get_dpi_for_monitor(&self, _h_monitor: HMONITOR, _dpi_type: MONITOR_DPI_TYPE) -> io::Result<Option<(UINT, UINT)>> {
+        if !self.has(Release::Windows81) {
+            return Ok(None);
+        }
+        let dpi = self.0.monitor_dpi.get();
+        Ok(Some((dpi, dpi)))
+    }
+
+    fn get_system_dpi_for_process(&self, _h_process: HANDLE) -> Option<UINT> {
+        if !self.has(Release::Windows10_1803) {
+            return None;
+        }
+        Some(self.0.system_dpi.get())
+    }
+
+    fn adjust_window_rect_ex_for_dpi(&self, rect: &mut RECT, _style: DWORD, _menu: bool, _ex_style: DWORD, dpi: UINT) -> Option<bool> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        // an 8px frame at 96 DPI
+        let frame = (8 * dpi / 96) as i32;
+        rect.left -= frame;
+        rect.top -= frame;
+        rect.right += frame;
+        rect.bottom += frame;
+        Some(true)
+    }
+
+    fn get_system_metrics_for_dpi(&self, index: c_int, dpi: UINT) -> Option<c_int> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        Some(index * dpi as c_int / 96)
+    }
+
+    fn get_icon_title_log_font_for_dpi(&self, dpi: UINT) -> Option<LOGFONTW> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        // 9pt
+        Some(LOGFONTW { lfHeight: -(9 * dpi as i32 / 72), ..LOGFONTW::default() })
+    }
+
+    fn enable_non_client_dpi_scaling(&self, _h_wnd: HWND) -> Option<bool> {
+        if !self.has(Release::Windows10_1607) {
+            return None;
+        }
+        Some(true)
+    }
+
+    fn logical_to_physical_point_for_per_monitor_dpi(&self, _h_wnd: HWND, point: &mut POINT) -> Option<bool> {
+        if !self.has(Release::Windows81) {
+            return None;
+        }
+        let dpi = self.0.window_dpi.get() as i32;
+        point.x = point.x * dpi / 96;
+        point.y = point.y * dpi / 96;
+        Some(true)
+    }
+}
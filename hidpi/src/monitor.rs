@@ -0,0 +1,344 @@
+//! Monitors and the virtual desktop they make up.
+//!
+//! `VirtualDesktop` is a plain description of a monitor layout. It answers the
+//! same questions Windows does for `MonitorFromRect` and `MonitorFromPoint`
+//! with `MONITOR_DEFAULTTONEAREST`, so DPI selection can be worked out for any
+//! layout, real or made up. On Windows `monitors()` and `monitor_for_rect()`
+//! describe the attached displays.
+
+use crate::units::{Dpi, Point, Rect};
+
+/// A display attached to the virtual desktop. Coordinates are physical pixels
+/// in virtual screen space, with the primary monitor's top left at the origin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    /// Stays the same for as long as the display is attached, e.g. `\\.\DISPLAY1`.
+    pub id: String,
+    pub bounds: Rect,
+    /// `bounds` without the taskbar and other docked app bars.
+    pub work_area: Rect,
+    /// The DPI applications should render at, including the user's scaling choice.
+    pub effective_dpi: Dpi,
+    /// The DPI that keeps the angular size of content constant for the viewing
+    /// distance the display was designed for.
+    pub angular_dpi: Dpi,
+    /// The physical pixel density of the panel.
+    pub raw_dpi: Dpi,
+    pub primary: bool,
+}
+
+impl Monitor {
+    /// A monitor whose work area is its full bounds and whose angular and raw
+    /// DPI equal its effective DPI.
+    pub fn new<S: Into<String>>(id: S, bounds: Rect, effective_dpi: Dpi) -> Monitor {
+        Monitor {
+            id: id.into(),
+            bounds,
+            work_area: bounds,
+            effective_dpi,
+            angular_dpi: effective_dpi,
+            raw_dpi: effective_dpi,
+            primary: false,
+        }
+    }
+}
+
+/// A set of monitors to hit-test against.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VirtualDesktop {
+    monitors: Vec<Monitor>,
+}
+
+// Squared distance between two rectangles, 0 if they touch or overlap.
+fn distance_squared(a: &Rect, b: &Rect) -> i64 {
+    fn gap(a_start: i32, a_end: i32, b_start: i32, b_end: i32) -> i64 {
+        if a_end <= b_start {
+            b_start as i64 - a_end as i64
+        } else if b_end <= a_start {
+            a_start as i64 - b_end as i64
+        } else {
+            0
+        }
+    }
+
+    let dx = gap(a.left, a.right, b.left, b.right);
+    let dy = gap(a.top, a.bottom, b.top, b.bottom);
+    dx.saturating_mul(dx).saturating_add(dy.saturating_mul(dy))
+}
+
+impl VirtualDesktop {
+    pub fn new(monitors: Vec<Monitor>) -> VirtualDesktop {
+        VirtualDesktop { monitors }
+    }
+
+    pub fn monitors(&self) -> &[Monitor] {
+        &self.monitors
+    }
+
+    /// The primary monitor, or the first one if none is marked primary.
+    pub fn primary(&self) -> Option<&Monitor> {
+        self.monitors.iter()
+            .find(|m| m.primary)
+            .or_else(|| self.monitors.first())
+    }
+
+    /// The bounding rectangle of all monitors.
+    pub fn bounds(&self) -> Rect {
+        let mut monitors = self.monitors.iter();
+        let first = match monitors.next() {
+            Some(m) => m.bounds,
+            None => return Rect::default(),
+        };
+        monitors.fold(first, |r, m| Rect {
+            left: r.left.min(m.bounds.left),
+            top: r.top.min(m.bounds.top),
+            right: r.right.max(m.bounds.right),
+            bottom: r.bottom.max(m.bounds.bottom),
+        })
+    }
+
+    /// The monitor with the largest intersection with `rect`, or if it is
+    /// off every monitor, the nearest one. Ties go to the earlier monitor.
+    /// `None` only if there are no monitors.
+    pub fn monitor_for_rect(&self, rect: Rect) -> Option<&Monitor> {
+        let mut best: Option<(&Monitor, i64)> = None;
+        for monitor in &self.monitors {
+            let area = monitor.bounds.intersection(&rect).map_or(0, |r| r.area());
+            if area > 0 && !best.is_some_and(|(_, best_area)| area <= best_area) {
+                best = Some((monitor, area));
+            }
+        }
+
+        if let Some((monitor, _)) = best {
+            return Some(monitor);
+        }
+
+        let mut nearest: Option<(&Monitor, i64)> = None;
+        for monitor in &self.monitors {
+            let distance = distance_squared(&monitor.bounds, &rect);
+            if !nearest.is_some_and(|(_, best_distance)| distance >= best_distance) {
+                nearest = Some((monitor, distance));
+            }
+        }
+        nearest.map(|(monitor, _)| monitor)
+    }
+
+    /// The monitor containing `point`, or the nearest one.
+    pub fn monitor_for_point(&self, point: Point) -> Option<&Monitor> {
+        self.monitors.iter()
+            .find(|m| m.bounds.contains(point))
+            .or_else(|| self.monitor_for_rect(Rect::new(point.x, point.y, point.x.saturating_add(1), point.y.saturating_add(1))))
+    }
+
+    /// The effective DPI for a window at `rect`, or 96 DPI with no monitors.
+    pub fn dpi_for_rect(&self, rect: Rect) -> Dpi {
+        self.monitor_for_rect(rect).map_or(Dpi::DEFAULT, |m| m.effective_dpi)
+    }
+}
+
+#[cfg(windows)]
+pub use self::win::{monitors, monitor_for_rect, virtual_desktop};
+
+#[cfg(windows)]
+mod win {
+    use std::mem;
+    use std::ptr;
+
+    use winapi::{
+        shared::{
+            minwindef::{BOOL, FALSE, LPARAM, TRUE},
+            windef::{HDC, HMONITOR, LPRECT, RECT},
+        },
+        um::{
+            shellscalingapi::{
+                MONITOR_DPI_TYPE,
+                MDT_ANGULAR_DPI,
+                MDT_EFFECTIVE_DPI,
+                MDT_RAW_DPI,
+            },
+            winuser::{self, MONITORINFO, MONITORINFOEXW},
+        },
+    };
+
+    use super::{Monitor, VirtualDesktop};
+    use crate::units::{Dpi, Rect};
+
+    unsafe extern "system" fn collect(h_monitor: HMONITOR, _hdc: HDC, _rect: LPRECT, data: LPARAM) -> BOOL {
+        let handles = &mut *(data as *mut Vec<HMONITOR>);
+        handles.push(h_monitor);
+        TRUE
+    }
+
+    fn monitor_dpi(h_monitor: HMONITOR, dpi_type: MONITOR_DPI_TYPE, system: Dpi) -> Dpi {
+        // Before Windows 8.1 every monitor is rendered at the system DPI
+        crate::get_dpi_for_monitor(h_monitor, dpi_type)
+            .ok()
+            .and_then(|dpi| dpi)
+            .map_or(system, |(dpi_x, _dpi_y)| Dpi(dpi_x))
+    }
+
+    fn describe(h_monitor: HMONITOR, system: Dpi) -> Option<Monitor> {
+        let mut info: MONITORINFOEXW = unsafe { mem::zeroed() };
+        info.cbSize = mem::size_of::<MONITORINFOEXW>() as u32;
+        if FALSE == unsafe { winuser::GetMonitorInfoW(h_monitor, &mut info as *mut MONITORINFOEXW as *mut MONITORINFO) } {
+            return None;
+        }
+
+        let len = info.szDevice.iter().position(|c| *c == 0).unwrap_or(info.szDevice.len());
+        Some(Monitor {
+            id: String::from_utf16_lossy(&info.szDevice[..len]),
+            bounds: info.rcMonitor.into(),
+            work_area: info.rcWork.into(),
+            effective_dpi: monitor_dpi(h_monitor, MDT_EFFECTIVE_DPI, system),
+            angular_dpi: monitor_dpi(h_monitor, MDT_ANGULAR_DPI, system),
+            raw_dpi: monitor_dpi(h_monitor, MDT_RAW_DPI, system),
+            primary: info.dwFlags & winuser::MONITORINFOF_PRIMARY != 0,
+        })
+    }
+
+    /// The attached displays, in `EnumDisplayMonitors` order.
+    ///
+    /// Bounds and DPIs are reported as the calling thread sees them, so they are
+    /// virtualized unless the thread is per-monitor DPI aware.
+    pub fn monitors() -> Vec<Monitor> {
+        let mut handles: Vec<HMONITOR> = Vec::new();
+        unsafe {
+            winuser::EnumDisplayMonitors(ptr::null_mut(), ptr::null(), Some(collect), &mut handles as *mut Vec<HMONITOR> as LPARAM);
+        }
+
+        let system = crate::get_system_dpi();
+        handles.into_iter()
+            .filter_map(|h_monitor| describe(h_monitor, system))
+            .collect()
+    }
+
+    pub fn virtual_desktop() -> VirtualDesktop {
+        VirtualDesktop::new(monitors())
+    }
+
+    /// The display with the largest intersection with `rect`, or the nearest one.
+    pub fn monitor_for_rect(rect: Rect) -> Option<Monitor> {
+        let rect: RECT = rect.into();
+        let h_monitor = unsafe { winuser::MonitorFromRect(&rect, winuser::MONITOR_DEFAULTTONEAREST) };
+        if h_monitor.is_null() {
+            return None;
+        }
+        describe(h_monitor, crate::get_system_dpi())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(id: &str, left: i32, top: i32, right: i32, bottom: i32, dpi: u32) -> Monitor {
+        Monitor::new(id, Rect::new(left, top, right, bottom), Dpi(dpi))
+    }
+
+    // A 1080p primary at 100%, a 4K panel at 150% to its right with its top
+    // 200 pixels higher, and a 1080p portrait display at 125% to the left.
+    fn layout() -> VirtualDesktop {
+        let mut primary = monitor("\\\\.\\DISPLAY1", 0, 0, 1920, 1080, 96);
+        primary.primary = true;
+        VirtualDesktop::new(vec![
+            monitor("\\\\.\\DISPLAY2", -1080, -420, 0, 1500, 120),
+            primary,
+            monitor("\\\\.\\DISPLAY3", 1920, -200, 5760, 1960, 144),
+        ])
+    }
+
+    fn id(monitor: Option<&Monitor>) -> Option<&str> {
+        monitor.map(|m| &m.id[4..])
+    }
+
+    #[test]
+    fn an_empty_desktop_has_no_monitors() {
+        let desktop = VirtualDesktop::default();
+        assert_eq!(desktop.primary(), None);
+        assert_eq!(desktop.bounds(), Rect::default());
+        assert_eq!(desktop.monitor_for_rect(Rect::new(0, 0, 10, 10)), None);
+        assert_eq!(desktop.monitor_for_point(Point::new(0, 0)), None);
+        assert_eq!(desktop.dpi_for_rect(Rect::new(0, 0, 10, 10)), Dpi::DEFAULT);
+    }
+
+    #[test]
+    fn primary_falls_back_to_the_first_monitor() {
+        assert_eq!(id(layout().primary()), Some("DISPLAY1"));
+
+        let desktop = VirtualDesktop::new(vec![monitor("\\\\.\\DISPLAY7", 0, 0, 10, 10, 96), monitor("\\\\.\\DISPLAY8", 10, 0, 20, 10, 96)]);
+        assert_eq!(id(desktop.primary()), Some("DISPLAY7"));
+    }
+
+    #[test]
+    fn bounds_cover_every_monitor() {
+        assert_eq!(layout().bounds(), Rect::new(-1080, -420, 5760, 1960));
+    }
+
+    #[test]
+    fn points_go_to_the_monitor_they_are_on() {
+        let desktop = layout();
+        assert_eq!(id(desktop.monitor_for_point(Point::new(0, 0))), Some("DISPLAY1"));
+        assert_eq!(id(desktop.monitor_for_point(Point::new(1919, 1079))), Some("DISPLAY1"));
+        // right and bottom edges are exclusive
+        assert_eq!(id(desktop.monitor_for_point(Point::new(1920, 0))), Some("DISPLAY3"));
+        assert_eq!(id(desktop.monitor_for_point(Point::new(-1, 0))), Some("DISPLAY2"));
+        assert_eq!(id(desktop.monitor_for_point(Point::new(-1080, -420))), Some("DISPLAY2"));
+    }
+
+    #[test]
+    fn points_off_every_monitor_go_to_the_nearest() {
+        let desktop = layout();
+        // in the notch above the primary, nearer the 4K panel's left edge
+        assert_eq!(id(desktop.monitor_for_point(Point::new(1900, -150))), Some("DISPLAY3"));
+        // nearer the portrait display's right edge
+        assert_eq!(id(desktop.monitor_for_point(Point::new(20, -150))), Some("DISPLAY2"));
+        // below the primary, which ends above the other two
+        assert_eq!(id(desktop.monitor_for_point(Point::new(960, 1100))), Some("DISPLAY1"));
+        assert_eq!(id(desktop.monitor_for_point(Point::new(i32::MAX, i32::MAX))), Some("DISPLAY3"));
+        assert_eq!(id(desktop.monitor_for_point(Point::new(i32::MIN, i32::MIN))), Some("DISPLAY2"));
+    }
+
+    #[test]
+    fn rects_go_to_the_largest_intersection() {
+        let desktop = layout();
+        // straddling the primary and the 4K panel, mostly on the panel
+        let window = Rect::new(1800, 100, 2600, 700);
+        assert_eq!(id(desktop.monitor_for_rect(window)), Some("DISPLAY3"));
+        assert_eq!(desktop.dpi_for_rect(window), Dpi(144));
+        // mostly on the primary
+        let window = Rect::new(1000, 100, 2000, 700);
+        assert_eq!(id(desktop.monitor_for_rect(window)), Some("DISPLAY1"));
+        assert_eq!(desktop.dpi_for_rect(window), Dpi(96));
+        // across all three, mostly on the primary
+        let window = Rect::new(-1000, 0, 2000, 1400);
+        assert_eq!(id(desktop.monitor_for_rect(window)), Some("DISPLAY1"));
+        // covering the whole desktop
+        assert_eq!(id(desktop.monitor_for_rect(desktop.bounds())), Some("DISPLAY3"));
+    }
+
+    #[test]
+    fn ties_go_to_the_earlier_monitor() {
+        let desktop = VirtualDesktop::new(vec![
+            monitor("\\\\.\\DISPLAY1", 0, 0, 100, 100, 96),
+            monitor("\\\\.\\DISPLAY2", 100, 0, 200, 100, 192),
+        ]);
+        // an equal share of each
+        assert_eq!(id(desktop.monitor_for_rect(Rect::new(50, 0, 150, 100))), Some("DISPLAY1"));
+        // equally far from each
+        assert_eq!(id(desktop.monitor_for_rect(Rect::new(90, 200, 110, 210))), Some("DISPLAY1"));
+
+        let reversed = VirtualDesktop::new(desktop.monitors().iter().rev().cloned().collect());
+        assert_eq!(id(reversed.monitor_for_rect(Rect::new(50, 0, 150, 100))), Some("DISPLAY2"));
+        assert_eq!(id(reversed.monitor_for_rect(Rect::new(90, 200, 110, 210))), Some("DISPLAY2"));
+    }
+
+    #[test]
+    fn rects_off_every_monitor_go_to_the_nearest() {
+        let desktop = layout();
+        // above the primary, partly over the portrait display's top edge
+        let window = Rect::new(-50, -600, 400, -500);
+        assert_eq!(id(desktop.monitor_for_rect(window)), Some("DISPLAY2"));
+        // an empty rect at a point on the 4K panel is off every monitor by area
+        assert_eq!(id(desktop.monitor_for_rect(Rect::new(3000, 500, 3000, 500))), Some("DISPLAY3"));
+    }
+}
@@ -0,0 +1,169 @@
+use std::marker::PhantomData;
+
+use crate::sys::DPI_AWARENESS_CONTEXT;
+use crate::{
+    DpiAwarenessContext,
+    DpiHostingBehavior,
+    set_thread_dpi_awareness_context,
+    set_thread_dpi_hosting_behavior,
+};
+
+/// Temporarily changes the calling thread's DPI awareness context and/or
+/// hosting behavior, putting back whatever was there before when dropped.
+///
+/// Only the settings that were actually changed are restored, so a scope that
+/// was entered with `None` for the hosting behavior leaves it alone. The
+/// previous context is put back exactly as Windows handed it out, even when it
+/// is none of the `DpiAwarenessContext` names. Scopes may be nested; each one
+/// restores the state it found. Restoration also happens while unwinding from
+/// a panic.
+///
+/// ```ignore
+/// let _scope = ThreadDpiScope::enter(Some(DpiAwarenessContext::System), Some(DpiHostingBehavior::Mixed));
+/// let h_wnd = create_content_hwnd(h_inst, width, height);
+/// ```
+#[must_use = "the previous thread DPI state is restored as soon as the scope is dropped"]
+pub struct ThreadDpiScope {
+    previous_context: Option<DPI_AWARENESS_CONTEXT>,
+    previous_hosting: Option<DpiHostingBehavior>,
+    // thread DPI state is per-thread, so the scope must be dropped on the thread that entered it
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ThreadDpiScope {
+    pub fn enter(context: Option<DpiAwarenessContext>, hosting: Option<DpiHostingBehavior>) -> ThreadDpiScope {
+        // The hosting behavior is set first so that it is in effect for any
+        // window created under the new awareness context.
+        let previous_hosting = hosting
+            .and_then(set_thread_dpi_hosting_behavior);
+        let previous_context = context
+            .and_then(set_thread_dpi_awareness_context);
+
+        ThreadDpiScope {
+            previous_context,
+            previous_hosting,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// The context that will be restored, or `None` if the context was not
+    /// changed or the previous one matches none of the names.
+    pub fn previous_context(&self) -> Option<DpiAwarenessContext> {
+        self.previous_context.and_then(DpiAwarenessContext::from_raw)
+    }
+
+    /// The hosting behavior that will be restored, or `None` if it was not changed.
+    pub fn previous_hosting(&self) -> Option<DpiHostingBehavior> {
+        self.previous_hosting
+    }
+}
+
+impl Drop for ThreadDpiScope {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous_context.take() {
+            set_thread_dpi_awareness_context(previous);
+        }
+        if let Some(previous) = self.previous_hosting.take() {
+            set_thread_dpi_hosting_behavior(previous);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::*;
+    use crate::backend::with_backend;
+    use crate::mock::{MockBackend, Release};
+    use crate::sys::{DPI_HOSTING_BEHAVIOR_DEFAULT, DPI_HOSTING_BEHAVIOR_MIXED};
+
+    fn calls_after(backend: &MockBackend, f: impl FnOnce()) -> Vec<String> {
+        with_backend(backend.clone(), f);
+        backend.calls()
+    }
+
+    #[test]
+    fn enters_hosting_first_and_restores_in_reverse() {
+        let backend = MockBackend::new(Release::Windows10_1803);
+        let calls = calls_after(&backend, || {
+            let scope = ThreadDpiScope::enter(Some(DpiAwarenessContext::System), Some(DpiHostingBehavior::Mixed));
+            assert_eq!(scope.previous_context(), Some(DpiAwarenessContext::Unaware));
+            assert_eq!(scope.previous_hosting(), Some(DpiHostingBehavior::Default));
+        });
+        assert_eq!(calls, [
+            "SetThreadDpiHostingBehavior(1)",
+            "SetThreadDpiAwarenessContext(System)",
+            "SetThreadDpiAwarenessContext(Unaware)",
+            "SetThreadDpiHostingBehavior(0)",
+        ]);
+        assert_eq!(backend.thread_context(), MockBackend::handle(DpiAwarenessContext::Unaware));
+        assert_eq!(backend.thread_hosting(), DPI_HOSTING_BEHAVIOR_DEFAULT);
+    }
+
+    #[test]
+    fn restores_a_context_none_of_the_names_match() {
+        let backend = MockBackend::new(Release::Windows10_1803).with_foreign_thread_context();
+        let calls = calls_after(&backend, || {
+            let scope = ThreadDpiScope::enter(Some(DpiAwarenessContext::PerMonitorV2), None);
+            assert_eq!(scope.previous_context(), Some(DpiAwarenessContext::System));
+        });
+        assert_eq!(calls, ["SetThreadDpiAwarenessContext(PerMonitorV2)", "SetThreadDpiAwarenessContext(Foreign)"]);
+        assert_eq!(backend.thread_context(), MockBackend::foreign());
+    }
+
+    #[test]
+    fn nested_scopes_restore_what_they_found() {
+        let backend = MockBackend::new(Release::Windows10_1803);
+        with_backend(backend.clone(), || {
+            let _outer = ThreadDpiScope::enter(Some(DpiAwarenessContext::System), Some(DpiHostingBehavior::Mixed));
+            {
+                let _inner = ThreadDpiScope::enter(Some(DpiAwarenessContext::PerMonitorV2), Some(DpiHostingBehavior::Default));
+                assert_eq!(backend.thread_context(), MockBackend::handle(DpiAwarenessContext::PerMonitorV2));
+                assert_eq!(backend.thread_hosting(), DPI_HOSTING_BEHAVIOR_DEFAULT);
+            }
+            assert_eq!(backend.thread_context(), MockBackend::handle(DpiAwarenessContext::System));
+            assert_eq!(backend.thread_hosting(), DPI_HOSTING_BEHAVIOR_MIXED);
+        });
+        assert_eq!(backend.thread_context(), MockBackend::handle(DpiAwarenessContext::Unaware));
+        assert_eq!(backend.thread_hosting(), DPI_HOSTING_BEHAVIOR_DEFAULT);
+    }
+
+    #[test]
+    fn restores_while_unwinding() {
+        let backend = MockBackend::new(Release::Windows10_1803);
+        let result = with_backend(backend.clone(), || {
+            panic::catch_unwind(|| {
+                let _scope = ThreadDpiScope::enter(Some(DpiAwarenessContext::System), Some(DpiHostingBehavior::Mixed));
+                panic!("creating the window failed");
+            })
+        });
+        assert!(result.is_err());
+        assert_eq!(backend.thread_context(), MockBackend::handle(DpiAwarenessContext::Unaware));
+        assert_eq!(backend.thread_hosting(), DPI_HOSTING_BEHAVIOR_DEFAULT);
+    }
+
+    #[test]
+    fn leaves_alone_what_it_did_not_change() {
+        let backend = MockBackend::new(Release::Windows10_1803);
+        let calls = calls_after(&backend, || {
+            let scope = ThreadDpiScope::enter(None, None);
+            assert_eq!(scope.previous_context(), None);
+            assert_eq!(scope.previous_hosting(), None);
+        });
+        assert!(calls.is_empty());
+
+        // 1607 has no hosting behavior, and refuses Per-Monitor V2
+        let backend = MockBackend::new(Release::Windows10_1607);
+        let calls = calls_after(&backend, || {
+            let scope = ThreadDpiScope::enter(Some(DpiAwarenessContext::PerMonitorV2), Some(DpiHostingBehavior::Mixed));
+            assert_eq!(scope.previous_context(), None);
+            assert_eq!(scope.previous_hosting(), None);
+        });
+        assert_eq!(calls, ["SetThreadDpiAwarenessContext(-4)"]);
+        assert_eq!(backend.thread_context(), MockBackend::handle(DpiAwarenessContext::Unaware));
+
+        let backend = MockBackend::new(Release::Windows81);
+        assert!(calls_after(&backend, || drop(ThreadDpiScope::enter(Some(DpiAwarenessContext::System), Some(DpiHostingBehavior::Mixed)))).is_empty());
+    }
+}
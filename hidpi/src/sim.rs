@@ -0,0 +1,208 @@
+//! A deterministic model of `WM_DPICHANGED` for per-monitor aware windows.
+//!
+//! Windows sends `WM_DPICHANGED` when the monitor owning a window (the one with
+//! the largest share of it) has a different DPI from the window. The message
+//! carries a suggested rectangle: the window's size scaled to the new DPI,
+//! positioned so that the point under the cursor stays under the cursor while
+//! the window is being dragged, or with the top left corner kept in place when
+//! the window was moved programmatically. `SimWindow` follows the same rules
+//! against a `VirtualDesktop`, so layout code can be exercised for any monitor
+//! arrangement without the monitors.
+//!
+//! ```ignore
+//! let desktop = VirtualDesktop::new(vec![
+//!     Monitor::new("left", Rect::new(0, 0, 1920, 1080), Dpi(96)),
+//!     Monitor::new("right", Rect::new(1920, 0, 4800, 1620), Dpi(144)),
+//! ]);
+//! let mut window = SimWindow::new(&desktop, Rect::new(100, 100, 900, 700));
+//! let change = window.drag_to(&desktop, Rect::new(1600, 100, 2400, 700), Point::new(2000, 110));
+//! assert_eq!(change.map(|c| c.dpi), Some(Dpi(144)));
+//! ```
+
+use crate::monitor::{Monitor, VirtualDesktop};
+use crate::units::{mul_div, Dpi, Point, Rect};
+
+/// What a `WM_DPICHANGED` carries: the new DPI in `wParam` and the suggested
+/// window rectangle in `lParam`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DpiChanged {
+    pub dpi: Dpi,
+    pub suggested: Rect,
+}
+
+/// Where the window is held still while it is resized for a new DPI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    /// The window is being dragged; keep the cursor at the same relative position.
+    Cursor(Point),
+    /// The window was moved by the program; keep the top left corner.
+    TopLeft,
+}
+
+// A zero DPI has no scale to convert from or to, so the offset is kept.
+fn scale_coordinate(offset: i32, from: Dpi, to: Dpi) -> i32 {
+    if from.0 == 0 || to.0 == 0 {
+        return offset;
+    }
+    mul_div(offset, to.0.min(i32::MAX as u32) as i32, from.0.min(i32::MAX as u32) as i32)
+}
+
+/// The rectangle Windows suggests for a window at `rect` moving from `from` to
+/// `to` DPI.
+pub fn suggested_rect(rect: Rect, from: Dpi, to: Dpi, anchor: Anchor) -> Rect {
+    let width = scale_coordinate(rect.width(), from, to);
+    let height = scale_coordinate(rect.height(), from, to);
+
+    let (left, top) = match anchor {
+        Anchor::TopLeft => (rect.left, rect.top),
+        Anchor::Cursor(cursor) => (
+            cursor.x.saturating_sub(scale_coordinate(cursor.x.saturating_sub(rect.left), from, to)),
+            cursor.y.saturating_sub(scale_coordinate(cursor.y.saturating_sub(rect.top), from, to)),
+        ),
+    };
+
+    Rect::new(left, top, left.saturating_add(width), top.saturating_add(height))
+}
+
+/// The monitor that decides a window's DPI.
+pub fn owning_monitor(desktop: &VirtualDesktop, rect: Rect) -> Option<&Monitor> {
+    desktop.monitor_for_rect(rect)
+}
+
+/// A top level window that accepts every suggested rectangle, as
+/// `handle_dpi_change` in `hidpi-gui` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimWindow {
+    pub rect: Rect,
+    pub dpi: Dpi,
+}
+
+impl SimWindow {
+    /// A window created at `rect`, at the DPI of the monitor it is created on.
+    pub fn new(desktop: &VirtualDesktop, rect: Rect) -> SimWindow {
+        SimWindow {
+            rect,
+            dpi: desktop.dpi_for_rect(rect),
+        }
+    }
+
+    /// The `WM_DPICHANGED` Windows would send if the window were at `rect`,
+    /// without moving it.
+    pub fn dpi_change_at(&self, desktop: &VirtualDesktop, rect: Rect, anchor: Anchor) -> Option<DpiChanged> {
+        let dpi = desktop.dpi_for_rect(rect);
+        if dpi == self.dpi {
+            return None;
+        }
+
+        Some(DpiChanged {
+            dpi,
+            suggested: suggested_rect(rect, self.dpi, dpi, anchor),
+        })
+    }
+
+    /// Move the window to `rect` and apply the resulting DPI change, if any.
+    pub fn move_to(&mut self, desktop: &VirtualDesktop, rect: Rect, anchor: Anchor) -> Option<DpiChanged> {
+        let change = self.dpi_change_at(desktop, rect, anchor);
+        match change {
+            Some(change) => {
+                self.rect = change.suggested;
+                self.dpi = change.dpi;
+            },
+            None => self.rect = rect,
+        }
+        change
+    }
+
+    /// `move_to` with the window dragged by the cursor at `cursor`.
+    pub fn drag_to(&mut self, desktop: &VirtualDesktop, rect: Rect, cursor: Point) -> Option<DpiChanged> {
+        self.move_to(desktop, rect, Anchor::Cursor(cursor))
+    }
+
+    /// `move_to` for a programmatic move, as with `SetWindowPos`.
+    pub fn set_position(&mut self, desktop: &VirtualDesktop, rect: Rect) -> Option<DpiChanged> {
+        self.move_to(desktop, rect, Anchor::TopLeft)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desktop() -> VirtualDesktop {
+        VirtualDesktop::new(vec![
+            Monitor::new("left", Rect::new(0, 0, 1920, 1080), Dpi(96)),
+            Monitor::new("right", Rect::new(1920, 0, 4800, 1620), Dpi(144)),
+        ])
+    }
+
+    #[test]
+    fn zero_dpi_keeps_coordinates() {
+        for &offset in &[0, 1, -1, 800, i32::MAX, i32::MIN] {
+            assert_eq!(scale_coordinate(offset, Dpi(0), Dpi(144)), offset);
+            assert_eq!(scale_coordinate(offset, Dpi(96), Dpi(0)), offset);
+            assert_eq!(scale_coordinate(offset, Dpi(0), Dpi(0)), offset);
+        }
+
+        let rect = Rect::new(100, 100, 900, 700);
+        assert_eq!(suggested_rect(rect, Dpi(0), Dpi(144), Anchor::TopLeft), rect);
+        assert_eq!(suggested_rect(rect, Dpi(96), Dpi(0), Anchor::Cursor(Point::new(500, 110))), rect);
+    }
+
+    #[test]
+    fn coordinates_scale_by_the_dpi_ratio() {
+        assert_eq!(scale_coordinate(800, Dpi(96), Dpi(144)), 1200);
+        assert_eq!(scale_coordinate(1200, Dpi(144), Dpi(96)), 800);
+        assert_eq!(scale_coordinate(-400, Dpi(96), Dpi(120)), -500);
+        assert_eq!(scale_coordinate(i32::MAX, Dpi(96), Dpi(192)), i32::MAX);
+        assert_eq!(scale_coordinate(1, Dpi(u32::MAX), Dpi(u32::MAX)), 1);
+    }
+
+    #[test]
+    fn programmatic_moves_keep_the_top_left_corner() {
+        let rect = Rect::new(1600, 100, 2400, 700);
+        assert_eq!(suggested_rect(rect, Dpi(96), Dpi(144), Anchor::TopLeft), Rect::new(1600, 100, 2800, 1000));
+    }
+
+    #[test]
+    fn drags_keep_the_cursor_over_the_same_point() {
+        let rect = Rect::new(1600, 100, 2400, 700);
+        // 400 pixels in at 96 DPI is 600 pixels in at 144 DPI
+        assert_eq!(suggested_rect(rect, Dpi(96), Dpi(144), Anchor::Cursor(Point::new(2000, 110))), Rect::new(1400, 95, 2600, 995));
+        // offsets from a cursor at the edge of the coordinate space saturate
+        let extreme = suggested_rect(rect, Dpi(96), Dpi(192), Anchor::Cursor(Point::new(i32::MIN, i32::MAX)));
+        assert_eq!((extreme.left, extreme.top), (0, 0));
+    }
+
+    #[test]
+    fn a_window_follows_the_monitor_with_the_largest_share() {
+        let desktop = desktop();
+        let mut window = SimWindow::new(&desktop, Rect::new(100, 100, 900, 700));
+        assert_eq!(window.dpi, Dpi(96));
+
+        // still mostly on the left monitor
+        assert_eq!(window.drag_to(&desktop, Rect::new(1200, 100, 2000, 700), Point::new(1600, 110)), None);
+        assert_eq!(window.rect, Rect::new(1200, 100, 2000, 700));
+
+        let change = window.drag_to(&desktop, Rect::new(1600, 100, 2400, 700), Point::new(2000, 110));
+        assert_eq!(change, Some(DpiChanged { dpi: Dpi(144), suggested: Rect::new(1400, 95, 2600, 995) }));
+        assert_eq!(window, SimWindow { rect: Rect::new(1400, 95, 2600, 995), dpi: Dpi(144) });
+
+        // and back, to the original size
+        let change = window.set_position(&desktop, Rect::new(100, 100, 1300, 1000));
+        assert_eq!(change, Some(DpiChanged { dpi: Dpi(96), suggested: Rect::new(100, 100, 900, 700) }));
+        assert_eq!(window.dpi, Dpi(96));
+    }
+
+    #[test]
+    fn a_zero_dpi_monitor_leaves_the_window_size_alone() {
+        let desktop = VirtualDesktop::new(vec![
+            Monitor::new("left", Rect::new(0, 0, 1920, 1080), Dpi(96)),
+            Monitor::new("broken", Rect::new(1920, 0, 3840, 1080), Dpi(0)),
+        ]);
+        let mut window = SimWindow::new(&desktop, Rect::new(100, 100, 900, 700));
+        let change = window.set_position(&desktop, Rect::new(2000, 100, 2800, 700));
+        assert_eq!(change, Some(DpiChanged { dpi: Dpi(0), suggested: Rect::new(2000, 100, 2800, 700) }));
+        let change = window.set_position(&desktop, Rect::new(100, 100, 900, 700));
+        assert_eq!(change, Some(DpiChanged { dpi: Dpi(96), suggested: Rect::new(100, 100, 900, 700) }));
+    }
+}
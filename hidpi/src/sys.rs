@@ -0,0 +1,145 @@
+//! The Win32 types and constants the DPI API is written in.
+//!
+//! On Windows these are winapi's. Elsewhere they are declared here with the
+//! same layout and values, so that `backend`, `ffi`, `scope`, `awareness` and
+//! the wrappers in the crate root build on every platform and can be
+//! exercised against a mock `DpiBackend`. Without one every dynamically
+//! loaded function reports itself missing there.
+
+#![allow(non_camel_case_types, non_snake_case)]
+
+#[cfg(windows)]
+pub use winapi::{
+    ctypes::{c_char, c_int, c_void, wchar_t},
+    shared::{
+        minwindef::{BOOL, DWORD, FALSE, INT, TRUE, UINT},
+        ntdef::{HANDLE, HRESULT, LONG},
+        windef::{
+            DPI_AWARENESS,
+            DPI_AWARENESS_CONTEXT,
+            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE,
+            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+            DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
+            DPI_AWARENESS_CONTEXT_UNAWARE,
+            DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED,
+            DPI_AWARENESS_INVALID,
+            DPI_AWARENESS_PER_MONITOR_AWARE,
+            DPI_AWARENESS_SYSTEM_AWARE,
+            DPI_AWARENESS_UNAWARE,
+            DPI_HOSTING_BEHAVIOR,
+            DPI_HOSTING_BEHAVIOR_DEFAULT,
+            DPI_HOSTING_BEHAVIOR_INVALID,
+            DPI_HOSTING_BEHAVIOR_MIXED,
+            HMONITOR,
+            HWND,
+            POINT,
+            RECT,
+        },
+        winerror::{ERROR_ACCESS_DENIED, E_ACCESSDENIED, S_OK},
+    },
+    um::{
+        shellscalingapi::{
+            MONITOR_DPI_TYPE,
+            MDT_EFFECTIVE_DPI,
+            PROCESS_DPI_AWARENESS,
+            PROCESS_DPI_UNAWARE,
+            PROCESS_PER_MONITOR_DPI_AWARE,
+            PROCESS_SYSTEM_DPI_AWARE,
+        },
+        wingdi::LOGFONTW,
+        winuser::SPI_GETICONTITLELOGFONT,
+    },
+};
+
+#[cfg(not(windows))]
+pub use self::other::*;
+
+#[cfg(not(windows))]
+mod other {
+    pub use std::os::raw::{c_char, c_int, c_void};
+
+    pub type wchar_t = u16;
+    pub type BOOL = c_int;
+    pub type INT = c_int;
+    pub type UINT = u32;
+    pub type DWORD = u32;
+    pub type LONG = i32;
+    pub type HRESULT = i32;
+    pub type HANDLE = *mut c_void;
+
+    pub const FALSE: BOOL = 0;
+    pub const TRUE: BOOL = 1;
+
+    pub const S_OK: HRESULT = 0;
+    pub const ERROR_ACCESS_DENIED: DWORD = 5;
+    pub const E_ACCESSDENIED: HRESULT = 0x8007_0005_u32 as HRESULT;
+
+    pub enum HWND__ {}
+    pub type HWND = *mut HWND__;
+    pub enum HMONITOR__ {}
+    pub type HMONITOR = *mut HMONITOR__;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct RECT {
+        pub left: LONG,
+        pub top: LONG,
+        pub right: LONG,
+        pub bottom: LONG,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    pub struct POINT {
+        pub x: LONG,
+        pub y: LONG,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    pub struct LOGFONTW {
+        pub lfHeight: LONG,
+        pub lfWidth: LONG,
+        pub lfEscapement: LONG,
+        pub lfOrientation: LONG,
+        pub lfWeight: LONG,
+        pub lfItalic: u8,
+        pub lfUnderline: u8,
+        pub lfStrikeOut: u8,
+        pub lfCharSet: u8,
+        pub lfOutPrecision: u8,
+        pub lfClipPrecision: u8,
+        pub lfQuality: u8,
+        pub lfPitchAndFamily: u8,
+        pub lfFaceName: [wchar_t; 32],
+    }
+
+    pub enum DPI_AWARENESS_CONTEXT__ {}
+    pub type DPI_AWARENESS_CONTEXT = *mut DPI_AWARENESS_CONTEXT__;
+    pub const DPI_AWARENESS_CONTEXT_UNAWARE: DPI_AWARENESS_CONTEXT = -1isize as DPI_AWARENESS_CONTEXT;
+    pub const DPI_AWARENESS_CONTEXT_SYSTEM_AWARE: DPI_AWARENESS_CONTEXT = -2isize as DPI_AWARENESS_CONTEXT;
+    pub const DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE: DPI_AWARENESS_CONTEXT = -3isize as DPI_AWARENESS_CONTEXT;
+    pub const DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2: DPI_AWARENESS_CONTEXT = -4isize as DPI_AWARENESS_CONTEXT;
+    pub const DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED: DPI_AWARENESS_CONTEXT = -5isize as DPI_AWARENESS_CONTEXT;
+
+    pub type DPI_AWARENESS = u32;
+    pub const DPI_AWARENESS_INVALID: DPI_AWARENESS = -1i32 as u32;
+    pub const DPI_AWARENESS_UNAWARE: DPI_AWARENESS = 0;
+    pub const DPI_AWARENESS_SYSTEM_AWARE: DPI_AWARENESS = 1;
+    pub const DPI_AWARENESS_PER_MONITOR_AWARE: DPI_AWARENESS = 2;
+
+    pub type DPI_HOSTING_BEHAVIOR = u32;
+    pub const DPI_HOSTING_BEHAVIOR_INVALID: DPI_HOSTING_BEHAVIOR = -1i32 as u32;
+    pub const DPI_HOSTING_BEHAVIOR_DEFAULT: DPI_HOSTING_BEHAVIOR = 0;
+    pub const DPI_HOSTING_BEHAVIOR_MIXED: DPI_HOSTING_BEHAVIOR = 1;
+
+    pub type PROCESS_DPI_AWARENESS = u32;
+    pub const PROCESS_DPI_UNAWARE: PROCESS_DPI_AWARENESS = 0;
+    pub const PROCESS_SYSTEM_DPI_AWARE: PROCESS_DPI_AWARENESS = 1;
+    pub const PROCESS_PER_MONITOR_DPI_AWARE: PROCESS_DPI_AWARENESS = 2;
+
+    pub type MONITOR_DPI_TYPE = u32;
+    pub const MDT_EFFECTIVE_DPI: MONITOR_DPI_TYPE = 0;
+
+    pub const SPI_GETICONTITLELOGFONT: UINT = 0x001F;
+}
@@ -0,0 +1,517 @@
+//! DPI-independent and device pixel geometry.
+//!
+//! Layout constants are written for 96 DPI as `Logical` values. They have to be
+//! `scale`d to a `Physical` value before they can be handed to Windows, and the
+//! two cannot be mixed by accident:
+//!
+//! ```ignore
+//! const DEFAULT_PADDING96: Logical<i32> = Logical(20);
+//! let padding: Physical<i32> = DEFAULT_PADDING96.scale(Dpi(144)); // Physical(30)
+//! ```
+
+use std::fmt;
+use std::ops::{Add, Sub, Neg};
+
+/// Dots per inch. 96 DPI is 100% scaling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Dpi(pub u32);
+
+impl Dpi {
+    /// `USER_DEFAULT_SCREEN_DPI`
+    pub const DEFAULT: Dpi = Dpi(96);
+
+    /// The scale factor relative to 96 DPI, e.g. 1.5 for 144 DPI.
+    pub fn scale_factor(self) -> f64 {
+        self.0 as f64 / Dpi::DEFAULT.0 as f64
+    }
+
+    /// The DPI for a scale factor, rounded to the nearest whole DPI.
+    pub fn from_scale_factor(factor: f64) -> Dpi {
+        let dpi = (factor * Dpi::DEFAULT.0 as f64).round();
+        if dpi.is_nan() || dpi <= 0.0 {
+            Dpi(0)
+        } else if dpi >= u32::MAX as f64 {
+            Dpi(u32::MAX)
+        } else {
+            Dpi(dpi as u32)
+        }
+    }
+}
+
+impl Default for Dpi {
+    fn default() -> Dpi {
+        Dpi::DEFAULT
+    }
+}
+
+impl fmt::Display for Dpi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} dpi", self.0)
+    }
+}
+
+/// `number * numerator / denominator` computed in 64 bits and rounded half
+/// away from zero, like `MulDiv`. Unlike `MulDiv`, which returns -1 when the
+/// result does not fit, the result saturates to the `i32` range; a zero
+/// denominator saturates in the direction of the numerator's sign.
+pub fn mul_div(number: i32, numerator: i32, denominator: i32) -> i32 {
+    let product = number as i64 * numerator as i64;
+    let negative = (product < 0) != (denominator < 0);
+
+    let magnitude = if denominator == 0 {
+        if product == 0 { 0 } else { i64::MAX }
+    } else {
+        let d = (denominator as i64).abs();
+        (product.abs() + d / 2) / d
+    };
+
+    let result = if negative { -magnitude } else { magnitude };
+    if result > i32::MAX as i64 {
+        i32::MAX
+    } else if result < i32::MIN as i64 {
+        i32::MIN
+    } else {
+        result as i32
+    }
+}
+
+/// Geometry that can be scaled between DPIs one coordinate at a time.
+pub trait Scalable: Copy {
+    fn mul_div(self, numerator: i32, denominator: i32) -> Self;
+}
+
+impl Scalable for i32 {
+    fn mul_div(self, numerator: i32, denominator: i32) -> i32 {
+        mul_div(self, numerator, denominator)
+    }
+}
+
+/// Arithmetic on lengths that saturates at the `i32` range instead of
+/// overflowing, like the rest of this module.
+pub trait Saturating: Copy {
+    fn saturating_add(self, rhs: Self) -> Self;
+    fn saturating_sub(self, rhs: Self) -> Self;
+    fn saturating_neg(self) -> Self;
+}
+
+impl Saturating for i32 {
+    fn saturating_add(self, rhs: i32) -> i32 {
+        i32::saturating_add(self, rhs)
+    }
+
+    fn saturating_sub(self, rhs: i32) -> i32 {
+        i32::saturating_sub(self, rhs)
+    }
+
+    fn saturating_neg(self) -> i32 {
+        i32::saturating_neg(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub fn new(x: i32, y: i32) -> Point {
+        Point { x, y }
+    }
+}
+
+impl Saturating for Point {
+    fn saturating_add(self, rhs: Point) -> Point {
+        Point { x: self.x.saturating_add(rhs.x), y: self.y.saturating_add(rhs.y) }
+    }
+
+    fn saturating_sub(self, rhs: Point) -> Point {
+        Point { x: self.x.saturating_sub(rhs.x), y: self.y.saturating_sub(rhs.y) }
+    }
+
+    fn saturating_neg(self) -> Point {
+        Point { x: self.x.saturating_neg(), y: self.y.saturating_neg() }
+    }
+}
+
+impl Scalable for Point {
+    fn mul_div(self, numerator: i32, denominator: i32) -> Point {
+        Point {
+            x: mul_div(self.x, numerator, denominator),
+            y: mul_div(self.y, numerator, denominator),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Size {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Size {
+    pub fn new(width: i32, height: i32) -> Size {
+        Size { width, height }
+    }
+}
+
+impl Saturating for Size {
+    fn saturating_add(self, rhs: Size) -> Size {
+        Size { width: self.width.saturating_add(rhs.width), height: self.height.saturating_add(rhs.height) }
+    }
+
+    fn saturating_sub(self, rhs: Size) -> Size {
+        Size { width: self.width.saturating_sub(rhs.width), height: self.height.saturating_sub(rhs.height) }
+    }
+
+    fn saturating_neg(self) -> Size {
+        Size { width: self.width.saturating_neg(), height: self.height.saturating_neg() }
+    }
+}
+
+impl Scalable for Size {
+    fn mul_div(self, numerator: i32, denominator: i32) -> Size {
+        Size {
+            width: mul_div(self.width, numerator, denominator),
+            height: mul_div(self.height, numerator, denominator),
+        }
+    }
+}
+
+/// A rectangle with exclusive `right` and `bottom` edges, like `RECT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Rect {
+    pub fn new(left: i32, top: i32, right: i32, bottom: i32) -> Rect {
+        Rect { left, top, right, bottom }
+    }
+
+    pub fn from_origin_size(origin: Point, size: Size) -> Rect {
+        Rect {
+            left: origin.x,
+            top: origin.y,
+            right: origin.x.saturating_add(size.width),
+            bottom: origin.y.saturating_add(size.height),
+        }
+    }
+
+    pub fn origin(&self) -> Point {
+        Point { x: self.left, y: self.top }
+    }
+
+    pub fn size(&self) -> Size {
+        Size {
+            width: self.right.saturating_sub(self.left),
+            height: self.bottom.saturating_sub(self.top),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.size().width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.size().height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.right <= self.left || self.bottom <= self.top
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.left && point.x < self.right && point.y >= self.top && point.y < self.bottom
+    }
+
+    /// The overlapping area, or `None` if the rectangles do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let r = Rect {
+            left: self.left.max(other.left),
+            top: self.top.max(other.top),
+            right: self.right.min(other.right),
+            bottom: self.bottom.min(other.bottom),
+        };
+        if r.is_empty() { None } else { Some(r) }
+    }
+
+    /// The area of the rectangle, 0 for empty rectangles.
+    pub fn area(&self) -> i64 {
+        if self.is_empty() {
+            0
+        } else {
+            self.width() as i64 * self.height() as i64
+        }
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect {
+            left: self.left.saturating_add(dx),
+            top: self.top.saturating_add(dy),
+            right: self.right.saturating_add(dx),
+            bottom: self.bottom.saturating_add(dy),
+        }
+    }
+}
+
+impl Scalable for Rect {
+    fn mul_div(self, numerator: i32, denominator: i32) -> Rect {
+        Rect {
+            left: mul_div(self.left, numerator, denominator),
+            top: mul_div(self.top, numerator, denominator),
+            right: mul_div(self.right, numerator, denominator),
+            bottom: mul_div(self.bottom, numerator, denominator),
+        }
+    }
+}
+
+/// A value in 96 DPI units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Logical<T>(pub T);
+
+/// A value in device pixels at some DPI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Physical<T>(pub T);
+
+impl<T: Scalable> Logical<T> {
+    /// Convert to device pixels at `dpi`.
+    pub fn scale(self, dpi: Dpi) -> Physical<T> {
+        Physical(self.0.mul_div(dpi_i32(dpi), Dpi::DEFAULT.0 as i32))
+    }
+}
+
+impl<T: Scalable> Physical<T> {
+    /// Convert device pixels at `dpi` back to 96 DPI units.
+    pub fn unscale(self, dpi: Dpi) -> Logical<T> {
+        Logical(self.0.mul_div(Dpi::DEFAULT.0 as i32, dpi_i32(dpi)))
+    }
+
+    /// Convert device pixels at `from` to device pixels at `to`, as Windows
+    /// does for the suggested rectangle in `WM_DPICHANGED`.
+    pub fn rescale(self, from: Dpi, to: Dpi) -> Physical<T> {
+        Physical(self.0.mul_div(dpi_i32(to), dpi_i32(from)))
+    }
+}
+
+fn dpi_i32(dpi: Dpi) -> i32 {
+    if dpi.0 > i32::MAX as u32 { i32::MAX } else { dpi.0 as i32 }
+}
+
+// Saturating, so that adding padding to a length near the `i32` limits
+// cannot wrap around to a negative size
+macro_rules! impl_unit_ops {
+    ($unit:ident) => {
+        impl<T: Saturating> Add for $unit<T> {
+            type Output = $unit<T>;
+            fn add(self, rhs: $unit<T>) -> $unit<T> {
+                $unit(self.0.saturating_add(rhs.0))
+            }
+        }
+
+        impl<T: Saturating> Sub for $unit<T> {
+            type Output = $unit<T>;
+            fn sub(self, rhs: $unit<T>) -> $unit<T> {
+                $unit(self.0.saturating_sub(rhs.0))
+            }
+        }
+
+        impl<T: Saturating> Neg for $unit<T> {
+            type Output = $unit<T>;
+            fn neg(self) -> $unit<T> {
+                $unit(self.0.saturating_neg())
+            }
+        }
+    };
+}
+
+impl_unit_ops!(Logical);
+impl_unit_ops!(Physical);
+
+#[cfg(windows)]
+mod win_conversions {
+    use winapi::shared::windef::{POINT, RECT, SIZE};
+    use super::{Point, Rect, Size};
+
+    impl From<POINT> for Point {
+        fn from(p: POINT) -> Point {
+            Point { x: p.x, y: p.y }
+        }
+    }
+
+    impl From<Point> for POINT {
+        fn from(p: Point) -> POINT {
+            POINT { x: p.x, y: p.y }
+        }
+    }
+
+    impl From<SIZE> for Size {
+        fn from(s: SIZE) -> Size {
+            Size { width: s.cx, height: s.cy }
+        }
+    }
+
+    impl From<Size> for SIZE {
+        fn from(s: Size) -> SIZE {
+            SIZE { cx: s.width, cy: s.height }
+        }
+    }
+
+    impl From<RECT> for Rect {
+        fn from(r: RECT) -> Rect {
+            Rect { left: r.left, top: r.top, right: r.right, bottom: r.bottom }
+        }
+    }
+
+    impl From<Rect> for RECT {
+        fn from(r: Rect) -> RECT {
+            RECT { left: r.left, top: r.top, right: r.right, bottom: r.bottom }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Wine's MulDiv: -1 for a zero divisor or a result outside -(2^31 - 1)..2^31
+    fn reference_mul_div(number: i32, numerator: i32, denominator: i32) -> i32 {
+        if denominator == 0 {
+            return -1;
+        }
+        let (mut number, mut denominator) = (number as i64, denominator as i64);
+        if denominator < 0 {
+            number = -number;
+            denominator = -denominator;
+        }
+        let product = number * numerator as i64;
+        let result = if (number < 0) == (numerator < 0) {
+            (product + denominator / 2) / denominator
+        } else {
+            (product - denominator / 2) / denominator
+        };
+        if result > i32::MAX as i64 || result < -(i32::MAX as i64) {
+            -1
+        } else {
+            result as i32
+        }
+    }
+
+    // The exact quotient, for telling MulDiv's -1 results apart
+    fn exact(number: i32, numerator: i32, denominator: i32) -> f64 {
+        number as f64 * numerator as f64 / denominator as f64
+    }
+
+    const EDGES: &[i32] = &[
+        0, 1, -1, 2, -2, 3, -3, 47, -47, 48, -48, 72, 95, -95, 96, -96, 97, 120, 144, -144, 192, 1 << 15, -(1 << 15),
+        i32::MAX, i32::MAX - 1, i32::MIN, i32::MIN + 1, i32::MAX / 96, i32::MIN / 96,
+    ];
+
+    // xorshift, so the cases are the same on every run
+    fn samples(count: usize) -> impl Iterator<Item = (i32, i32, i32)> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        (0..count).map(move |_| {
+            let r = next();
+            // mostly small values, where rounding matters, and some of any size
+            let pick = |bits: u64| if bits & 0xf < 12 { (bits >> 8) as i32 % 2000 } else { (bits >> 16) as i32 };
+            (pick(r), pick(r.rotate_left(21)), pick(r.rotate_left(42)))
+        })
+    }
+
+    fn cases() -> impl Iterator<Item = (i32, i32, i32)> {
+        let edges = EDGES.iter().flat_map(|&a| EDGES.iter().flat_map(move |&b| EDGES.iter().map(move |&c| (a, b, c))));
+        edges.chain(samples(200_000))
+    }
+
+    #[test]
+    fn mul_div_matches_muldiv_where_it_fits() {
+        for (number, numerator, denominator) in cases() {
+            let expected = reference_mul_div(number, numerator, denominator);
+            let fits = denominator != 0 && exact(number, numerator, denominator).abs() < i32::MAX as f64;
+            if expected != -1 || fits {
+                assert_eq!(mul_div(number, numerator, denominator), expected, "{} * {} / {}", number, numerator, denominator);
+            }
+        }
+    }
+
+    #[test]
+    fn mul_div_saturates_where_muldiv_overflows_to_minus_one() {
+        for (number, numerator, denominator) in cases() {
+            if denominator == 0 || reference_mul_div(number, numerator, denominator) != -1 {
+                continue;
+            }
+            let exact = exact(number, numerator, denominator);
+            if exact >= i32::MAX as f64 {
+                assert_eq!(mul_div(number, numerator, denominator), i32::MAX, "{} * {} / {}", number, numerator, denominator);
+            } else if exact <= i32::MIN as f64 {
+                assert_eq!(mul_div(number, numerator, denominator), i32::MIN, "{} * {} / {}", number, numerator, denominator);
+            }
+        }
+        assert_eq!(mul_div(i32::MAX, 2, 1), i32::MAX);
+        assert_eq!(mul_div(i32::MIN, 2, 1), i32::MIN);
+        assert_eq!(mul_div(i32::MIN, -1, 1), i32::MAX);
+    }
+
+    #[test]
+    fn mul_div_rounds_half_away_from_zero() {
+        assert_eq!(mul_div(1, 1, 2), 1);
+        assert_eq!(mul_div(-1, 1, 2), -1);
+        assert_eq!(mul_div(1, -1, 2), -1);
+        assert_eq!(mul_div(1, 1, -2), -1);
+        assert_eq!(mul_div(-1, -1, -2), -1);
+        assert_eq!(mul_div(5, 144, 96), 8);
+        assert_eq!(mul_div(-5, 144, 96), -8);
+        assert_eq!(mul_div(1, 1, 3), 0);
+        assert_eq!(mul_div(2, 1, 3), 1);
+    }
+
+    #[test]
+    fn a_zero_divisor_saturates_towards_the_sign() {
+        assert_eq!(mul_div(0, 5, 0), 0);
+        assert_eq!(mul_div(5, 0, 0), 0);
+        assert_eq!(mul_div(5, 3, 0), i32::MAX);
+        assert_eq!(mul_div(-5, 3, 0), i32::MIN);
+        assert_eq!(mul_div(-5, -3, 0), i32::MAX);
+    }
+
+    #[test]
+    fn scaling_up_round_trips() {
+        for &dpi in &[96, 120, 144, 168, 192, 240, 288, 384] {
+            for x in -5000..5000 {
+                assert_eq!(Logical(x).scale(Dpi(dpi)).unscale(Dpi(dpi)), Logical(x), "{} at {} dpi", x, dpi);
+            }
+        }
+        assert_eq!(Logical(20).scale(Dpi(144)), Physical(30));
+        assert_eq!(Physical(Rect::new(0, 0, 150, 75)).rescale(Dpi(144), Dpi(96)), Physical(Rect::new(0, 0, 100, 50)));
+        assert_eq!(Physical(Point::new(-3, 3)).unscale(Dpi(0)), Logical(Point::new(i32::MIN, i32::MAX)));
+    }
+
+    #[test]
+    fn unit_arithmetic_saturates() {
+        assert_eq!(Logical(i32::MAX) + Logical(1), Logical(i32::MAX));
+        assert_eq!(Logical(i32::MIN) - Logical(1), Logical(i32::MIN));
+        assert_eq!(-Physical(i32::MIN), Physical(i32::MAX));
+        assert_eq!(Physical(Size::new(i32::MAX, 1)) + Physical(Size::new(1, 1)), Physical(Size::new(i32::MAX, 2)));
+        assert_eq!(Logical(Point::new(i32::MIN, 0)) - Logical(Point::new(1, 1)), Logical(Point::new(i32::MIN, -1)));
+        assert_eq!(Logical(300) - Logical(20) - Logical(20), Logical(260));
+    }
+
+    #[test]
+    fn dpi_from_scale_factor() {
+        assert_eq!(Dpi::from_scale_factor(1.5), Dpi(144));
+        assert_eq!(Dpi::from_scale_factor(1.25), Dpi(120));
+        assert_eq!(Dpi::from_scale_factor(-1.0), Dpi(0));
+        assert_eq!(Dpi::from_scale_factor(f64::NAN), Dpi(0));
+        assert_eq!(Dpi::from_scale_factor(f64::INFINITY), Dpi(u32::MAX));
+        assert_eq!(Dpi(144).scale_factor(), 1.5);
+    }
+}
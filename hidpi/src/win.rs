@@ -1,27 +1,49 @@
+// Every wrapper keeps the same `if let` shape around its dynamic symbol
+#![allow(clippy::manual_map)]
+
 use declare_macro::declare_functions;
 use std::io;
 
-use winapi::{
-	shared::{
-        minwindef::BOOL, ntdef::HANDLE, winerror::S_OK,
-        windef::{
-            DPI_AWARENESS,
-            DPI_AWARENESS_CONTEXT,
-            DPI_HOSTING_BEHAVIOR,
-        }
-    },
-	um::{
-        shellscalingapi::PROCESS_DPI_AWARENESS,
-        shellscalingapi::PROCESS_DPI_UNAWARE,
-        shellscalingapi::PROCESS_SYSTEM_DPI_AWARE,
-        shellscalingapi::PROCESS_PER_MONITOR_DPI_AWARE,
-        winnt::HRESULT
-    },
+use std::mem;
+
+use crate::sys::{
+    c_int,
+    c_void,
+    BOOL,
+    DWORD,
+    FALSE,
+    UINT,
+    HANDLE,
+    HRESULT,
+    S_OK,
+    DPI_AWARENESS,
+    DPI_AWARENESS_CONTEXT,
+    DPI_HOSTING_BEHAVIOR,
+    HMONITOR,
+    HWND,
+    POINT,
+    RECT,
+    MONITOR_DPI_TYPE,
+    PROCESS_DPI_AWARENESS,
+    PROCESS_DPI_UNAWARE,
+    PROCESS_SYSTEM_DPI_AWARE,
+    PROCESS_PER_MONITOR_DPI_AWARE,
+    LOGFONTW,
+    SPI_GETICONTITLELOGFONT,
 };
 
 lazy_static::lazy_static! {
-	static ref USER32: Option<libloading::Library> = { libloading::Library::new("user32.dll").ok() };
-	static ref SHCORE: Option<libloading::Library> = { libloading::Library::new("shcore.dll").ok() };
+	static ref USER32: Option<libloading::Library> = load("user32.dll");
+	static ref SHCORE: Option<libloading::Library> = load("shcore.dll");
+}
+
+// Other platforms have neither, so every function is missing there
+fn load(name: &str) -> Option<libloading::Library> {
+    if cfg!(windows) {
+        libloading::Library::new(name).ok()
+    } else {
+        None
+    }
 }
 
 declare_functions! {
@@ -32,7 +54,7 @@ declare_functions! {
 		pub fn SetProcessDPIAware() -> BOOL;
 		#[library(SHCORE)]
 		pub fn GetProcessDpiAwareness(
-			hProcess: HANDLE,
+			h_process: HANDLE,
 			value: *mut PROCESS_DPI_AWARENESS,
 		) -> HRESULT;
         #[library(SHCORE)]
@@ -58,11 +80,79 @@ declare_functions! {
         pub fn GetThreadDpiHostingBehavior() -> DPI_HOSTING_BEHAVIOR;
         #[library(USER32)]
         pub fn SetThreadDpiAwarenessContext(
-            dpiContext: DPI_AWARENESS_CONTEXT
+            dpi_context: DPI_AWARENESS_CONTEXT
         ) -> DPI_AWARENESS_CONTEXT;
+        #[library(USER32)]
+        pub fn SetProcessDpiAwarenessContext(
+            value: DPI_AWARENESS_CONTEXT
+        ) -> BOOL;
+        #[library(USER32)]
+        pub fn GetWindowDpiAwarenessContext(
+            hwnd: HWND
+        ) -> DPI_AWARENESS_CONTEXT;
+        #[library(USER32)]
+        pub fn GetWindowDpiHostingBehavior(
+            hwnd: HWND
+        ) -> DPI_HOSTING_BEHAVIOR;
+        #[library(USER32)]
+        pub fn GetDpiFromDpiAwarenessContext(
+            value: DPI_AWARENESS_CONTEXT
+        ) -> UINT;
+        #[library(USER32)]
+        pub fn IsValidDpiAwarenessContext(
+            value: DPI_AWARENESS_CONTEXT
+        ) -> BOOL;
+        #[library(USER32)]
+        pub fn GetDpiForSystem() -> UINT;
+        #[library(USER32)]
+        pub fn GetDpiForWindow(
+            hwnd: HWND
+        ) -> UINT;
+        #[library(SHCORE)]
+        pub fn GetDpiForMonitor(
+            hmonitor: HMONITOR,
+            dpi_type: MONITOR_DPI_TYPE,
+            dpi_x: *mut UINT,
+            dpi_y: *mut UINT
+        ) -> HRESULT;
+        #[library(USER32)]
+        pub fn GetSystemDpiForProcess(
+            process: HANDLE
+        ) -> UINT;
+        #[library(USER32)]
+        pub fn AdjustWindowRectExForDpi(
+            rect: *mut RECT,
+            style: DWORD,
+            menu: BOOL,
+            ex_style: DWORD,
+            dpi: UINT
+        ) -> BOOL;
+        #[library(USER32)]
+        pub fn GetSystemMetricsForDpi(
+            index: c_int,
+            dpi: UINT
+        ) -> c_int;
+        #[library(USER32)]
+        pub fn SystemParametersInfoForDpi(
+            action: UINT,
+            param: UINT,
+            pv_param: *mut c_void,
+            win_ini: UINT,
+            dpi: UINT
+        ) -> BOOL;
+        #[library(USER32)]
+        pub fn EnableNonClientDpiScaling(
+            hwnd: HWND
+        ) -> BOOL;
+        #[library(USER32)]
+        pub fn LogicalToPhysicalPointForPerMonitorDPI(
+            hwnd: HWND,
+            point: *mut POINT
+        ) -> BOOL;
 	}
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum WinDpiAwareness {
     ProcessDpiUnaware,
     ProcessSystemDpiAware,
@@ -143,7 +233,20 @@ pub fn set_process_dpi_awareness(win_awareness: WinDpiAwareness) -> io::Result<b
     };
 
     if let Some(set_process_dpi_awareness) = dynamic::SetProcessDpiAwareness.as_ref() {
-        if S_OK == unsafe { set_process_dpi_awareness(awareness) } {
+        let hr = unsafe { set_process_dpi_awareness(awareness) };
+        if S_OK == hr {
+            Ok(true)
+        } else {
+            Err(io::Error::from_raw_os_error(hr))
+        }
+    } else {
+        Ok(false)
+    }
+}
+
+pub fn set_process_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT) -> io::Result<bool> {
+    if let Some(set_process_dpi_awareness_context) = dynamic::SetProcessDpiAwarenessContext.as_ref() {
+        if FALSE != unsafe { set_process_dpi_awareness_context(context) } {
             Ok(true)
         } else {
             Err(io::Error::last_os_error())
@@ -175,4 +278,140 @@ pub fn set_thread_dpi_hosting_behavior(behavior: DPI_HOSTING_BEHAVIOR) -> Option
     } else {
         None
     }
-}
\ No newline at end of file
+}
+
+pub fn get_window_dpi_awareness_context(h_wnd: HWND) -> Option<DPI_AWARENESS_CONTEXT> {
+    if let Some(get_window_dpi_awareness_context) = dynamic::GetWindowDpiAwarenessContext.as_ref() {
+        Some(unsafe { get_window_dpi_awareness_context(h_wnd) })
+    } else {
+        None
+    }
+}
+
+pub fn get_window_dpi_hosting_behavior(h_wnd: HWND) -> Option<DPI_HOSTING_BEHAVIOR> {
+    if let Some(get_window_dpi_hosting_behavior) = dynamic::GetWindowDpiHostingBehavior.as_ref() {
+        Some(unsafe { get_window_dpi_hosting_behavior(h_wnd) })
+    } else {
+        None
+    }
+}
+
+pub fn get_dpi_from_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT) -> Option<UINT> {
+    if let Some(get_dpi_from_dpi_awareness_context) = dynamic::GetDpiFromDpiAwarenessContext.as_ref() {
+        Some(unsafe { get_dpi_from_dpi_awareness_context(context) })
+    } else {
+        None
+    }
+}
+
+pub fn is_valid_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT) -> Option<bool> {
+    if let Some(is_valid_dpi_awareness_context) = dynamic::IsValidDpiAwarenessContext.as_ref() {
+        Some(unsafe { is_valid_dpi_awareness_context(context) } != FALSE)
+    } else {
+        None
+    }
+}
+
+pub fn get_dpi_for_system() -> Option<UINT> {
+    if let Some(get_dpi_for_system) = dynamic::GetDpiForSystem.as_ref() {
+        Some(unsafe { get_dpi_for_system() })
+    } else {
+        None
+    }
+}
+
+pub fn get_dpi_for_window(h_wnd: HWND) -> Option<UINT> {
+    if let Some(get_dpi_for_window) = dynamic::GetDpiForWindow.as_ref() {
+        Some(unsafe { get_dpi_for_window(h_wnd) })
+    } else {
+        None
+    }
+}
+
+/// Returns the horizontal and vertical DPI of the monitor.
+pub fn get_dpi_for_monitor(h_monitor: HMONITOR, dpi_type: MONITOR_DPI_TYPE) -> io::Result<Option<(UINT, UINT)>> {
+    if let Some(get_dpi_for_monitor) = dynamic::GetDpiForMonitor.as_ref() {
+        let mut dpi_x: UINT = 0;
+        let mut dpi_y: UINT = 0;
+        let hr = unsafe { get_dpi_for_monitor(h_monitor, dpi_type, &mut dpi_x, &mut dpi_y) };
+        if S_OK == hr {
+            Ok(Some((dpi_x, dpi_y)))
+        } else {
+            Err(io::Error::from_raw_os_error(hr))
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn get_system_dpi_for_process(h_process: HANDLE) -> Option<UINT> {
+    if let Some(get_system_dpi_for_process) = dynamic::GetSystemDpiForProcess.as_ref() {
+        Some(unsafe { get_system_dpi_for_process(h_process) })
+    } else {
+        None
+    }
+}
+
+pub fn adjust_window_rect_ex_for_dpi(rect: &mut RECT, style: DWORD, menu: bool, ex_style: DWORD, dpi: UINT) -> Option<bool> {
+    if let Some(adjust_window_rect_ex_for_dpi) = dynamic::AdjustWindowRectExForDpi.as_ref() {
+        Some(unsafe { adjust_window_rect_ex_for_dpi(rect, style, menu as BOOL, ex_style, dpi) } != FALSE)
+    } else {
+        None
+    }
+}
+
+pub fn get_system_metrics_for_dpi(index: c_int, dpi: UINT) -> Option<c_int> {
+    if let Some(get_system_metrics_for_dpi) = dynamic::GetSystemMetricsForDpi.as_ref() {
+        Some(unsafe { get_system_metrics_for_dpi(index, dpi) })
+    } else {
+        None
+    }
+}
+
+/// `SystemParametersInfoForDpi`, or `None` before Windows 10 1607.
+///
+/// # Safety
+///
+/// `pv_param` must point to whatever `action` expects.
+pub unsafe fn system_parameters_info_for_dpi(action: UINT, param: UINT, pv_param: *mut c_void, win_ini: UINT, dpi: UINT) -> Option<bool> {
+    if let Some(system_parameters_info_for_dpi) = dynamic::SystemParametersInfoForDpi.as_ref() {
+        Some(system_parameters_info_for_dpi(action, param, pv_param, win_ini, dpi) != FALSE)
+    } else {
+        None
+    }
+}
+
+/// `SystemParametersInfoForDpi(SPI_GETICONTITLELOGFONT)`: the font used for icon titles, scaled for `dpi`.
+pub fn get_icon_title_log_font_for_dpi(dpi: UINT) -> Option<LOGFONTW> {
+    let mut lf = LOGFONTW::default();
+    let ok = unsafe {
+        system_parameters_info_for_dpi(
+            SPI_GETICONTITLELOGFONT,
+            mem::size_of::<LOGFONTW>() as UINT,
+            &mut lf as *mut LOGFONTW as *mut c_void,
+            0,
+            dpi)
+    }?;
+
+    if ok {
+        Some(lf)
+    } else {
+        None
+    }
+}
+
+pub fn enable_non_client_dpi_scaling(h_wnd: HWND) -> Option<bool> {
+    if let Some(enable_non_client_dpi_scaling) = dynamic::EnableNonClientDpiScaling.as_ref() {
+        Some(unsafe { enable_non_client_dpi_scaling(h_wnd) } != FALSE)
+    } else {
+        None
+    }
+}
+
+pub fn logical_to_physical_point_for_per_monitor_dpi(h_wnd: HWND, point: &mut POINT) -> Option<bool> {
+    if let Some(logical_to_physical_point_for_per_monitor_dpi) = dynamic::LogicalToPhysicalPointForPerMonitorDPI.as_ref() {
+        Some(unsafe { logical_to_physical_point_for_per_monitor_dpi(h_wnd, point) } != FALSE)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,419 @@
+//! The Win32 implementation of the crate's API, re-exported from the crate root.
+//!
+//! Everything but the handful of user32 and GDI helpers goes through the
+//! `backend`, so it builds on every platform and can be exercised against a
+//! mock there.
+
+use std::io;
+#[cfg(windows)]
+use std::ptr;
+#[cfg(windows)]
+use winapi::{
+	shared::{
+        minwindef::{
+            WPARAM,
+            LPARAM,
+            LRESULT,
+            HINSTANCE,
+         },
+        windef::{
+            HFONT,
+            HBRUSH,
+        }
+    },
+	um::{
+        winuser::{
+            self,
+            HWND_DESKTOP,
+        },
+        wingdi,
+    },
+};
+
+use crate::sys::{
+    DWORD,
+    FALSE,
+    UINT,
+    INT,
+    HANDLE,
+    POINT,
+    RECT,
+    HWND,
+    HMONITOR,
+    DPI_AWARENESS,
+    DPI_AWARENESS_INVALID,
+    DPI_AWARENESS_SYSTEM_AWARE,
+    DPI_AWARENESS_PER_MONITOR_AWARE,
+    DPI_AWARENESS_CONTEXT,
+    MONITOR_DPI_TYPE,
+    MDT_EFFECTIVE_DPI,
+    LOGFONTW,
+};
+#[cfg(windows)]
+use crate::sys::{BOOL, TRUE};
+use crate::{backend, win, DpiAwareness, DpiAwarenessContext, DpiHostingBehavior, USER_DEFAULT_SCREEN_DPI};
+
+pub const GA_PARENT: UINT = 1;
+
+/// The window handle `get_window_dpi` takes on Windows.
+#[cfg(windows)]
+pub type NativeWindow = HWND;
+
+pub fn is_process_dpi_aware() -> Option<bool> {
+	backend::current(|b| b.is_process_dpi_aware())
+}
+
+pub fn get_process_dpi_awareness() -> io::Result<Option<DpiAwareness>> {
+	Ok(backend::current(|b| b.get_process_dpi_awareness())?.map(|awareness| {
+		match awareness {
+			win::WinDpiAwareness::ProcessDpiUnaware => DpiAwareness::Unaware,
+			win::WinDpiAwareness::ProcessSystemDpiAware => DpiAwareness::System,
+			win::WinDpiAwareness::ProcessPerMonitorDpiAware => DpiAwareness::PerMonitor,
+			win::WinDpiAwareness::Unknown(_o) => DpiAwareness::Other,
+		}
+	}))
+}
+
+pub fn set_process_dpi_aware() -> Option<bool> {
+	backend::current(|b| b.set_process_dpi_aware())
+}
+
+pub fn set_process_dpi_awareness(awareness: DpiAwareness) -> io::Result<bool> {
+	let win_awareness = match awareness {
+		DpiAwareness::Unaware => Ok(win::WinDpiAwareness::ProcessDpiUnaware),
+		DpiAwareness::System => Ok(win::WinDpiAwareness::ProcessSystemDpiAware),
+		DpiAwareness::PerMonitor => Ok(win::WinDpiAwareness::ProcessPerMonitorDpiAware),
+		_ => Err(io::Error::new(io::ErrorKind::Other, format!("unsupported dpi awareness type {:?}", awareness)))
+	}?;
+
+	backend::current(|b| b.set_process_dpi_awareness(win_awareness))
+}
+
+#[cfg(windows)]
+pub fn get_parent_relative_window_rect(h_wnd: HWND, child_bounds: *mut RECT) -> BOOL
+{
+    if FALSE == unsafe { winuser::GetWindowRect(h_wnd, child_bounds) }
+    {
+        return FALSE;
+    }
+    
+    unsafe { winuser::MapWindowPoints(HWND_DESKTOP, winuser::GetAncestor(h_wnd, GA_PARENT), child_bounds as *mut POINT, 2); }
+
+    return TRUE;
+}
+
+#[cfg(windows)]
+pub fn get_stock_brush(brush: UINT) -> HBRUSH {
+    unsafe { wingdi::GetStockObject(brush as INT) as HBRUSH }
+}
+
+#[cfg(windows)]
+pub fn get_hinstance_for_h_wnd(h_wnd: HWND) -> HINSTANCE {
+    unsafe { winuser::GetWindowLongW(h_wnd, winuser::GWL_HINSTANCE) as HINSTANCE }
+}
+
+#[cfg(windows)]
+pub fn get_window_font(h_wnd: HWND) -> HFONT {
+    unsafe  {  winuser::SendMessageW(h_wnd, winuser::WM_GETFONT, 0, 0) as HFONT }
+}
+
+#[cfg(windows)]
+pub fn set_window_font(h_wnd: HWND, h_font: HFONT, f_redraw: BOOL) -> LRESULT {
+    unsafe { winuser::SendMessageW(h_wnd, winuser::WM_SETFONT, h_font as WPARAM, f_redraw as LPARAM) }
+}
+
+/// `GetDpiForSystem`, or the `LOGPIXELSX` of the screen DC before Windows 10 1607.
+pub fn get_dpi_for_system() -> UINT {
+    backend::current(|b| b.get_dpi_for_system())
+        .unwrap_or_else(screen_dpi)
+}
+
+#[cfg(windows)]
+fn screen_dpi() -> UINT {
+    unsafe {
+        let hdc = winuser::GetDC(ptr::null_mut());
+        if hdc.is_null() {
+            return USER_DEFAULT_SCREEN_DPI;
+        }
+        let dpi = wingdi::GetDeviceCaps(hdc, wingdi::LOGPIXELSX);
+        winuser::ReleaseDC(ptr::null_mut(), hdc);
+        dpi as UINT
+    }
+}
+
+#[cfg(not(windows))]
+fn screen_dpi() -> UINT {
+    USER_DEFAULT_SCREEN_DPI
+}
+
+/// `GetDpiForWindow`, or the effective DPI of the window's monitor before
+/// Windows 10 1607, or the system DPI before Windows 8.1.
+pub fn get_dpi_for_window(h_wnd: HWND) -> UINT {
+    backend::current(|b| {
+        b.get_dpi_for_window(h_wnd)
+            .or_else(|| {
+                b.get_dpi_for_monitor(monitor_from_window(h_wnd), MDT_EFFECTIVE_DPI)
+                    .ok()
+                    .flatten()
+                    .map(|(dpi_x, _dpi_y)| dpi_x)
+            })
+    })
+    .unwrap_or_else(get_dpi_for_system)
+}
+
+#[cfg(windows)]
+fn monitor_from_window(h_wnd: HWND) -> HMONITOR {
+    unsafe { winuser::MonitorFromWindow(h_wnd, winuser::MONITOR_DEFAULTTONEAREST) }
+}
+
+// There are no monitors to ask without Windows, a mock backend answers for
+// the null one
+#[cfg(not(windows))]
+fn monitor_from_window(_h_wnd: HWND) -> HMONITOR {
+    std::ptr::null_mut()
+}
+
+/// Returns the horizontal and vertical DPI of a monitor.
+pub fn get_dpi_for_monitor(h_monitor: HMONITOR, dpi_type: MONITOR_DPI_TYPE) -> io::Result<Option<(UINT, UINT)>> {
+    backend::current(|b| b.get_dpi_for_monitor(h_monitor, dpi_type))
+}
+
+pub fn get_system_dpi_for_process(h_process: HANDLE) -> Option<UINT> {
+    backend::current(|b| b.get_system_dpi_for_process(h_process))
+}
+
+pub fn get_window_dpi_awareness_context(h_wnd: HWND) -> Option<DpiAwarenessContext> {
+    backend::current(|b| b.get_window_dpi_awareness_context(h_wnd))
+        .and_then(DpiAwarenessContext::from_raw)
+}
+
+pub fn get_window_dpi_hosting_behavior(h_wnd: HWND) -> Option<DpiHostingBehavior> {
+    backend::current(|b| b.get_window_dpi_hosting_behavior(h_wnd))
+        .and_then(DpiHostingBehavior::from_raw)
+}
+
+/// The DPI a System-aware context was created with, or 0 for the other contexts.
+pub fn get_dpi_from_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT) -> Option<UINT> {
+    backend::current(|b| b.get_dpi_from_dpi_awareness_context(context))
+}
+
+pub fn is_valid_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT) -> Option<bool> {
+    backend::current(|b| b.is_valid_dpi_awareness_context(context))
+}
+
+pub fn adjust_window_rect_ex_for_dpi(rect: &mut RECT, style: DWORD, menu: bool, ex_style: DWORD, dpi: UINT) -> Option<bool> {
+    backend::current(|b| b.adjust_window_rect_ex_for_dpi(rect, style, menu, ex_style, dpi))
+}
+
+pub fn get_system_metrics_for_dpi(index: INT, dpi: UINT) -> Option<INT> {
+    backend::current(|b| b.get_system_metrics_for_dpi(index, dpi))
+}
+
+/// The icon title font (the usual font for child controls) scaled for `dpi`.
+pub fn get_icon_title_log_font_for_dpi(dpi: UINT) -> Option<LOGFONTW> {
+    backend::current(|b| b.get_icon_title_log_font_for_dpi(dpi))
+}
+
+/// Per-monitor (V1) windows need this in `WM_NCCREATE` to get a DPI scaled
+/// caption, menu and scroll bars on Windows 10 1607. Per-Monitor V2 windows get
+/// it automatically.
+pub fn enable_non_client_dpi_scaling(h_wnd: HWND) -> Option<bool> {
+    backend::current(|b| b.enable_non_client_dpi_scaling(h_wnd))
+}
+
+pub fn logical_to_physical_point_for_per_monitor_dpi(h_wnd: HWND, point: &mut POINT) -> Option<bool> {
+    backend::current(|b| b.logical_to_physical_point_for_per_monitor_dpi(h_wnd, point))
+}
+
+pub fn get_maybe_dpi_by_awareness(h_wnd: HWND) -> Option<UINT> {
+    match get_thread_dpi_awareness() {
+        Some(DPI_AWARENESS_SYSTEM_AWARE) => {
+            Some(get_dpi_for_system())
+        },
+        Some(DPI_AWARENESS_PER_MONITOR_AWARE) => {
+            Some(get_dpi_for_window(h_wnd))
+        },
+        _ => None
+    }
+}
+
+pub fn are_dpi_awareness_contexts_equal(a: DPI_AWARENESS_CONTEXT, b: DPI_AWARENESS_CONTEXT) -> Option<bool> {
+    backend::current(|backend| backend.are_dpi_awareness_contexts_equal(a, b))
+        .map(|equal| equal != FALSE)
+}
+
+/// The thread's context as Windows hands it out, which is not one of the
+/// predefined pseudo-handles: identify it with `DpiAwarenessContext::from_raw`.
+pub fn get_thread_dpi_awareness_context() -> Option<DPI_AWARENESS_CONTEXT> {
+    backend::current(|b| b.get_thread_dpi_awareness_context())
+}
+
+pub fn get_thread_dpi_awareness() -> Option<DPI_AWARENESS> {
+    backend::current(|b| {
+        b.get_thread_dpi_awareness_context()
+            .and_then(|context| b.get_awareness_from_dpi_awareness_context(context))
+    })
+}
+
+pub fn get_awareness_from_dpi_awareness_context(context: DPI_AWARENESS_CONTEXT) -> Option<DPI_AWARENESS> {
+    backend::current(|b| b.get_awareness_from_dpi_awareness_context(context))
+}
+
+pub fn get_thread_dpi_hosting_behavior() -> Option<DpiHostingBehavior> {
+    backend::current(|b| b.get_thread_dpi_hosting_behavior())
+        .and_then(DpiHostingBehavior::from_raw)
+}
+
+/// Returns the previous hosting behavior, or `None` if the thread's hosting
+/// behavior cannot be changed on this version of Windows.
+pub fn set_thread_dpi_hosting_behavior(behavior: DpiHostingBehavior) -> Option<DpiHostingBehavior> {
+    backend::current(|b| b.set_thread_dpi_hosting_behavior(behavior.to_raw()))
+        .and_then(DpiHostingBehavior::from_raw)
+}
+
+/// Returns the previous awareness context, or `None` if the thread's awareness
+/// context cannot be changed on this version of Windows or `context` was
+/// refused.
+///
+/// The previous context is returned as Windows handed it out, so passing it
+/// back restores exactly what was there, whether or not it is one of the
+/// `DpiAwarenessContext` names.
+pub fn set_thread_dpi_awareness_context<C>(context: C) -> Option<DPI_AWARENESS_CONTEXT> where C: Into<DPI_AWARENESS_CONTEXT> {
+    let context = context.into();
+    backend::current(|b| b.set_thread_dpi_awareness_context(context))
+        .filter(|previous| !previous.is_null())
+}
+
+pub fn awareness_to_str(awareness: DPI_AWARENESS) -> &'static str {
+    match awareness {
+        DPI_AWARENESS_INVALID => {
+           "DPI_AWARENESS_INVALID"
+        },
+        DPI_AWARENESS_SYSTEM_AWARE => {
+            "DPI_AWARENESS_SYSTEM_AWARE"
+        },
+        DPI_AWARENESS_PER_MONITOR_AWARE => {
+            "DPI_AWARENESS_PER_MONITOR_AWARE"
+        },
+        _ => {
+            "DPI_AWARENESS_UNAWARE"
+        }
+    }
+}
+
+pub fn awareness_context_to_str(context: DpiAwarenessContext) -> &'static str {
+    context.name()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use super::*;
+    use crate::backend::with_backend;
+    use crate::mock::{MockBackend, Release};
+    use crate::sys::{
+        DPI_AWARENESS_CONTEXT_SYSTEM_AWARE,
+        DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED,
+    };
+
+    // The functions each release is expected to have, by whether they answer
+    fn available() -> [bool; 11] {
+        let mut rect = RECT::default();
+        let mut point = POINT { x: 10, y: 20 };
+        [
+            get_window_dpi_awareness_context(ptr::null_mut()).is_some(),
+            get_window_dpi_hosting_behavior(ptr::null_mut()).is_some(),
+            get_dpi_from_dpi_awareness_context(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE).is_some(),
+            is_valid_dpi_awareness_context(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE).is_some(),
+            get_system_dpi_for_process(ptr::null_mut()).is_some(),
+            adjust_window_rect_ex_for_dpi(&mut rect, 0, false, 0, 96).is_some(),
+            get_system_metrics_for_dpi(10, 96).is_some(),
+            get_icon_title_log_font_for_dpi(96).is_some(),
+            enable_non_client_dpi_scaling(ptr::null_mut()).is_some(),
+            logical_to_physical_point_for_per_monitor_dpi(ptr::null_mut(), &mut point).is_some(),
+            get_dpi_for_monitor(ptr::null_mut(), MDT_EFFECTIVE_DPI).unwrap().is_some(),
+        ]
+    }
+
+    #[test]
+    fn missing_functions_are_none_on_each_release() {
+        let expected = [
+            (Release::None, [false, false, false, false, false, false, false, false, false, false, false]),
+            (Release::Windows7, [false, false, false, false, false, false, false, false, false, false, false]),
+            (Release::Windows81, [false, false, false, false, false, false, false, false, false, true, true]),
+            (Release::Windows10_1607, [true, false, false, true, false, true, true, true, true, true, true]),
+            (Release::Windows10_1703, [true, false, false, true, false, true, true, true, true, true, true]),
+            (Release::Windows10_1803, [true; 11]),
+        ];
+        for (release, expected) in &expected {
+            assert_eq!(with_backend(MockBackend::new(*release), available), *expected, "{:?}", release);
+        }
+    }
+
+    #[test]
+    fn window_dpi_falls_back_to_the_monitor_then_the_system() {
+        let dpi = |release| with_backend(MockBackend::new(release).with_dpi(120, 144, 192), || get_dpi_for_window(ptr::null_mut()));
+        assert_eq!(dpi(Release::Windows10_1607), 144);
+        assert_eq!(dpi(Release::Windows81), 192);
+        assert_eq!(dpi(Release::Windows7), get_dpi_for_system());
+        assert_eq!(with_backend(MockBackend::new(Release::Windows10_1607).with_dpi(120, 144, 192), get_dpi_for_system), 120);
+    }
+
+    #[test]
+    fn dpi_by_awareness_follows_the_thread_context() {
+        let backend = MockBackend::new(Release::Windows10_1607).with_dpi(120, 144, 192);
+        with_backend(backend, || {
+            assert_eq!(get_maybe_dpi_by_awareness(ptr::null_mut()), None);
+            set_thread_dpi_awareness_context(DpiAwarenessContext::System);
+            assert_eq!(get_maybe_dpi_by_awareness(ptr::null_mut()), Some(120));
+            set_thread_dpi_awareness_context(DpiAwarenessContext::PerMonitor);
+            assert_eq!(get_maybe_dpi_by_awareness(ptr::null_mut()), Some(144));
+        });
+        with_backend(MockBackend::new(Release::Windows81), || {
+            assert_eq!(get_maybe_dpi_by_awareness(ptr::null_mut()), None);
+        });
+    }
+
+    #[test]
+    fn modern_functions_answer_for_the_dpi_given() {
+        with_backend(MockBackend::new(Release::Windows10_1809).with_dpi(144, 96, 96), || {
+            let mut rect = RECT { left: 0, top: 0, right: 100, bottom: 50 };
+            assert_eq!(adjust_window_rect_ex_for_dpi(&mut rect, 0, false, 0, 192), Some(true));
+            assert_eq!((rect.left, rect.top, rect.right, rect.bottom), (-16, -16, 116, 66));
+            assert_eq!(get_system_metrics_for_dpi(10, 144), Some(15));
+            assert_eq!(get_icon_title_log_font_for_dpi(96).map(|lf| lf.lfHeight), Some(-12));
+            assert_eq!(get_icon_title_log_font_for_dpi(192).map(|lf| lf.lfHeight), Some(-24));
+            assert_eq!(get_dpi_from_dpi_awareness_context(DPI_AWARENESS_CONTEXT_SYSTEM_AWARE), Some(144));
+            assert_eq!(get_dpi_from_dpi_awareness_context(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2), Some(0));
+            assert_eq!(is_valid_dpi_awareness_context(DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED), Some(true));
+            assert_eq!(is_valid_dpi_awareness_context(ptr::null_mut()), Some(false));
+            assert_eq!(get_window_dpi_awareness_context(ptr::null_mut()), Some(DpiAwarenessContext::Unaware));
+            assert_eq!(get_window_dpi_hosting_behavior(ptr::null_mut()), Some(DpiHostingBehavior::Default));
+        });
+        // unknown until 1809
+        with_backend(MockBackend::new(Release::Windows10_1803), || {
+            assert_eq!(is_valid_dpi_awareness_context(DPI_AWARENESS_CONTEXT_UNAWARE_GDISCALED), Some(false));
+        });
+    }
+
+    #[test]
+    fn process_awareness_maps_to_dpi_awareness() {
+        with_backend(MockBackend::new(Release::Windows7), || {
+            assert_eq!(get_process_dpi_awareness().unwrap(), None);
+            assert_eq!(is_process_dpi_aware(), Some(false));
+            assert!(!set_process_dpi_awareness(DpiAwareness::PerMonitor).unwrap());
+            assert_eq!(set_process_dpi_aware(), Some(true));
+            assert_eq!(is_process_dpi_aware(), Some(true));
+        });
+        with_backend(MockBackend::new(Release::Windows81), || {
+            assert_eq!(get_process_dpi_awareness().unwrap(), Some(DpiAwareness::Unaware));
+            assert!(set_process_dpi_awareness(DpiAwareness::PerMonitor).unwrap());
+            assert_eq!(get_process_dpi_awareness().unwrap(), Some(DpiAwareness::PerMonitor));
+            // fixed once set
+            assert_eq!(set_process_dpi_awareness(DpiAwareness::System).unwrap_err().raw_os_error(), Some(crate::sys::E_ACCESSDENIED));
+            assert_eq!(set_process_dpi_awareness(DpiAwareness::Other).unwrap_err().kind(), io::ErrorKind::Other);
+        });
+    }
+}
@@ -1,23 +1,25 @@
 use hidpi::{
 	is_process_dpi_aware,
-	set_process_dpi_aware,
-	set_process_dpi_awareness,
 	get_process_dpi_awareness,
-	DpiAwareness,
+	enable_dpi_awareness,
+	DpiAwarenessContext,
 };
 
 fn main() {
 	println!("is_process_dpi_aware? {:?}", is_process_dpi_aware());
 	print_awareness();
-	println!("set_process_dpi_aware? {:?}", set_process_dpi_aware());
+
+	let applied = enable_dpi_awareness(DpiAwarenessContext::PerMonitorV2);
+	for attempt in &applied.failed {
+		println!("{:?} via {:?} failed: {}", attempt.context, attempt.api, attempt.error);
+	}
+	match (applied.context, applied.api) {
+		(Some(context), Some(api)) => println!("enable_dpi_awareness applied {} via {:?}", context, api),
+		_ => println!("enable_dpi_awareness could not change the process dpi awareness"),
+	}
+
 	println!("is_process_dpi_aware? {:?}", is_process_dpi_aware());
 	print_awareness();
-	println!("set_process_dpi_awareness {:?}", set_process_dpi_awareness(DpiAwareness::Unaware));
-	print_awareness();
-	println!("set_process_dpi_awareness {:?}", set_process_dpi_awareness(DpiAwareness::System));
-	print_awareness();
-	println!("set_process_dpi_awareness {:?}", set_process_dpi_awareness(DpiAwareness::PerMonitor));
-	print_awareness();
 }
 
 fn print_awareness() {